@@ -1,13 +1,17 @@
 use std::str::FromStr;
 
+use anyhow::{bail, Error};
 use serde::de::{value, IntoDeserializer};
 use serde::{Deserialize, Serialize};
 
 use proxmox_lang::constnamedbitmap;
 use proxmox_schema::{
-    api, const_regex, ApiStringFormat, BooleanSchema, EnumEntry, Schema, StringSchema,
+    api, const_regex, ApiStringFormat, ArraySchema, BooleanSchema, EnumEntry, Schema, StringSchema,
+    Updater,
 };
 
+use crate::{PROXMOX_SAFE_ID_FORMAT, SINGLE_LINE_COMMENT_SCHEMA};
+
 const_regex! {
     pub ACL_PATH_REGEX = concat!(r"^(?:/|", r"(?:/", PROXMOX_SAFE_ID_REGEX_STR!(), ")+", r")$");
 }
@@ -55,6 +59,10 @@ constnamedbitmap! {
         PRIV_REMOTE_MODIFY("Remote.Modify");
         /// Remote.Read allows reading data from a configured `Remote`
         PRIV_REMOTE_READ("Remote.Read");
+        /// Remote.DatastoreBackup allows creating new snapshots on a configured `Remote`
+        PRIV_REMOTE_DATASTORE_BACKUP("Remote.DatastoreBackup");
+        /// Remote.DatastorePrune allows deleting snapshots on a configured `Remote`
+        PRIV_REMOTE_DATASTORE_PRUNE("Remote.DatastorePrune");
 
         /// Sys.Console allows access to the system's console
         PRIV_SYS_CONSOLE("Sys.Console");
@@ -148,7 +156,9 @@ pub const ROLE_REMOTE_AUDIT: u64 = 0
 pub const ROLE_REMOTE_ADMIN: u64 = 0
     | PRIV_REMOTE_AUDIT
     | PRIV_REMOTE_MODIFY
-    | PRIV_REMOTE_READ;
+    | PRIV_REMOTE_READ
+    | PRIV_REMOTE_DATASTORE_BACKUP
+    | PRIV_REMOTE_DATASTORE_PRUNE;
 
 #[rustfmt::skip]
 #[allow(clippy::identity_op)]
@@ -157,6 +167,14 @@ pub const ROLE_REMOTE_SYNC_OPERATOR: u64 = 0
     | PRIV_REMOTE_AUDIT
     | PRIV_REMOTE_READ;
 
+#[rustfmt::skip]
+#[allow(clippy::identity_op)]
+/// Remote.SyncPushOperator can push and prune on the remote.
+pub const ROLE_REMOTE_SYNC_PUSH_OPERATOR: u64 = 0
+    | PRIV_REMOTE_AUDIT
+    | PRIV_REMOTE_DATASTORE_BACKUP
+    | PRIV_REMOTE_DATASTORE_PRUNE;
+
 #[rustfmt::skip]
 #[allow(clippy::identity_op)]
 /// Tape.Audit can audit the tape backup configuration and media content
@@ -222,6 +240,8 @@ pub enum Role {
     RemoteAdmin = ROLE_REMOTE_ADMIN,
     /// Syncronisation Opertator
     RemoteSyncOperator = ROLE_REMOTE_SYNC_OPERATOR,
+    /// Synchronisation Operator (push direction)
+    RemoteSyncPushOperator = ROLE_REMOTE_SYNC_PUSH_OPERATOR,
     /// Tape Auditor
     TapeAudit = ROLE_TAPE_AUDIT,
     /// Tape Administrator
@@ -240,6 +260,78 @@ impl FromStr for Role {
     }
 }
 
+pub const ROLE_ID_SCHEMA: Schema = StringSchema::new("Role ID (built-in or custom role).")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(2)
+    .max_length(32)
+    .schema();
+
+fn verify_privilege_name(name: &str) -> Result<(), Error> {
+    if !PRIVILEGES.iter().any(|(priv_name, _)| *priv_name == name) {
+        bail!("unknown privilege '{name}'");
+    }
+    Ok(())
+}
+
+pub const PRIVILEGE_NAME_SCHEMA: Schema = StringSchema::new("Privilege name.")
+    .format(&ApiStringFormat::VerifyFn(verify_privilege_name))
+    .schema();
+
+pub const PRIVILEGE_ARRAY_SCHEMA: Schema =
+    ArraySchema::new("Array of privilege names.", &PRIVILEGE_NAME_SCHEMA).schema();
+
+pub const PRIVILEGE_LIST_SCHEMA: Schema =
+    StringSchema::new("Comma separated list of privilege names.")
+        .format(&ApiStringFormat::PropertyString(&PRIVILEGE_ARRAY_SCHEMA))
+        .type_text("<privilege>[,<privilege>...]")
+        .schema();
+
+/// Parse a list of privilege names (as used by [PRIVILEGE_LIST_SCHEMA]) into a privilege bitmap.
+pub fn priv_names_to_privs(list: &str) -> Result<u64, Error> {
+    let mut privs = 0;
+    for name in list
+        .split(|c: char| c == ',' || c == ';' || c.is_ascii_whitespace())
+        .filter(|name| !name.is_empty())
+    {
+        match PRIVILEGES.iter().find(|(priv_name, _)| *priv_name == name) {
+            Some((_, value)) => privs |= value,
+            None => bail!("unknown privilege '{name}'"),
+        }
+    }
+    Ok(privs)
+}
+
+#[api(
+    properties: {
+        roleid: {
+            schema: ROLE_ID_SCHEMA,
+        },
+        privs: {
+            schema: PRIVILEGE_LIST_SCHEMA,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
+/// Custom role, composed of a set of [PRIVILEGES].
+pub struct RoleConfig {
+    #[updater(skip)]
+    pub roleid: String,
+    pub privs: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl RoleConfig {
+    /// Returns the privilege bitmap of this role.
+    pub fn privileges(&self) -> Result<u64, Error> {
+        priv_names_to_privs(&self.privs)
+    }
+}
+
 pub const ACL_PATH_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&ACL_PATH_REGEX);
 
 pub const ACL_PATH_SCHEMA: Schema = StringSchema::new("Access control path.")
@@ -276,7 +368,7 @@ pub const ACL_UGID_TYPE_SCHEMA: Schema = StringSchema::new("Type of 'ugid' prope
             description: "User or Group ID.",
         },
 	roleid: {
-            type: Role,
+            schema: ROLE_ID_SCHEMA,
         }
     }
 )]
@@ -1,18 +1,20 @@
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, format_err, Error};
 use serde::{Deserialize, Serialize};
 
+use proxmox_human_byte::HumanByte;
 use proxmox_schema::{
     api, const_regex, ApiStringFormat, ApiType, ArraySchema, EnumEntry, IntegerSchema, ReturnType,
     Schema, StringSchema, Updater, UpdaterType,
 };
 
 use crate::{
-    Authid, CryptMode, Fingerprint, GroupFilter, MaintenanceMode, Userid,
+    Authid, CryptMode, Fingerprint, GroupFilter, MaintenanceMode, NotificationMode, Userid,
     DATASTORE_NOTIFY_STRING_SCHEMA, GC_SCHEDULE_SCHEMA, PROXMOX_SAFE_ID_FORMAT,
-    PRUNE_SCHEDULE_SCHEMA, SHA256_HEX_REGEX, SINGLE_LINE_COMMENT_SCHEMA, UPID,
+    PRUNE_SCHEDULE_SCHEMA, SCRUB_SCHEDULE_SCHEMA, SHA256_HEX_REGEX, SINGLE_LINE_COMMENT_SCHEMA,
+    UPID, UUID_FORMAT,
 };
 
 const_regex! {
@@ -102,6 +104,22 @@ pub const DATASTORE_SCHEMA: Schema = StringSchema::new("Datastore name.")
     .max_length(32)
     .schema();
 
+/// Base directory below which removable datastores get mounted.
+pub const DATASTORE_MOUNT_DIR: &str = "/mnt/datastore";
+
+pub const BACKING_DEVICE_SCHEMA: Schema =
+    StringSchema::new("Filesystem UUID of the device backing a removable datastore.")
+        .format(&UUID_FORMAT)
+        .schema();
+
+pub const CHUNK_MIRROR_SCHEMA: Schema = StringSchema::new(
+    "Absolute path of a directory keeping a second copy of every chunk, used to repair corrupt \
+    chunks. Should be on a different disk than the datastore.",
+)
+.min_length(2)
+.max_length(4096)
+.schema();
+
 pub const CHUNK_DIGEST_SCHEMA: Schema = StringSchema::new("Chunk digest (SHA256).")
     .format(&CHUNK_DIGEST_FORMAT)
     .schema();
@@ -157,6 +175,42 @@ pub const PRUNE_SCHEMA_KEEP_YEARLY: Schema =
         .minimum(1)
         .schema();
 
+pub const PRUNE_SCHEMA_KEEP_WITHIN: Schema =
+    StringSchema::new("Keep all backups newer than this time span.")
+        .format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+        .type_text("<time-span>")
+        .schema();
+
+pub const PRUNE_SCHEMA_KEEP_WITHIN_HOURLY: Schema =
+    StringSchema::new("Keep the last backup of every hour for backups newer than this time span.")
+        .format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+        .type_text("<time-span>")
+        .schema();
+
+pub const PRUNE_SCHEMA_KEEP_WITHIN_DAILY: Schema =
+    StringSchema::new("Keep the last backup of every day for backups newer than this time span.")
+        .format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+        .type_text("<time-span>")
+        .schema();
+
+pub const PRUNE_SCHEMA_KEEP_WITHIN_WEEKLY: Schema =
+    StringSchema::new("Keep the last backup of every week for backups newer than this time span.")
+        .format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+        .type_text("<time-span>")
+        .schema();
+
+pub const PRUNE_SCHEMA_KEEP_WITHIN_MONTHLY: Schema =
+    StringSchema::new("Keep the last backup of every month for backups newer than this time span.")
+        .format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+        .type_text("<time-span>")
+        .schema();
+
+pub const PRUNE_SCHEMA_KEEP_WITHIN_YEARLY: Schema =
+    StringSchema::new("Keep the last backup of every year for backups newer than this time span.")
+        .format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+        .type_text("<time-span>")
+        .schema();
+
 #[api]
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -200,12 +254,113 @@ pub enum DatastoreFSyncLevel {
     Filesystem,
 }
 
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// How garbage collection marks chunks which are still in use.
+pub enum GarbageCollectionMode {
+    /// Update the access time of used chunks and remove all chunks with an access time older
+    /// than the cutoff. Needs a file system with working atime updates and keeps unused chunks
+    /// for at least 24 hours.
+    #[default]
+    Atime,
+    /// Record the digests of all used chunks in a sorted list on disk and remove all other
+    /// chunks not touched by an active backup writer. Does not depend on atime and has no 24
+    /// hour grace period.
+    DigestList,
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// The compression algorithm used for chunks.
+pub enum ChunkCompressionAlgorithm {
+    /// Store chunks uncompressed.
+    None,
+    /// Zstandard, the compression level can be configured.
+    #[default]
+    Zstd,
+    /// Zstandard with long distance matching, trades CPU time and memory for a better ratio.
+    ZstdLong,
+    /// LZ4, very fast but with a lower compression ratio.
+    Lz4,
+}
+
+/// Default zstd compression level used for chunks.
+pub const CHUNK_COMPRESSION_DEFAULT_LEVEL: u8 = 1;
+
+pub const CHUNK_COMPRESSION_LEVEL_SCHEMA: Schema =
+    IntegerSchema::new("Compression level, only used by zstd based algorithms.")
+        .minimum(1)
+        .maximum(22)
+        .default(CHUNK_COMPRESSION_DEFAULT_LEVEL as isize)
+        .schema();
+
+/// Chunk compression algorithm together with its level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkCompression {
+    pub algorithm: ChunkCompressionAlgorithm,
+    pub level: u8,
+}
+
+impl ChunkCompression {
+    /// Do not compress at all.
+    pub const NONE: Self = Self {
+        algorithm: ChunkCompressionAlgorithm::None,
+        level: 0,
+    };
+
+    /// Create a new compression setting, `level` defaults to
+    /// [`CHUNK_COMPRESSION_DEFAULT_LEVEL`] and is clamped to the range supported by zstd.
+    pub fn new(algorithm: ChunkCompressionAlgorithm, level: Option<u64>) -> Self {
+        let level = level
+            .unwrap_or(CHUNK_COMPRESSION_DEFAULT_LEVEL as u64)
+            .clamp(1, 22) as u8;
+        Self { algorithm, level }
+    }
+}
+
+impl Default for ChunkCompression {
+    fn default() -> Self {
+        Self::new(ChunkCompressionAlgorithm::default(), None)
+    }
+}
+
+pub const GC_SWEEP_PARTS_SCHEMA: Schema = IntegerSchema::new(
+    "Split the chunk store into this many parts, scheduled garbage collection runs only sweep \
+    one part at a time, in rotation.",
+)
+.minimum(1)
+.maximum(4096)
+.default(1)
+.schema();
+
 #[api(
     properties: {
         "chunk-order": {
             type: ChunkOrder,
             optional: true,
         },
+        compression: {
+            type: ChunkCompressionAlgorithm,
+            optional: true,
+        },
+        "compression-level": {
+            schema: CHUNK_COMPRESSION_LEVEL_SCHEMA,
+            optional: true,
+        },
+        "gc-mode": {
+            type: GarbageCollectionMode,
+            optional: true,
+        },
+        "gc-sweep-parts": {
+            schema: GC_SWEEP_PARTS_SCHEMA,
+            optional: true,
+        },
+        "scrub-rate-limit": {
+            type: HumanByte,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Default)]
@@ -217,6 +372,26 @@ pub struct DatastoreTuning {
     pub chunk_order: Option<ChunkOrder>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sync_level: Option<DatastoreFSyncLevel>,
+    /// Re-compress unencrypted chunks with this algorithm when they are stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<ChunkCompressionAlgorithm>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_level: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_mode: Option<GarbageCollectionMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_sweep_parts: Option<u64>,
+    /// Maximum read rate of the chunk store scrub job in bytes per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scrub_rate_limit: Option<HumanByte>,
+}
+
+impl DatastoreTuning {
+    /// The configured chunk compression, `None` keeps chunks as uploaded.
+    pub fn chunk_compression(&self) -> Option<ChunkCompression> {
+        self.compression
+            .map(|algorithm| ChunkCompression::new(algorithm, self.compression_level))
+    }
 }
 
 pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore tuning options")
@@ -225,6 +400,69 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
     ))
     .schema();
 
+pub const RETENTION_LOCK_SCHEMA: Schema = StringSchema::new(
+    "Lock new snapshots against removal for this time span after their backup time.",
+)
+.format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+.type_text("<time-span>")
+.schema();
+
+pub const RETENTION_LOCKED_UNTIL_SCHEMA: Schema =
+    IntegerSchema::new("Snapshot cannot be removed before this time (Unix epoch).")
+        .minimum(0)
+        .schema();
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The type of the storage backend holding the datastore contents.
+pub enum DatastoreBackendType {
+    /// Chunks, indexes and manifests are stored in the local datastore path.
+    #[default]
+    Filesystem,
+    /// Chunks, indexes and manifests are stored in an S3 compatible object store, the local
+    /// datastore path is used as cache.
+    S3,
+}
+
+#[api(
+    properties: {
+        type: {
+            type: DatastoreBackendType,
+            optional: true,
+        },
+        client: {
+            schema: crate::S3_CLIENT_ID_SCHEMA,
+            optional: true,
+        },
+        bucket: {
+            schema: crate::S3_BUCKET_NAME_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Datastore backend configuration
+pub struct DatastoreBackendConfig {
+    /// Storage backend type
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<DatastoreBackendType>,
+    /// S3 client configuration to use for the backend
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    /// Bucket holding the datastore contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+}
+
+pub const DATASTORE_BACKEND_CONFIG_STRING_SCHEMA: Schema =
+    StringSchema::new("Datastore backend configuration")
+        .format(&ApiStringFormat::PropertyString(
+            &DatastoreBackendConfig::API_SCHEMA,
+        ))
+        .schema();
+
 #[api(
     properties: {
         name: {
@@ -241,6 +479,10 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
             optional: true,
             schema: DATASTORE_NOTIFY_STRING_SCHEMA,
         },
+        "notification-mode": {
+            optional: true,
+            type: NotificationMode,
+        },
         comment: {
             optional: true,
             schema: SINGLE_LINE_COMMENT_SCHEMA,
@@ -253,6 +495,10 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
             optional: true,
             schema: PRUNE_SCHEDULE_SCHEMA,
         },
+        "scrub-schedule": {
+            optional: true,
+            schema: SCRUB_SCHEDULE_SCHEMA,
+        },
         keep: {
             type: crate::KeepOptions,
         },
@@ -261,6 +507,14 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
             optional: true,
             type: bool,
         },
+        "retention-lock": {
+            optional: true,
+            schema: RETENTION_LOCK_SCHEMA,
+        },
+        "chunk-mirror": {
+            optional: true,
+            schema: CHUNK_MIRROR_SCHEMA,
+        },
         tuning: {
             optional: true,
             schema: DATASTORE_TUNING_STRING_SCHEMA,
@@ -270,6 +524,14 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
             format: &ApiStringFormat::PropertyString(&MaintenanceMode::API_SCHEMA),
             type: String,
         },
+        backend: {
+            optional: true,
+            schema: DATASTORE_BACKEND_CONFIG_STRING_SCHEMA,
+        },
+        "backing-device": {
+            optional: true,
+            schema: BACKING_DEVICE_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
@@ -291,6 +553,9 @@ pub struct DataStoreConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prune_schedule: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scrub_schedule: Option<String>,
+
     #[serde(flatten)]
     pub keep: crate::KeepOptions,
 
@@ -298,6 +563,14 @@ pub struct DataStoreConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub verify_new: Option<bool>,
 
+    /// Default retention lock for new snapshots, namespaces can override it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention_lock: Option<String>,
+
+    /// Directory with a second copy of every chunk, not supported with the S3 backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_mirror: Option<String>,
+
     /// Send job email notification to this user
     #[serde(skip_serializing_if = "Option::is_none")]
     pub notify_user: Option<Userid>,
@@ -306,6 +579,10 @@ pub struct DataStoreConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub notify: Option<String>,
 
+    /// Whether job notifications use the legacy sendmail or the notification system
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification_mode: Option<NotificationMode>,
+
     /// Datastore tuning options
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tuning: Option<String>,
@@ -313,6 +590,17 @@ pub struct DataStoreConfig {
     /// Maintenance mode, type is either 'offline' or 'read-only', message should be enclosed in "
     #[serde(skip_serializing_if = "Option::is_none")]
     pub maintenance_mode: Option<String>,
+
+    /// Storage backend, defaults to the local filesystem
+    #[updater(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+
+    /// Filesystem UUID of the backing device, only set for removable datastores. The path is
+    /// relative to the root of that filesystem in this case.
+    #[updater(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backing_device: Option<String>,
 }
 
 impl DataStoreConfig {
@@ -323,13 +611,54 @@ impl DataStoreConfig {
             comment: None,
             gc_schedule: None,
             prune_schedule: None,
+            scrub_schedule: None,
             keep: Default::default(),
             verify_new: None,
+            retention_lock: None,
+            chunk_mirror: None,
             notify_user: None,
             notify: None,
+            notification_mode: None,
             tuning: None,
             maintenance_mode: None,
+            backend: None,
+            backing_device: None,
+        }
+    }
+
+    /// Returns true if the datastore lives on a removable device.
+    pub fn is_removable(&self) -> bool {
+        self.backing_device.is_some()
+    }
+
+    /// The directory the backing device of a removable datastore gets mounted on.
+    pub fn mount_point(&self) -> Option<PathBuf> {
+        self.backing_device
+            .as_ref()
+            .map(|_| Path::new(DATASTORE_MOUNT_DIR).join(&self.name))
+    }
+
+    /// The absolute path of the datastore on this host.
+    pub fn absolute_path(&self) -> PathBuf {
+        match self.mount_point() {
+            Some(mount_point) => mount_point.join(self.path.trim_start_matches('/')),
+            None => PathBuf::from(&self.path),
+        }
+    }
+
+    pub fn backend_config(&self) -> Result<DatastoreBackendConfig, Error> {
+        let value = DatastoreBackendConfig::API_SCHEMA
+            .parse_property_string(self.backend.as_deref().unwrap_or(""))?;
+        let config = DatastoreBackendConfig::deserialize(value)?;
+        if config.ty.unwrap_or_default() == DatastoreBackendType::S3 {
+            if config.client.is_none() {
+                bail!("s3 backend requires a 'client' to be set");
+            }
+            if config.bucket.is_none() {
+                bail!("s3 backend requires a 'bucket' to be set");
+            }
         }
+        Ok(config)
     }
 
     pub fn get_maintenance_mode(&self) -> Option<MaintenanceMode> {
@@ -1120,6 +1449,18 @@ pub struct SnapshotListItem {
     /// Protection from prunes
     #[serde(default)]
     pub protected: bool,
+    /// Snapshot cannot be removed before this time (Unix epoch).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<i64>,
+    /// On-disk size of all referenced chunks and blobs (from the last space accounting).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referenced_size: Option<u64>,
+    /// Size only referenced by this snapshot (from the last space accounting).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unique_size: Option<u64>,
+    /// Size also referenced by other snapshots (from the last space accounting).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_size: Option<u64>,
 }
 
 #[api(
@@ -1158,6 +1499,73 @@ pub struct GroupListItem {
     /// The first line from group "notes"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
+    /// On-disk size of all referenced chunks and blobs (from the last space accounting).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referenced_size: Option<u64>,
+    /// Size only referenced by this group (from the last space accounting).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unique_size: Option<u64>,
+    /// Size also referenced by other groups (from the last space accounting).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_size: Option<u64>,
+}
+
+#[api]
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Space used by a snapshot, backup group or namespace.
+pub struct SpaceUsage {
+    /// On-disk size of all referenced chunks and blobs, every chunk counted once.
+    pub referenced_size: u64,
+    /// Size only referenced by this entity, which would be freed by removing it.
+    pub unique_size: u64,
+    /// Size also referenced by other entities of the same kind.
+    pub shared_size: u64,
+}
+
+#[api(
+    properties: {
+        ns: {
+            type: BackupNamespace,
+        },
+        usage: {
+            type: SpaceUsage,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Space used by the snapshots directly in a namespace.
+pub struct NamespaceSpaceUsage {
+    pub ns: BackupNamespace,
+    #[serde(flatten)]
+    pub usage: SpaceUsage,
+}
+
+#[api(
+    properties: {
+        upid: {
+            type: UPID,
+            optional: true,
+        },
+        namespaces: {
+            type: Array,
+            items: {
+                type: NamespaceSpaceUsage,
+            },
+        },
+    },
+)]
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Result of the last space accounting run of a datastore.
+pub struct SpaceAccountingStatus {
+    /// Task which computed the result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upid: Option<String>,
+    /// Time the accounting finished, zero if it never ran.
+    pub time: i64,
+    pub namespaces: Vec<NamespaceSpaceUsage>,
 }
 
 #[api()]
@@ -1268,6 +1676,45 @@ pub struct GarbageCollectionStatus {
     pub removed_bad: usize,
     /// Number of chunks still marked as .bad after garbage collection.
     pub still_bad: usize,
+    /// Part of the chunk store swept by an incremental run (see ``gc-sweep-parts`` tuning).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sweep_part: Option<usize>,
+    /// Number of parts the chunk store was split into for an incremental run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sweep_parts: Option<usize>,
+}
+
+#[api(
+    properties: {
+        "upid": {
+            optional: true,
+            type: UPID,
+        },
+    },
+)]
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Chunk store scrub status.
+pub struct ScrubStatus {
+    pub upid: Option<String>,
+    /// Number of checked chunks.
+    pub checked_chunks: usize,
+    /// Sum of bytes read from checked chunks.
+    pub checked_bytes: u64,
+    /// Number of encrypted chunks, only their CRC can be checked.
+    pub encrypted_chunks: usize,
+    /// Number of chunks found corrupt and renamed to .bad, as they could not be repaired.
+    pub corrupt_chunks: usize,
+    /// Number of chunks which could not be checked, e.g. markers of remote stored chunks.
+    pub skipped_chunks: usize,
+    /// Number of chunks already marked as .bad, for example by verify.
+    pub bad_chunks: usize,
+    /// Number of corrupt chunks replaced by their mirror copy.
+    #[serde(default)]
+    pub repaired_chunks: usize,
+    /// Number of missing or corrupt mirror copies which were written again.
+    #[serde(default)]
+    pub repaired_mirror_copies: usize,
 }
 
 #[api(
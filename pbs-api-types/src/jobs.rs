@@ -8,8 +8,8 @@ use proxmox_schema::*;
 
 use crate::{
     Authid, BackupNamespace, BackupType, RateLimitConfig, Userid, BACKUP_GROUP_SCHEMA,
-    BACKUP_NAMESPACE_SCHEMA, DATASTORE_SCHEMA, DRIVE_NAME_SCHEMA, MEDIA_POOL_NAME_SCHEMA,
-    NS_MAX_DEPTH_REDUCED_SCHEMA, PROXMOX_SAFE_ID_FORMAT, REMOTE_ID_SCHEMA,
+    BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, DATASTORE_SCHEMA, DRIVE_NAME_SCHEMA,
+    MEDIA_POOL_NAME_SCHEMA, NS_MAX_DEPTH_REDUCED_SCHEMA, PROXMOX_SAFE_ID_FORMAT, REMOTE_ID_SCHEMA,
     SINGLE_LINE_COMMENT_SCHEMA,
 };
 
@@ -42,6 +42,14 @@ pub const GC_SCHEDULE_SCHEMA: Schema =
         .type_text("<calendar-event>")
         .schema();
 
+pub const SCRUB_SCHEDULE_SCHEMA: Schema =
+    StringSchema::new("Run chunk store scrub job at specified schedule.")
+        .format(&ApiStringFormat::VerifyFn(
+            proxmox_time::verify_calendar_event,
+        ))
+        .type_text("<calendar-event>")
+        .schema();
+
 pub const PRUNE_SCHEDULE_SCHEMA: Schema = StringSchema::new("Run prune job at specified schedule.")
     .format(&ApiStringFormat::VerifyFn(
         proxmox_time::verify_calendar_event,
@@ -63,6 +71,12 @@ pub const REMOVE_VANISHED_BACKUPS_SCHEMA: Schema = BooleanSchema::new(
 .default(false)
 .schema();
 
+pub const SYNC_DRY_RUN_SCHEMA: Schema = BooleanSchema::new(
+    "Only show what would be synced or removed, without writing to the target datastore.",
+)
+.default(false)
+.schema();
+
 #[api(
     properties: {
         "next-run": {
@@ -114,6 +128,18 @@ pub enum Notify {
     Error,
 }
 
+#[api()]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// How job notifications are delivered
+pub enum NotificationMode {
+    /// Send an email via the local sendmail to the configured notify user
+    #[default]
+    LegacySendmail,
+    /// Route notifications through the configured notification matchers and targets
+    NotificationSystem,
+}
+
 #[api(
     properties: {
         gc: {
@@ -293,6 +319,10 @@ pub struct VerificationJobStatus {
             optional: true,
             type: Userid,
         },
+        "notification-mode": {
+            optional: true,
+            type: NotificationMode,
+        },
         "group-filter": {
             schema: GROUP_FILTER_LIST_SCHEMA,
             optional: true,
@@ -324,6 +354,8 @@ pub struct TapeBackupJobSetup {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub notify_user: Option<Userid>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification_mode: Option<NotificationMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub group_filter: Option<Vec<GroupFilter>>,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub ns: Option<BackupNamespace>,
@@ -490,11 +522,320 @@ pub const GROUP_FILTER_SCHEMA: Schema = StringSchema::new(
 pub const GROUP_FILTER_LIST_SCHEMA: Schema =
     ArraySchema::new("List of group filters.", &GROUP_FILTER_SCHEMA).schema();
 
+#[derive(Clone, Debug)]
+/// How a rename rule rewrites a namespace path or backup ID.
+pub enum RenameAction {
+    /// Prepend a fixed string
+    Prefix(String),
+    /// Append a fixed string
+    Suffix(String),
+    /// Replace the first match of a regular expression, `$N` refers to capture groups
+    Regex(Regex, String),
+}
+
+impl PartialEq for RenameAction {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Prefix(a), Self::Prefix(b)) => a == b,
+            (Self::Suffix(a), Self::Suffix(b)) => a == b,
+            (Self::Regex(a, x), Self::Regex(b, y)) => a.as_str() == b.as_str() && x == y,
+            _ => false,
+        }
+    }
+}
+
+impl RenameAction {
+    fn parse(ty: &str, value: &str) -> Result<Self, anyhow::Error> {
+        Ok(match ty {
+            "prefix" => RenameAction::Prefix(value.to_string()),
+            "suffix" => RenameAction::Suffix(value.to_string()),
+            // neither namespaces nor backup IDs can contain a colon, the regex can
+            "regex" => match value.rsplit_once(':') {
+                Some((regex, replacement)) => {
+                    RenameAction::Regex(Regex::new(regex)?, replacement.to_string())
+                }
+                None => bail!("expected 'REGEX:REPLACEMENT', got '{}'", value),
+            },
+            _ => bail!("expected 'prefix', 'suffix' or 'regex', got '{}'", ty),
+        })
+    }
+
+    /// Applies the action to `value`, `separator` is put between a prefix or suffix and a
+    /// non-empty value.
+    fn apply(&self, value: &str, separator: &str) -> String {
+        match self {
+            RenameAction::Prefix(affix) | RenameAction::Suffix(affix) if value.is_empty() => {
+                affix.clone()
+            }
+            RenameAction::Prefix(prefix) => format!("{prefix}{separator}{value}"),
+            RenameAction::Suffix(suffix) => format!("{value}{separator}{suffix}"),
+            RenameAction::Regex(regex, replacement) => {
+                regex.replace(value, replacement.as_str()).into_owned()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RenameAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameAction::Prefix(prefix) => write!(f, "prefix:{}", prefix),
+            RenameAction::Suffix(suffix) => write!(f, "suffix:{}", suffix),
+            RenameAction::Regex(regex, replacement) => {
+                write!(f, "regex:{}:{}", regex.as_str(), replacement)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Rule for renaming namespaces or backup IDs when pulling.
+pub enum SyncRenameRule {
+    /// Rewrites the namespace path below the namespace anchor of the sync
+    Namespace(RenameAction),
+    /// Rewrites the ID of backup groups
+    BackupId(RenameAction),
+}
+
+impl std::str::FromStr for SyncRenameRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = match s.split_once(':') {
+            Some(split) => split,
+            None => {
+                bail!("input doesn't match expected format '<ns|id>-<prefix|suffix|regex>:VALUE'")
+            }
+        };
+        Ok(match kind.split_once('-') {
+            Some(("ns", ty)) => {
+                let action = RenameAction::parse(ty, value)?;
+                if let RenameAction::Prefix(affix) | RenameAction::Suffix(affix) = &action {
+                    if BackupNamespace::new(affix)?.is_root() {
+                        bail!("namespace prefix or suffix must not be empty");
+                    }
+                }
+                SyncRenameRule::Namespace(action)
+            }
+            Some(("id", ty)) => {
+                let action = RenameAction::parse(ty, value)?;
+                match &action {
+                    RenameAction::Prefix(prefix) => {
+                        BACKUP_ID_SCHEMA.parse_simple_value(prefix)?;
+                    }
+                    RenameAction::Suffix(suffix) => {
+                        BACKUP_ID_SCHEMA.parse_simple_value(&format!("_{suffix}"))?;
+                    }
+                    RenameAction::Regex(..) => (),
+                }
+                SyncRenameRule::BackupId(action)
+            }
+            _ => bail!("expected 'ns-' or 'id-' prefix, got '{}'", kind),
+        })
+    }
+}
+
+// used for serializing below, caution!
+impl std::fmt::Display for SyncRenameRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncRenameRule::Namespace(action) => write!(f, "ns-{}", action),
+            SyncRenameRule::BackupId(action) => write!(f, "id-{}", action),
+        }
+    }
+}
+
+proxmox_serde::forward_deserialize_to_from_str!(SyncRenameRule);
+proxmox_serde::forward_serialize_to_display!(SyncRenameRule);
+
+impl SyncRenameRule {
+    /// Applies the namespace rules of `rules` in order to `ns`, which is relative to the
+    /// namespace anchor of the sync.
+    pub fn rename_namespace(
+        rules: &[SyncRenameRule],
+        ns: &BackupNamespace,
+    ) -> Result<BackupNamespace, anyhow::Error> {
+        let mut name = ns.name();
+        for rule in rules {
+            if let SyncRenameRule::Namespace(action) = rule {
+                name = action.apply(&name, "/");
+            }
+        }
+        BackupNamespace::new(&name)
+            .map_err(|err| anyhow::format_err!("renaming namespace '{ns}' failed - {err}"))
+    }
+
+    /// Applies the backup ID rules of `rules` in order to `id`.
+    pub fn rename_backup_id(rules: &[SyncRenameRule], id: &str) -> Result<String, anyhow::Error> {
+        let mut new_id = id.to_string();
+        for rule in rules {
+            if let SyncRenameRule::BackupId(action) = rule {
+                new_id = action.apply(&new_id, "");
+            }
+        }
+        BACKUP_ID_SCHEMA
+            .parse_simple_value(&new_id)
+            .map_err(|err| anyhow::format_err!("renaming backup ID '{id}' failed - {err}"))?;
+        Ok(new_id)
+    }
+}
+
+fn verify_sync_rename_rule(input: &str) -> Result<(), anyhow::Error> {
+    SyncRenameRule::from_str(input).map(|_| ())
+}
+
+pub const SYNC_RENAME_RULE_SCHEMA: Schema = StringSchema::new(
+    "Rule for renaming namespaces ('ns-') or backup IDs ('id-') when pulling, either by adding a \
+    prefix ('prefix:VALUE') or suffix ('suffix:VALUE'), or by regex substitution \
+    ('regex:RE:REPLACEMENT').",
+)
+.format(&ApiStringFormat::VerifyFn(verify_sync_rename_rule))
+.type_text("<ns|id>-<prefix:VALUE|suffix:VALUE|regex:RE:REPLACEMENT>")
+.schema();
+
+pub const SYNC_RENAME_RULE_LIST_SCHEMA: Schema = ArraySchema::new(
+    "List of rename rules, applied in order.",
+    &SYNC_RENAME_RULE_SCHEMA,
+)
+.schema();
+
 pub const TRANSFER_LAST_SCHEMA: Schema =
     IntegerSchema::new("Limit transfer to last N snapshots (per group), skipping others")
         .minimum(1)
         .schema();
 
+pub const SYNC_PARALLEL_GROUPS_SCHEMA: Schema =
+    IntegerSchema::new("Number of backup groups to pull in parallel.")
+        .minimum(1)
+        .maximum(32)
+        .default(1)
+        .schema();
+
+pub const SYNC_CHUNK_CONCURRENCY_SCHEMA: Schema =
+    IntegerSchema::new("Number of chunks to download in parallel for each group.")
+        .minimum(1)
+        .maximum(256)
+        .default(20)
+        .schema();
+
+pub const REPAIR_CORRUPT_SCHEMA: Schema = BooleanSchema::new(
+    "Repair snapshots which failed verification by fetching their missing or corrupt chunks \
+    from the source again and verifying them afterwards.",
+)
+.default(false)
+.schema();
+
+/// Parses a time limit of a sync snapshot filter, which is either an absolute time in RFC 3339
+/// format or a time span before `now`. Returns the limit as UNIX epoch.
+pub fn parse_sync_time_limit(value: &str, now: i64) -> Result<i64, anyhow::Error> {
+    if let Ok(epoch) = proxmox_time::parse_rfc3339(value) {
+        return Ok(epoch);
+    }
+    let span: proxmox_time::TimeSpan = value.parse()?;
+    Ok(now - f64::from(span) as i64)
+}
+
+fn verify_sync_time_limit(value: &str) -> Result<(), anyhow::Error> {
+    parse_sync_time_limit(value, 0).map(|_| ())
+}
+
+pub const SYNC_NEWER_THAN_SCHEMA: Schema = StringSchema::new(
+    "Only sync snapshots newer than this, either a time span before the start of the sync or \
+    an absolute time (RFC 3339).",
+)
+.format(&ApiStringFormat::VerifyFn(verify_sync_time_limit))
+.type_text("<time-span|rfc3339>")
+.schema();
+
+pub const SYNC_OLDER_THAN_SCHEMA: Schema = StringSchema::new(
+    "Only sync snapshots older than this time span before the start of the sync, for delayed \
+    replication.",
+)
+.format(&ApiStringFormat::VerifyFn(proxmox_time::verify_time_span))
+.type_text("<time-span>")
+.schema();
+
+pub const SYNC_VERIFIED_ONLY_SCHEMA: Schema =
+    BooleanSchema::new("Only sync snapshots whose last verification was successful.")
+        .default(false)
+        .schema();
+
+pub const SYNC_PROTECTED_ONLY_SCHEMA: Schema = BooleanSchema::new("Only sync protected snapshots.")
+    .default(false)
+    .schema();
+
+#[api()]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Filter snapshots by their encryption.
+pub enum SyncEncryptionFilter {
+    /// Only snapshots with at least one encrypted archive.
+    Encrypted,
+    /// Only snapshots without encrypted archives.
+    Unencrypted,
+}
+
+#[api(
+    properties: {
+        "newer-than": {
+            schema: SYNC_NEWER_THAN_SCHEMA,
+            optional: true,
+        },
+        "older-than": {
+            schema: SYNC_OLDER_THAN_SCHEMA,
+            optional: true,
+        },
+        "verified-only": {
+            schema: SYNC_VERIFIED_ONLY_SCHEMA,
+            optional: true,
+        },
+        "protected-only": {
+            schema: SYNC_PROTECTED_ONLY_SCHEMA,
+            optional: true,
+        },
+        "encryption-filter": {
+            type: SyncEncryptionFilter,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Default, Clone, Updater, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Snapshot filters of a sync job, applied to the snapshots of each synced group.
+pub struct SyncSnapshotFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub newer_than: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption_filter: Option<SyncEncryptionFilter>,
+}
+
+#[api()]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Direction of a sync job.
+pub enum SyncDirection {
+    /// Sync direction pull: copy from `remote-store` into the local `store`.
+    #[default]
+    Pull,
+    /// Sync direction push: copy from the local `store` into `remote-store` on `remote`.
+    Push,
+}
+
+impl std::fmt::Display for SyncDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncDirection::Pull => f.write_str("pull"),
+            SyncDirection::Push => f.write_str("push"),
+        }
+    }
+}
+
 #[api(
     properties: {
         id: {
@@ -549,6 +890,29 @@ pub const TRANSFER_LAST_SCHEMA: Schema =
             schema: TRANSFER_LAST_SCHEMA,
             optional: true,
         },
+        "repair-corrupt": {
+            schema: REPAIR_CORRUPT_SCHEMA,
+            optional: true,
+        },
+        "parallel-groups": {
+            schema: SYNC_PARALLEL_GROUPS_SCHEMA,
+            optional: true,
+        },
+        "chunk-concurrency": {
+            schema: SYNC_CHUNK_CONCURRENCY_SCHEMA,
+            optional: true,
+        },
+        "snapshot-filter": {
+            type: SyncSnapshotFilter,
+        },
+        "rename-rules": {
+            schema: SYNC_RENAME_RULE_LIST_SCHEMA,
+            optional: true,
+        },
+        "sync-direction": {
+            type: SyncDirection,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, Updater, PartialEq)]
@@ -582,6 +946,18 @@ pub struct SyncJobConfig {
     pub limit: RateLimitConfig,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transfer_last: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair_corrupt: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_groups: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_concurrency: Option<usize>,
+    #[serde(flatten)]
+    pub snapshot_filter: SyncSnapshotFilter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rename_rules: Option<Vec<SyncRenameRule>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_direction: Option<SyncDirection>,
 }
 
 impl SyncJobConfig {
@@ -591,6 +967,11 @@ impl SyncJobConfig {
             None => vec!["datastore", &self.store],
         }
     }
+
+    /// Whether this job pushes the local `store` to a remote instead of pulling from it.
+    pub fn is_push(&self) -> bool {
+        self.sync_direction.unwrap_or_default() == SyncDirection::Push
+    }
 }
 
 #[api(
@@ -641,6 +1022,30 @@ pub struct SyncJobStatus {
             schema: crate::PRUNE_SCHEMA_KEEP_YEARLY,
             optional: true,
         },
+        "keep-within": {
+            schema: crate::PRUNE_SCHEMA_KEEP_WITHIN,
+            optional: true,
+        },
+        "keep-within-hourly": {
+            schema: crate::PRUNE_SCHEMA_KEEP_WITHIN_HOURLY,
+            optional: true,
+        },
+        "keep-within-daily": {
+            schema: crate::PRUNE_SCHEMA_KEEP_WITHIN_DAILY,
+            optional: true,
+        },
+        "keep-within-weekly": {
+            schema: crate::PRUNE_SCHEMA_KEEP_WITHIN_WEEKLY,
+            optional: true,
+        },
+        "keep-within-monthly": {
+            schema: crate::PRUNE_SCHEMA_KEEP_WITHIN_MONTHLY,
+            optional: true,
+        },
+        "keep-within-yearly": {
+            schema: crate::PRUNE_SCHEMA_KEEP_WITHIN_YEARLY,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Default, Updater, Clone, PartialEq)]
@@ -659,6 +1064,18 @@ pub struct KeepOptions {
     pub keep_monthly: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub keep_yearly: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_hourly: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_daily: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_weekly: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_monthly: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_within_yearly: Option<String>,
 }
 
 impl KeepOptions {
@@ -670,6 +1087,25 @@ impl KeepOptions {
             + self.keep_monthly.unwrap_or(0)
             + self.keep_yearly.unwrap_or(0)
             > 0
+            || self.keep_within_options().any(|(_, span)| span > 0)
+    }
+
+    /// Iterates over the set time span based options as (key, seconds) pairs, invalid time spans
+    /// are skipped.
+    pub fn keep_within_options(&self) -> impl Iterator<Item = (&'static str, i64)> + '_ {
+        [
+            ("keep-within", &self.keep_within),
+            ("keep-within-hourly", &self.keep_within_hourly),
+            ("keep-within-daily", &self.keep_within_daily),
+            ("keep-within-weekly", &self.keep_within_weekly),
+            ("keep-within-monthly", &self.keep_within_monthly),
+            ("keep-within-yearly", &self.keep_within_yearly),
+        ]
+        .into_iter()
+        .filter_map(|(key, span)| {
+            let span: proxmox_time::TimeSpan = span.as_deref()?.parse().ok()?;
+            Some((key, f64::from(span) as i64))
+        })
     }
 }
 
@@ -794,3 +1230,156 @@ pub struct PruneJobStatus {
     #[serde(flatten)]
     pub status: JobScheduleStatus,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_sync_time_limit() -> Result<(), anyhow::Error> {
+        let now = 1_700_000_000;
+
+        // absolute times are independent of the start of the sync
+        assert_eq!(
+            parse_sync_time_limit("2024-01-01T00:00:00Z", now)?,
+            1_704_067_200
+        );
+        assert_eq!(
+            parse_sync_time_limit("2024-01-01T01:00:00+01:00", now)?,
+            1_704_067_200
+        );
+
+        // time spans are relative to it
+        assert_eq!(parse_sync_time_limit("7d", now)?, now - 7 * 86400);
+        assert_eq!(parse_sync_time_limit("1h 30min", now)?, now - 5400);
+
+        assert!(parse_sync_time_limit("", now).is_err());
+        assert!(parse_sync_time_limit("yesterday", now).is_err());
+        assert!(parse_sync_time_limit("2024-13-01T00:00:00Z", now).is_err());
+
+        // the schemas use the same parsing
+        assert!(verify_sync_time_limit("2024-01-01T00:00:00Z").is_ok());
+        assert!(verify_sync_time_limit("yesterday").is_err());
+
+        Ok(())
+    }
+
+    fn parse_rules(rules: &[&str]) -> Result<Vec<SyncRenameRule>, anyhow::Error> {
+        rules.iter().map(|rule| rule.parse()).collect()
+    }
+
+    #[test]
+    fn test_rename_rule_parsing() -> Result<(), anyhow::Error> {
+        for rule in [
+            "ns-prefix:cluster1",
+            "ns-prefix:site-a/cluster1",
+            "ns-suffix:old",
+            "ns-regex:^dept-([a-z]+):$1",
+            "id-prefix:cluster1-",
+            "id-suffix:-c1",
+            "id-regex:^([0-9]+)$:vm$1",
+        ] {
+            let parsed: SyncRenameRule = rule.parse()?;
+            assert_eq!(parsed.to_string(), rule);
+            assert_eq!(parsed.to_string().parse::<SyncRenameRule>()?, parsed);
+        }
+
+        // the regex is split from the replacement at the last colon
+        match "id-regex:a:b:c".parse()? {
+            SyncRenameRule::BackupId(RenameAction::Regex(regex, replacement)) => {
+                assert_eq!(regex.as_str(), "a:b");
+                assert_eq!(replacement, "c");
+            }
+            other => panic!("unexpected rule {other}"),
+        }
+
+        for rule in [
+            "",
+            "id-prefix",
+            "prefix:foo",
+            "group-prefix:foo",
+            "id-append:foo",
+            "ns-prefix:",
+            "ns-suffix:a//b",
+            "ns-regex:no-replacement",
+            "id-prefix:a/b",
+            "id-suffix:c 1",
+            "id-regex:(:x",
+        ] {
+            assert!(
+                rule.parse::<SyncRenameRule>().is_err(),
+                "{rule:?} was accepted"
+            );
+            assert!(verify_sync_rename_rule(rule).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_backup_id() -> Result<(), anyhow::Error> {
+        let rename = |rules: &[&str], id: &str| {
+            parse_rules(rules).and_then(|rules| SyncRenameRule::rename_backup_id(&rules, id))
+        };
+
+        assert_eq!(rename(&[], "100")?, "100");
+        assert_eq!(rename(&["id-prefix:cluster1-"], "100")?, "cluster1-100");
+        assert_eq!(rename(&["id-suffix:.c1"], "100")?, "100.c1");
+        assert_eq!(rename(&["id-regex:^([0-9]+)$:vm$1"], "100")?, "vm100");
+        assert_eq!(rename(&["id-regex:^([0-9]+)$:vm$1"], "host1")?, "host1");
+
+        // only the first match is replaced
+        assert_eq!(rename(&["id-regex:0:x"], "100")?, "1x0");
+
+        // rules are applied in order, namespace rules are ignored
+        let rules = ["id-prefix:a-", "ns-prefix:ignored", "id-regex:^a-1:b-2"];
+        assert_eq!(rename(&rules, "100")?, "b-200");
+
+        // the result has to be a valid backup ID
+        assert!(rename(&["id-regex:.*:"], "100").is_err());
+        assert!(rename(&["id-regex:^:/"], "100").is_err());
+        assert!(rename(&["id-regex:^1:-"], "100").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_namespace() -> Result<(), anyhow::Error> {
+        let rename = |rules: &[&str], ns: &str| -> Result<String, anyhow::Error> {
+            let rules = parse_rules(rules)?;
+            let ns = BackupNamespace::new(ns)?;
+            Ok(SyncRenameRule::rename_namespace(&rules, &ns)?.name())
+        };
+
+        assert_eq!(rename(&[], "a/b")?, "a/b");
+
+        // prefixes and suffixes are added as namespace levels, also to the root namespace
+        assert_eq!(rename(&["ns-prefix:cluster1"], "")?, "cluster1");
+        assert_eq!(rename(&["ns-prefix:cluster1"], "a/b")?, "cluster1/a/b");
+        assert_eq!(
+            rename(&["ns-prefix:site/cluster1"], "a")?,
+            "site/cluster1/a"
+        );
+        assert_eq!(rename(&["ns-suffix:old"], "")?, "old");
+        assert_eq!(rename(&["ns-suffix:old"], "a/b")?, "a/b/old");
+
+        assert_eq!(
+            rename(&["ns-regex:^dept-([a-z]+):$1"], "dept-x/team")?,
+            "x/team"
+        );
+        assert_eq!(rename(&["ns-regex:^dept-([a-z]+):$1"], "other")?, "other");
+        // removing the whole path maps to the anchor
+        assert_eq!(rename(&["ns-regex:^.*$:"], "a/b")?, "");
+
+        // rules are applied in order, backup ID rules are ignored
+        let rules = ["ns-prefix:x", "id-prefix:ignored-", "ns-regex:^x/a:y"];
+        assert_eq!(rename(&rules, "a/b")?, "y/b");
+
+        // the result has to be a valid namespace
+        assert!(rename(&["ns-regex:b:b c"], "a/b").is_err());
+        assert!(rename(&["ns-regex:/://"], "a/b").is_err());
+        assert!(rename(&["ns-prefix:a/b/c/d"], "e/f/g/h").is_err());
+
+        Ok(())
+    }
+}
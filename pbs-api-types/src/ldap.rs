@@ -45,6 +45,14 @@ pub enum LdapMode {
             optional: true,
             schema: USER_CLASSES_SCHEMA,
         },
+        "group-dn" : {
+            schema: LDAP_DOMAIN_SCHEMA,
+            optional: true,
+        },
+        "group-classes" : {
+            optional: true,
+            schema: GROUP_CLASSES_SCHEMA,
+        },
         "base-dn" : {
             schema: LDAP_DOMAIN_SCHEMA,
         },
@@ -106,6 +114,42 @@ pub struct LdapRealmConfig {
     /// User ``objectClass`` classes to sync
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user_classes: Option<String>,
+    /// Base domain name for the group search, defaults to ``base-dn``.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_dn: Option<String>,
+    /// Custom LDAP search filter for group sync
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_filter: Option<String>,
+    /// Group ``objectClass`` classes to sync
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_classes: Option<String>,
+    /// LDAP attribute containing the group name, defaults to ``cn``.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_name_attr: Option<String>,
+}
+
+#[api()]
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Which entities to synchronize
+pub enum SyncScope {
+    /// Synchronize users only
+    #[default]
+    Users,
+    /// Synchronize groups and the membership of existing users only
+    Groups,
+    /// Synchronize users and groups
+    Both,
+}
+
+impl SyncScope {
+    pub fn users(self) -> bool {
+        matches!(self, SyncScope::Users | SyncScope::Both)
+    }
+
+    pub fn groups(self) -> bool {
+        matches!(self, SyncScope::Groups | SyncScope::Both)
+    }
 }
 
 #[api(
@@ -114,6 +158,10 @@ pub struct LdapRealmConfig {
             optional: true,
             schema: REMOVE_VANISHED_SCHEMA,
         },
+        scope: {
+            type: SyncScope,
+            optional: true,
+        },
     },
 
 )]
@@ -125,6 +173,8 @@ pub struct SyncDefaultsOptions {
     pub remove_vanished: Option<String>,
     /// Enable new users after sync
     pub enable_new: Option<bool>,
+    /// Which entities to synchronize
+    pub scope: Option<SyncScope>,
 }
 
 #[api()]
@@ -151,9 +201,9 @@ pub const SYNC_DEFAULTS_STRING_SCHEMA: Schema = StringSchema::new("sync defaults
 const REMOVE_VANISHED_DESCRIPTION: &str =
     "A semicolon-seperated list of things to remove when they or the user \
 vanishes during user synchronization. The following values are possible: ``entry`` removes the \
-user when not returned from the sync; ``properties`` removes any  \
+user (or synced group) when not returned from the sync; ``properties`` removes any  \
 properties on existing user that do not appear in the source. \
-``acl`` removes ACLs when the user is not returned from the sync.";
+``acl`` removes ACLs when the user (or synced group) is not returned from the sync.";
 
 pub const REMOVE_VANISHED_SCHEMA: Schema = StringSchema::new(REMOVE_VANISHED_DESCRIPTION)
     .format(&ApiStringFormat::PropertyString(&REMOVE_VANISHED_ARRAY))
@@ -206,3 +256,18 @@ pub const USER_CLASSES_SCHEMA: Schema = StringSchema::new(USER_CLASSES_TEXT)
     .format(&ApiStringFormat::PropertyString(&USER_CLASSES_ARRAY))
     .default("inetorgperson,posixaccount,person,user")
     .schema();
+
+pub const GROUP_CLASSES_ARRAY: Schema = ArraySchema::new(
+    "Array of group classes",
+    &StringSchema::new("group class").schema(),
+)
+.min_length(1)
+.schema();
+
+const GROUP_CLASSES_TEXT: &str = "Comma-separated list of allowed objectClass values for \
+group synchronization.";
+
+pub const GROUP_CLASSES_SCHEMA: Schema = StringSchema::new(GROUP_CLASSES_TEXT)
+    .format(&ApiStringFormat::PropertyString(&GROUP_CLASSES_ARRAY))
+    .default("groupofnames,groupofuniquenames,group,posixgroup,univentiongroup,ipausergroup")
+    .schema();
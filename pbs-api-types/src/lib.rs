@@ -84,6 +84,9 @@ pub use maintenance::*;
 mod network;
 pub use network::*;
 
+mod notifications;
+pub use notifications::*;
+
 mod node;
 pub use node::*;
 
@@ -113,9 +116,15 @@ pub use openid::*;
 mod ldap;
 pub use ldap::*;
 
+mod quota;
+pub use quota::*;
+
 mod remote;
 pub use remote::*;
 
+mod s3;
+pub use s3::*;
+
 mod tape;
 pub use tape::*;
 
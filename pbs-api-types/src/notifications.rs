@@ -0,0 +1,474 @@
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::{
+    api, const_regex, ApiStringFormat, ApiType, ArraySchema, Schema, StringSchema, Updater,
+};
+
+use crate::{
+    Userid, DNS_NAME_OR_IP_SCHEMA, EMAIL_SCHEMA, HTTP_URL_SCHEMA, PASSWORD_FORMAT,
+    PROXMOX_SAFE_ID_FORMAT, SINGLE_LINE_COMMENT_FORMAT, SINGLE_LINE_COMMENT_SCHEMA,
+};
+
+pub const NOTIFICATION_ID_SCHEMA: Schema =
+    StringSchema::new("Name of a notification target or matcher.")
+        .format(&PROXMOX_SAFE_ID_FORMAT)
+        .min_length(2)
+        .max_length(32)
+        .schema();
+
+pub const NOTIFICATION_TARGET_LIST_SCHEMA: Schema =
+    ArraySchema::new("List of notification targets.", &NOTIFICATION_ID_SCHEMA).schema();
+
+const_regex! {
+    pub NOTIFICATION_MATCH_FIELD_REGEX = r"^(?:exact|regex):[a-z0-9_\-]+=.+$";
+    pub NOTIFICATION_SECRET_REGEX = r"^[a-zA-Z0-9_\-]+=[[:^cntrl:]]*$";
+    pub NOTIFICATION_HEADER_REGEX = r"^[a-zA-Z0-9_\-]+:[[:^cntrl:]]*$";
+}
+
+pub const NOTIFICATION_MATCH_FIELD_SCHEMA: Schema = StringSchema::new(
+    "Match a metadata field of the notification, either 'exact:<field>=<value>' or \
+    'regex:<field>=<regex>'.",
+)
+.format(&ApiStringFormat::Pattern(&NOTIFICATION_MATCH_FIELD_REGEX))
+.max_length(256)
+.schema();
+
+pub const NOTIFICATION_MATCH_FIELD_LIST_SCHEMA: Schema = ArraySchema::new(
+    "List of metadata field matchers.",
+    &NOTIFICATION_MATCH_FIELD_SCHEMA,
+)
+.schema();
+
+pub const NOTIFICATION_MATCH_SEVERITY_LIST_SCHEMA: Schema = ArraySchema::new(
+    "Match notifications with one of the given severities.",
+    &NotificationSeverity::API_SCHEMA,
+)
+.schema();
+
+pub const NOTIFICATION_MAILTO_LIST_SCHEMA: Schema =
+    ArraySchema::new("List of email recipients.", &EMAIL_SCHEMA).schema();
+
+pub const NOTIFICATION_MAILTO_USER_LIST_SCHEMA: Schema = ArraySchema::new(
+    "List of users to notify, using the email address of their user configuration.",
+    &Userid::API_SCHEMA,
+)
+.schema();
+
+pub const SMTP_PASSWORD_SCHEMA: Schema = StringSchema::new("Password for SMTP authentication.")
+    .format(&PASSWORD_FORMAT)
+    .min_length(1)
+    .max_length(1024)
+    .schema();
+
+pub const GOTIFY_TOKEN_SCHEMA: Schema = StringSchema::new("Gotify application token.")
+    .format(&PASSWORD_FORMAT)
+    .min_length(1)
+    .max_length(1024)
+    .schema();
+
+pub const WEBHOOK_HEADER_SCHEMA: Schema = StringSchema::new(
+    "HTTP header in the form '<name>: <value>', the value is rendered as template.",
+)
+.format(&ApiStringFormat::Pattern(&NOTIFICATION_HEADER_REGEX))
+.max_length(1024)
+.schema();
+
+pub const WEBHOOK_HEADER_LIST_SCHEMA: Schema =
+    ArraySchema::new("List of HTTP headers.", &WEBHOOK_HEADER_SCHEMA).schema();
+
+pub const WEBHOOK_SECRET_SCHEMA: Schema = StringSchema::new(
+    "Secret in the form '<name>=<value>', usable as '{{ secrets.<name> }}' in templates.",
+)
+.format(&ApiStringFormat::Pattern(&NOTIFICATION_SECRET_REGEX))
+.max_length(1024)
+.schema();
+
+pub const WEBHOOK_SECRET_LIST_SCHEMA: Schema =
+    ArraySchema::new("List of secrets.", &WEBHOOK_SECRET_SCHEMA).schema();
+
+pub const WEBHOOK_BODY_SCHEMA: Schema =
+    StringSchema::new("Handlebars template for the request body.")
+        .format(&SINGLE_LINE_COMMENT_FORMAT)
+        .max_length(8192)
+        .schema();
+
+fn return_true() -> bool {
+    true
+}
+
+fn is_true(b: &bool) -> bool {
+    *b
+}
+
+#[api]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Severity of a notification
+pub enum NotificationSeverity {
+    /// General information
+    Info,
+    /// Noteworthy, but not a problem
+    Notice,
+    /// Something might need attention
+    Warning,
+    /// Something failed
+    Error,
+}
+
+impl NotificationSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationSeverity::Info => "info",
+            NotificationSeverity::Notice => "notice",
+            NotificationSeverity::Warning => "warning",
+            NotificationSeverity::Error => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for NotificationSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[api]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Connection security of an SMTP target
+pub enum SmtpMode {
+    /// Plain connection, no encryption
+    Insecure,
+    /// Upgrade a plain connection via STARTTLS
+    Starttls,
+    /// TLS from the start (SMTPS)
+    #[default]
+    Tls,
+}
+
+impl SmtpMode {
+    /// The well known port for this mode.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            SmtpMode::Insecure => 25,
+            SmtpMode::Starttls => 587,
+            SmtpMode::Tls => 465,
+        }
+    }
+}
+
+#[api(
+    properties: {
+        name: {
+            schema: NOTIFICATION_ID_SCHEMA,
+        },
+        enable: {
+            type: bool,
+            optional: true,
+            default: true,
+        },
+        server: {
+            schema: DNS_NAME_OR_IP_SCHEMA,
+        },
+        port: {
+            type: u16,
+            optional: true,
+            description: "Port of the SMTP server, defaults to the well known port of the mode.",
+        },
+        mode: {
+            type: SmtpMode,
+            optional: true,
+        },
+        username: {
+            type: String,
+            optional: true,
+            format: &SINGLE_LINE_COMMENT_FORMAT,
+            max_length: 256,
+            description: "Username for SMTP authentication.",
+        },
+        password: {
+            schema: SMTP_PASSWORD_SCHEMA,
+            optional: true,
+        },
+        "from-address": {
+            schema: EMAIL_SCHEMA,
+        },
+        mailto: {
+            schema: NOTIFICATION_MAILTO_LIST_SCHEMA,
+            optional: true,
+        },
+        "mailto-user": {
+            schema: NOTIFICATION_MAILTO_USER_LIST_SCHEMA,
+            optional: true,
+        },
+        author: {
+            type: String,
+            optional: true,
+            format: &SINGLE_LINE_COMMENT_FORMAT,
+            max_length: 128,
+            description: "Author of the mail, defaults to 'Proxmox Backup Server - <nodename>'.",
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// SMTP notification target
+pub struct SmtpConfig {
+    #[updater(skip)]
+    pub name: String,
+    #[serde(default = "return_true", skip_serializing_if = "is_true")]
+    #[updater(serde(skip_serializing_if = "Option::is_none"))]
+    /// Enables or disables the target
+    pub enable: bool,
+    pub server: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<SmtpMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub from_address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mailto: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mailto_user: Option<Vec<Userid>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[api(
+    properties: {
+        name: {
+            schema: NOTIFICATION_ID_SCHEMA,
+        },
+        enable: {
+            type: bool,
+            optional: true,
+            default: true,
+        },
+        server: {
+            schema: HTTP_URL_SCHEMA,
+        },
+        token: {
+            schema: GOTIFY_TOKEN_SCHEMA,
+            optional: true,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// Gotify notification target
+pub struct GotifyConfig {
+    #[updater(skip)]
+    pub name: String,
+    #[serde(default = "return_true", skip_serializing_if = "is_true")]
+    #[updater(serde(skip_serializing_if = "Option::is_none"))]
+    /// Enables or disables the target
+    pub enable: bool,
+    /// Base url of the gotify server
+    pub server: String,
+    /// Application token, required for sending but never returned via the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[api]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// HTTP method used by a webhook target
+pub enum WebhookMethod {
+    /// HTTP POST
+    #[default]
+    Post,
+    /// HTTP PUT
+    Put,
+}
+
+#[api(
+    properties: {
+        name: {
+            schema: NOTIFICATION_ID_SCHEMA,
+        },
+        enable: {
+            type: bool,
+            optional: true,
+            default: true,
+        },
+        url: {
+            schema: HTTP_URL_SCHEMA,
+        },
+        method: {
+            type: WebhookMethod,
+            optional: true,
+        },
+        header: {
+            schema: WEBHOOK_HEADER_LIST_SCHEMA,
+            optional: true,
+        },
+        body: {
+            schema: WEBHOOK_BODY_SCHEMA,
+            optional: true,
+        },
+        secret: {
+            schema: WEBHOOK_SECRET_LIST_SCHEMA,
+            optional: true,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// Generic HTTP webhook notification target
+pub struct WebhookConfig {
+    #[updater(skip)]
+    pub name: String,
+    #[serde(default = "return_true", skip_serializing_if = "is_true")]
+    #[updater(serde(skip_serializing_if = "Option::is_none"))]
+    /// Enables or disables the target
+    pub enable: bool,
+    /// Target url, rendered as template
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<WebhookMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[api]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// How the individual match rules of a matcher are combined
+pub enum MatcherMode {
+    /// All rules have to match
+    #[default]
+    All,
+    /// At least one rule has to match
+    Any,
+}
+
+#[api(
+    properties: {
+        name: {
+            schema: NOTIFICATION_ID_SCHEMA,
+        },
+        enable: {
+            type: bool,
+            optional: true,
+            default: true,
+        },
+        "match-field": {
+            schema: NOTIFICATION_MATCH_FIELD_LIST_SCHEMA,
+            optional: true,
+        },
+        "match-severity": {
+            schema: NOTIFICATION_MATCH_SEVERITY_LIST_SCHEMA,
+            optional: true,
+        },
+        mode: {
+            type: MatcherMode,
+            optional: true,
+        },
+        "invert-match": {
+            type: bool,
+            optional: true,
+            default: false,
+            description: "Invert the result of the match rules.",
+        },
+        target: {
+            schema: NOTIFICATION_TARGET_LIST_SCHEMA,
+            optional: true,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// Notification matcher, routing notifications to targets
+pub struct MatcherConfig {
+    #[updater(skip)]
+    pub name: String,
+    #[serde(default = "return_true", skip_serializing_if = "is_true")]
+    #[updater(serde(skip_serializing_if = "Option::is_none"))]
+    /// Enables or disables the matcher
+    pub enable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_field: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_severity: Option<Vec<NotificationSeverity>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MatcherMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invert_match: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[api]
+#[derive(Copy, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+/// Type of a notification target
+pub enum NotificationTargetType {
+    /// Direct SMTP delivery
+    Smtp,
+    /// Gotify server
+    Gotify,
+    /// Generic HTTP webhook
+    Webhook,
+}
+
+#[api(
+    properties: {
+        name: {
+            schema: NOTIFICATION_ID_SCHEMA,
+        },
+        "type": {
+            type: NotificationTargetType,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+/// Basic information about a notification target that's available for all types
+pub struct NotificationTargetInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: NotificationTargetType,
+    /// Enables or disables the target
+    pub enable: bool,
+    /// Server or url the notifications are sent to
+    pub server: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
@@ -53,6 +53,15 @@ pub const OPENID_USERNAME_CLAIM_SCHEMA: Schema = StringSchema::new(
 .format(&PROXMOX_SAFE_ID_FORMAT)
 .schema();
 
+pub const OPENID_GROUPS_CLAIM_SCHEMA: Schema = StringSchema::new(
+    "Use the values of this claim to set the group memberships of the user. Group names \
+    get the realm name appended, i.e. a value 'admins' maps to the group 'admins-<realm>'.",
+)
+.max_length(64)
+.min_length(1)
+.format(&PROXMOX_SAFE_ID_FORMAT)
+.schema();
+
 #[api(
     properties: {
         realm: {
@@ -87,6 +96,14 @@ pub const OPENID_USERNAME_CLAIM_SCHEMA: Schema = StringSchema::new(
             schema: OPENID_USERNAME_CLAIM_SCHEMA,
             optional: true,
         },
+        "groups-claim": {
+            schema: OPENID_GROUPS_CLAIM_SCHEMA,
+            optional: true,
+        },
+        "groups-autocreate": {
+            optional: true,
+            default: false,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Updater)]
@@ -116,4 +133,9 @@ pub struct OpenIdRealmConfig {
     #[updater(skip)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username_claim: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups_claim: Option<String>,
+    /// Automatically create groups listed in the groups claim if they do not exist.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups_autocreate: Option<bool>,
 }
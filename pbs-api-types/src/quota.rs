@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+
+use proxmox_human_byte::HumanByte;
+use proxmox_schema::{api, IntegerSchema, Schema, StringSchema, Updater};
+
+use crate::{
+    Authid, BackupNamespace, DATASTORE_SCHEMA, PROXMOX_SAFE_ID_FORMAT, SINGLE_LINE_COMMENT_SCHEMA,
+};
+
+pub const QUOTA_ID_SCHEMA: Schema = StringSchema::new("Quota ID.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(3)
+    .max_length(32)
+    .schema();
+
+pub const QUOTA_SOFT_LIMIT_SCHEMA: Schema = IntegerSchema::new(
+    "Send a warning notification once the usage exceeds this percentage of a limit.",
+)
+.minimum(1)
+.maximum(100)
+.schema();
+
+#[api(
+    properties: {
+        id: {
+            schema: QUOTA_ID_SCHEMA,
+        },
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        ns: {
+            type: BackupNamespace,
+            optional: true,
+        },
+        owner: {
+            type: Authid,
+            optional: true,
+        },
+        "logical-limit": {
+            type: HumanByte,
+            optional: true,
+        },
+        "unique-limit": {
+            type: HumanByte,
+            optional: true,
+        },
+        "soft-limit": {
+            schema: QUOTA_SOFT_LIMIT_SCHEMA,
+            optional: true,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// Storage quota for a namespace (including its sub-namespaces) and/or an owner.
+pub struct DatastoreQuota {
+    #[updater(skip)]
+    pub id: String,
+    pub store: String,
+    /// The quota covers this namespace and all namespaces below it. Defaults to the root
+    /// namespace, i.e. the whole datastore.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ns: Option<BackupNamespace>,
+    /// Only account backup groups owned by this user or API token. The quota of a user also
+    /// covers the groups owned by its API tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Authid>,
+    /// Limit for the sum of the sizes of all backed up files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logical_limit: Option<HumanByte>,
+    /// Limit for the estimated on-disk size of the chunks referenced by the covered snapshots.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unique_limit: Option<HumanByte>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soft_limit: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl DatastoreQuota {
+    pub fn acl_path(&self) -> Vec<&str> {
+        match &self.ns {
+            Some(ns) => ns.acl_path(&self.store),
+            None => vec!["datastore", &self.store],
+        }
+    }
+
+    /// Returns whether a backup group in `ns` owned by `owner` is covered by this quota.
+    pub fn covers(&self, store: &str, ns: &BackupNamespace, owner: &Authid) -> bool {
+        if self.store != store {
+            return false;
+        }
+
+        if let Some(ref quota_ns) = self.ns {
+            if quota_ns.contains(ns).is_none() {
+                return false;
+            }
+        }
+
+        match self.owner {
+            Some(ref quota_owner) if quota_owner.is_token() => quota_owner == owner,
+            Some(ref quota_owner) => quota_owner.user() == owner.user(),
+            None => true,
+        }
+    }
+}
+
+#[api]
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Storage consumption of a set of snapshots.
+pub struct StorageUsage {
+    /// Number of snapshots.
+    pub snapshots: u64,
+    /// Sum of the sizes of all backed up files.
+    pub logical_size: u64,
+    /// Estimated on-disk size of the chunks referenced by the snapshots, counting every chunk
+    /// only once.
+    pub unique_size: u64,
+}
+
+#[api(
+    properties: {
+        config: {
+            type: DatastoreQuota,
+        },
+        usage: {
+            type: StorageUsage,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Quota configuration with current usage.
+pub struct DatastoreQuotaStatus {
+    #[serde(flatten)]
+    pub config: DatastoreQuota,
+    #[serde(flatten)]
+    pub usage: StorageUsage,
+}
+
+#[api(
+    properties: {
+        ns: {
+            type: BackupNamespace,
+        },
+        usage: {
+            type: StorageUsage,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Storage consumption of the snapshots directly in a namespace (without sub-namespaces).
+pub struct NamespaceUsage {
+    pub ns: BackupNamespace,
+    #[serde(flatten)]
+    pub usage: StorageUsage,
+}
+
+#[test]
+fn test_quota_covers() -> Result<(), anyhow::Error> {
+    let mut quota = DatastoreQuota {
+        id: "test".to_string(),
+        store: "store1".to_string(),
+        ns: None,
+        owner: None,
+        logical_limit: None,
+        unique_limit: None,
+        soft_limit: None,
+        comment: None,
+    };
+
+    let user: Authid = "user@pbs".parse()?;
+    let token: Authid = "user@pbs!backup".parse()?;
+    let other_token: Authid = "user@pbs!other".parse()?;
+    let other: Authid = "other@pbs".parse()?;
+
+    let root = BackupNamespace::root();
+    let team: BackupNamespace = "team".parse()?;
+    let sub: BackupNamespace = "team/vm".parse()?;
+    let sibling: BackupNamespace = "team2".parse()?;
+
+    // without namespace and owner, the whole datastore is covered
+    assert!(quota.covers("store1", &root, &other));
+    assert!(quota.covers("store1", &sub, &token));
+    assert!(!quota.covers("store2", &root, &user));
+
+    // a namespace covers itself and everything below it
+    quota.ns = Some(team.clone());
+    assert!(quota.covers("store1", &team, &user));
+    assert!(quota.covers("store1", &sub, &user));
+    assert!(!quota.covers("store1", &root, &user));
+    assert!(!quota.covers("store1", &sibling, &user));
+
+    // a user covers its own API tokens
+    quota.ns = None;
+    quota.owner = Some(user.clone());
+    assert!(quota.covers("store1", &root, &user));
+    assert!(quota.covers("store1", &root, &token));
+    assert!(!quota.covers("store1", &root, &other));
+
+    // an API token only covers itself
+    quota.owner = Some(token.clone());
+    assert!(quota.covers("store1", &root, &token));
+    assert!(!quota.covers("store1", &root, &other_token));
+    assert!(!quota.covers("store1", &root, &user));
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use proxmox_schema::*;
+
+pub const S3_CLIENT_ID_SCHEMA: Schema = StringSchema::new("S3 client configuration ID.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(3)
+    .max_length(32)
+    .schema();
+
+pub const S3_BUCKET_NAME_REGEX_STR: &str = r"[a-z0-9][a-z0-9.\-]{1,61}[a-z0-9]";
+
+const_regex! {
+    pub S3_BUCKET_NAME_REGEX = concat!(r"^", S3_BUCKET_NAME_REGEX_STR, r"$");
+}
+
+pub const S3_BUCKET_NAME_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&S3_BUCKET_NAME_REGEX);
+
+pub const S3_BUCKET_NAME_SCHEMA: Schema = StringSchema::new("Name of the S3 bucket.")
+    .format(&S3_BUCKET_NAME_FORMAT)
+    .min_length(3)
+    .max_length(63)
+    .schema();
+
+pub const S3_REGION_SCHEMA: Schema = StringSchema::new("Region used for request signing.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(1)
+    .max_length(32)
+    .schema();
+
+pub const S3_ACCESS_KEY_SCHEMA: Schema = StringSchema::new("S3 access key ID.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(1)
+    .max_length(128)
+    .schema();
+
+pub const S3_SECRET_KEY_SCHEMA: Schema = StringSchema::new("S3 secret access key.")
+    .format(&PASSWORD_FORMAT)
+    .min_length(1)
+    .max_length(256)
+    .schema();
+
+pub const S3_SECRET_KEY_BASE64_SCHEMA: Schema =
+    StringSchema::new("S3 secret access key (stored as base64 string).")
+        .format(&PASSWORD_FORMAT)
+        .min_length(1)
+        .max_length(512)
+        .schema();
+
+#[api(
+    properties: {
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+        endpoint: {
+            schema: DNS_NAME_OR_IP_SCHEMA,
+        },
+        port: {
+            optional: true,
+            description: "The (optional) port",
+            type: u16,
+        },
+        region: {
+            optional: true,
+            schema: S3_REGION_SCHEMA,
+        },
+        "access-key": {
+            schema: S3_ACCESS_KEY_SCHEMA,
+        },
+        fingerprint: {
+            optional: true,
+            schema: CERT_FINGERPRINT_SHA256_SCHEMA,
+        },
+        "path-style": {
+            optional: true,
+            default: false,
+            description: "Use path style bucket addressing instead of virtual host style.",
+        },
+        "use-http": {
+            optional: true,
+            default: false,
+            description: "Use plain HTTP instead of HTTPS (only meant for local testing).",
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// S3 client configuration properties.
+pub struct S3ClientConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub endpoint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub access_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_style: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_http: Option<bool>,
+}
+
+#[api(
+    properties: {
+        id: {
+            schema: S3_CLIENT_ID_SCHEMA,
+        },
+        config: {
+            type: S3ClientConfig,
+        },
+        "secret-key": {
+            schema: S3_SECRET_KEY_BASE64_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// S3 client configuration including the secret key.
+pub struct S3Client {
+    pub id: String,
+    // Note: The stored secret key is base64 encoded
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    #[serde(with = "proxmox_serde::string_as_base64")]
+    pub secret_key: String,
+    #[serde(flatten)]
+    pub config: S3ClientConfig,
+}
+
+#[api(
+    properties: {
+        id: {
+            schema: S3_CLIENT_ID_SCHEMA,
+        },
+        config: {
+            type: S3ClientConfig,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// S3 client configuration without the secret key.
+pub struct S3ClientWithoutSecret {
+    pub id: String,
+    #[serde(flatten)]
+    pub config: S3ClientConfig,
+}
@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-use proxmox_schema::{api, BooleanSchema, IntegerSchema, Schema, StringSchema, Updater};
+use proxmox_schema::{
+    api, ApiType, ArraySchema, BooleanSchema, IntegerSchema, Schema, StringSchema, Updater,
+};
 
 use super::userid::{Authid, Userid, PROXMOX_TOKEN_ID_SCHEMA};
-use super::{SINGLE_LINE_COMMENT_FORMAT, SINGLE_LINE_COMMENT_SCHEMA};
+use super::{PROXMOX_GROUP_ID_SCHEMA, SINGLE_LINE_COMMENT_FORMAT, SINGLE_LINE_COMMENT_SCHEMA};
 
 pub const ENABLE_USER_SCHEMA: Schema = BooleanSchema::new(
     "Enable the account (default). You can set this to '0' to disable the account.",
@@ -18,6 +20,14 @@ pub const EXPIRE_USER_SCHEMA: Schema = IntegerSchema::new(
 .minimum(0)
 .schema();
 
+pub const APPEND_ONLY_TOKEN_SCHEMA: Schema = BooleanSchema::new(
+    "Restrict the API token to creating and reading backups. Deleting, pruning, changing the \
+    owner and removing the protection or notes of backups is not allowed, even if the ACLs \
+    would permit it.",
+)
+.default(false)
+.schema();
+
 pub const FIRST_NAME_SCHEMA: Schema = StringSchema::new("First name.")
     .format(&SINGLE_LINE_COMMENT_FORMAT)
     .min_length(2)
@@ -131,9 +141,14 @@ fn bool_is_false(b: &bool) -> bool {
             optional: true,
             schema: EXPIRE_USER_SCHEMA,
         },
+        "append-only": {
+            optional: true,
+            schema: APPEND_ONLY_TOKEN_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 /// ApiToken properties.
 pub struct ApiToken {
     pub tokenid: Authid,
@@ -143,6 +158,8 @@ pub struct ApiToken {
     pub enable: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expire: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub append_only: Option<bool>,
 }
 
 impl ApiToken {
@@ -158,6 +175,11 @@ impl ApiToken {
         }
         true
     }
+
+    /// Whether the token is restricted to appending new backups.
+    pub fn is_append_only(&self) -> bool {
+        self.append_only.unwrap_or(false)
+    }
 }
 
 #[api(
@@ -224,3 +246,65 @@ impl User {
         true
     }
 }
+
+pub const GROUP_MEMBER_LIST_SCHEMA: Schema =
+    ArraySchema::new("List of group members.", &Userid::API_SCHEMA).schema();
+
+#[api(
+    properties: {
+        groupid: {
+            schema: PROXMOX_GROUP_ID_SCHEMA,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+        members: {
+            optional: true,
+            schema: GROUP_MEMBER_LIST_SCHEMA,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, PartialEq, Eq)]
+/// Group properties.
+pub struct Group {
+    #[updater(skip)]
+    pub groupid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<Userid>>,
+}
+
+impl Group {
+    pub fn is_member(&self, userid: &Userid) -> bool {
+        self.members
+            .as_ref()
+            .map_or(false, |members| members.contains(userid))
+    }
+
+    /// Add a user to the group, returns `false` if the user was already a member.
+    pub fn add_member(&mut self, userid: Userid) -> bool {
+        let members = self.members.get_or_insert_with(Vec::new);
+        if members.contains(&userid) {
+            return false;
+        }
+        members.push(userid);
+        true
+    }
+
+    /// Remove a user from the group, returns `false` if the user was no member.
+    pub fn remove_member(&mut self, userid: &Userid) -> bool {
+        let members = match self.members.as_mut() {
+            Some(members) => members,
+            None => return false,
+        };
+        let len = members.len();
+        members.retain(|member| member != userid);
+        let removed = members.len() != len;
+        if members.is_empty() {
+            self.members = None;
+        }
+        removed
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
 
 use proxmox_schema::*;
 
@@ -45,3 +46,22 @@ pub fn parse_backup_specification(value: &str) -> Result<BackupSpecification, Er
 
     bail!("unable to parse backup source specification '{}'", value);
 }
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// How to detect files which changed since the previous backup.
+pub enum BackupDetectionMode {
+    /// Read and chunk all files.
+    #[default]
+    Legacy,
+    /// Reuse the chunks of files with unchanged metadata from the previous backup.
+    Metadata,
+}
+
+impl BackupDetectionMode {
+    /// Whether file payloads get aligned to chunk boundaries and may be reused.
+    pub fn is_metadata(&self) -> bool {
+        matches!(self, BackupDetectionMode::Metadata)
+    }
+}
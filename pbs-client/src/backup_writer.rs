@@ -12,17 +12,19 @@ use tokio::io::AsyncReadExt;
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
 
-use pbs_api_types::{BackupDir, BackupNamespace};
+use pbs_api_types::{BackupDir, BackupNamespace, ChunkCompression};
 use pbs_datastore::data_blob::{ChunkInfo, DataBlob, DataChunkBuilder};
 use pbs_datastore::dynamic_index::DynamicIndexReader;
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
 use pbs_datastore::manifest::{ArchiveType, BackupManifest, MANIFEST_BLOB_NAME};
+use pbs_datastore::read_chunk::AsyncReadChunk;
 use pbs_datastore::{CATALOG_NAME, PROXMOX_BACKUP_PROTOCOL_ID_V1};
 use pbs_tools::crypt_config::CryptConfig;
 
 use proxmox_human_byte::HumanByte;
 
+use super::inject_reused_chunks::{InjectChunks, InjectReusedChunks, InjectedChunksInfo};
 use super::merge_known_chunks::{MergeKnownChunks, MergedChunkInfo};
 
 use super::{H2Client, HttpClient};
@@ -49,6 +51,8 @@ pub struct BackupStats {
 pub struct UploadOptions {
     pub previous_manifest: Option<Arc<BackupManifest>>,
     pub compress: bool,
+    /// Compression used for chunks if `compress` is set, defaults to zstd level 1
+    pub compression: Option<ChunkCompression>,
     pub encrypt: bool,
     pub fixed_size: Option<u64>,
 }
@@ -85,6 +89,29 @@ impl BackupWriter {
         backup: &BackupDir,
         debug: bool,
         benchmark: bool,
+    ) -> Result<Arc<BackupWriter>, Error> {
+        Self::start_shared(
+            &client,
+            crypt_config,
+            datastore,
+            ns,
+            backup,
+            debug,
+            benchmark,
+        )
+        .await
+    }
+
+    /// Like [`start`](Self::start), but leaves the client usable for further API calls.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn start_shared(
+        client: &HttpClient,
+        crypt_config: Option<Arc<CryptConfig>>,
+        datastore: &str,
+        ns: &BackupNamespace,
+        backup: &BackupDir,
+        debug: bool,
+        benchmark: bool,
     ) -> Result<Arc<BackupWriter>, Error> {
         let mut param = json!({
             "backup-type": backup.ty(),
@@ -265,6 +292,7 @@ impl BackupWriter {
         archive_name: &str,
         stream: impl Stream<Item = Result<bytes::BytesMut, Error>>,
         options: UploadOptions,
+        injections: Option<std::sync::mpsc::Receiver<InjectChunks>>,
     ) -> Result<BackupStats, Error> {
         let known_chunks = Arc::new(Mutex::new(HashSet::new()));
 
@@ -332,7 +360,7 @@ impl BackupWriter {
         let upload_stats = Self::upload_chunk_info_stream(
             self.h2.clone(),
             wid,
-            stream,
+            stream.inject_reused_chunks(injections),
             prefix,
             known_chunks.clone(),
             if options.encrypt {
@@ -340,7 +368,11 @@ impl BackupWriter {
             } else {
                 None
             },
-            options.compress,
+            if options.compress {
+                options.compression.unwrap_or_default()
+            } else {
+                ChunkCompression::NONE
+            },
         )
         .await?;
 
@@ -412,6 +444,105 @@ impl BackupWriter {
         })
     }
 
+    /// Upload an index together with all chunks it references which are not part of
+    /// `known_chunks`.
+    ///
+    /// Chunks are read in their encoded form via `chunk_reader` and sent as they are, so this can
+    /// be used to transfer (possibly encrypted) snapshots between datastores without access to the
+    /// encryption key.
+    pub async fn upload_index_chunk_info<I: IndexFile>(
+        &self,
+        archive_name: &str,
+        index: &I,
+        chunk_reader: Arc<dyn AsyncReadChunk>,
+        known_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    ) -> Result<BackupStats, Error> {
+        let mut param = json!({ "archive-name": archive_name });
+        let prefix = match ArchiveType::from_path(archive_name)? {
+            ArchiveType::FixedIndex => {
+                param["size"] = index.index_bytes().into();
+                "fixed"
+            }
+            ArchiveType::DynamicIndex => "dynamic",
+            ArchiveType::Blob => bail!("cannot upload blob '{archive_name}' as index"),
+        };
+
+        let index_path = format!("{}_index", prefix);
+        let upload_chunk_path = format!("{}_chunk", prefix);
+        let close_path = format!("{}_close", prefix);
+
+        let wid = self
+            .h2
+            .post(&index_path, Some(param))
+            .await?
+            .as_u64()
+            .unwrap();
+
+        let (upload_queue, upload_result) =
+            Self::append_chunk_queue(self.h2.clone(), wid, index_path.clone());
+
+        let result: Result<(), Error> = async {
+            for pos in 0..index.index_count() {
+                let info = index.chunk_info(pos).unwrap();
+                let offset = info.range.start;
+                let digest = info.digest;
+
+                let chunk_is_known = !known_chunks.lock().unwrap().insert(digest);
+                if chunk_is_known {
+                    upload_queue
+                        .send((MergedChunkInfo::Known(vec![(offset, digest)]), None))
+                        .await
+                        .map_err(|err| format_err!("failed to send to upload queue: {}", err))?;
+                    continue;
+                }
+
+                let chunk_data = chunk_reader.read_raw_chunk(&digest).await?.into_inner();
+                let param = json!({
+                    "wid": wid,
+                    "digest": hex::encode(digest),
+                    "size": info.size(),
+                    "encoded-size": chunk_data.len(),
+                });
+                let request = H2Client::request_builder(
+                    "localhost",
+                    "POST",
+                    &upload_chunk_path,
+                    Some(param),
+                    Some("application/octet-stream"),
+                )?;
+                let response = self
+                    .h2
+                    .send_request(request, Some(bytes::Bytes::from(chunk_data)))
+                    .await?;
+
+                upload_queue
+                    .send((
+                        MergedChunkInfo::Known(vec![(offset, digest)]),
+                        Some(response),
+                    ))
+                    .await
+                    .map_err(|err| format_err!("failed to send to upload queue: {}", err))?;
+            }
+            Ok(())
+        }
+        .await;
+
+        drop(upload_queue);
+        upload_result.await??;
+        result?;
+
+        let (csum, size) = index.compute_csum();
+        let param = json!({
+            "wid": wid ,
+            "chunk-count": index.index_count(),
+            "size": size,
+            "csum": hex::encode(csum),
+        });
+        let _value = self.h2.post(&close_path, Some(param)).await?;
+
+        Ok(BackupStats { size, csum })
+    }
+
     fn response_queue() -> (
         mpsc::Sender<h2::client::ResponseFuture>,
         oneshot::Receiver<Result<(), Error>>,
@@ -632,11 +763,11 @@ impl BackupWriter {
     fn upload_chunk_info_stream(
         h2: H2Client,
         wid: u64,
-        stream: impl Stream<Item = Result<bytes::BytesMut, Error>>,
+        stream: impl Stream<Item = Result<InjectedChunksInfo, Error>>,
         prefix: &str,
         known_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
         crypt_config: Option<Arc<CryptConfig>>,
-        compress: bool,
+        compression: ChunkCompression,
     ) -> impl Future<Output = Result<UploadStats, Error>> {
         let total_chunks = Arc::new(AtomicUsize::new(0));
         let total_chunks2 = total_chunks.clone();
@@ -663,13 +794,45 @@ impl BackupWriter {
         let index_csum_2 = index_csum.clone();
 
         stream
-            .and_then(move |data| {
+            .and_then(move |chunk_info| {
+                let data = match chunk_info {
+                    InjectedChunksInfo::Known(chunks) => {
+                        let known_chunks = known_chunks.lock().unwrap();
+                        let mut guard = index_csum.lock().unwrap();
+                        let csum = guard.as_mut().unwrap();
+
+                        let mut merged = Vec::with_capacity(chunks.len());
+                        for chunk in chunks {
+                            if !known_chunks.contains(&chunk.digest) {
+                                return future::ready(Err(format_err!(
+                                    "injected chunk {} is not known to the server",
+                                    hex::encode(chunk.digest)
+                                )));
+                            }
+
+                            let chunk_len = chunk.size as usize;
+                            total_chunks.fetch_add(1, Ordering::SeqCst);
+                            known_chunk_count.fetch_add(1, Ordering::SeqCst);
+                            reused_len.fetch_add(chunk_len, Ordering::SeqCst);
+                            let offset = stream_len.fetch_add(chunk_len, Ordering::SeqCst) as u64;
+
+                            csum.update(&(offset + chunk.size).to_le_bytes());
+                            csum.update(&chunk.digest);
+
+                            merged.push((offset, chunk.digest));
+                        }
+                        return future::ok(MergedChunkInfo::Known(merged));
+                    }
+                    InjectedChunksInfo::Raw(data) => data,
+                };
+
                 let chunk_len = data.len();
 
                 total_chunks.fetch_add(1, Ordering::SeqCst);
                 let offset = stream_len.fetch_add(chunk_len, Ordering::SeqCst) as u64;
 
-                let mut chunk_builder = DataChunkBuilder::new(data.as_ref()).compress(compress);
+                let mut chunk_builder =
+                    DataChunkBuilder::new(data.as_ref()).compression(compression);
 
                 if let Some(ref crypt_config) = crypt_config {
                     chunk_builder = chunk_builder.crypt_config(crypt_config);
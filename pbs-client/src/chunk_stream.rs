@@ -1,30 +1,69 @@
 use std::pin::Pin;
+use std::sync::mpsc;
 use std::task::{Context, Poll};
 
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use bytes::BytesMut;
 use futures::ready;
 use futures::stream::{Stream, TryStream};
 
 use pbs_datastore::Chunker;
 
+use crate::inject_reused_chunks::InjectChunks;
+
+/// Chunk boundaries and reused chunks announced by the archive encoder.
+pub struct InjectionData {
+    boundaries: mpsc::Receiver<InjectChunks>,
+    next_boundary: Option<InjectChunks>,
+    injections: mpsc::Sender<InjectChunks>,
+    consumed: u64,
+}
+
+impl InjectionData {
+    /// Chunk boundaries are read from `boundaries`, reused chunks are passed
+    /// on to the uploader via `injections`.
+    pub fn new(
+        boundaries: mpsc::Receiver<InjectChunks>,
+        injections: mpsc::Sender<InjectChunks>,
+    ) -> Self {
+        Self {
+            boundaries,
+            next_boundary: None,
+            injections,
+            consumed: 0,
+        }
+    }
+}
+
 /// Split input stream into dynamic sized chunks
 pub struct ChunkStream<S: Unpin> {
     input: S,
     chunker: Chunker,
     buffer: BytesMut,
     scan_pos: usize,
+    input_done: bool,
+    injection_data: Option<InjectionData>,
 }
 
 impl<S: Unpin> ChunkStream<S> {
-    pub fn new(input: S, chunk_size: Option<usize>) -> Self {
+    pub fn new(input: S, chunk_size: Option<usize>, injection_data: Option<InjectionData>) -> Self {
         Self {
             input,
             chunker: Chunker::new(chunk_size.unwrap_or(4 * 1024 * 1024)),
             buffer: BytesMut::new(),
             scan_pos: 0,
+            input_done: false,
+            injection_data,
         }
     }
+
+    fn split_chunk(&mut self, chunk_size: usize) -> BytesMut {
+        if let Some(ctx) = self.injection_data.as_mut() {
+            ctx.consumed += chunk_size as u64;
+        }
+        self.scan_pos = 0;
+        self.buffer.split_to(chunk_size)
+    }
 }
 
 impl<S: Unpin> Unpin for ChunkStream<S> {}
@@ -40,34 +79,72 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
         loop {
-            if this.scan_pos < this.buffer.len() {
-                let boundary = this.chunker.scan(&this.buffer[this.scan_pos..]);
+            // position of the next forced boundary, relative to the buffer start
+            let mut boundary_pos = None;
+            if let Some(ctx) = this.injection_data.as_mut() {
+                if ctx.next_boundary.is_none() {
+                    ctx.next_boundary = ctx.boundaries.try_recv().ok();
+                }
+                if let Some(inject) = ctx.next_boundary.as_ref() {
+                    boundary_pos = Some((inject.boundary - ctx.consumed) as usize);
+                }
+            }
+
+            let scan_end = match boundary_pos {
+                Some(pos) if pos <= this.buffer.len() => pos,
+                _ => this.buffer.len(),
+            };
+
+            if this.scan_pos < scan_end {
+                let boundary = this.chunker.scan(&this.buffer[this.scan_pos..scan_end]);
 
                 let chunk_size = this.scan_pos + boundary;
 
                 if boundary == 0 {
-                    this.scan_pos = this.buffer.len();
+                    this.scan_pos = scan_end;
                     // continue poll
                 } else if chunk_size <= this.buffer.len() {
-                    let result = this.buffer.split_to(chunk_size);
-                    this.scan_pos = 0;
-                    return Poll::Ready(Some(Ok(result)));
+                    return Poll::Ready(Some(Ok(this.split_chunk(chunk_size))));
                 } else {
                     panic!("got unexpected chunk boundary from chunker");
                 }
             }
 
+            if let Some(pos) = boundary_pos.filter(|pos| *pos <= this.buffer.len()) {
+                // reached a forced boundary, cut here
+                this.chunker.reset();
+                if pos > 0 {
+                    return Poll::Ready(Some(Ok(this.split_chunk(pos))));
+                }
+
+                let ctx = this.injection_data.as_mut().unwrap();
+                let inject = ctx.next_boundary.take().unwrap();
+                if inject.size > 0 {
+                    ctx.consumed += inject.size;
+                    if ctx.injections.send(inject).is_err() {
+                        return Poll::Ready(Some(Err(format_err!(
+                            "failed to send chunk injection - channel closed"
+                        ))));
+                    }
+                }
+                continue;
+            }
+
+            if this.input_done {
+                this.scan_pos = 0;
+                if !this.buffer.is_empty() {
+                    return Poll::Ready(Some(Ok(this.split_chunk(this.buffer.len()))));
+                } else {
+                    return Poll::Ready(None);
+                }
+            }
+
             match ready!(Pin::new(&mut this.input).try_poll_next(cx)) {
                 Some(Err(err)) => {
                     return Poll::Ready(Some(Err(err.into())));
                 }
                 None => {
-                    this.scan_pos = 0;
-                    if !this.buffer.is_empty() {
-                        return Poll::Ready(Some(Ok(this.buffer.split())));
-                    } else {
-                        return Poll::Ready(None);
-                    }
+                    this.input_done = true;
                 }
                 Some(Ok(data)) => {
                     this.buffer.extend_from_slice(data.as_ref());
@@ -0,0 +1,290 @@
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use anyhow::{format_err, Error};
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+/// Chunk of a previous archive, which can be referenced as is.
+#[derive(Clone, Debug)]
+pub struct ReusableChunk {
+    pub digest: [u8; 32],
+    pub size: u64,
+}
+
+/// Reused chunks to inject into the chunk stream at the given stream offset.
+///
+/// An event with an empty chunk list only forces a chunk boundary.
+#[derive(Debug)]
+pub struct InjectChunks {
+    pub boundary: u64,
+    pub chunks: Vec<ReusableChunk>,
+    pub size: u64,
+}
+
+#[derive(Default)]
+struct InjectionState {
+    position: u64,
+    discard_end: u64,
+}
+
+/// Writer tracking the archive stream position.
+///
+/// Data written within a range announced via [`PayloadInjector::inject`] is
+/// discarded, as it gets replaced by the injected chunks.
+pub struct InjectionWriter<W: Write> {
+    inner: W,
+    state: Arc<Mutex<InjectionState>>,
+}
+
+impl<W: Write> Write for InjectionWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.discard_end > state.position {
+            let skip = (state.discard_end - state.position).min(buf.len() as u64);
+            state.position += skip;
+            return Ok(skip as usize);
+        }
+
+        let written = self.inner.write(buf)?;
+        state.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Archiver side of the chunk injection.
+///
+/// Announces forced chunk boundaries and reused chunks to the chunk stream.
+pub struct PayloadInjector {
+    state: Arc<Mutex<InjectionState>>,
+    boundaries: mpsc::Sender<InjectChunks>,
+}
+
+impl PayloadInjector {
+    /// Wrap `writer` and return the injector together with the receiving end
+    /// of the boundary channel, to be passed on to the `ChunkStream`.
+    pub fn new<W: Write>(writer: W) -> (Self, InjectionWriter<W>, mpsc::Receiver<InjectChunks>) {
+        let state = Arc::new(Mutex::new(InjectionState::default()));
+        let (boundaries, boundaries_rx) = mpsc::channel();
+
+        let writer = InjectionWriter {
+            inner: writer,
+            state: Arc::clone(&state),
+        };
+
+        (Self { state, boundaries }, writer, boundaries_rx)
+    }
+
+    /// Current position in the archive stream.
+    pub fn position(&self) -> u64 {
+        self.state.lock().unwrap().position
+    }
+
+    /// Force a chunk boundary at the current stream position.
+    pub fn boundary(&self) -> Result<(), Error> {
+        self.send(Vec::new(), 0)
+    }
+
+    /// Replace the next `size` bytes of the stream by the given chunks.
+    pub fn inject(&self, chunks: Vec<ReusableChunk>, size: u64) -> Result<(), Error> {
+        self.send(chunks, size)
+    }
+
+    fn send(&self, chunks: Vec<ReusableChunk>, size: u64) -> Result<(), Error> {
+        let boundary = {
+            let mut state = self.state.lock().unwrap();
+            state.discard_end = state.position + size;
+            state.position
+        };
+
+        self.boundaries
+            .send(InjectChunks {
+                boundary,
+                chunks,
+                size,
+            })
+            .map_err(|_| format_err!("failed to send chunk boundary - channel closed"))
+    }
+}
+
+pub enum InjectedChunksInfo {
+    Known(Vec<ReusableChunk>),
+    Raw(bytes::BytesMut),
+}
+
+pub trait InjectReusedChunks: Sized {
+    fn inject_reused_chunks(
+        self,
+        injections: Option<mpsc::Receiver<InjectChunks>>,
+    ) -> InjectReusedChunksQueue<Self>;
+}
+
+pin_project! {
+    pub struct InjectReusedChunksQueue<S> {
+        #[pin]
+        input: S,
+        injections: Option<mpsc::Receiver<InjectChunks>>,
+        pending: Option<InjectChunks>,
+        buffer: Option<bytes::BytesMut>,
+        stream_len: u64,
+    }
+}
+
+impl<S> InjectReusedChunks for S
+where
+    S: Stream<Item = Result<bytes::BytesMut, Error>>,
+{
+    fn inject_reused_chunks(
+        self,
+        injections: Option<mpsc::Receiver<InjectChunks>>,
+    ) -> InjectReusedChunksQueue<Self> {
+        InjectReusedChunksQueue {
+            input: self,
+            injections,
+            pending: None,
+            buffer: None,
+            stream_len: 0,
+        }
+    }
+}
+
+impl<S> Stream for InjectReusedChunksQueue<S>
+where
+    S: Stream<Item = Result<bytes::BytesMut, Error>>,
+{
+    type Item = Result<InjectedChunksInfo, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if this.pending.is_none() {
+                if let Some(injections) = this.injections.as_ref() {
+                    *this.pending = injections.try_recv().ok();
+                }
+            }
+
+            if let Some(inject) = this.pending.as_ref() {
+                if inject.boundary < *this.stream_len {
+                    let err = format_err!(
+                        "missed chunk injection at offset {} (stream at {})",
+                        inject.boundary,
+                        this.stream_len
+                    );
+                    return Poll::Ready(Some(Err(err)));
+                }
+                if inject.boundary == *this.stream_len {
+                    let inject = this.pending.take().unwrap();
+                    *this.stream_len += inject.size;
+                    return Poll::Ready(Some(Ok(InjectedChunksInfo::Known(inject.chunks))));
+                }
+            }
+
+            if let Some(data) = this.buffer.take() {
+                *this.stream_len += data.len() as u64;
+                return Poll::Ready(Some(Ok(InjectedChunksInfo::Raw(data))));
+            }
+
+            match ready!(this.input.as_mut().poll_next(cx)) {
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Some(Ok(data)) => *this.buffer = Some(data),
+                None => {
+                    if let Some(inject) = this.pending.take() {
+                        return Poll::Ready(Some(Err(format_err!(
+                            "chunk injection at offset {} beyond end of stream",
+                            inject.boundary
+                        ))));
+                    }
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::StreamExt;
+
+    fn test_chunk(byte: u8, size: u64) -> ReusableChunk {
+        ReusableChunk {
+            digest: [byte; 32],
+            size,
+        }
+    }
+
+    #[test]
+    fn test_injection_writer() -> Result<(), Error> {
+        let (injector, mut writer, boundaries) = PayloadInjector::new(Vec::new());
+
+        writer.write_all(b"abc")?;
+        injector.boundary()?;
+        injector.inject(vec![test_chunk(1, 4)], 4)?;
+        // the payload of the injected range is discarded
+        writer.write_all(b"1234xyz")?;
+        assert_eq!(injector.position(), 10);
+        assert_eq!(writer.inner, b"abcxyz");
+
+        let boundary = boundaries.try_recv()?;
+        assert_eq!((boundary.boundary, boundary.size), (3, 0));
+        assert!(boundary.chunks.is_empty());
+
+        let inject = boundaries.try_recv()?;
+        assert_eq!((inject.boundary, inject.size), (3, 4));
+        assert_eq!(inject.chunks.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inject_reused_chunks() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(InjectChunks {
+            boundary: 3,
+            chunks: vec![test_chunk(1, 2), test_chunk(2, 2)],
+            size: 4,
+        })
+        .unwrap();
+
+        let input = futures::stream::iter(vec![
+            Ok::<_, Error>(bytes::BytesMut::from(&b"abc"[..])),
+            Ok::<_, Error>(bytes::BytesMut::from(&b"xyz"[..])),
+        ]);
+        let output: Vec<_> =
+            futures::executor::block_on(input.inject_reused_chunks(Some(rx)).collect());
+
+        assert_eq!(output.len(), 3);
+        assert!(matches!(&output[0], Ok(InjectedChunksInfo::Raw(data)) if data[..] == b"abc"[..]));
+        assert!(matches!(&output[1], Ok(InjectedChunksInfo::Known(chunks)) if chunks.len() == 2));
+        assert!(matches!(&output[2], Ok(InjectedChunksInfo::Raw(data)) if data[..] == b"xyz"[..]));
+    }
+
+    #[test]
+    fn test_missed_injection() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(InjectChunks {
+            boundary: 2,
+            chunks: vec![test_chunk(1, 4)],
+            size: 4,
+        })
+        .unwrap();
+
+        // the boundary lies within the first buffer, so the chunks cannot be injected anymore
+        let input = futures::stream::iter(vec![Ok::<_, Error>(bytes::BytesMut::from(&b"abc"[..]))]);
+        let output: Vec<_> =
+            futures::executor::block_on(input.inject_reused_chunks(Some(rx)).take(2).collect());
+
+        assert!(matches!(&output[0], Ok(InjectedChunksInfo::Raw(_))));
+        assert!(output[1].is_err());
+    }
+}
@@ -17,15 +17,20 @@ use nix::sys::stat::{FileStat, Mode};
 
 use pathpatterns::{MatchEntry, MatchFlag, MatchList, MatchType, PatternFlag};
 use proxmox_sys::error::SysError;
+use pxar::accessor::aio::{Accessor, Directory};
+use pxar::accessor::ReadAt;
 use pxar::encoder::{LinkOffset, SeqWrite};
-use pxar::Metadata;
+use pxar::{EntryKind, Metadata};
 
 use proxmox_io::vec;
 use proxmox_lang::c_str;
 use proxmox_sys::fs::{self, acl, xattr};
 
 use pbs_datastore::catalog::BackupCatalogWriter;
+use pbs_datastore::dynamic_index::DynamicIndexReader;
+use pbs_datastore::index::IndexFile;
 
+use crate::inject_reused_chunks::{PayloadInjector, ReusableChunk};
 use crate::pxar::metadata::errno_is_unsupported;
 use crate::pxar::tools::assert_single_path_component;
 use crate::pxar::Flags;
@@ -41,8 +46,116 @@ pub struct PxarCreateOptions {
     pub entries_max: usize,
     /// Skip lost+found directory
     pub skip_lost_and_found: bool,
+    /// Previous archive to reuse the payload of unchanged files from
+    pub previous_ref: Option<PxarPrevRef>,
+    /// Collects the change information of all files with chunk aligned payload, to be stored
+    /// next to the archive for the metadata change detection of the following backup
+    pub change_info: Option<Arc<Mutex<PxarChangeInfo>>>,
 }
 
+type PrevReader = Arc<dyn ReadAt + Send + Sync>;
+
+/// Reference to the previous snapshot of an archive, used by the metadata based change
+/// detection.
+#[derive(Clone)]
+pub struct PxarPrevRef {
+    /// Accessor to the previous archive
+    pub accessor: Arc<Accessor<PrevReader>>,
+    /// Dynamic index of the previous archive, used to map file payloads to chunks
+    pub index: Arc<DynamicIndexReader>,
+    /// Change information stored with the previous archive
+    pub change_info: Arc<PxarChangeInfo>,
+}
+
+/// Inode and change time of a regular file.
+///
+/// Neither is part of the pxar format, but both change on modifications which keep size and
+/// mtime, e.g. if the mtime was reset after writing or the file was replaced by another one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileChangeInfo {
+    pub ino: u64,
+    pub ctime: i64,
+    pub ctime_nsec: i64,
+}
+
+impl From<&FileStat> for FileChangeInfo {
+    fn from(stat: &FileStat) -> Self {
+        Self {
+            ino: stat.st_ino,
+            ctime: stat.st_ctime,
+            ctime_nsec: stat.st_ctime_nsec,
+        }
+    }
+}
+
+/// Change information of all files with chunk aligned payload of an archive, by path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PxarChangeInfo {
+    files: HashMap<PathBuf, FileChangeInfo>,
+}
+
+impl PxarChangeInfo {
+    /// Name of the blob storing the change information of the given archive.
+    pub fn blob_name(archive_name: &str) -> String {
+        let archive_name = archive_name.strip_suffix(".didx").unwrap_or(archive_name);
+        format!("{archive_name}.change-info.blob")
+    }
+
+    pub fn insert(&mut self, path: PathBuf, info: FileChangeInfo) {
+        self.files.insert(path, info);
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&FileChangeInfo> {
+        self.files.get(path)
+    }
+
+    /// Encode as a list of inode, ctime, ctime nsec, path length and path, all little endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (path, info) in self.files.iter() {
+            let path = path.as_os_str().as_bytes();
+            data.extend_from_slice(&info.ino.to_le_bytes());
+            data.extend_from_slice(&info.ctime.to_le_bytes());
+            data.extend_from_slice(&info.ctime_nsec.to_le_bytes());
+            data.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            data.extend_from_slice(path);
+        }
+        data
+    }
+
+    pub fn decode(mut data: &[u8]) -> Result<Self, Error> {
+        fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+            if data.len() < len {
+                bail!("unexpected end of change information");
+            }
+            let (head, tail) = data.split_at(len);
+            *data = tail;
+            Ok(head)
+        }
+
+        let mut files = HashMap::new();
+        while !data.is_empty() {
+            let ino = u64::from_le_bytes(take(&mut data, 8)?.try_into()?);
+            let ctime = i64::from_le_bytes(take(&mut data, 8)?.try_into()?);
+            let ctime_nsec = i64::from_le_bytes(take(&mut data, 8)?.try_into()?);
+            let len = u32::from_le_bytes(take(&mut data, 4)?.try_into()?) as usize;
+            let path = PathBuf::from(OsStr::from_bytes(take(&mut data, len)?));
+            let info = FileChangeInfo {
+                ino,
+                ctime,
+                ctime_nsec,
+            };
+            files.insert(path, info);
+        }
+
+        Ok(Self { files })
+    }
+}
+
+/// Regular files with at least this size get their payload aligned to chunk boundaries, so
+/// their chunks can be reused by following backups in metadata change detection mode.
+const PAYLOAD_REUSE_MIN_SIZE: u64 = 1024 * 1024;
+
 fn detect_fs_type(fd: RawFd) -> Result<i64, Error> {
     let mut fs_stat = std::mem::MaybeUninit::uninit();
     let res = unsafe { libc::fstatfs(fd, fs_stat.as_mut_ptr()) };
@@ -52,6 +165,96 @@ fn detect_fs_type(fd: RawFd) -> Result<i64, Error> {
     Ok(fs_stat.f_type)
 }
 
+async fn lookup_previous_dir(
+    previous_dir: &Directory<PrevReader>,
+    dir_name: &OsStr,
+) -> Option<Directory<PrevReader>> {
+    match previous_dir.lookup(dir_name).await {
+        Ok(Some(entry)) if entry.is_dir() => entry.enter_directory().await.ok(),
+        Ok(_) => None,
+        Err(err) => {
+            log::debug!("lookup of {dir_name:?} in previous archive failed - {err}");
+            None
+        }
+    }
+}
+
+/// Check whether a file is unchanged since the previous archive.
+///
+/// Besides mtime and file type, the inode and ctime stored with the previous archive have to
+/// match, files without stored change information are always considered changed.
+fn is_unchanged_file(
+    previous: &Metadata,
+    previous_change: Option<&FileChangeInfo>,
+    metadata: &Metadata,
+    change: &FileChangeInfo,
+) -> bool {
+    previous.stat.mtime == metadata.stat.mtime
+        && previous.file_type() == metadata.file_type()
+        && previous_change == Some(change)
+}
+
+/// Look up the chunks holding the unchanged payload of a file in the previous archive.
+///
+/// The payload can only be reused if the size matches, the file is unchanged according to
+/// [`is_unchanged_file`] and the payload is aligned to chunk boundaries in the previous index.
+async fn lookup_reusable_chunks(
+    previous_dir: &Directory<PrevReader>,
+    previous_ref: &PxarPrevRef,
+    path: &Path,
+    file_name: &Path,
+    metadata: &Metadata,
+    change: &FileChangeInfo,
+    file_size: u64,
+) -> Option<Vec<ReusableChunk>> {
+    let index = &previous_ref.index;
+
+    let entry = match previous_dir.lookup(file_name).await {
+        Ok(entry) => entry?,
+        Err(err) => {
+            log::debug!("lookup of {file_name:?} in previous archive failed - {err}");
+            return None;
+        }
+    };
+
+    let start = match entry.kind() {
+        EntryKind::File {
+            size,
+            offset: Some(offset),
+        } if *size == file_size => *offset,
+        _ => return None,
+    };
+
+    let previous_change = previous_ref.change_info.get(path);
+    if !is_unchanged_file(entry.metadata(), previous_change, metadata, change) {
+        return None;
+    }
+
+    let end = start + file_size;
+
+    let (first, start_offset) = index.chunk_from_offset(start)?;
+    if start_offset != 0 {
+        return None;
+    }
+
+    let mut chunks = Vec::new();
+    for pos in first..index.index_count() {
+        let info = index.chunk_info(pos)?;
+        if info.range.end > end {
+            return None;
+        }
+        chunks.push(ReusableChunk {
+            digest: info.digest,
+            size: info.size(),
+        });
+        if info.range.end == end {
+            return Some(chunks);
+        }
+    }
+
+    None
+}
+
 fn strip_ascii_whitespace(line: &[u8]) -> &[u8] {
     let line = match line.iter().position(|&b| !b.is_ascii_whitespace()) {
         Some(n) => &line[n..],
@@ -128,17 +331,50 @@ struct Archiver {
     device_set: Option<HashSet<u64>>,
     hardlinks: HashMap<HardLinkInfo, (PathBuf, LinkOffset)>,
     file_copy_buffer: Vec<u8>,
+    previous_ref: Option<PxarPrevRef>,
+    previous_dir: Option<Directory<PrevReader>>,
+    injector: Option<PayloadInjector>,
+    change_info: Option<Arc<Mutex<PxarChangeInfo>>>,
+    reused_files: u64,
+    reused_size: u64,
 }
 
 type Encoder<'a, T> = pxar::encoder::aio::Encoder<'a, T>;
 
 pub async fn create_archive<T, F>(
+    source_dir: Dir,
+    writer: T,
+    feature_flags: Flags,
+    callback: F,
+    catalog: Option<Arc<Mutex<dyn BackupCatalogWriter + Send>>>,
+    options: PxarCreateOptions,
+) -> Result<(), Error>
+where
+    T: SeqWrite + Send,
+    F: FnMut(&Path) -> Result<(), Error> + Send + 'static,
+{
+    create_archive_with_injector(
+        source_dir,
+        writer,
+        feature_flags,
+        callback,
+        catalog,
+        options,
+        None,
+    )
+    .await
+}
+
+/// Like [`create_archive`], but aligns file payloads to chunk boundaries and reuses the chunks
+/// of unchanged files from `options.previous_ref` via the given `injector`.
+pub(crate) async fn create_archive_with_injector<T, F>(
     source_dir: Dir,
     mut writer: T,
     feature_flags: Flags,
     callback: F,
     catalog: Option<Arc<Mutex<dyn BackupCatalogWriter + Send>>>,
     options: PxarCreateOptions,
+    injector: Option<PayloadInjector>,
 ) -> Result<(), Error>
 where
     T: SeqWrite + Send,
@@ -178,6 +414,16 @@ where
         )?);
     }
 
+    let previous_ref = if injector.is_some() {
+        options.previous_ref
+    } else {
+        None
+    };
+    let previous_dir = match previous_ref {
+        Some(ref previous_ref) => Some(previous_ref.accessor.open_root().await?),
+        None => None,
+    };
+
     let mut archiver = Archiver {
         feature_flags,
         fs_feature_flags,
@@ -192,12 +438,27 @@ where
         device_set,
         hardlinks: HashMap::new(),
         file_copy_buffer: vec::undefined(4 * 1024 * 1024),
+        previous_ref,
+        previous_dir,
+        injector,
+        change_info: options.change_info,
+        reused_files: 0,
+        reused_size: 0,
     };
 
     archiver
         .archive_dir_contents(&mut encoder, source_dir, true)
         .await?;
     encoder.finish().await?;
+
+    if archiver.reused_files > 0 {
+        log::info!(
+            "reused {} unchanged files ({} bytes) from previous archive",
+            archiver.reused_files,
+            archiver.reused_size
+        );
+    }
+
     Ok(())
 }
 
@@ -580,7 +841,7 @@ impl Archiver {
                 }
 
                 let offset: LinkOffset = self
-                    .add_regular_file(encoder, fd, file_name, &metadata, file_size)
+                    .add_regular_file(encoder, fd, file_name, &metadata, stat, file_size)
                     .await?;
 
                 if stat.st_nlink > 1 {
@@ -676,6 +937,11 @@ impl Archiver {
             }
         }
 
+        let old_previous_dir = self.previous_dir.take();
+        if let Some(ref previous_dir) = old_previous_dir {
+            self.previous_dir = lookup_previous_dir(previous_dir, dir_name).await;
+        }
+
         let result = if skip_contents {
             log::info!("skipping mount point: {:?}", self.path);
             Ok(())
@@ -683,6 +949,8 @@ impl Archiver {
             self.archive_dir_contents(&mut encoder, dir, false).await
         };
 
+        self.previous_dir = old_previous_dir;
+
         self.fs_magic = old_fs_magic;
         self.fs_feature_flags = old_fs_feature_flags;
         self.current_st_dev = old_st_dev;
@@ -697,11 +965,56 @@ impl Archiver {
         fd: OwnedFd,
         file_name: &Path,
         metadata: &Metadata,
+        stat: &FileStat,
         file_size: u64,
     ) -> Result<LinkOffset, Error> {
+        let align_payload = self.injector.is_some() && file_size >= PAYLOAD_REUSE_MIN_SIZE;
+        let change = FileChangeInfo::from(stat);
+        let reusable_chunks = match (&self.previous_dir, &self.previous_ref) {
+            (Some(previous_dir), Some(previous_ref)) if align_payload => {
+                lookup_reusable_chunks(
+                    previous_dir,
+                    previous_ref,
+                    &self.path,
+                    file_name,
+                    metadata,
+                    &change,
+                    file_size,
+                )
+                .await
+            }
+            _ => None,
+        };
+
+        if let Some(change_info) = self.change_info.as_ref().filter(|_| align_payload) {
+            change_info
+                .lock()
+                .unwrap()
+                .insert(self.path.clone(), change);
+        }
+
         let mut file = unsafe { std::fs::File::from_raw_fd(fd.into_raw_fd()) };
         let mut remaining = file_size;
         let mut out = encoder.create_file(metadata, file_name, file_size).await?;
+
+        if let Some(injector) = self.injector.as_ref().filter(|_| align_payload) {
+            if let Some(chunks) = reusable_chunks {
+                // the payload gets replaced by the chunks of the previous archive, the data
+                // written here only keeps the encoder state consistent and is discarded
+                injector.inject(chunks, file_size)?;
+                vec::clear(&mut self.file_copy_buffer[..]);
+                while remaining != 0 {
+                    let fill = remaining.min(self.file_copy_buffer.len() as u64) as usize;
+                    out.write_all(&self.file_copy_buffer[..fill]).await?;
+                    remaining -= fill as u64;
+                }
+                self.reused_files += 1;
+                self.reused_size += file_size;
+                return Ok(out.file_offset());
+            }
+            injector.boundary()?;
+        }
+
         while remaining != 0 {
             let mut got = match file.read(&mut self.file_copy_buffer[..]) {
                 Ok(0) => break,
@@ -727,6 +1040,10 @@ impl Archiver {
             }
         }
 
+        if let Some(injector) = self.injector.as_ref().filter(|_| align_payload) {
+            injector.boundary()?;
+        }
+
         Ok(out.file_offset())
     }
 
@@ -1105,3 +1422,85 @@ fn generate_pxar_excludes_cli(patterns: &[MatchEntry]) -> Vec<u8> {
 
     content
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_metadata(mode: u64, mtime: i64) -> Metadata {
+        Metadata {
+            stat: pxar::Stat {
+                mode,
+                flags: 0,
+                uid: 0,
+                gid: 0,
+                mtime: pxar::format::StatxTimestamp::new(mtime, 0),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unchanged_file() {
+        let file = test_metadata(pxar::format::mode::IFREG | 0o644, 1000);
+        let change = FileChangeInfo {
+            ino: 42,
+            ctime: 2000,
+            ctime_nsec: 5,
+        };
+        assert!(is_unchanged_file(&file, Some(&change), &file, &change));
+
+        // no change information stored with the previous archive
+        assert!(!is_unchanged_file(&file, None, &file, &change));
+
+        let modified = test_metadata(pxar::format::mode::IFREG | 0o644, 1001);
+        assert!(!is_unchanged_file(&file, Some(&change), &modified, &change));
+
+        let symlink = test_metadata(pxar::format::mode::IFLNK | 0o777, 1000);
+        assert!(!is_unchanged_file(&file, Some(&change), &symlink, &change));
+
+        // mtime restored after writing, or replaced by another file with the same mtime
+        for changed in [
+            FileChangeInfo {
+                ctime_nsec: 6,
+                ..change
+            },
+            FileChangeInfo { ino: 43, ..change },
+        ] {
+            assert!(!is_unchanged_file(&file, Some(&change), &file, &changed));
+        }
+    }
+
+    #[test]
+    fn test_change_info_encoding() -> Result<(), Error> {
+        let mut change_info = PxarChangeInfo::default();
+        change_info.insert(
+            PathBuf::from("etc/hosts"),
+            FileChangeInfo {
+                ino: 1,
+                ctime: -1,
+                ctime_nsec: 999_999_999,
+            },
+        );
+        change_info.insert(
+            PathBuf::from(OsStr::from_bytes(b"non-utf8-\xff")),
+            FileChangeInfo {
+                ino: u64::MAX,
+                ctime: 1_700_000_000,
+                ctime_nsec: 0,
+            },
+        );
+
+        let data = change_info.encode();
+        assert_eq!(PxarChangeInfo::decode(&data)?, change_info);
+        assert_eq!(PxarChangeInfo::decode(&[])?, PxarChangeInfo::default());
+        assert!(PxarChangeInfo::decode(&data[..data.len() - 1]).is_err());
+
+        assert_eq!(
+            PxarChangeInfo::blob_name("root.pxar.didx"),
+            "root.pxar.change-info.blob"
+        );
+
+        Ok(())
+    }
+}
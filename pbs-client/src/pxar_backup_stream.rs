@@ -17,6 +17,9 @@ use proxmox_io::StdChannelWriter;
 
 use pbs_datastore::catalog::CatalogWriter;
 
+use crate::inject_reused_chunks::{InjectChunks, PayloadInjector};
+use crate::BackupDetectionMode;
+
 /// Stream implementation to encode and upload .pxar archives.
 ///
 /// The hyper client needs an async Stream for file upload, so we
@@ -26,6 +29,7 @@ pub struct PxarBackupStream {
     rx: Option<std::sync::mpsc::Receiver<Result<Vec<u8>, Error>>>,
     handle: Option<AbortHandle>,
     error: Arc<Mutex<Option<String>>>,
+    boundaries: Option<std::sync::mpsc::Receiver<InjectChunks>>,
 }
 
 impl Drop for PxarBackupStream {
@@ -40,21 +44,30 @@ impl PxarBackupStream {
         dir: Dir,
         catalog: Arc<Mutex<CatalogWriter<W>>>,
         options: crate::pxar::PxarCreateOptions,
+        detection_mode: BackupDetectionMode,
     ) -> Result<Self, Error> {
         let (tx, rx) = std::sync::mpsc::sync_channel(10);
 
         let buffer_size = 256 * 1024;
 
+        let writer = TokioWriterAdapter::new(std::io::BufWriter::with_capacity(
+            buffer_size,
+            StdChannelWriter::new(tx),
+        ));
+
+        // the injector needs to see the data before it gets buffered, to track the stream offset
+        let (injector, writer, boundaries) = PayloadInjector::new(writer);
+        let (injector, boundaries) = if detection_mode.is_metadata() {
+            (Some(injector), Some(boundaries))
+        } else {
+            (None, None)
+        };
+
         let error = Arc::new(Mutex::new(None));
         let error2 = Arc::clone(&error);
         let handler = async move {
-            let writer = TokioWriterAdapter::new(std::io::BufWriter::with_capacity(
-                buffer_size,
-                StdChannelWriter::new(tx),
-            ));
-
             let writer = pxar::encoder::sync::StandardWriter::new(writer);
-            if let Err(err) = crate::pxar::create_archive(
+            if let Err(err) = crate::pxar::create::create_archive_with_injector(
                 dir,
                 writer,
                 crate::pxar::Flags::DEFAULT,
@@ -64,6 +77,7 @@ impl PxarBackupStream {
                 },
                 Some(catalog),
                 options,
+                injector,
             )
             .await
             {
@@ -80,6 +94,7 @@ impl PxarBackupStream {
             rx: Some(rx),
             handle: Some(handle),
             error,
+            boundaries,
         })
     }
 
@@ -87,10 +102,17 @@ impl PxarBackupStream {
         dirname: &Path,
         catalog: Arc<Mutex<CatalogWriter<W>>>,
         options: crate::pxar::PxarCreateOptions,
+        detection_mode: BackupDetectionMode,
     ) -> Result<Self, Error> {
         let dir = nix::dir::Dir::open(dirname, OFlag::O_DIRECTORY, Mode::empty())?;
 
-        Self::new(dir, catalog, options)
+        Self::new(dir, catalog, options, detection_mode)
+    }
+
+    /// Take the receiving end of the chunk boundary channel, only available in metadata change
+    /// detection mode.
+    pub fn take_boundaries(&mut self) -> Option<std::sync::mpsc::Receiver<InjectChunks>> {
+        self.boundaries.take()
     }
 }
 
@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -10,7 +10,7 @@ use lazy_static::lazy_static;
 
 use proxmox_schema::{ApiStringFormat, ApiType, Schema, StringSchema};
 
-use pbs_api_types::{Authid, Role, Userid, ROLE_NAME_NO_ACCESS};
+use pbs_api_types::{Authid, Role, ROLE_NAME_NO_ACCESS};
 
 use crate::{open_backup_lockfile, replace_backup_config, BackupLockGuard};
 
@@ -66,7 +66,7 @@ pub fn check_acl_path(path: &str) -> Result<(), Error> {
                 return Ok(());
             }
             match components[1] {
-                "acl" | "users" | "domains" => {
+                "acl" | "groups" | "users" | "domains" => {
                     if components_len == 2 {
                         return Ok(());
                     }
@@ -181,7 +181,7 @@ pub struct AclTreeNode {
     /// [User](pbs_api_types::User) or
     /// [Token](pbs_api_types::ApiToken) ACLs for this node.
     pub users: HashMap<Authid, HashMap<String, bool>>,
-    /// `Group` ACLs for this node (not yet implemented)
+    /// [Group](pbs_api_types::Group) ACLs for this node.
     pub groups: HashMap<String, HashMap<String, bool>>,
     /// `AclTreeNodes` representing ACL paths directly below the current one.
     pub children: BTreeMap<String, AclTreeNode>,
@@ -201,18 +201,23 @@ impl AclTreeNode {
     /// [Authid](pbs_api_types::Authid).
     ///
     /// If the `Authid` is a [User](pbs_api_types::User) that has no specific `Roles` configured on
-    /// this node, applicable `Group` roles will be returned instead.
+    /// this node, the roles of the `groups` the user is a member of will be returned instead.
     ///
     /// If `leaf` is `false`, only those roles where the propagate flag in the ACL is set to `true`
     /// are returned. Otherwise, all roles will be returned.
-    pub fn extract_roles(&self, auth_id: &Authid, leaf: bool) -> HashMap<String, bool> {
+    pub fn extract_roles(
+        &self,
+        auth_id: &Authid,
+        groups: &[String],
+        leaf: bool,
+    ) -> HashMap<String, bool> {
         let user_roles = self.extract_user_roles(auth_id, leaf);
         if !user_roles.is_empty() || auth_id.is_token() {
             // user privs always override group privs
             return user_roles;
         };
 
-        self.extract_group_roles(auth_id.user(), leaf)
+        self.extract_group_roles(groups, leaf)
     }
 
     fn extract_user_roles(&self, auth_id: &Authid, leaf: bool) -> HashMap<String, bool> {
@@ -238,15 +243,14 @@ impl AclTreeNode {
         map
     }
 
-    fn extract_group_roles(&self, _user: &Userid, leaf: bool) -> HashMap<String, bool> {
+    fn extract_group_roles(&self, groups: &[String], leaf: bool) -> HashMap<String, bool> {
         let mut map = HashMap::new();
 
-        #[allow(clippy::for_kv_map)]
-        for (_group, roles) in &self.groups {
-            let is_member = false; // fixme: check if user is member of the group
-            if !is_member {
-                continue;
-            }
+        for group in groups {
+            let roles = match self.groups.get(group) {
+                Some(roles) => roles,
+                None => continue,
+            };
 
             for (role, propagate) in roles {
                 if *propagate || leaf {
@@ -287,6 +291,27 @@ impl AclTreeNode {
         self.users.remove(auth_id);
     }
 
+    fn delete_group(&mut self, group: &str) {
+        for node in self.children.values_mut() {
+            node.delete_group(group);
+        }
+        self.groups.remove(group);
+    }
+
+    fn delete_role(&mut self, role: &str) {
+        for node in self.children.values_mut() {
+            node.delete_role(role);
+        }
+        for roles in self.users.values_mut() {
+            roles.remove(role);
+        }
+        self.users.retain(|_, roles| !roles.is_empty());
+        for roles in self.groups.values_mut() {
+            roles.remove(role);
+        }
+        self.groups.retain(|_, roles| !roles.is_empty());
+    }
+
     fn insert_group_role(&mut self, group: String, role: String, propagate: bool) {
         let map = self.groups.entry(group).or_default();
         if role == ROLE_NAME_NO_ACCESS {
@@ -313,15 +338,16 @@ impl AclTreeNode {
         &self,
         path: String,
         auth_id: &Authid,
+        groups: &[String],
         paths: &mut Vec<String>,
     ) -> Result<(), Error> {
         for (sub_comp, child_node) in &self.children {
-            let roles = child_node.extract_roles(auth_id, true);
+            let roles = child_node.extract_roles(auth_id, groups, true);
             let child_path = format!("{path}/{sub_comp}");
             if !roles.is_empty() {
                 paths.push(child_path.clone());
             }
-            child_node.get_child_paths(child_path, auth_id, paths)?;
+            child_node.get_child_paths(child_path, auth_id, groups, paths)?;
         }
         Ok(())
     }
@@ -426,6 +452,20 @@ impl AclTree {
         self.root.delete_authid(auth_id);
     }
 
+    /// Deletes a group from the ACL-tree
+    ///
+    /// Traverses the tree in-order and removes the given group from every node in the tree.
+    pub fn delete_group(&mut self, group: &str) {
+        self.root.delete_group(group);
+    }
+
+    /// Deletes a role from the ACL-tree
+    ///
+    /// Traverses the tree in-order and removes the given role from every node in the tree.
+    pub fn delete_role(&mut self, role: &str) {
+        self.root.delete_role(role);
+    }
+
     /// Inserts the specified `role` into the `group` ACL on `path`.
     ///
     /// The [`AclTreeNode`] representing `path` will be created and inserted into the tree if
@@ -559,7 +599,7 @@ impl AclTree {
         Self::write_node_config(&self.root, "", w)
     }
 
-    fn parse_acl_line(&mut self, line: &str) -> Result<(), Error> {
+    fn parse_acl_line(&mut self, line: &str, custom_roles: &HashSet<String>) -> Result<(), Error> {
         let items: Vec<&str> = line.split(':').collect();
 
         if items.len() != 5 {
@@ -588,7 +628,7 @@ impl AclTree {
 
         for user_or_group in &uglist {
             for role in &rolelist {
-                if !ROLE_NAMES.contains_key(role) {
+                if !ROLE_NAMES.contains_key(role) && !custom_roles.contains(*role) {
                     bail!("unknown role '{}'", role);
                 }
                 if let Some(group) = user_or_group.strip_prefix('@') {
@@ -618,12 +658,15 @@ impl AclTree {
 
         let digest = openssl::sha::sha256(raw.as_bytes());
 
+        let (role_config, _digest) = crate::role::config()?;
+        let custom_roles = crate::role::custom_role_ids(&role_config);
+
         for (linenr, line) in raw.lines().enumerate() {
             let line = line.trim();
             if line.is_empty() {
                 continue;
             }
-            if let Err(err) = tree.parse_acl_line(line) {
+            if let Err(err) = tree.parse_acl_line(line, &custom_roles) {
                 bail!(
                     "unable to parse acl config {:?}, line {} - {}",
                     filename,
@@ -638,13 +681,19 @@ impl AclTree {
 
     /// This is used for testing
     pub fn from_raw(raw: &str) -> Result<Self, Error> {
+        Self::from_raw_with_roles(raw, &[])
+    }
+
+    /// This is used for testing, `custom_roles` are accepted in addition to the built-in roles.
+    pub fn from_raw_with_roles(raw: &str, custom_roles: &[&str]) -> Result<Self, Error> {
+        let custom_roles: HashSet<String> = custom_roles.iter().map(|r| r.to_string()).collect();
         let mut tree = Self::new();
         for (linenr, line) in raw.lines().enumerate() {
             let line = line.trim();
             if line.is_empty() {
                 continue;
             }
-            if let Err(err) = tree.parse_acl_line(line) {
+            if let Err(err) = tree.parse_acl_line(line, &custom_roles) {
                 bail!(
                     "unable to parse acl config data, line {} - {}",
                     linenr + 1,
@@ -657,15 +706,22 @@ impl AclTree {
 
     /// Returns a map of role name and propagation status for a given `auth_id` and `path`.
     ///
+    /// `groups` are the groups the user (or the owner of the token) is a member of.
+    ///
     /// This will collect role mappings according to the following algorithm:
     /// - iterate over all intermediate nodes along `path` and collect roles with `propagate` set
     /// - get all (propagating and non-propagating) roles for last component of path
     /// - more specific role maps replace less specific role maps
     /// -- user/token is more specific than group at each level
     /// -- roles lower in the tree are more specific than those higher up along the path
-    pub fn roles(&self, auth_id: &Authid, path: &[&str]) -> HashMap<String, bool> {
+    pub fn roles(
+        &self,
+        auth_id: &Authid,
+        groups: &[String],
+        path: &[&str],
+    ) -> HashMap<String, bool> {
         let mut node = &self.root;
-        let mut role_map = node.extract_roles(auth_id, path.is_empty());
+        let mut role_map = node.extract_roles(auth_id, groups, path.is_empty());
 
         let mut comp_iter = path.iter().peekable();
 
@@ -682,7 +738,7 @@ impl AclTree {
                     None => return role_map, // path not found
                 };
 
-                let new_map = node.extract_roles(auth_id, last_sub_comp);
+                let new_map = node.extract_roles(auth_id, groups, last_sub_comp);
                 if !new_map.is_empty() {
                     // overwrite previous mappings
                     role_map = new_map;
@@ -693,12 +749,17 @@ impl AclTree {
         role_map
     }
 
-    pub fn get_child_paths(&self, auth_id: &Authid, path: &[&str]) -> Result<Vec<String>, Error> {
+    pub fn get_child_paths(
+        &self,
+        auth_id: &Authid,
+        groups: &[String],
+        path: &[&str],
+    ) -> Result<Vec<String>, Error> {
         let mut res = Vec::new();
 
         if let Some(node) = self.get_node(path) {
             let path = path.join("/");
-            node.get_child_paths(path, auth_id, &mut res)?;
+            node.get_child_paths(path, auth_id, groups, &mut res)?;
         }
 
         Ok(res)
@@ -793,9 +854,20 @@ mod test {
     use pbs_api_types::Authid;
 
     fn check_roles(tree: &AclTree, auth_id: &Authid, path: &str, expected_roles: &str) {
+        check_group_roles(tree, auth_id, &[], path, expected_roles)
+    }
+
+    fn check_group_roles(
+        tree: &AclTree,
+        auth_id: &Authid,
+        groups: &[&str],
+        path: &str,
+        expected_roles: &str,
+    ) {
+        let groups: Vec<String> = groups.iter().map(|group| group.to_string()).collect();
         let path_vec = super::split_acl_path(path);
         let mut roles = tree
-            .roles(auth_id, &path_vec)
+            .roles(auth_id, &groups, &path_vec)
             .keys()
             .cloned()
             .collect::<Vec<String>>();
@@ -888,6 +960,136 @@ acl:1:/storage/store1:user1@pbs:DatastoreBackup
         Ok(())
     }
 
+    #[test]
+    fn test_group_roles() -> Result<(), Error> {
+        let tree = AclTree::from_raw(
+            r###"
+acl:1:/datastore:@tenants:Audit
+acl:1:/datastore/store1:@tenant1:DatastoreBackup
+acl:0:/datastore/store2:@tenant2:DatastoreBackup
+acl:1:/datastore/store3:@tenant1,@tenant2:NoAccess
+acl:1:/datastore/store1:user2@pbs:DatastoreReader
+"###,
+        )?;
+        let user1: Authid = "user1@pbs".parse()?;
+        let user2: Authid = "user2@pbs".parse()?;
+        let token: Authid = "user1@pbs!token".parse()?;
+
+        check_group_roles(&tree, &user1, &[], "/datastore/store1", "");
+        check_group_roles(&tree, &user1, &["tenants"], "/datastore/store1", "Audit");
+        check_group_roles(
+            &tree,
+            &user1,
+            &["tenants", "tenant1"],
+            "/datastore/store1",
+            "DatastoreBackup",
+        );
+        check_group_roles(
+            &tree,
+            &user1,
+            &["tenant1", "tenant2"],
+            "/datastore/store1/ns",
+            "DatastoreBackup",
+        );
+        check_group_roles(
+            &tree,
+            &user1,
+            &["tenant2"],
+            "/datastore/store2",
+            "DatastoreBackup",
+        );
+        check_group_roles(
+            &tree,
+            &user1,
+            &["tenants", "tenant2"],
+            "/datastore/store2/ns",
+            "Audit",
+        );
+        check_group_roles(
+            &tree,
+            &user1,
+            &["tenants", "tenant2"],
+            "/datastore/store3",
+            "NoAccess",
+        );
+
+        // user ACLs override group ACLs on the same node
+        check_group_roles(
+            &tree,
+            &user2,
+            &["tenant1"],
+            "/datastore/store1",
+            "DatastoreReader",
+        );
+        check_group_roles(&tree, &user2, &["tenant1"], "/datastore", "");
+
+        // tokens never get group roles
+        check_group_roles(&tree, &token, &["tenant1"], "/datastore/store1", "");
+
+        let paths = tree.get_child_paths(&user1, &["tenant2".to_string()], &["datastore"])?;
+        assert_eq!(paths, vec!["datastore/store2", "datastore/store3"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_group() -> Result<(), Error> {
+        let mut tree = AclTree::new();
+
+        let user1: Authid = "user1@pbs".parse()?;
+
+        tree.insert_group_role("/", "group1", "Audit", true);
+        tree.insert_group_role("/datastore", "group1", "Admin", true);
+        tree.insert_group_role("/datastore", "group2", "Audit", true);
+        tree.insert_user_role("/datastore", &user1, "DatastoreReader", true);
+
+        tree.delete_group("group1");
+
+        let mut raw: Vec<u8> = Vec::new();
+        tree.write_config(&mut raw)?;
+        let raw = std::str::from_utf8(&raw)?;
+
+        assert_eq!(
+            raw,
+            "acl:1:/datastore:@group2:Audit\nacl:1:/datastore:user1@pbs:DatastoreReader\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_roles() -> Result<(), Error> {
+        let raw = "\
+            acl:1:/:user1@pbs:Audit\n\
+            acl:1:/datastore:user1@pbs:ReadVerify\n\
+            acl:1:/datastore:@group1:ReadVerify\n\
+            acl:1:/datastore/store1:user2@pbs:ReadVerify,DatastoreAudit\n\
+            ";
+
+        // unknown roles are rejected, just like unknown built-in roles
+        assert!(AclTree::from_raw(raw).is_err());
+        assert!(AclTree::from_raw_with_roles(raw, &["OtherRole"]).is_err());
+        assert!(AclTree::from_raw("acl:1:/:user1@pbs:Bad Role\n").is_err());
+
+        let mut tree = AclTree::from_raw_with_roles(raw, &["ReadVerify"])?;
+
+        let user1: Authid = "user1@pbs".parse()?;
+        check_roles(&tree, &user1, "/datastore", "ReadVerify");
+
+        tree.delete_role("ReadVerify");
+
+        let mut raw: Vec<u8> = Vec::new();
+        tree.write_config(&mut raw)?;
+        let raw = std::str::from_utf8(&raw)?;
+
+        assert_eq!(
+            raw,
+            "acl:1:/:user1@pbs:Audit\nacl:1:/datastore/store1:user2@pbs:DatastoreAudit\n"
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_role_add_delete() -> Result<(), Error> {
         let mut tree = AclTree::new();
@@ -948,7 +1150,7 @@ acl:1:/storage/store1:user1@pbs:DatastoreBackup
         let user2: Authid = "user2@pbs".parse()?;
 
         // user1 has admin on "/store/store2/store3" -> return paths
-        let paths = tree.get_child_paths(&user1, &["store"])?;
+        let paths = tree.get_child_paths(&user1, &[], &["store"])?;
         assert!(
             paths.len() == 2
                 && paths.contains(&"store/store2".to_string())
@@ -957,24 +1159,24 @@ acl:1:/storage/store1:user1@pbs:DatastoreBackup
 
         // user2 has no privileges under "/store/store2/store3" --> return empty
         assert!(tree
-            .get_child_paths(&user2, &["store", "store2", "store3"],)?
+            .get_child_paths(&user2, &[], &["store", "store2", "store3"],)?
             .is_empty());
 
         // user2 has DatastoreReader privileges under "/store/store2/store31" --> return paths
-        let paths = tree.get_child_paths(&user2, &["store/store2/store31"])?;
+        let paths = tree.get_child_paths(&user2, &[], &["store/store2/store31"])?;
         assert!(
             paths.len() == 1 && paths.contains(&"store/store2/store31/store4/store6".to_string())
         );
 
         // user2 has no privileges under "/store/store2/foo/bar/baz"
         assert!(tree
-            .get_child_paths(&user2, &["store", "store2", "foo/bar/baz"])?
+            .get_child_paths(&user2, &[], &["store", "store2", "foo/bar/baz"])?
             .is_empty());
 
         // user2 has DatastoreReader privileges on "/store/store2/store31/store4/store6", but not
         // on any child paths --> return empty
         assert!(tree
-            .get_child_paths(&user2, &["store/store2/store31/store4/store6"],)?
+            .get_child_paths(&user2, &[], &["store/store2/store31/store4/store6"],)?
             .is_empty());
 
         Ok(())
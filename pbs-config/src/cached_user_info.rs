@@ -1,5 +1,6 @@
 //! Cached user info for fast ACL permission checks
 
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 use anyhow::{bail, Error};
@@ -9,7 +10,7 @@ use proxmox_router::UserInformation;
 use proxmox_section_config::SectionConfigData;
 use proxmox_time::epoch_i64;
 
-use pbs_api_types::{privs_to_priv_names, ApiToken, Authid, User, Userid, ROLE_ADMIN};
+use pbs_api_types::{privs_to_priv_names, ApiToken, Authid, Group, User, Userid, ROLE_ADMIN};
 
 use crate::acl::{AclTree, ROLE_NAMES};
 use crate::ConfigVersionCache;
@@ -18,6 +19,8 @@ use crate::ConfigVersionCache;
 pub struct CachedUserInfo {
     user_cfg: Arc<SectionConfigData>,
     acl_tree: Arc<AclTree>,
+    user_groups: HashMap<Userid, Vec<String>>,
+    custom_roles: HashMap<String, u64>,
 }
 
 struct ConfigCache {
@@ -54,9 +57,13 @@ impl CachedUserInfo {
             }
         }
 
+        let user_cfg = crate::user::cached_config()?;
+        let (role_cfg, _digest) = crate::role::config()?;
         let config = Arc::new(CachedUserInfo {
-            user_cfg: crate::user::cached_config()?,
+            user_groups: group_memberships(&user_cfg),
+            user_cfg,
             acl_tree: crate::acl::cached_config()?,
+            custom_roles: crate::role::custom_role_privs(&role_cfg),
         });
 
         let mut cache = CACHED_CONFIG.write().unwrap();
@@ -71,8 +78,10 @@ impl CachedUserInfo {
     #[doc(hidden)]
     pub fn test_new(user_cfg: SectionConfigData, acl_tree: AclTree) -> Self {
         Self {
+            user_groups: group_memberships(&user_cfg),
             user_cfg: Arc::new(user_cfg),
             acl_tree: Arc::new(acl_tree),
+            custom_roles: HashMap::new(),
         }
     }
 
@@ -107,6 +116,21 @@ impl CachedUserInfo {
         true
     }
 
+    /// Test if `auth_id` is an API token restricted to appending new backups.
+    pub fn is_append_only(&self, auth_id: &Authid) -> bool {
+        if !auth_id.is_token() {
+            return false;
+        }
+        match self
+            .user_cfg
+            .lookup::<ApiToken>("token", &auth_id.to_string())
+        {
+            Ok(info) => info.is_append_only(),
+            // be restrictive if the token cannot be looked up
+            Err(_) => true,
+        }
+    }
+
     pub fn check_privs(
         &self,
         auth_id: &Authid,
@@ -141,8 +165,24 @@ impl CachedUserInfo {
         !auth_id.is_token() && auth_id.user() == "root@pam"
     }
 
-    pub fn is_group_member(&self, _userid: &Userid, _group: &str) -> bool {
-        false
+    pub fn is_group_member(&self, userid: &Userid, group: &str) -> bool {
+        self.groups(userid).iter().any(|g| g == group)
+    }
+
+    /// Returns the groups `userid` is a member of.
+    pub fn groups(&self, userid: &Userid) -> &[String] {
+        self.user_groups
+            .get(userid)
+            .map(|groups| groups.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the privileges of a built-in or custom role.
+    fn role_privs(&self, role: &str) -> Option<u64> {
+        match ROLE_NAMES.get(role) {
+            Some((privs, _)) => Some(*privs),
+            None => self.custom_roles.get(role).copied(),
+        }
     }
 
     pub fn lookup_privs(&self, auth_id: &Authid, path: &[&str]) -> u64 {
@@ -155,11 +195,13 @@ impl CachedUserInfo {
             return (ROLE_ADMIN, ROLE_ADMIN);
         }
 
-        let roles = self.acl_tree.roles(auth_id, path);
+        let roles = self
+            .acl_tree
+            .roles(auth_id, self.groups(auth_id.user()), path);
         let mut privs: u64 = 0;
         let mut propagated_privs: u64 = 0;
         for (role, propagate) in roles {
-            if let Some((role_privs, _)) = ROLE_NAMES.get(role.as_str()) {
+            if let Some(role_privs) = self.role_privs(&role) {
                 if propagate {
                     propagated_privs |= role_privs;
                 }
@@ -193,7 +235,9 @@ impl CachedUserInfo {
         }
 
         // get all sub-paths with roles defined for `auth_id`
-        let paths = self.acl_tree.get_child_paths(auth_id, path)?;
+        let paths = self
+            .acl_tree
+            .get_child_paths(auth_id, self.groups(auth_id.user()), path)?;
 
         for path in paths.iter() {
             // early return if any sub-path has any of the privs we are looking for
@@ -207,13 +251,32 @@ impl CachedUserInfo {
     }
 }
 
+/// Map users to the groups they are a member of.
+fn group_memberships(user_cfg: &SectionConfigData) -> HashMap<Userid, Vec<String>> {
+    let mut map: HashMap<Userid, Vec<String>> = HashMap::new();
+
+    let groups: Vec<Group> = user_cfg.convert_to_typed_array("group").unwrap_or_default();
+    for group in groups {
+        for member in group.members.iter().flatten() {
+            map.entry(member.clone())
+                .or_default()
+                .push(group.groupid.clone());
+        }
+    }
+
+    map
+}
+
 impl UserInformation for CachedUserInfo {
     fn is_superuser(&self, userid: &str) -> bool {
         userid == "root@pam"
     }
 
-    fn is_group_member(&self, _userid: &str, _group: &str) -> bool {
-        false
+    fn is_group_member(&self, userid: &str, group: &str) -> bool {
+        match userid.parse::<Userid>() {
+            Ok(userid) => Self::is_group_member(self, &userid, group),
+            Err(_) => false,
+        }
     }
 
     fn lookup_privs(&self, auth_id: &str, path: &[&str]) -> u64 {
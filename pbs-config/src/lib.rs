@@ -7,8 +7,12 @@ pub mod drive;
 pub mod media_pool;
 pub mod metrics;
 pub mod network;
+pub mod notifications;
 pub mod prune;
+pub mod quota;
 pub mod remote;
+pub mod role;
+pub mod s3;
 pub mod sync;
 pub mod tape_job;
 pub mod token_shadow;
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use lazy_static::lazy_static;
+
+use proxmox_schema::*;
+use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
+
+use pbs_api_types::{
+    GotifyConfig, MatcherConfig, SmtpConfig, WebhookConfig, NOTIFICATION_ID_SCHEMA,
+};
+
+use crate::{open_backup_lockfile, BackupLockGuard};
+
+lazy_static! {
+    pub static ref CONFIG: SectionConfig = init();
+}
+
+fn init() -> SectionConfig {
+    let mut config = SectionConfig::new(&NOTIFICATION_ID_SCHEMA);
+
+    const SMTP_SCHEMA: &ObjectSchema = SmtpConfig::API_SCHEMA.unwrap_object_schema();
+    let smtp_plugin =
+        SectionConfigPlugin::new("smtp".to_string(), Some("name".to_string()), SMTP_SCHEMA);
+    config.register_plugin(smtp_plugin);
+
+    const GOTIFY_SCHEMA: &ObjectSchema = GotifyConfig::API_SCHEMA.unwrap_object_schema();
+    let gotify_plugin = SectionConfigPlugin::new(
+        "gotify".to_string(),
+        Some("name".to_string()),
+        GOTIFY_SCHEMA,
+    );
+    config.register_plugin(gotify_plugin);
+
+    const WEBHOOK_SCHEMA: &ObjectSchema = WebhookConfig::API_SCHEMA.unwrap_object_schema();
+    let webhook_plugin = SectionConfigPlugin::new(
+        "webhook".to_string(),
+        Some("name".to_string()),
+        WEBHOOK_SCHEMA,
+    );
+    config.register_plugin(webhook_plugin);
+
+    const MATCHER_SCHEMA: &ObjectSchema = MatcherConfig::API_SCHEMA.unwrap_object_schema();
+    let matcher_plugin = SectionConfigPlugin::new(
+        "matcher".to_string(),
+        Some("name".to_string()),
+        MATCHER_SCHEMA,
+    );
+    config.register_plugin(matcher_plugin);
+
+    config
+}
+
+pub const NOTIFICATION_CFG_FILENAME: &str = "/etc/proxmox-backup/notifications.cfg";
+pub const NOTIFICATION_CFG_LOCKFILE: &str = "/etc/proxmox-backup/.notifications.lck";
+
+/// Section types which are notification targets (everything but matchers).
+pub const NOTIFICATION_TARGET_TYPES: &[&str] = &["smtp", "gotify", "webhook"];
+
+/// Get exclusive lock
+pub fn lock_config() -> Result<BackupLockGuard, Error> {
+    open_backup_lockfile(NOTIFICATION_CFG_LOCKFILE, None, true)
+}
+
+pub fn config() -> Result<(SectionConfigData, [u8; 32]), Error> {
+    let content =
+        proxmox_sys::fs::file_read_optional_string(NOTIFICATION_CFG_FILENAME)?.unwrap_or_default();
+
+    let digest = openssl::sha::sha256(content.as_bytes());
+    let data = CONFIG.parse(NOTIFICATION_CFG_FILENAME, &content)?;
+    Ok((data, digest))
+}
+
+pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
+    let raw = CONFIG.write(NOTIFICATION_CFG_FILENAME, config)?;
+    crate::replace_backup_config(NOTIFICATION_CFG_FILENAME, raw.as_bytes())
+}
+
+// shell completion helper
+pub fn complete_target_name(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    match config() {
+        Ok((data, _digest)) => data
+            .sections
+            .iter()
+            .filter(|(_, (ty, _))| NOTIFICATION_TARGET_TYPES.contains(&ty.as_str()))
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// shell completion helper
+pub fn complete_matcher_name(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    match config() {
+        Ok((data, _digest)) => data
+            .sections
+            .iter()
+            .filter(|(_, (ty, _))| ty == "matcher")
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
@@ -0,0 +1,91 @@
+//! Datastore storage quotas
+use std::collections::HashMap;
+
+use anyhow::Error;
+use lazy_static::lazy_static;
+
+use proxmox_schema::*;
+use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
+
+use pbs_api_types::{DatastoreQuota, QUOTA_ID_SCHEMA};
+
+use crate::{open_backup_lockfile, replace_backup_config, BackupLockGuard};
+
+lazy_static! {
+    pub static ref CONFIG: SectionConfig = init();
+}
+
+fn init() -> SectionConfig {
+    const OBJ_SCHEMA: &ObjectSchema = DatastoreQuota::API_SCHEMA.unwrap_object_schema();
+
+    let plugin =
+        SectionConfigPlugin::new("quota".to_string(), Some(String::from("id")), OBJ_SCHEMA);
+    let mut config = SectionConfig::new(&QUOTA_ID_SCHEMA);
+    config.register_plugin(plugin);
+
+    config
+}
+
+pub const QUOTA_CFG_FILENAME: &str = "/etc/proxmox-backup/quota.cfg";
+pub const QUOTA_CFG_LOCKFILE: &str = "/etc/proxmox-backup/.quota.lck";
+
+/// Get exclusive lock
+pub fn lock_config() -> Result<BackupLockGuard, Error> {
+    open_backup_lockfile(QUOTA_CFG_LOCKFILE, None, true)
+}
+
+pub fn config() -> Result<(SectionConfigData, [u8; 32]), Error> {
+    let content = proxmox_sys::fs::file_read_optional_string(QUOTA_CFG_FILENAME)?;
+    let content = content.unwrap_or_default();
+
+    let digest = openssl::sha::sha256(content.as_bytes());
+    let data = CONFIG.parse(QUOTA_CFG_FILENAME, &content)?;
+
+    Ok((data, digest))
+}
+
+pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
+    let raw = CONFIG.write(QUOTA_CFG_FILENAME, config)?;
+    replace_backup_config(QUOTA_CFG_FILENAME, raw.as_bytes())
+}
+
+// shell completion helper
+pub fn complete_quota_id(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    match config() {
+        Ok((data, _digest)) => data.sections.keys().map(|id| id.to_string()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::{Authid, BackupNamespace};
+
+    #[test]
+    fn test_quota_config() -> Result<(), Error> {
+        let content = "quota: team-a
+ store store1
+ ns team-a
+ owner team@pbs
+ logical-limit 10 TiB
+ soft-limit 90
+";
+        let data = CONFIG.parse(QUOTA_CFG_FILENAME, content)?;
+        let quota: DatastoreQuota = data.lookup("quota", "team-a")?;
+
+        let owner: Authid = "team@pbs".parse()?;
+        let token: Authid = "team@pbs!backup".parse()?;
+        let other: Authid = "other@pbs".parse()?;
+        let ns: BackupNamespace = "team-a/vm".parse()?;
+
+        assert!(quota.covers("store1", &ns, &owner));
+        assert!(quota.covers("store1", &ns, &token));
+        assert!(!quota.covers("store1", &ns, &other));
+        assert!(!quota.covers("store2", &ns, &owner));
+        assert!(!quota.covers("store1", &BackupNamespace::root(), &owner));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Custom roles, composed from the built-in privileges
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Error};
+use lazy_static::lazy_static;
+
+use proxmox_schema::{ApiType, Schema};
+use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
+
+use pbs_api_types::{RoleConfig, ROLE_ID_SCHEMA};
+
+use crate::acl::ROLE_NAMES;
+use crate::ConfigVersionCache;
+use crate::{open_backup_lockfile, replace_backup_config, BackupLockGuard};
+
+lazy_static! {
+    /// Static [`SectionConfig`] to access parser/writer functions.
+    pub static ref CONFIG: SectionConfig = init();
+}
+
+fn init() -> SectionConfig {
+    let obj_schema = match RoleConfig::API_SCHEMA {
+        Schema::Object(ref obj_schema) => obj_schema,
+        _ => unreachable!(),
+    };
+
+    let plugin =
+        SectionConfigPlugin::new("role".to_string(), Some("roleid".to_string()), obj_schema);
+    let mut config = SectionConfig::new(&ROLE_ID_SCHEMA);
+    config.register_plugin(plugin);
+
+    config
+}
+
+/// Configuration file name
+pub const ROLE_CFG_FILENAME: &str = "/etc/proxmox-backup/role.cfg";
+/// Lock file name (used to prevent concurrent access)
+pub const ROLE_CFG_LOCKFILE: &str = "/etc/proxmox-backup/.role.lck";
+
+/// Get exclusive lock
+pub fn lock_config() -> Result<BackupLockGuard, Error> {
+    open_backup_lockfile(ROLE_CFG_LOCKFILE, None, true)
+}
+
+/// Read and parse the configuration file
+pub fn config() -> Result<(SectionConfigData, [u8; 32]), Error> {
+    let content =
+        proxmox_sys::fs::file_read_optional_string(ROLE_CFG_FILENAME)?.unwrap_or_default();
+
+    let digest = openssl::sha::sha256(content.as_bytes());
+    let data = CONFIG.parse(ROLE_CFG_FILENAME, &content)?;
+    Ok((data, digest))
+}
+
+/// Save the configuration file
+pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
+    let raw = CONFIG.write(ROLE_CFG_FILENAME, config)?;
+    replace_backup_config(ROLE_CFG_FILENAME, raw.as_bytes())?;
+
+    // roles are resolved by the CachedUserInfo, so invalidate it
+    let version_cache = ConfigVersionCache::new()?;
+    version_cache.increase_user_cache_generation();
+
+    Ok(())
+}
+
+/// Returns the privileges of all custom roles in `config`.
+///
+/// Roles with invalid privilege lists are skipped.
+pub fn custom_role_privs(config: &SectionConfigData) -> HashMap<String, u64> {
+    let mut map = HashMap::new();
+
+    for (roleid, (section_type, data)) in config.sections.iter() {
+        if section_type != "role" {
+            continue;
+        }
+        if let Ok(role) = serde_json::from_value::<RoleConfig>(data.clone()) {
+            if let Ok(privs) = role.privileges() {
+                map.insert(roleid.clone(), privs);
+            }
+        }
+    }
+
+    map
+}
+
+/// Returns the IDs of all custom roles in `config`.
+pub fn custom_role_ids(config: &SectionConfigData) -> HashSet<String> {
+    config
+        .sections
+        .iter()
+        .filter(|(_, (section_type, _))| section_type == "role")
+        .map(|(roleid, _)| roleid.clone())
+        .collect()
+}
+
+/// Check whether `roleid` names a built-in or an existing custom role.
+pub fn check_role_exists(roleid: &str) -> Result<(), Error> {
+    if ROLE_NAMES.contains_key(roleid) {
+        return Ok(());
+    }
+
+    let (config, _digest) = config()?;
+    if config.lookup::<RoleConfig>("role", roleid).is_err() {
+        bail!("no such role '{roleid}'");
+    }
+
+    Ok(())
+}
+
+// shell completion helper
+pub fn complete_role_id(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    match config() {
+        Ok((data, _digest)) => data.sections.keys().map(|id| id.to_string()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// shell completion helper, includes the built-in roles
+pub fn complete_any_role_id(arg: &str, param: &HashMap<String, String>) -> Vec<String> {
+    let mut list: Vec<String> = ROLE_NAMES.keys().map(|role| role.to_string()).collect();
+    list.extend(complete_role_id(arg, param));
+    list
+}
@@ -7,7 +7,7 @@ use lazy_static::lazy_static;
 use proxmox_schema::*;
 use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
 
-use pbs_api_types::{ApiToken, Authid, User, Userid};
+use pbs_api_types::{ApiToken, Authid, Group, User, Userid};
 
 use crate::ConfigVersionCache;
 
@@ -39,6 +39,17 @@ fn init() -> SectionConfig {
     );
     config.register_plugin(token_plugin);
 
+    let group_schema = match Group::API_SCHEMA {
+        Schema::Object(ref group_schema) => group_schema,
+        _ => unreachable!(),
+    };
+    let group_plugin = SectionConfigPlugin::new(
+        "group".to_string(),
+        Some("groupid".to_string()),
+        group_schema,
+    );
+    config.register_plugin(group_plugin);
+
     config
 }
 
@@ -161,10 +172,58 @@ pub fn complete_userid(_arg: &str, _param: &HashMap<String, String>) -> Vec<Stri
     }
 }
 
+/// Returns the groups `userid` is a member of.
+pub fn user_groups(config: &SectionConfigData, userid: &Userid) -> Result<Vec<String>, Error> {
+    Ok(config
+        .convert_to_typed_array::<Group>("group")?
+        .into_iter()
+        .filter(|group| group.is_member(userid))
+        .map(|group| group.groupid)
+        .collect())
+}
+
+/// Removes `userid` from all groups, returns whether any group was changed.
+pub fn remove_user_from_groups(
+    config: &mut SectionConfigData,
+    userid: &Userid,
+) -> Result<bool, Error> {
+    let mut changed = false;
+    for mut group in config.convert_to_typed_array::<Group>("group")? {
+        if group.remove_member(userid) {
+            config.set_data(&group.groupid, "group", &group)?;
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+// shell completion helper
+pub fn complete_group_id(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    match config() {
+        Ok((data, _digest)) => data
+            .sections
+            .iter()
+            .filter_map(|(id, (section_type, _))| {
+                if section_type == "group" {
+                    Some(id.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 // shell completion helper
 pub fn complete_authid(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
     match config() {
-        Ok((data, _digest)) => data.sections.keys().map(|id| id.to_string()).collect(),
+        Ok((data, _digest)) => data
+            .sections
+            .iter()
+            .filter(|(_, (section_type, _))| section_type != "group")
+            .map(|(id, _)| id.to_string())
+            .collect(),
         Err(_) => vec![],
     }
 }
@@ -5,7 +5,7 @@ use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
 
-use proxmox_sys::fs::{lock_dir_noblock, replace_file, CreateOptions};
+use proxmox_sys::fs::{file_read_optional_string, lock_dir_noblock, replace_file, CreateOptions};
 
 use pbs_api_types::{
     Authid, BackupNamespace, BackupType, GroupFilter, BACKUP_DATE_REGEX, BACKUP_FILE_REGEX,
@@ -17,6 +17,15 @@ use crate::manifest::{
 };
 use crate::{DataBlob, DataStore};
 
+/// Name of the file holding the retention lock of a snapshot.
+pub const RETENTION_LOCK_FILE_NAME: &str = ".retention-lock";
+
+/// Name of the file marking a snapshot which a sync job has only partially pulled yet.
+pub const PULL_PROGRESS_FILE_NAME: &str = ".pull-progress";
+
+/// Name of the file recording the source of a group which a sync job pulled under another name.
+pub const SYNC_SOURCE_FILE_NAME: &str = ".sync-source";
+
 /// BackupGroup is a directory containing a list of BackupDir
 #[derive(Clone)]
 pub struct BackupGroup {
@@ -83,6 +92,12 @@ impl BackupGroup {
         self.full_group_path().exists()
     }
 
+    pub fn sync_source_file(&self) -> PathBuf {
+        let mut path = self.full_group_path();
+        path.push(SYNC_SOURCE_FILE_NAME);
+        path
+    }
+
     pub fn list_backups(&self) -> Result<Vec<BackupInfo>, Error> {
         let mut list = vec![];
 
@@ -218,6 +233,7 @@ impl BackupGroup {
             std::fs::remove_dir_all(&path).map_err(|err| {
                 format_err!("removing group directory {:?} failed - {}", path, err)
             })?;
+            self.store.remove_path_from_backend(&path)?;
         }
 
         Ok(removed_all_snaps)
@@ -392,9 +408,54 @@ impl BackupDir {
         path
     }
 
+    /// Returns true if the snapshot is protected or its retention lock has not expired yet.
     pub fn is_protected(&self) -> bool {
         let path = self.protected_file();
-        path.exists()
+        path.exists() || self.is_retention_locked()
+    }
+
+    pub fn retention_lock_file(&self) -> PathBuf {
+        let mut path = self.full_path();
+        path.push(RETENTION_LOCK_FILE_NAME);
+        path
+    }
+
+    pub fn pull_progress_file(&self) -> PathBuf {
+        let mut path = self.full_path();
+        path.push(PULL_PROGRESS_FILE_NAME);
+        path
+    }
+
+    /// Returns true if a sync job has only partially pulled the snapshot and will resume it.
+    pub fn is_partially_pulled(&self) -> bool {
+        self.pull_progress_file().exists()
+    }
+
+    /// Returns the time until which the snapshot is locked, expired locks are returned as well.
+    pub fn retention_locked_until(&self) -> Result<Option<i64>, Error> {
+        let path = self.retention_lock_file();
+        match file_read_optional_string(&path)? {
+            Some(data) => data
+                .trim()
+                .parse::<i64>()
+                .map(Some)
+                .map_err(|err| format_err!("unable to parse retention lock {path:?} - {err}")),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns true if the retention lock has not expired yet.
+    ///
+    /// An unreadable lock counts as active, so that it can never be bypassed by corrupting it.
+    pub fn is_retention_locked(&self) -> bool {
+        match self.retention_locked_until() {
+            Ok(Some(locked_until)) => locked_until > proxmox_time::epoch_i64(),
+            Ok(None) => false,
+            Err(err) => {
+                log::error!("{err}");
+                true
+            }
+        }
     }
 
     pub fn backup_time_to_string(backup_time: i64) -> Result<String, Error> {
@@ -450,6 +511,9 @@ impl BackupDir {
             _manifest_guard = self.lock_manifest()?;
         }
 
+        if self.is_retention_locked() {
+            bail!("cannot remove snapshot with active retention lock");
+        }
         if self.is_protected() {
             bail!("cannot remove protected snapshot"); // use special error type?
         }
@@ -458,6 +522,7 @@ impl BackupDir {
         std::fs::remove_dir_all(&full_path).map_err(|err| {
             format_err!("removing backup snapshot {:?} failed - {}", full_path, err,)
         })?;
+        self.store.remove_path_from_backend(&full_path)?;
 
         // the manifest doesn't exist anymore, no need to keep the lock (already done by guard?)
         if let Ok(path) = self.manifest_lock_path() {
@@ -497,6 +562,17 @@ impl BackupDir {
     pub fn update_manifest(
         &self,
         update_fn: impl FnOnce(&mut BackupManifest),
+    ) -> Result<(), Error> {
+        self.update_local_manifest(update_fn)?;
+        self.store
+            .upload_file_to_backend(&self.full_path().join(MANIFEST_BLOB_NAME))
+    }
+
+    /// Like [`update_manifest`](Self::update_manifest), but without uploading the manifest to
+    /// the backend, for callers which upload the whole snapshot afterwards.
+    pub fn update_local_manifest(
+        &self,
+        update_fn: impl FnOnce(&mut BackupManifest),
     ) -> Result<(), Error> {
         let _guard = self.lock_manifest()?;
         let (mut manifest, _) = self.load_manifest()?;
@@ -523,6 +599,7 @@ impl BackupDir {
         let mut wanted_files = std::collections::HashSet::new();
         wanted_files.insert(MANIFEST_BLOB_NAME.to_string());
         wanted_files.insert(CLIENT_LOG_BLOB_NAME.to_string());
+        wanted_files.insert(RETENTION_LOCK_FILE_NAME.to_string());
         manifest.files().iter().for_each(|item| {
             wanted_files.insert(item.filename.clone());
         });
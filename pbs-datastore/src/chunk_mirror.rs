@@ -0,0 +1,208 @@
+//! Mirrored chunk copies
+//!
+//! A datastore can keep a second copy of every chunk in another directory, ideally on a
+//! different disk. The copies use the same `.chunks/<prefix>/<digest>` layout as the chunk store.
+//! They are only read when verification or scrubbing finds a corrupt chunk, which then gets
+//! replaced by its copy.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+use hex::FromHex;
+
+use pbs_api_types::DatastoreFSyncLevel;
+use proxmox_sys::fs::{create_path, replace_file, CreateOptions};
+use proxmox_sys::WorkerTaskContext;
+
+use crate::{ChunkStore, DataBlob};
+
+/// Second copy of the chunks of a chunk store.
+pub struct ChunkMirror {
+    chunk_dir: PathBuf,
+    sync_level: DatastoreFSyncLevel,
+}
+
+impl ChunkMirror {
+    fn chunk_dir(path: &Path) -> Result<PathBuf, Error> {
+        if !path.is_absolute() {
+            bail!("expected absolute path for chunk mirror - got {path:?}");
+        }
+        Ok(path.join(".chunks"))
+    }
+
+    /// Create the chunk directory of a new mirror below `path`.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        uid: nix::unistd::Uid,
+        gid: nix::unistd::Gid,
+    ) -> Result<(), Error> {
+        let chunk_dir = Self::chunk_dir(path.as_ref())?;
+        let options = CreateOptions::new().owner(uid).group(gid);
+
+        create_path(&chunk_dir, None, Some(options))
+            .map_err(|err| format_err!("unable to create chunk mirror {chunk_dir:?} - {err}"))?;
+
+        Ok(())
+    }
+
+    /// Open an existing mirror below `path`.
+    pub fn open<P: AsRef<Path>>(path: P, sync_level: DatastoreFSyncLevel) -> Result<Self, Error> {
+        let chunk_dir = Self::chunk_dir(path.as_ref())?;
+
+        if let Err(err) = std::fs::metadata(&chunk_dir) {
+            bail!("unable to open chunk mirror {chunk_dir:?} - {err}");
+        }
+
+        Ok(Self {
+            chunk_dir,
+            sync_level,
+        })
+    }
+
+    pub fn chunk_path(&self, digest: &[u8; 32]) -> PathBuf {
+        let digest_str = hex::encode(digest);
+        self.chunk_dir.join(&digest_str[..4]).join(digest_str)
+    }
+
+    /// Store a copy of the chunk, does nothing if there is one already.
+    ///
+    /// The caller must hold the chunk store mutex.
+    pub fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(), Error> {
+        if self.chunk_path(digest).exists() {
+            return Ok(());
+        }
+        self.replace_chunk(chunk, digest)
+    }
+
+    /// Store a copy of the chunk, overwriting an existing one.
+    ///
+    /// The caller must hold the chunk store mutex.
+    pub fn replace_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(), Error> {
+        let path = self.chunk_path(digest);
+
+        // prefix directories are only created once they are needed
+        let prefix_dir = path
+            .parent()
+            .ok_or_else(|| format_err!("unable to get chunk mirror dir"))?;
+        if let Err(err) = std::fs::create_dir(prefix_dir) {
+            if err.kind() != std::io::ErrorKind::AlreadyExists {
+                bail!("unable to create chunk mirror dir {prefix_dir:?} - {err}");
+            }
+        }
+
+        replace_file(
+            &path,
+            chunk.raw_data(),
+            CreateOptions::new(),
+            self.sync_level != DatastoreFSyncLevel::None,
+        )
+        .map_err(|err| format_err!("writing chunk copy {path:?} failed - {err}"))
+    }
+
+    /// Load the copy of a chunk, verifying its CRC.
+    pub fn load_chunk(&self, digest: &[u8; 32]) -> Result<Option<DataBlob>, Error> {
+        let path = self.chunk_path(digest);
+
+        let mut file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("unable to open chunk copy {path:?} - {err}"),
+        };
+
+        DataBlob::load_from_reader(&mut file)
+            .map(Some)
+            .map_err(|err| format_err!("unable to load chunk copy {path:?} - {err}"))
+    }
+
+    /// Remove copies of chunks which are not in `chunk_store` anymore, e.g. because garbage
+    /// collection removed them. Returns the number of removed copies.
+    pub fn sweep_removed_chunks(
+        &self,
+        chunk_store: &ChunkStore,
+        prefixes: Range<usize>,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<usize, Error> {
+        let mut removed = 0;
+
+        for prefix in prefixes.start..prefixes.end.min(0x10000) {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let prefix_dir = self.chunk_dir.join(format!("{prefix:04x}"));
+            let entries = match std::fs::read_dir(&prefix_dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => bail!("unable to read chunk mirror dir {prefix_dir:?} - {err}"),
+            };
+
+            for entry in entries {
+                let entry = entry?;
+                let digest = match entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| <[u8; 32]>::from_hex(name).ok())
+                {
+                    Some(digest) => digest,
+                    None => continue, // not a chunk, e.g. a leftover temporary file
+                };
+
+                let _lock = chunk_store.mutex().lock();
+
+                let (chunk_path, _digest_str) = chunk_store.chunk_path(&digest);
+                if chunk_path.exists() {
+                    continue;
+                }
+
+                match std::fs::remove_file(entry.path()) {
+                    Ok(()) => removed += 1,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(err) => bail!("unable to remove chunk copy {:?} - {err}", entry.path()),
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+#[test]
+fn test_chunk_mirror() {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-mirror");
+
+    if let Err(_e) = std::fs::remove_dir_all(".testdir-mirror") { /* ignore */ }
+
+    assert!(ChunkMirror::open(&path, DatastoreFSyncLevel::None).is_err());
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .unwrap()
+        .unwrap();
+    ChunkMirror::create(&path, user.uid, user.gid).unwrap();
+    let mirror = ChunkMirror::open(&path, DatastoreFSyncLevel::None).unwrap();
+
+    let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(&[0u8, 1u8])
+        .build()
+        .unwrap();
+
+    assert!(mirror.load_chunk(&digest).unwrap().is_none());
+
+    mirror.insert_chunk(&chunk, &digest).unwrap();
+    let copy = mirror.load_chunk(&digest).unwrap().unwrap();
+    assert_eq!(copy.raw_data(), chunk.raw_data());
+
+    // a corrupt copy fails the CRC check
+    let mut data = chunk.raw_data().to_vec();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    std::fs::write(mirror.chunk_path(&digest), &data).unwrap();
+    assert!(mirror.load_chunk(&digest).is_err());
+
+    // insert keeps the existing copy, replace overwrites it
+    mirror.insert_chunk(&chunk, &digest).unwrap();
+    assert!(mirror.load_chunk(&digest).is_err());
+    mirror.replace_chunk(&chunk, &digest).unwrap();
+    assert!(mirror.load_chunk(&digest).unwrap().is_some());
+
+    if let Err(_e) = std::fs::remove_dir_all(".testdir-mirror") { /* ignore */ }
+}
@@ -1,8 +1,11 @@
+use std::ops::Range;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, format_err, Error};
+use hex::FromHex;
 
 use pbs_api_types::{DatastoreFSyncLevel, GarbageCollectionStatus};
 use proxmox_io::ReadExt;
@@ -14,8 +17,10 @@ use proxmox_sys::task_log;
 use proxmox_sys::WorkerTaskContext;
 
 use crate::file_formats::{
-    COMPRESSED_BLOB_MAGIC_1_0, ENCRYPTED_BLOB_MAGIC_1_0, UNCOMPRESSED_BLOB_MAGIC_1_0,
+    COMPRESSED_BLOB_MAGIC_1_0, COMPRESSED_BLOB_MAGIC_2_0, ENCRYPTED_BLOB_MAGIC_1_0,
+    UNCOMPRESSED_BLOB_MAGIC_1_0,
 };
+use crate::live_chunks::LiveChunkList;
 use crate::DataBlob;
 
 /// File system based chunk store
@@ -26,6 +31,8 @@ pub struct ChunkStore {
     mutex: Mutex<()>,
     locker: Option<Arc<Mutex<ProcessLocker>>>,
     sync_level: DatastoreFSyncLevel,
+    // update the modification time when touching chunks, see `set_touch_mtime`
+    touch_mtime: AtomicBool,
 }
 
 // TODO: what about sysctl setting vm.vfs_cache_pressure (0 - 100) ?
@@ -73,6 +80,7 @@ impl ChunkStore {
             mutex: Mutex::new(()),
             locker: None,
             sync_level: Default::default(),
+            touch_mtime: AtomicBool::new(false),
         }
     }
 
@@ -192,9 +200,18 @@ impl ChunkStore {
             locker: Some(locker),
             mutex: Mutex::new(()),
             sync_level,
+            touch_mtime: AtomicBool::new(false),
         })
     }
 
+    /// Set whether touching a chunk also updates its modification time.
+    ///
+    /// GC in digest-list mode uses it to detect chunks touched by active writers, as it is not
+    /// affected by noatime/relatime. Otherwise only the access time is updated.
+    pub fn set_touch_mtime(&self, touch_mtime: bool) {
+        self.touch_mtime.store(touch_mtime, Ordering::Relaxed);
+    }
+
     pub fn touch_chunk(&self, digest: &[u8; 32]) -> Result<(), Error> {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -224,10 +241,14 @@ impl ChunkStore {
                 tv_sec: 0,
                 tv_nsec: UTIME_NOW,
             },
-            // modification time -> keep as is
+            // modification time -> keep as is, unless used by GC in digest-list mode
             libc::timespec {
                 tv_sec: 0,
-                tv_nsec: UTIME_OMIT,
+                tv_nsec: if self.touch_mtime.load(Ordering::Relaxed) {
+                    UTIME_NOW
+                } else {
+                    UTIME_OMIT
+                },
             },
         ];
 
@@ -254,6 +275,20 @@ impl ChunkStore {
         impl Iterator<Item = (Result<proxmox_sys::fs::ReadDirEntry, Error>, usize, bool)>
             + std::iter::FusedIterator,
         Error,
+    > {
+        self.get_chunk_iterator_range(0..0x10000)
+    }
+
+    /// Iterate over the chunks in the given range of prefix directories.
+    ///
+    /// The reported percentage is relative to the range.
+    pub fn get_chunk_iterator_range(
+        &self,
+        prefixes: Range<usize>,
+    ) -> Result<
+        impl Iterator<Item = (Result<proxmox_sys::fs::ReadDirEntry, Error>, usize, bool)>
+            + std::iter::FusedIterator,
+        Error,
     > {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -273,7 +308,9 @@ impl ChunkStore {
 
         let mut done = false;
         let mut inner: Option<proxmox_sys::fs::ReadDir> = None;
-        let mut at = 0;
+        let Range { start, end } = prefixes;
+        let end = end.min(0x10000);
+        let mut at = start;
         let mut percentage = 0;
         Ok(std::iter::from_fn(move || {
             if done {
@@ -308,13 +345,13 @@ impl ChunkStore {
 
                 inner = None;
 
-                if at == 0x10000 {
+                if at >= end {
                     done = true;
                     return None;
                 }
 
                 let subdir: &str = &format!("{:04x}", at);
-                percentage = (at * 100) / 0x10000;
+                percentage = ((at - start) * 100) / (end - start);
                 at += 1;
                 match proxmox_sys::fs::read_subdir(base_handle.as_raw_fd(), subdir) {
                     Ok(dir) => {
@@ -347,11 +384,40 @@ impl ChunkStore {
         ProcessLocker::oldest_shared_lock(self.locker.clone().unwrap())
     }
 
+    /// Returns the access time cutoff, chunks not touched since are considered unused by GC.
+    pub fn gc_min_atime(oldest_writer: i64, phase1_start_time: i64) -> i64 {
+        let mut min_atime = phase1_start_time - 3600 * 24; // at least 24h (see mount option relatime)
+
+        if oldest_writer < min_atime {
+            min_atime = oldest_writer;
+        }
+
+        min_atime - 300 // add 5 mins gap for safety
+    }
+
+    /// Returns the modification time cutoff used by GC in digest-list mode, chunks not in the
+    /// live chunk list and not touched since are considered unused.
+    pub fn gc_min_mtime(oldest_writer: i64, phase1_start_time: i64) -> i64 {
+        phase1_start_time.min(oldest_writer) - 300 // add 5 mins gap for safety
+    }
+
+    /// Remove unused chunks in the given range of prefix directories.
+    ///
+    /// Without `live_chunks`, all chunks with an access time older than the cutoff are removed.
+    /// Otherwise, all chunks which are not in the list and were not modified (touched) after
+    /// the oldest writer started are removed.
+    ///
+    /// `checkpoint` is called regularly with the first prefix directory which was not completely
+    /// swept yet, allowing to resume an interrupted sweep from there.
+    #[allow(clippy::too_many_arguments)]
     pub fn sweep_unused_chunks(
         &self,
         oldest_writer: i64,
         phase1_start_time: i64,
+        prefixes: Range<usize>,
+        mut live_chunks: Option<&mut LiveChunkList>,
         status: &mut GarbageCollectionStatus,
+        checkpoint: &mut dyn FnMut(usize, &GarbageCollectionStatus) -> Result<(), Error>,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
         // unwrap: only `None` in unit tests
@@ -360,23 +426,13 @@ impl ChunkStore {
         use nix::sys::stat::fstatat;
         use nix::unistd::{unlinkat, UnlinkatFlags};
 
-        let mut min_atime = phase1_start_time - 3600 * 24; // at least 24h (see mount option relatime)
-
-        if oldest_writer < min_atime {
-            min_atime = oldest_writer;
-        }
-
-        min_atime -= 300; // add 5 mins gap for safety
+        let min_atime = Self::gc_min_atime(oldest_writer, phase1_start_time);
+        let min_mtime = Self::gc_min_mtime(oldest_writer, phase1_start_time);
 
         let mut last_percentage = 0;
         let mut chunk_count = 0;
 
-        for (entry, percentage, bad) in self.get_chunk_iterator()? {
-            if last_percentage != percentage {
-                last_percentage = percentage;
-                task_log!(worker, "processed {}% ({} chunks)", percentage, chunk_count,);
-            }
-
+        for (entry, percentage, bad) in self.get_chunk_iterator_range(prefixes)? {
             worker.check_abort()?;
             worker.fail_on_shutdown()?;
 
@@ -390,6 +446,14 @@ impl ChunkStore {
 
             let filename = entry.file_name();
 
+            if last_percentage != percentage {
+                last_percentage = percentage;
+                task_log!(worker, "processed {}% ({} chunks)", percentage, chunk_count,);
+                // all prefix directories before the current one are done
+                let name = std::str::from_utf8(&filename.to_bytes()[..4])?;
+                checkpoint(usize::from_str_radix(name, 16)?, status)?;
+            }
+
             let lock = self.mutex.lock();
 
             if let Ok(stat) = fstatat(dirfd, filename, nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW) {
@@ -401,7 +465,25 @@ impl ChunkStore {
 
                 chunk_count += 1;
 
-                if stat.st_atime < min_atime {
+                let (unused, pending) = match live_chunks.as_deref_mut() {
+                    Some(live_chunks) => {
+                        let name = filename.to_bytes();
+                        let digest = <[u8; 32]>::from_hex(&name[..64])?;
+                        let mut used = live_chunks.contains(&digest)?;
+                        if used && bad {
+                            // keep bad chunks only as long as the chunk itself is missing
+                            let flags = nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW;
+                            used = fstatat(dirfd, &name[..64], flags).is_err();
+                        }
+                        // writers only update the atime while the store is in atime mode, so
+                        // respect it too in case the mode was switched during a backup
+                        let touched = stat.st_mtime.max(stat.st_atime) >= min_mtime;
+                        (!used && !touched, !used && touched)
+                    }
+                    None => (stat.st_atime < min_atime, stat.st_atime < oldest_writer),
+                };
+
+                if unused {
                     //let age = now - stat.st_atime;
                     //println!("UNLINK {}  {:?}", age/(3600*24), filename);
                     if let Err(err) = unlinkat(Some(dirfd), filename, UnlinkatFlags::NoRemoveDir) {
@@ -419,7 +501,7 @@ impl ChunkStore {
                         status.removed_chunks += 1;
                     }
                     status.removed_bytes += stat.st_size as u64;
-                } else if stat.st_atime < oldest_writer {
+                } else if pending {
                     if bad {
                         status.still_bad += 1;
                     } else {
@@ -471,7 +553,10 @@ impl ChunkStore {
 
                 // going from unencrypted to encrypted can never be right, since the digest
                 // includes data derived from the encryption key
-                if magic == UNCOMPRESSED_BLOB_MAGIC_1_0 || magic == COMPRESSED_BLOB_MAGIC_1_0 {
+                if magic == UNCOMPRESSED_BLOB_MAGIC_1_0
+                    || magic == COMPRESSED_BLOB_MAGIC_1_0
+                    || magic == COMPRESSED_BLOB_MAGIC_2_0
+                {
                     bail!("Overwriting unencrypted chunk '{digest_str}' on store '{name}' with encrypted chunk with same digest not allowed!");
                 }
 
@@ -522,6 +607,55 @@ impl ChunkStore {
         Ok((false, encoded_size))
     }
 
+    /// Insert an empty marker file for a chunk whose data is stored on a remote backend.
+    ///
+    /// The marker takes part in GC like a regular chunk file. Returns true if the marker (or
+    /// chunk) already existed, in which case its atime gets updated.
+    pub fn insert_chunk_marker(&self, digest: &[u8; 32]) -> Result<bool, Error> {
+        // unwrap: only `None` in unit tests
+        assert!(self.locker.is_some());
+
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let _lock = self.mutex.lock();
+
+        if self.cond_touch_path(&chunk_path, false)? {
+            return Ok(true);
+        }
+
+        proxmox_sys::fs::replace_file(&chunk_path, &[], CreateOptions::new(), false).map_err(
+            |err| {
+                format_err!(
+                    "inserting chunk marker on store '{}' failed for {digest_str} - {err}",
+                    self.name,
+                )
+            },
+        )?;
+
+        Ok(false)
+    }
+
+    /// Remove the marker file of a chunk, e.g. if uploading the chunk data failed.
+    pub fn remove_chunk_marker(&self, digest: &[u8; 32]) -> Result<(), Error> {
+        // unwrap: only `None` in unit tests
+        assert!(self.locker.is_some());
+
+        let (chunk_path, _digest_str) = self.chunk_path(digest);
+
+        let _lock = self.mutex.lock();
+
+        match std::fs::remove_file(&chunk_path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => bail!("removing chunk marker {chunk_path:?} failed - {err}"),
+        }
+    }
+
+    /// Mutex serializing chunk insertion and removal.
+    pub(crate) fn mutex(&self) -> &Mutex<()> {
+        &self.mutex
+    }
+
     pub fn chunk_path(&self, digest: &[u8; 32]) -> (PathBuf, String) {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
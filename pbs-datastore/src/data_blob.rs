@@ -1,11 +1,11 @@
 use std::io::Write;
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 use openssl::symm::{decrypt_aead, Mode};
 
 use proxmox_io::{ReadExt, WriteExt};
 
-use pbs_api_types::CryptMode;
+use pbs_api_types::{ChunkCompression, ChunkCompressionAlgorithm, CryptMode};
 use pbs_tools::crypt_config::CryptConfig;
 
 use super::file_formats::*;
@@ -84,89 +84,110 @@ impl DataBlob {
     }
 
     /// Create a DataBlob, optionally compressed and/or encrypted
+    ///
+    /// If `compress` is set, the default compression (zstd level 1) is used.
     pub fn encode(
         data: &[u8],
         config: Option<&CryptConfig>,
         compress: bool,
+    ) -> Result<Self, Error> {
+        let compression = if compress {
+            ChunkCompression::default()
+        } else {
+            ChunkCompression::NONE
+        };
+        Self::encode_with_compression(data, config, compression)
+    }
+
+    /// Create a DataBlob using the given compression, optionally encrypted
+    ///
+    /// Zstd compressed data is stored in the v1.0 format, so that older
+    /// readers can still decode it. Other algorithms use the v2.0 format,
+    /// which records the algorithm in the header.
+    pub fn encode_with_compression(
+        data: &[u8],
+        config: Option<&CryptConfig>,
+        compression: ChunkCompression,
     ) -> Result<Self, Error> {
         if data.len() > MAX_BLOB_SIZE {
             bail!("data blob too large ({} bytes).", data.len());
         }
 
+        // Note: We only use compression if result is shorter
+        let compressed = Self::compress_data(data, compression)?
+            .filter(|(compr_data, _)| compr_data.len() < data.len());
+
+        let (data, compression_head) = match compressed {
+            Some((ref compr_data, compression_head)) => (&compr_data[..], Some(compression_head)),
+            None => (data, None),
+        };
+
         let mut blob = if let Some(config) = config {
-            let compr_data;
-            let (_compress, data, magic) = if compress {
-                compr_data = zstd::bulk::compress(data, 1)?;
-                // Note: We only use compression if result is shorter
-                if compr_data.len() < data.len() {
-                    (true, &compr_data[..], ENCR_COMPR_BLOB_MAGIC_1_0)
-                } else {
-                    (false, data, ENCRYPTED_BLOB_MAGIC_1_0)
-                }
-            } else {
-                (false, data, ENCRYPTED_BLOB_MAGIC_1_0)
+            let (magic, header_len) = match compression_head {
+                None => (
+                    ENCRYPTED_BLOB_MAGIC_1_0,
+                    std::mem::size_of::<EncryptedDataBlobHeader>(),
+                ),
+                Some(None) => (
+                    ENCR_COMPR_BLOB_MAGIC_1_0,
+                    std::mem::size_of::<EncryptedDataBlobHeader>(),
+                ),
+                Some(Some(_)) => (
+                    ENCR_COMPR_BLOB_MAGIC_2_0,
+                    std::mem::size_of::<EncrCompressedDataBlobHeader>(),
+                ),
             };
 
-            let header_len = std::mem::size_of::<EncryptedDataBlobHeader>();
             let mut raw_data = Vec::with_capacity(data.len() + header_len);
-
-            let dummy_head = EncryptedDataBlobHeader {
-                head: DataBlobHeader {
-                    magic: [0u8; 8],
-                    crc: [0; 4],
-                },
-                iv: [0u8; 16],
-                tag: [0u8; 16],
-            };
-            unsafe {
-                raw_data.write_le_value(dummy_head)?;
-            }
+            raw_data.resize(header_len, 0u8);
 
             let (iv, tag) = Self::encrypt_to(config, data, &mut raw_data)?;
 
-            let head = EncryptedDataBlobHeader {
-                head: DataBlobHeader { magic, crc: [0; 4] },
-                iv,
-                tag,
-            };
-
-            unsafe {
-                (&mut raw_data[0..header_len]).write_le_value(head)?;
-            }
-
-            DataBlob { raw_data }
-        } else {
-            let max_data_len = data.len() + std::mem::size_of::<DataBlobHeader>();
-            if compress {
-                let mut comp_data = Vec::with_capacity(max_data_len);
-
-                let head = DataBlobHeader {
-                    magic: COMPRESSED_BLOB_MAGIC_1_0,
-                    crc: [0; 4],
+            let head = DataBlobHeader { magic, crc: [0; 4] };
+            if let Some(Some(compression)) = compression_head {
+                let head = EncrCompressedDataBlobHeader {
+                    head,
+                    compression,
+                    iv,
+                    tag,
                 };
                 unsafe {
-                    comp_data.write_le_value(head)?;
+                    (&mut raw_data[0..header_len]).write_le_value(head)?;
                 }
-
-                zstd::stream::copy_encode(data, &mut comp_data, 1)?;
-
-                if comp_data.len() < max_data_len {
-                    let mut blob = DataBlob {
-                        raw_data: comp_data,
-                    };
-                    blob.set_crc(blob.compute_crc());
-                    return Ok(blob);
+            } else {
+                let head = EncryptedDataBlobHeader { head, iv, tag };
+                unsafe {
+                    (&mut raw_data[0..header_len]).write_le_value(head)?;
                 }
             }
 
-            let mut raw_data = Vec::with_capacity(max_data_len);
-
-            let head = DataBlobHeader {
-                magic: UNCOMPRESSED_BLOB_MAGIC_1_0,
-                crc: [0; 4],
-            };
-            unsafe {
-                raw_data.write_le_value(head)?;
+            DataBlob { raw_data }
+        } else {
+            let mut raw_data =
+                Vec::with_capacity(data.len() + std::mem::size_of::<CompressedDataBlobHeader>());
+
+            match compression_head {
+                None => unsafe {
+                    raw_data.write_le_value(DataBlobHeader {
+                        magic: UNCOMPRESSED_BLOB_MAGIC_1_0,
+                        crc: [0; 4],
+                    })?;
+                },
+                Some(None) => unsafe {
+                    raw_data.write_le_value(DataBlobHeader {
+                        magic: COMPRESSED_BLOB_MAGIC_1_0,
+                        crc: [0; 4],
+                    })?;
+                },
+                Some(Some(compression)) => unsafe {
+                    raw_data.write_le_value(CompressedDataBlobHeader {
+                        head: DataBlobHeader {
+                            magic: COMPRESSED_BLOB_MAGIC_2_0,
+                            crc: [0; 4],
+                        },
+                        compression,
+                    })?;
+                },
             }
             raw_data.extend_from_slice(data);
 
@@ -178,19 +199,85 @@ impl DataBlob {
         Ok(blob)
     }
 
+    /// Re-encode an unencrypted blob using the given compression
+    ///
+    /// The digest is verified while decoding the original data.
+    pub fn recompress(
+        &self,
+        compression: ChunkCompression,
+        digest: &[u8; 32],
+    ) -> Result<Self, Error> {
+        if self.is_encrypted() {
+            bail!("unable to recompress encrypted blob");
+        }
+        let data = self.decode(None, Some(digest))?;
+        Self::encode_with_compression(&data, None, compression)
+    }
+
+    // Compress data, returns `None` if compression is disabled.
+    //
+    // The second value contains the v2.0 compression header, or `None`
+    // if the result can be stored in the v1.0 (zstd) format.
+    #[allow(clippy::type_complexity)]
+    fn compress_data(
+        data: &[u8],
+        compression: ChunkCompression,
+    ) -> Result<Option<(Vec<u8>, Option<BlobCompressionHeader>)>, Error> {
+        let level = compression.level as i32;
+        let compressed = match compression.algorithm {
+            ChunkCompressionAlgorithm::None => return Ok(None),
+            ChunkCompressionAlgorithm::Zstd => (zstd::bulk::compress(data, level)?, None),
+            ChunkCompressionAlgorithm::ZstdLong => {
+                let mut compressor = zstd::bulk::Compressor::new(level)?;
+                compressor.set_parameter(
+                    zstd::zstd_safe::CParameter::EnableLongDistanceMatching(true),
+                )?;
+                (compressor.compress(data)?, None)
+            }
+            ChunkCompressionAlgorithm::Lz4 => {
+                let head = BlobCompressionHeader {
+                    algorithm: BLOB_COMPRESSION_LZ4,
+                    level: 0,
+                    reserved: [0u8; 2],
+                };
+                (lz4_flex::block::compress_prepend_size(data), Some(head))
+            }
+        };
+        Ok(Some(compressed))
+    }
+
+    // Decompress data of v2.0 blobs.
+    fn decompress_data(algorithm: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match algorithm {
+            BLOB_COMPRESSION_ZSTD => Ok(zstd::bulk::decompress(data, MAX_BLOB_SIZE)?),
+            BLOB_COMPRESSION_LZ4 => {
+                if data.len() < 4 {
+                    bail!("lz4 compressed blob too small ({} bytes).", data.len());
+                }
+                let size = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+                if size > MAX_BLOB_SIZE {
+                    bail!("lz4 compressed blob too large ({} bytes).", size);
+                }
+                lz4_flex::block::decompress_size_prepended(data)
+                    .map_err(|err| format_err!("lz4 decompression failed - {err}"))
+            }
+            _ => bail!("unknown blob compression algorithm {}", algorithm),
+        }
+    }
+
     /// Get the encryption mode for this blob.
     pub fn crypt_mode(&self) -> Result<CryptMode, Error> {
         let magic = self.magic();
 
-        Ok(
-            if magic == &UNCOMPRESSED_BLOB_MAGIC_1_0 || magic == &COMPRESSED_BLOB_MAGIC_1_0 {
+        Ok(match *magic {
+            UNCOMPRESSED_BLOB_MAGIC_1_0 | COMPRESSED_BLOB_MAGIC_1_0 | COMPRESSED_BLOB_MAGIC_2_0 => {
                 CryptMode::None
-            } else if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0 {
+            }
+            ENCR_COMPR_BLOB_MAGIC_1_0 | ENCRYPTED_BLOB_MAGIC_1_0 | ENCR_COMPR_BLOB_MAGIC_2_0 => {
                 CryptMode::Encrypt
-            } else {
-                bail!("Invalid blob magic number.");
-            },
-        )
+            }
+            _ => bail!("Invalid blob magic number."),
+        })
     }
 
     /// Decode blob data
@@ -247,6 +334,38 @@ impl DataBlob {
             } else {
                 bail!("unable to decrypt blob - missing CryptConfig");
             }
+        } else if magic == &COMPRESSED_BLOB_MAGIC_2_0 {
+            let header_len = std::mem::size_of::<CompressedDataBlobHeader>();
+            let head = unsafe {
+                (&self.raw_data[..header_len]).read_le_value::<CompressedDataBlobHeader>()?
+            };
+            let data =
+                Self::decompress_data(head.compression.algorithm, &self.raw_data[header_len..])?;
+            if let Some(digest) = digest {
+                Self::verify_digest(&data, None, digest)?;
+            }
+            Ok(data)
+        } else if magic == &ENCR_COMPR_BLOB_MAGIC_2_0 {
+            let header_len = std::mem::size_of::<EncrCompressedDataBlobHeader>();
+            let head = unsafe {
+                (&self.raw_data[..header_len]).read_le_value::<EncrCompressedDataBlobHeader>()?
+            };
+
+            if let Some(config) = config {
+                let compr_data = Self::decode_uncompressed_chunk(
+                    config,
+                    &self.raw_data[header_len..],
+                    &head.iv,
+                    &head.tag,
+                )?;
+                let data = Self::decompress_data(head.compression.algorithm, &compr_data)?;
+                if let Some(digest) = digest {
+                    Self::verify_digest(&data, Some(config), digest)?;
+                }
+                Ok(data)
+            } else {
+                bail!("unable to decrypt blob - missing CryptConfig");
+            }
         } else {
             bail!("Invalid blob magic number.");
         }
@@ -283,6 +402,15 @@ impl DataBlob {
         } else if magic == COMPRESSED_BLOB_MAGIC_1_0 || magic == UNCOMPRESSED_BLOB_MAGIC_1_0 {
             let blob = DataBlob { raw_data: data };
 
+            Ok(blob)
+        } else if magic == ENCR_COMPR_BLOB_MAGIC_2_0 || magic == COMPRESSED_BLOB_MAGIC_2_0 {
+            let header_len = header_size(magic.try_into().unwrap());
+            if data.len() < header_len {
+                bail!("compressed blob too small ({} bytes).", data.len());
+            }
+
+            let blob = DataBlob { raw_data: data };
+
             Ok(blob)
         } else {
             bail!("unable to parse raw blob - wrong magic");
@@ -291,14 +419,57 @@ impl DataBlob {
 
     /// Returns if chunk is encrypted
     pub fn is_encrypted(&self) -> bool {
-        let magic = self.magic();
-        magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0
+        matches!(
+            *self.magic(),
+            ENCR_COMPR_BLOB_MAGIC_1_0 | ENCRYPTED_BLOB_MAGIC_1_0 | ENCR_COMPR_BLOB_MAGIC_2_0
+        )
     }
 
     /// Returns if chunk is compressed
     pub fn is_compressed(&self) -> bool {
-        let magic = self.magic();
-        magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &COMPRESSED_BLOB_MAGIC_1_0
+        matches!(
+            *self.magic(),
+            ENCR_COMPR_BLOB_MAGIC_1_0
+                | COMPRESSED_BLOB_MAGIC_1_0
+                | ENCR_COMPR_BLOB_MAGIC_2_0
+                | COMPRESSED_BLOB_MAGIC_2_0
+        )
+    }
+
+    /// Returns if an unencrypted chunk is already stored with the given compression algorithm
+    ///
+    /// The v1.0 zstd format does not record the level or whether long distance matching was
+    /// used, so any zstd compressed blob matches both zstd variants.
+    pub fn uses_compression(&self, algorithm: ChunkCompressionAlgorithm) -> bool {
+        match *self.magic() {
+            UNCOMPRESSED_BLOB_MAGIC_1_0 => algorithm == ChunkCompressionAlgorithm::None,
+            COMPRESSED_BLOB_MAGIC_1_0 => matches!(
+                algorithm,
+                ChunkCompressionAlgorithm::Zstd | ChunkCompressionAlgorithm::ZstdLong
+            ),
+            COMPRESSED_BLOB_MAGIC_2_0 => {
+                let header_len = std::mem::size_of::<CompressedDataBlobHeader>();
+                if self.raw_data.len() < header_len {
+                    return false;
+                }
+                let head = unsafe {
+                    match (&self.raw_data[..header_len]).read_le_value::<CompressedDataBlobHeader>()
+                    {
+                        Ok(head) => head,
+                        Err(_) => return false,
+                    }
+                };
+                match head.compression.algorithm {
+                    BLOB_COMPRESSION_ZSTD => matches!(
+                        algorithm,
+                        ChunkCompressionAlgorithm::Zstd | ChunkCompressionAlgorithm::ZstdLong
+                    ),
+                    BLOB_COMPRESSION_LZ4 => algorithm == ChunkCompressionAlgorithm::Lz4,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
     }
 
     /// Verify digest and data length for unencrypted chunks.
@@ -312,9 +483,7 @@ impl DataBlob {
         expected_chunk_size: usize,
         expected_digest: &[u8; 32],
     ) -> Result<(), Error> {
-        let magic = self.magic();
-
-        if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0 {
+        if self.is_encrypted() {
             return Ok(());
         }
 
@@ -479,7 +648,7 @@ pub struct DataChunkBuilder<'a, 'b> {
     orig_data: &'a [u8],
     digest_computed: bool,
     digest: [u8; 32],
-    compress: bool,
+    compression: ChunkCompression,
 }
 
 impl<'a, 'b> DataChunkBuilder<'a, 'b> {
@@ -490,7 +659,7 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
             config: None,
             digest_computed: false,
             digest: [0u8; 32],
-            compress: true,
+            compression: ChunkCompression::default(),
         }
     }
 
@@ -498,7 +667,17 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
     ///
     /// If true, chunk data is compressed using zstd (level 1).
     pub fn compress(mut self, value: bool) -> Self {
-        self.compress = value;
+        self.compression = if value {
+            ChunkCompression::default()
+        } else {
+            ChunkCompression::NONE
+        };
+        self
+    }
+
+    /// Set the compression algorithm and level used for the chunk data.
+    pub fn compression(mut self, value: ChunkCompression) -> Self {
+        self.compression = value;
         self
     }
 
@@ -543,7 +722,8 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
             self.compute_digest();
         }
 
-        let chunk = DataBlob::encode(self.orig_data, self.config, self.compress)?;
+        let chunk =
+            DataBlob::encode_with_compression(self.orig_data, self.config, self.compression)?;
         Ok((chunk, self.digest))
     }
 
@@ -1,14 +1,15 @@
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Cursor, Read};
 use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
 
-use proxmox_io::ReadExt;
+use proxmox_io::{ReadExt, WriteExt};
 
 use pbs_tools::crypt_config::CryptConfig;
 
 use crate::checksum_reader::ChecksumReader;
 use crate::crypt_reader::CryptReader;
+use crate::data_blob::DataBlob;
 use crate::file_formats::{self, DataBlobHeader};
 
 enum BlobReaderState<'reader, R: Read> {
@@ -31,6 +32,11 @@ enum BlobReaderState<'reader, R: Read> {
             BufReader<CryptReader<BufReader<ChecksumReader<R>>>>,
         >,
     },
+    // v2.0 blobs may use block based compression, so they are decoded in memory
+    Buffered {
+        reader: R,
+        data: Cursor<Vec<u8>>,
+    },
 }
 
 /// Read data blobs
@@ -113,6 +119,25 @@ impl<R: Read> DataBlobReader<'_, R> {
                     },
                 })
             }
+            file_formats::COMPRESSED_BLOB_MAGIC_2_0 | file_formats::ENCR_COMPR_BLOB_MAGIC_2_0 => {
+                let mut raw_data = Vec::with_capacity(1024 * 1024);
+                unsafe {
+                    raw_data.write_le_value(head)?;
+                }
+                reader.read_to_end(&mut raw_data)?;
+
+                let blob = DataBlob::from_raw(raw_data)?;
+                if blob.verify_crc().is_err() {
+                    bail!("blob crc check failed");
+                }
+                let data = blob.decode(config.as_deref(), None)?;
+                Ok(Self {
+                    state: BlobReaderState::Buffered {
+                        reader,
+                        data: Cursor::new(data),
+                    },
+                })
+            }
             _ => bail!("got wrong magic number {:?}", head.magic),
         }
     }
@@ -163,6 +188,7 @@ impl<R: Read> DataBlobReader<'_, R> {
                 }
                 Ok(reader)
             }
+            BlobReaderState::Buffered { reader, .. } => Ok(reader),
         }
     }
 }
@@ -174,6 +200,7 @@ impl<R: Read> Read for DataBlobReader<'_, R> {
             BlobReaderState::Compressed { decompr, .. } => decompr.read(buf),
             BlobReaderState::Encrypted { decrypt_reader, .. } => decrypt_reader.read(buf),
             BlobReaderState::EncryptedCompressed { decompr, .. } => decompr.read(buf),
+            BlobReaderState::Buffered { data, .. } => data.read(buf),
         }
     }
 }
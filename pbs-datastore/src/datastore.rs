@@ -1,12 +1,15 @@
 use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
+use std::ops::Range;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, format_err, Error};
+use hex::FromHex;
 use lazy_static::lazy_static;
 use nix::unistd::{unlinkat, UnlinkatFlags};
+use serde::{Deserialize, Serialize};
 
 use proxmox_human_byte::HumanByte;
 use proxmox_schema::ApiType;
@@ -17,22 +20,33 @@ use proxmox_sys::fs::{lock_dir_noblock, DirLockGuard};
 use proxmox_sys::process_locker::ProcessLockSharedGuard;
 use proxmox_sys::WorkerTaskContext;
 use proxmox_sys::{task_log, task_warn};
+use proxmox_time::TimeSpan;
 
 use pbs_api_types::{
-    Authid, BackupNamespace, BackupType, ChunkOrder, DataStoreConfig, DatastoreFSyncLevel,
-    DatastoreTuning, GarbageCollectionStatus, Operation, UPID,
+    Authid, BackupNamespace, BackupType, ChunkCompression, ChunkOrder, DataStoreConfig,
+    DatastoreBackendType, DatastoreFSyncLevel, DatastoreTuning, GarbageCollectionMode,
+    GarbageCollectionStatus, MaintenanceType, Operation, UPID,
 };
 
-use crate::backup_info::{BackupDir, BackupGroup};
+use crate::backup_info::{BackupDir, BackupGroup, RETENTION_LOCK_FILE_NAME};
+use crate::chunk_mirror::ChunkMirror;
 use crate::chunk_store::ChunkStore;
 use crate::dynamic_index::{DynamicIndexReader, DynamicIndexWriter};
 use crate::fixed_index::{FixedIndexReader, FixedIndexWriter};
 use crate::hierarchy::{ListGroups, ListGroupsType, ListNamespaces, ListNamespacesRecursive};
 use crate::index::IndexFile;
-use crate::manifest::{archive_type, ArchiveType};
+use crate::live_chunks::{
+    self, LiveChunkCollector, LiveChunkList, LIVE_CHUNK_LIST_FILENAME,
+    PARTIAL_LIVE_CHUNK_LIST_FILENAME,
+};
+use crate::manifest::{archive_type, ArchiveType, MANIFEST_BLOB_NAME};
+use crate::s3::{self, S3Client};
 use crate::task_tracking::{self, update_active_operations};
 use crate::DataBlob;
 
+/// Name of the file holding the default retention lock of a namespace.
+const NAMESPACE_RETENTION_LOCK_FILE_NAME: &str = ".default-retention-lock";
+
 lazy_static! {
     static ref DATASTORE_MAP: Mutex<HashMap<String, Arc<DataStoreImpl>>> =
         Mutex::new(HashMap::new());
@@ -49,18 +63,140 @@ pub fn check_backup_owner(owner: &Authid, auth_id: &Authid) -> Result<(), Error>
     Ok(())
 }
 
+/// Returns the mount status of a removable datastore, `None` if the datastore is not removable.
+///
+/// A removable datastore counts as mounted if its mount point is backed by the filesystem with the
+/// configured UUID.
+pub fn get_datastore_mount_status(config: &DataStoreConfig) -> Option<bool> {
+    let mount_point = config.mount_point()?;
+    let uuid = config.backing_device.as_deref()?;
+
+    let device = Path::new("/dev/disk/by-uuid").join(uuid);
+    let mounted = match (
+        nix::sys::stat::stat(&device),
+        nix::sys::stat::stat(&mount_point),
+    ) {
+        (Ok(device), Ok(mount_point)) => device.st_rdev == mount_point.st_dev,
+        _ => false,
+    };
+
+    Some(mounted)
+}
+
+/// Fails if the datastore is removable and currently not mounted.
+pub fn ensure_datastore_is_mounted(config: &DataStoreConfig) -> Result<(), Error> {
+    if get_datastore_mount_status(config) == Some(false) {
+        bail!("datastore '{}' is not mounted", config.name);
+    }
+    Ok(())
+}
+
+/// Storage backend of a datastore
+#[derive(Clone)]
+pub enum DatastoreBackend {
+    /// Everything is stored in the local datastore directory.
+    Filesystem,
+    /// Chunks and snapshot contents are stored in an S3 compatible bucket. The local datastore
+    /// directory holds a copy of the snapshot metadata and empty marker files for chunks.
+    S3(Arc<S3Client>),
+}
+
+impl DatastoreBackend {
+    fn from_config(config: &DataStoreConfig) -> Result<Self, Error> {
+        let backend_config = config.backend_config()?;
+        match backend_config.ty.unwrap_or_default() {
+            DatastoreBackendType::Filesystem => Ok(DatastoreBackend::Filesystem),
+            DatastoreBackendType::S3 => {
+                // unwrap: checked by backend_config()
+                let client = S3Client::new(
+                    backend_config.client.as_deref().unwrap(),
+                    backend_config.bucket.as_deref().unwrap(),
+                )?;
+                Ok(DatastoreBackend::S3(client))
+            }
+        }
+    }
+}
+
+/// File name of the garbage collection checkpoint, relative to the datastore base directory.
+const GC_CHECKPOINT_FILENAME: &str = ".gc-checkpoint";
+
+/// File name of the disk usage per chunk store part, as found by incremental garbage collection.
+const GC_PART_USAGE_FILENAME: &str = ".gc-part-usage";
+
+/// Disk usage of the chunk store parts, updated by the incremental run sweeping the part.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct GarbageCollectionPartUsage {
+    /// Bytes and chunks on disk per part, `None` for parts not swept yet.
+    parts: Vec<Option<(u64, usize)>>,
+}
+
+impl GarbageCollectionPartUsage {
+    /// Returns the bytes and chunks on disk of the whole chunk store.
+    ///
+    /// The usage of parts which were not swept yet is extrapolated from the swept ones, chunks
+    /// are evenly distributed over the parts.
+    fn total(&self) -> (u64, usize) {
+        let swept: Vec<(u64, usize)> = self.parts.iter().flatten().copied().collect();
+        if swept.is_empty() {
+            return (0, 0);
+        }
+
+        let bytes: u128 = swept.iter().map(|(bytes, _)| *bytes as u128).sum();
+        let chunks: u128 = swept.iter().map(|(_, chunks)| *chunks as u128).sum();
+        let parts = self.parts.len() as u128;
+        let count = swept.len() as u128;
+
+        (
+            (bytes * parts / count) as u64,
+            (chunks * parts / count) as usize,
+        )
+    }
+}
+
+/// Progress of a garbage collection run, allows to resume it after an interruption.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct GarbageCollectionCheckpoint {
+    /// Phase 1 start time of the original run, the cutoff is based on it.
+    phase1_start_time: i64,
+    /// Oldest active writer seen by any attempt of the run.
+    oldest_writer: i64,
+    gc_mode: GarbageCollectionMode,
+    /// Index files are marked in sorted order, all files up to this one are done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_marked_index: Option<PathBuf>,
+    /// Length of the partial live chunk list covered by this checkpoint, anything appended
+    /// after it is discarded when resuming.
+    partial_live_chunks_len: u64,
+    /// Set once phase 1 is complete.
+    #[serde(default)]
+    phase1_done: bool,
+    /// Chunk prefix directories still to be swept in phase 2.
+    prefixes: Range<usize>,
+    status: GarbageCollectionStatus,
+}
+
 /// Datastore Management
 ///
 /// A Datastore can store severals backups, and provides the
 /// management interface for backup.
 pub struct DataStoreImpl {
     chunk_store: Arc<ChunkStore>,
+    backend: DatastoreBackend,
     gc_mutex: Mutex<()>,
     last_gc_status: Mutex<GarbageCollectionStatus>,
     verify_new: bool,
     chunk_order: ChunkOrder,
     last_digest: Option<[u8; 32]>,
     sync_level: DatastoreFSyncLevel,
+    chunk_compression: Option<ChunkCompression>,
+    gc_mode: GarbageCollectionMode,
+    gc_sweep_parts: usize,
+    retention_lock: Option<i64>,
+    scrub_rate_limit: Option<u64>,
+    chunk_mirror: Option<ChunkMirror>,
 }
 
 impl DataStoreImpl {
@@ -69,12 +205,19 @@ impl DataStoreImpl {
     pub(crate) unsafe fn new_test() -> Arc<Self> {
         Arc::new(Self {
             chunk_store: Arc::new(unsafe { ChunkStore::panic_store() }),
+            backend: DatastoreBackend::Filesystem,
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(GarbageCollectionStatus::default()),
             verify_new: false,
             chunk_order: Default::default(),
             last_digest: None,
             sync_level: Default::default(),
+            chunk_compression: None,
+            gc_mode: Default::default(),
+            gc_sweep_parts: 1,
+            retention_lock: None,
+            scrub_rate_limit: None,
+            chunk_mirror: None,
         })
     }
 }
@@ -140,6 +283,12 @@ impl DataStore {
             }
         }
 
+        if get_datastore_mount_status(&config) == Some(false) {
+            // drop a possibly cached instance, the device might get mounted again later on
+            DATASTORE_MAP.lock().unwrap().remove(name);
+            bail!("datastore '{name}' is not mounted");
+        }
+
         if let Some(operation) = operation {
             update_active_operations(name, operation, 1)?;
         }
@@ -167,7 +316,7 @@ impl DataStore {
             )?;
             Arc::new(ChunkStore::open(
                 name,
-                &config.path,
+                config.absolute_path(),
                 tuning.sync_level.unwrap_or_default(),
             )?)
         };
@@ -183,13 +332,22 @@ impl DataStore {
         }))
     }
 
-    /// removes all datastores that are not configured anymore
+    /// removes all datastores that are not configured anymore, as well as removable datastores
+    /// which are being unmounted or are not mounted at all
     pub fn remove_unused_datastores() -> Result<(), Error> {
         let (config, _digest) = pbs_config::datastore::config()?;
 
         let mut map = DATASTORE_MAP.lock().unwrap();
-        // removes all elements that are not in the config
-        map.retain(|key, _| config.sections.contains_key(key));
+        map.retain(|key, _| {
+            let store_config: DataStoreConfig = match config.lookup("datastore", key) {
+                Ok(store_config) => store_config,
+                Err(_) => return false, // not in the config anymore
+            };
+            let unmounting = store_config
+                .get_maintenance_mode()
+                .map_or(false, |mode| *mode.ty() == MaintenanceType::Unmount);
+            !unmounting && get_datastore_mount_status(&store_config) != Some(false)
+        });
         Ok(())
     }
 
@@ -229,8 +387,11 @@ impl DataStore {
             DatastoreTuning::API_SCHEMA
                 .parse_property_string(config.tuning.as_deref().unwrap_or(""))?,
         )?;
-        let chunk_store =
-            ChunkStore::open(&name, &config.path, tuning.sync_level.unwrap_or_default())?;
+        let chunk_store = ChunkStore::open(
+            &name,
+            config.absolute_path(),
+            tuning.sync_level.unwrap_or_default(),
+        )?;
         let inner = Arc::new(Self::with_store_and_config(
             Arc::new(chunk_store),
             config,
@@ -269,14 +430,46 @@ impl DataStore {
                 .parse_property_string(config.tuning.as_deref().unwrap_or(""))?,
         )?;
 
+        let backend = DatastoreBackend::from_config(&config)?;
+
+        let retention_lock = match config.retention_lock {
+            Some(ref span) => Some(f64::from(span.parse::<TimeSpan>()?) as i64),
+            None => None,
+        };
+
+        // a missing mirror must not make the datastore unusable, repairs are not possible then
+        let chunk_mirror = match (&config.chunk_mirror, &backend) {
+            (Some(path), DatastoreBackend::Filesystem) => {
+                match ChunkMirror::open(path, tuning.sync_level.unwrap_or_default()) {
+                    Ok(mirror) => Some(mirror),
+                    Err(err) => {
+                        log::error!("datastore '{}': {err}", config.name);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let gc_mode = tuning.gc_mode.unwrap_or_default();
+        // the chunk store is shared with instances using the previous config
+        chunk_store.set_touch_mtime(gc_mode == GarbageCollectionMode::DigestList);
+
         Ok(DataStoreImpl {
             chunk_store,
+            backend,
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(gc_status),
             verify_new: config.verify_new.unwrap_or(false),
             chunk_order: tuning.chunk_order.unwrap_or_default(),
             last_digest,
             sync_level: tuning.sync_level.unwrap_or_default(),
+            chunk_compression: tuning.chunk_compression(),
+            gc_mode,
+            gc_sweep_parts: tuning.gc_sweep_parts.unwrap_or(1) as usize,
+            retention_lock,
+            scrub_rate_limit: tuning.scrub_rate_limit.map(|rate| rate.as_u64()),
+            chunk_mirror,
         })
     }
 
@@ -523,6 +716,8 @@ impl DataStore {
             let _ = unlinkat(Some(base_fd), &ns_dir, UnlinkatFlags::RemoveDir);
 
             if !ns.is_root() {
+                remove_namespace_settings(&self.namespace_path(ns));
+
                 match unlinkat(Some(base_fd), &ns.path(), UnlinkatFlags::RemoveDir) {
                     Ok(()) => log::debug!("removed namespace {ns}"),
                     Err(nix::errno::Errno::ENOENT) => {
@@ -648,6 +843,8 @@ impl DataStore {
         writeln!(file, "{}", auth_id)
             .map_err(|err| format_err!("unable to write owner file  {:?} - {}", path, err))?;
 
+        self.upload_file_to_backend(&path)?;
+
         Ok(())
     }
 
@@ -913,11 +1110,13 @@ impl DataStore {
         Ok(list)
     }
 
-    // mark chunks  used by ``index`` as used
+    // mark chunks  used by ``index`` as used, either by touching them or by recording them in
+    // ``live_chunks``
     fn index_mark_used_chunks<I: IndexFile>(
         &self,
         index: I,
         file_name: &Path, // only used for error reporting
+        mut live_chunks: Option<&mut LiveChunkCollector>,
         status: &mut GarbageCollectionStatus,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
@@ -928,7 +1127,14 @@ impl DataStore {
             worker.check_abort()?;
             worker.fail_on_shutdown()?;
             let digest = index.index_digest(pos).unwrap();
-            if !self.inner.chunk_store.cond_touch_chunk(digest, false)? {
+            let exists = match live_chunks.as_deref_mut() {
+                Some(live_chunks) => {
+                    live_chunks.insert(digest);
+                    self.inner.chunk_store.chunk_path(digest).0.exists()
+                }
+                None => self.inner.chunk_store.cond_touch_chunk(digest, false)?,
+            };
+            if !exists && !self.restore_chunk_marker(digest)? {
                 let hex = hex::encode(digest);
                 task_warn!(
                     worker,
@@ -952,20 +1158,32 @@ impl DataStore {
 
     fn mark_used_chunks(
         &self,
-        status: &mut GarbageCollectionStatus,
+        mut live_chunks: Option<&mut LiveChunkCollector>,
+        checkpoint: &mut GarbageCollectionCheckpoint,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
-        let image_list = self.list_images()?;
+        let mut image_list = self.list_images()?;
         let image_count = image_list.len();
 
+        // a stable order allows to resume after the last checkpoint
+        image_list.sort_unstable();
+
         let mut last_percentage: usize = 0;
 
         let mut strange_paths_count: u64 = 0;
 
+        let partial_path = self.base_path().join(PARTIAL_LIVE_CHUNK_LIST_FILENAME);
+
         for (i, img) in image_list.into_iter().enumerate() {
             worker.check_abort()?;
             worker.fail_on_shutdown()?;
 
+            if matches!(checkpoint.last_marked_index, Some(ref last) if img <= *last) {
+                continue;
+            }
+
+            let status = &mut checkpoint.status;
+
             if let Some(backup_dir_path) = img.parent() {
                 let backup_dir_path = backup_dir_path.strip_prefix(self.base_path())?;
                 if let Some(backup_dir_str) = backup_dir_path.to_str() {
@@ -982,12 +1200,24 @@ impl DataStore {
                             let index = FixedIndexReader::new(file).map_err(|e| {
                                 format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
                             })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
+                            self.index_mark_used_chunks(
+                                index,
+                                &img,
+                                live_chunks.as_deref_mut(),
+                                status,
+                                worker,
+                            )?;
                         } else if archive_type == ArchiveType::DynamicIndex {
                             let index = DynamicIndexReader::new(file).map_err(|e| {
                                 format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
                             })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
+                            self.index_mark_used_chunks(
+                                index,
+                                &img,
+                                live_chunks.as_deref_mut(),
+                                status,
+                                worker,
+                            )?;
                         }
                     }
                 }
@@ -1005,6 +1235,13 @@ impl DataStore {
                     image_count,
                 );
                 last_percentage = percentage;
+
+                if let Some(live_chunks) = live_chunks.as_deref_mut() {
+                    checkpoint.partial_live_chunks_len =
+                        live_chunks.persist_partial(&partial_path)?;
+                }
+                checkpoint.last_marked_index = Some(img);
+                self.save_gc_checkpoint(checkpoint)?;
             }
         }
 
@@ -1027,10 +1264,92 @@ impl DataStore {
         !matches!(self.inner.gc_mutex.try_lock(), Ok(_))
     }
 
+    fn gc_checkpoint_path(&self) -> PathBuf {
+        self.base_path().join(GC_CHECKPOINT_FILENAME)
+    }
+
+    fn load_gc_checkpoint(&self) -> Option<GarbageCollectionCheckpoint> {
+        match file_read_optional_string(self.gc_checkpoint_path()) {
+            Ok(Some(data)) => match serde_json::from_str(&data) {
+                Ok(checkpoint) => Some(checkpoint),
+                Err(err) => {
+                    log::error!("error parsing gc checkpoint: {err}");
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(err) => {
+                log::error!("error reading gc checkpoint: {err}");
+                None
+            }
+        }
+    }
+
+    fn save_gc_checkpoint(&self, checkpoint: &GarbageCollectionCheckpoint) -> Result<(), Error> {
+        let data = serde_json::to_vec(checkpoint)?;
+
+        let backup_user = pbs_config::backup_user()?;
+        let options = CreateOptions::new()
+            .perm(nix::sys::stat::Mode::from_bits_truncate(0o0644))
+            .owner(backup_user.uid)
+            .group(backup_user.gid);
+
+        replace_file(self.gc_checkpoint_path(), &data, options, false)
+    }
+
+    /// Record the disk usage found by an incremental run sweeping `part` of `parts`, and update
+    /// `status` to cover the whole chunk store instead of just the swept part.
+    ///
+    /// Without a part, the whole chunk store was swept and the recorded usage gets dropped.
+    fn update_gc_part_usage(
+        &self,
+        part: Option<(usize, usize)>,
+        status: &mut GarbageCollectionStatus,
+    ) -> Result<(), Error> {
+        let path = self.base_path().join(GC_PART_USAGE_FILENAME);
+
+        let (part, parts) = match part {
+            Some(part) => part,
+            None => {
+                if let Err(err) = std::fs::remove_file(&path) {
+                    if err.kind() != io::ErrorKind::NotFound {
+                        bail!("unable to remove gc part usage - {err}");
+                    }
+                }
+                return Ok(());
+            }
+        };
+
+        let mut usage: GarbageCollectionPartUsage = match file_read_optional_string(&path)? {
+            Some(data) => serde_json::from_str(&data).unwrap_or_default(),
+            None => GarbageCollectionPartUsage::default(),
+        };
+        if usage.parts.len() != parts {
+            usage.parts = vec![None; parts];
+        }
+        usage.parts[part] = Some((status.disk_bytes, status.disk_chunks));
+
+        (status.disk_bytes, status.disk_chunks) = usage.total();
+
+        let backup_user = pbs_config::backup_user()?;
+        let options = CreateOptions::new()
+            .perm(nix::sys::stat::Mode::from_bits_truncate(0o0644))
+            .owner(backup_user.uid)
+            .group(backup_user.gid);
+
+        replace_file(path, &serde_json::to_vec(&usage)?, options, false)
+    }
+
+    /// Run garbage collection.
+    ///
+    /// An interrupted run is resumed from its last checkpoint. If `incremental` is set and the
+    /// datastore has ``gc-sweep-parts`` configured, only the next part of the chunk store is
+    /// swept.
     pub fn garbage_collection(
         &self,
         worker: &dyn WorkerTaskContext,
         upid: &UPID,
+        incremental: bool,
     ) -> Result<(), Error> {
         if let Ok(ref mut _mutex) = self.inner.gc_mutex.try_lock() {
             // avoids that we run GC if an old daemon process has still a
@@ -1038,30 +1357,156 @@ impl DataStore {
             // writer" information and thus no safe atime cutoff
             let _exclusive_lock = self.inner.chunk_store.try_exclusive_lock()?;
 
-            let phase1_start_time = proxmox_time::epoch_i64();
-            let oldest_writer = self
-                .inner
-                .chunk_store
-                .oldest_writer()
-                .unwrap_or(phase1_start_time);
-
-            let mut gc_status = GarbageCollectionStatus {
-                upid: Some(upid.to_string()),
-                ..Default::default()
+            let now = proxmox_time::epoch_i64();
+            let oldest_writer = self.inner.chunk_store.oldest_writer().unwrap_or(now);
+
+            let mut checkpoint = match self.load_gc_checkpoint() {
+                Some(checkpoint) if checkpoint.gc_mode == self.inner.gc_mode => {
+                    task_log!(
+                        worker,
+                        "resuming interrupted garbage collection started at {}",
+                        proxmox_time::strftime_local("%c", checkpoint.phase1_start_time)?,
+                    );
+                    checkpoint
+                }
+                _ => {
+                    let mut status = GarbageCollectionStatus::default();
+                    let parts = self.inner.gc_sweep_parts;
+                    let prefixes = if incremental && parts > 1 {
+                        let last = self.last_gc_status();
+                        let part = match (last.sweep_part, last.sweep_parts) {
+                            (Some(part), Some(last_parts)) if last_parts == parts => {
+                                (part + 1) % parts
+                            }
+                            _ => 0,
+                        };
+                        status.sweep_part = Some(part);
+                        status.sweep_parts = Some(parts);
+                        (part * 0x10000 / parts)..((part + 1) * 0x10000 / parts)
+                    } else {
+                        0..0x10000
+                    };
+                    GarbageCollectionCheckpoint {
+                        phase1_start_time: now,
+                        oldest_writer,
+                        gc_mode: self.inner.gc_mode,
+                        last_marked_index: None,
+                        partial_live_chunks_len: 0,
+                        phase1_done: false,
+                        prefixes,
+                        status,
+                    }
+                }
             };
+            checkpoint.oldest_writer = checkpoint.oldest_writer.min(oldest_writer);
+            checkpoint.status.upid = Some(upid.to_string());
+
+            let phase1_start_time = checkpoint.phase1_start_time;
+            let oldest_writer = checkpoint.oldest_writer;
 
-            task_log!(worker, "Start GC phase1 (mark used chunks)");
+            let live_chunk_path = self.base_path().join(LIVE_CHUNK_LIST_FILENAME);
 
-            self.mark_used_chunks(&mut gc_status, worker)?;
+            if !checkpoint.phase1_done {
+                task_log!(worker, "Start GC phase1 (mark used chunks)");
+
+                match self.inner.gc_mode {
+                    GarbageCollectionMode::Atime => {
+                        self.mark_used_chunks(None, &mut checkpoint, worker)?;
+                    }
+                    GarbageCollectionMode::DigestList => {
+                        let partial_path = self.base_path().join(PARTIAL_LIVE_CHUNK_LIST_FILENAME);
+                        if checkpoint.last_marked_index.is_none() {
+                            live_chunks::remove_partial(&partial_path)?;
+                        } else {
+                            // drop digests appended after the checkpoint, possibly torn
+                            live_chunks::truncate_partial(
+                                &partial_path,
+                                checkpoint.partial_live_chunks_len,
+                            )?;
+                        }
+
+                        let mut collector = LiveChunkCollector::new();
+                        self.mark_used_chunks(Some(&mut collector), &mut checkpoint, worker)?;
+
+                        let backup_user = pbs_config::backup_user()?;
+                        let options = CreateOptions::new()
+                            .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+                            .owner(backup_user.uid)
+                            .group(backup_user.gid);
+                        let count = collector.write(&live_chunk_path, &partial_path, options)?;
+                        task_log!(
+                            worker,
+                            "recorded {count} used chunks in {live_chunk_path:?}"
+                        );
+                    }
+                }
+
+                checkpoint.phase1_done = true;
+                self.save_gc_checkpoint(&checkpoint)?;
+            }
+
+            let mut live_chunks = match self.inner.gc_mode {
+                GarbageCollectionMode::Atime => None,
+                GarbageCollectionMode::DigestList => Some(LiveChunkList::open(&live_chunk_path)?),
+            };
 
             task_log!(worker, "Start GC phase2 (sweep unused chunks)");
+            if let (Some(part), Some(parts)) =
+                (checkpoint.status.sweep_part, checkpoint.status.sweep_parts)
+            {
+                task_log!(
+                    worker,
+                    "incremental run, sweeping part {} of {}",
+                    part + 1,
+                    parts
+                );
+            }
+
+            let prefixes = checkpoint.prefixes.clone();
+            let mirror_prefixes = prefixes.clone();
+            let mut gc_status = std::mem::take(&mut checkpoint.status);
             self.inner.chunk_store.sweep_unused_chunks(
                 oldest_writer,
                 phase1_start_time,
+                prefixes,
+                live_chunks.as_mut(),
                 &mut gc_status,
+                &mut |next_prefix, status| {
+                    checkpoint.prefixes.start = next_prefix;
+                    checkpoint.status = status.clone();
+                    self.save_gc_checkpoint(&checkpoint)
+                },
                 worker,
             )?;
 
+            if let Some(ref mirror) = self.inner.chunk_mirror {
+                let removed = mirror.sweep_removed_chunks(
+                    &self.inner.chunk_store,
+                    mirror_prefixes,
+                    worker,
+                )?;
+                task_log!(worker, "Removed mirror copies of {removed} chunks");
+            }
+
+            let part = gc_status.sweep_part.zip(gc_status.sweep_parts);
+            self.update_gc_part_usage(part, &mut gc_status)?;
+
+            if let DatastoreBackend::S3(ref client) = self.inner.backend {
+                task_log!(
+                    worker,
+                    "Start GC phase3 (sweep unused chunks on S3 backend)"
+                );
+                let min_time = match self.inner.gc_mode {
+                    GarbageCollectionMode::Atime => {
+                        ChunkStore::gc_min_atime(oldest_writer, phase1_start_time)
+                    }
+                    GarbageCollectionMode::DigestList => {
+                        ChunkStore::gc_min_mtime(oldest_writer, phase1_start_time)
+                    }
+                };
+                self.sweep_unused_backend_chunks(client, min_time, &mut gc_status, worker)?;
+            }
+
             task_log!(
                 worker,
                 "Removed garbage: {}",
@@ -1134,6 +1579,12 @@ impl DataStore {
             }
 
             *self.inner.last_gc_status.lock().unwrap() = gc_status;
+
+            if let Err(err) = std::fs::remove_file(self.gc_checkpoint_path()) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    bail!("unable to remove gc checkpoint - {err}");
+                }
+            }
         } else {
             bail!("Start GC failed - (already running/locked)");
         }
@@ -1150,13 +1601,79 @@ impl DataStore {
     }
 
     pub fn cond_touch_chunk(&self, digest: &[u8; 32], assert_exists: bool) -> Result<bool, Error> {
+        // hold the chunk store mutex, so that GC cannot remove the chunk between our existence
+        // check and the touch
+        let _lock = self.inner.chunk_store.mutex().lock();
         self.inner
             .chunk_store
             .cond_touch_chunk(digest, assert_exists)
     }
 
     pub fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error> {
-        self.inner.chunk_store.insert_chunk(chunk, digest)
+        // re-encode new unencrypted chunks if the datastore has its own compression configured,
+        // unless the client already used that algorithm. Encrypted chunks can only be compressed
+        // by the client
+        let recompressed;
+        let chunk = match self.inner.chunk_compression {
+            Some(compression)
+                if !chunk.is_encrypted()
+                    && !chunk.uses_compression(compression.algorithm)
+                    && !self.inner.chunk_store.chunk_path(digest).0.exists() =>
+            {
+                recompressed = chunk.recompress(compression, digest)?;
+                &recompressed
+            }
+            _ => chunk,
+        };
+
+        let client = match self.inner.backend {
+            DatastoreBackend::Filesystem => {
+                let result = self.inner.chunk_store.insert_chunk(chunk, digest)?;
+                if let Some(ref mirror) = self.inner.chunk_mirror {
+                    // a missing copy is restored by the next scrub, so don't fail the insert
+                    let _lock = self.inner.chunk_store.mutex().lock();
+                    if let Err(err) = mirror.insert_chunk(chunk, digest) {
+                        log::warn!("store '{}': {err}", self.name());
+                    }
+                }
+                return Ok(result);
+            }
+            DatastoreBackend::S3(ref client) => client,
+        };
+
+        let raw_data = chunk.raw_data();
+        let encoded_size = raw_data.len() as u64;
+
+        let key = s3::chunk_object_key(digest);
+
+        // the marker is created before uploading, so that a concurrent GC never removes the
+        // object of a chunk which is about to be (re-)inserted
+        if self.inner.chunk_store.insert_chunk_marker(digest)? {
+            // a marker without object is left behind by an interrupted upload, so only trust
+            // it if the object really exists
+            if proxmox_async::runtime::block_on(client.head_object(&key))?.is_some() {
+                return Ok((true, encoded_size));
+            }
+            log::warn!(
+                "store '{}': object of chunk {} missing in bucket, uploading it again",
+                self.name(),
+                hex::encode(digest),
+            );
+        }
+
+        let upload = client.put_object(&key, raw_data.to_vec());
+        if let Err(err) = proxmox_async::runtime::block_on(upload) {
+            if let Err(err) = self.inner.chunk_store.remove_chunk_marker(digest) {
+                log::error!("{err}");
+            }
+            bail!(
+                "inserting chunk on store '{}' failed for {} - {err}",
+                self.name(),
+                hex::encode(digest),
+            );
+        }
+
+        Ok((false, encoded_size))
     }
 
     pub fn stat_chunk(&self, digest: &[u8; 32]) -> Result<std::fs::Metadata, Error> {
@@ -1168,8 +1685,18 @@ impl DataStore {
         let (chunk_path, digest_str) = self.inner.chunk_store.chunk_path(digest);
 
         proxmox_lang::try_block!({
-            let mut file = std::fs::File::open(&chunk_path)?;
-            DataBlob::load_from_reader(&mut file)
+            match self.inner.backend {
+                DatastoreBackend::Filesystem => {
+                    let mut file = std::fs::File::open(&chunk_path)?;
+                    DataBlob::load_from_reader(&mut file)
+                }
+                DatastoreBackend::S3(ref client) => {
+                    let key = s3::chunk_object_key(digest);
+                    let data = proxmox_async::runtime::block_on(client.get_object(&key))?
+                        .ok_or_else(|| format_err!("object missing in bucket"))?;
+                    DataBlob::load_from_reader(&mut &data[..])
+                }
+            }
         })
         .map_err(|err| {
             format_err!(
@@ -1181,6 +1708,257 @@ impl DataStore {
         })
     }
 
+    /// Read the raw, encoded data of a chunk.
+    pub async fn read_raw_chunk_data(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        match self.inner.backend {
+            DatastoreBackend::Filesystem => {
+                let (chunk_path, _digest_str) = self.inner.chunk_store.chunk_path(digest);
+                tokio::fs::read(&chunk_path)
+                    .await
+                    .map_err(|err| format_err!("reading file {chunk_path:?} failed: {err}"))
+            }
+            DatastoreBackend::S3(ref client) => {
+                let key = s3::chunk_object_key(digest);
+                client
+                    .get_object(&key)
+                    .await?
+                    .ok_or_else(|| format_err!("chunk object '{key}' missing in bucket"))
+            }
+        }
+    }
+
+    /// Returns the storage backend of this datastore.
+    pub fn backend(&self) -> &DatastoreBackend {
+        &self.inner.backend
+    }
+
+    /// Returns the object key for a path inside the datastore directory.
+    fn backend_key(&self, path: &Path) -> Result<String, Error> {
+        let base = self.base_path();
+        let relative = path
+            .strip_prefix(&base)
+            .map_err(|_| format_err!("path {path:?} is outside of datastore {base:?}"))?;
+        relative
+            .to_str()
+            .map(|key| key.to_string())
+            .ok_or_else(|| format_err!("non-utf8 paths not supported"))
+    }
+
+    /// Upload a file from the datastore directory to the backend, a no-op for the filesystem
+    /// backend.
+    pub fn upload_file_to_backend(&self, path: &Path) -> Result<(), Error> {
+        let client = match self.inner.backend {
+            DatastoreBackend::Filesystem => return Ok(()),
+            DatastoreBackend::S3(ref client) => client,
+        };
+
+        let key = self.backend_key(path)?;
+        let data = std::fs::read(path)
+            .map_err(|err| format_err!("unable to read {path:?} for upload - {err}"))?;
+        proxmox_async::runtime::block_on(client.put_object(&key, data))
+    }
+
+    /// Remove a file or directory of the datastore from the backend, a no-op for the filesystem
+    /// backend.
+    pub fn remove_path_from_backend(&self, path: &Path) -> Result<(), Error> {
+        let client = match self.inner.backend {
+            DatastoreBackend::Filesystem => return Ok(()),
+            DatastoreBackend::S3(ref client) => client,
+        };
+
+        let key = self.backend_key(path)?;
+        proxmox_async::runtime::block_on(async {
+            client.delete_object(&key).await?;
+            client.delete_prefix(&format!("{key}/")).await
+        })
+    }
+
+    /// Upload the group owner and all files of a finished snapshot to the backend.
+    ///
+    /// The manifest is uploaded last, so a snapshot is only considered complete on the backend
+    /// once all its contents are stored.
+    pub fn upload_snapshot_to_backend(&self, backup_dir: &BackupDir) -> Result<(), Error> {
+        if let DatastoreBackend::Filesystem = self.inner.backend {
+            return Ok(());
+        }
+
+        let owner_path = self.owner_path(backup_dir.backup_ns(), backup_dir.as_ref());
+        self.upload_file_to_backend(&owner_path)?;
+
+        let full_path = backup_dir.full_path();
+        let mut manifest_path = None;
+        for item in proxmox_sys::fs::read_subdir(libc::AT_FDCWD, &full_path)? {
+            let item = item?;
+            if item.file_type() != Some(nix::dir::Type::File) {
+                continue;
+            }
+            let file_name = match item.file_name().to_str() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let path = full_path.join(file_name);
+            if file_name == MANIFEST_BLOB_NAME {
+                manifest_path = Some(path);
+            } else {
+                self.upload_file_to_backend(&path)?;
+            }
+        }
+
+        if let Some(path) = manifest_path {
+            self.upload_file_to_backend(&path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Download all snapshot metadata missing in the local datastore directory from the backend
+    /// and create the marker files for all stored chunks.
+    pub fn refresh_from_backend(&self, worker: &dyn WorkerTaskContext) -> Result<(), Error> {
+        let client = match self.inner.backend {
+            DatastoreBackend::Filesystem => bail!("datastore '{}' has no S3 backend", self.name()),
+            DatastoreBackend::S3(ref client) => client,
+        };
+
+        let base = self.base_path();
+        let objects = proxmox_async::runtime::block_on(client.list_objects(""))?;
+
+        task_log!(
+            worker,
+            "found {} objects in bucket '{}'",
+            objects.len(),
+            client.bucket()
+        );
+
+        let backup_user = pbs_config::backup_user()?;
+        let options = CreateOptions::new()
+            .owner(backup_user.uid)
+            .group(backup_user.gid);
+
+        let mut chunk_count = 0;
+        let mut file_count = 0;
+        for object in objects {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            if let Some(digest_str) = object.key.strip_prefix(s3::S3_CHUNK_PREFIX) {
+                let digest_str = digest_str.rsplit('/').next().unwrap_or_default();
+                match <[u8; 32]>::from_hex(digest_str) {
+                    Ok(digest) => {
+                        self.inner.chunk_store.insert_chunk_marker(&digest)?;
+                        chunk_count += 1;
+                    }
+                    Err(_) => task_warn!(worker, "ignoring unexpected object '{}'", object.key),
+                }
+                continue;
+            }
+
+            // skip datastore internal files like the GC status
+            if object.key.starts_with('.') {
+                continue;
+            }
+
+            let path = base.join(&object.key);
+            if let Ok(metadata) = std::fs::metadata(&path) {
+                if metadata.len() == object.size {
+                    continue;
+                }
+            }
+
+            if let Some(parent) = path.parent() {
+                proxmox_sys::fs::create_path(parent, None, Some(options.clone()))?;
+            }
+
+            let data = proxmox_async::runtime::block_on(client.get_object(&object.key))?
+                .ok_or_else(|| format_err!("object '{}' vanished during refresh", object.key))?;
+            replace_file(&path, &data, options.clone(), false)?;
+            file_count += 1;
+        }
+
+        task_log!(
+            worker,
+            "refreshed {file_count} files and {chunk_count} chunk markers from bucket"
+        );
+
+        Ok(())
+    }
+
+    /// Recreate the marker file of a chunk if its data exists on the backend.
+    ///
+    /// Returns true if the chunk exists on the backend.
+    fn restore_chunk_marker(&self, digest: &[u8; 32]) -> Result<bool, Error> {
+        let client = match self.inner.backend {
+            DatastoreBackend::Filesystem => return Ok(false),
+            DatastoreBackend::S3(ref client) => client,
+        };
+
+        let key = s3::chunk_object_key(digest);
+        if proxmox_async::runtime::block_on(client.head_object(&key))?.is_some() {
+            self.inner.chunk_store.insert_chunk_marker(digest)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Remove all chunk objects from the backend whose marker file was removed by GC, or which
+    /// never had one, and which were not modified since `min_atime`.
+    fn sweep_unused_backend_chunks(
+        &self,
+        client: &S3Client,
+        min_atime: i64,
+        status: &mut GarbageCollectionStatus,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(), Error> {
+        let objects = proxmox_async::runtime::block_on(client.list_objects(s3::S3_CHUNK_PREFIX))?;
+
+        let mut unused = Vec::new();
+
+        for object in objects {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let digest_str = object.key.rsplit('/').next().unwrap_or_default();
+            let digest = match <[u8; 32]>::from_hex(digest_str) {
+                Ok(digest) => digest,
+                Err(_) => continue,
+            };
+            let (chunk_path, _digest_str) = self.inner.chunk_store.chunk_path(&digest);
+
+            // hold the chunk store mutex, so no marker gets created while we decide
+            let _lock = self.inner.chunk_store.mutex().lock();
+
+            if chunk_path.exists() {
+                status.disk_bytes += object.size;
+            } else if object.last_modified < min_atime {
+                unused.push((digest, object));
+            } else {
+                status.pending_bytes += object.size;
+            }
+        }
+
+        // the mutex is only held for a single delete at a time, so inserts are not blocked for
+        // the whole sweep
+        for (digest, object) in unused {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let (chunk_path, _digest_str) = self.inner.chunk_store.chunk_path(&digest);
+
+            // inserts create the marker under the mutex before uploading, so holding it until
+            // the object is deleted ensures we never remove a chunk which is being re-inserted
+            let _lock = self.inner.chunk_store.mutex().lock();
+
+            if chunk_path.exists() {
+                status.disk_bytes += object.size;
+                continue;
+            }
+
+            proxmox_async::runtime::block_on(client.delete_object(&object.key))?;
+            status.removed_bytes += object.size;
+        }
+
+        Ok(())
+    }
+
     /// Updates the protection status of the specified snapshot.
     pub fn update_protection(&self, backup_dir: &BackupDir, protection: bool) -> Result<(), Error> {
         let full_path = backup_dir.full_path();
@@ -1193,22 +1971,217 @@ impl DataStore {
 
         let protected_path = backup_dir.protected_file();
         if protection {
-            std::fs::File::create(protected_path)
+            std::fs::File::create(&protected_path)
                 .map_err(|err| format_err!("could not create protection file: {}", err))?;
-        } else if let Err(err) = std::fs::remove_file(protected_path) {
-            // ignore error for non-existing file
-            if err.kind() != std::io::ErrorKind::NotFound {
-                bail!("could not remove protection file: {}", err);
+            self.upload_file_to_backend(&protected_path)?;
+        } else {
+            if let Err(err) = std::fs::remove_file(&protected_path) {
+                // ignore error for non-existing file
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    bail!("could not remove protection file: {}", err);
+                }
             }
+            self.remove_path_from_backend(&protected_path)?;
         }
 
         Ok(())
     }
 
+    /// Extends the retention lock of the specified snapshot.
+    ///
+    /// An active lock can never be shortened or removed, only extended.
+    pub fn update_retention_lock(
+        &self,
+        backup_dir: &BackupDir,
+        locked_until: i64,
+    ) -> Result<(), Error> {
+        let full_path = backup_dir.full_path();
+
+        if !full_path.exists() {
+            bail!("snapshot {} does not exist!", backup_dir.dir());
+        }
+
+        let _guard = lock_dir_noblock(&full_path, "snapshot", "possibly running or in use")?;
+
+        if locked_until <= proxmox_time::epoch_i64() {
+            bail!("retention lock must end in the future");
+        }
+
+        if let Some(current) = backup_dir.retention_locked_until()? {
+            if current > locked_until {
+                bail!(
+                    "cannot shorten retention lock of snapshot {} (locked until {})",
+                    backup_dir.dir(),
+                    proxmox_time::epoch_to_rfc3339_utc(current)?,
+                );
+            }
+        }
+
+        self.write_retention_lock(backup_dir, locked_until)
+    }
+
+    /// Applies the default retention lock of the snapshot's namespace to a new snapshot.
+    ///
+    /// The caller must hold the snapshot lock.
+    pub fn apply_default_retention_lock(&self, backup_dir: &BackupDir) -> Result<(), Error> {
+        let duration = match self.default_retention_lock(backup_dir.backup_ns())? {
+            Some(duration) if duration > 0 => duration,
+            _ => return Ok(()),
+        };
+
+        let locked_until = backup_dir.backup_time() + duration;
+        if locked_until <= proxmox_time::epoch_i64() {
+            return Ok(());
+        }
+
+        if let Some(current) = backup_dir.retention_locked_until()? {
+            if current >= locked_until {
+                return Ok(());
+            }
+        }
+
+        self.write_retention_lock(backup_dir, locked_until)
+    }
+
+    fn write_retention_lock(&self, backup_dir: &BackupDir, locked_until: i64) -> Result<(), Error> {
+        let path = backup_dir.retention_lock_file();
+
+        let backup_user = pbs_config::backup_user()?;
+        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+        let options = CreateOptions::new()
+            .perm(mode)
+            .owner(backup_user.uid)
+            .group(backup_user.gid);
+
+        replace_file(&path, locked_until.to_string().as_bytes(), options, true)
+            .map_err(|err| format_err!("could not write retention lock - {err}"))?;
+        self.upload_file_to_backend(&path)
+    }
+
+    /// Returns the default retention lock in seconds for new snapshots in `ns`.
+    ///
+    /// The setting of the nearest namespace wins, the datastore configuration is the fallback.
+    pub fn default_retention_lock(&self, ns: &BackupNamespace) -> Result<Option<i64>, Error> {
+        let mut ns = ns.clone();
+        while !ns.is_root() {
+            if let Some(span) = self.namespace_retention_lock(&ns)? {
+                let span: TimeSpan = span.parse()?;
+                return Ok(Some(f64::from(span) as i64));
+            }
+            ns.pop();
+        }
+        Ok(self.inner.retention_lock)
+    }
+
+    /// Returns the default retention lock configured directly on a (non-root) namespace.
+    pub fn namespace_retention_lock(&self, ns: &BackupNamespace) -> Result<Option<String>, Error> {
+        if ns.is_root() {
+            return Ok(None);
+        }
+        let path = self
+            .namespace_path(ns)
+            .join(NAMESPACE_RETENTION_LOCK_FILE_NAME);
+        Ok(file_read_optional_string(path)?.map(|span| span.trim().to_string()))
+    }
+
+    /// Sets or clears the default retention lock of a (non-root) namespace.
+    pub fn set_namespace_retention_lock(
+        &self,
+        ns: &BackupNamespace,
+        span: Option<&str>,
+    ) -> Result<(), Error> {
+        if ns.is_root() {
+            bail!("use the datastore config for the default retention lock of the root namespace");
+        }
+        if !self.namespace_exists(ns) {
+            bail!("namespace '{ns}' does not exist");
+        }
+
+        let path = self
+            .namespace_path(ns)
+            .join(NAMESPACE_RETENTION_LOCK_FILE_NAME);
+
+        match span {
+            Some(span) => {
+                let _: TimeSpan = span.parse()?;
+
+                let backup_user = pbs_config::backup_user()?;
+                let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+                let options = CreateOptions::new()
+                    .perm(mode)
+                    .owner(backup_user.uid)
+                    .group(backup_user.gid);
+
+                replace_file(&path, span.as_bytes(), options, false)
+            }
+            None => match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    bail!("could not remove default retention lock - {err}")
+                }
+                _ => Ok(()),
+            },
+        }
+    }
+
     pub fn verify_new(&self) -> bool {
         self.inner.verify_new
     }
 
+    /// Maximum read rate of the chunk store scrub job in bytes per second.
+    pub fn scrub_rate_limit(&self) -> Option<u64> {
+        self.inner.scrub_rate_limit
+    }
+
+    /// Returns true if the datastore keeps a mirror copy of its chunks.
+    pub fn has_chunk_mirror(&self) -> bool {
+        self.inner.chunk_mirror.is_some()
+    }
+
+    /// Load the mirror copy of a chunk, `None` if there is no mirror or no copy.
+    pub fn load_mirror_chunk(&self, digest: &[u8; 32]) -> Result<Option<DataBlob>, Error> {
+        match self.inner.chunk_mirror {
+            Some(ref mirror) => mirror.load_chunk(digest),
+            None => Ok(None),
+        }
+    }
+
+    /// Overwrite the mirror copy of a chunk with a known good `chunk`.
+    pub fn restore_mirror_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(), Error> {
+        let mirror = match self.inner.chunk_mirror {
+            Some(ref mirror) => mirror,
+            None => bail!("datastore '{}' has no chunk mirror", self.name()),
+        };
+        let _lock = self.inner.chunk_store.mutex().lock();
+        mirror.replace_chunk(chunk, digest)
+    }
+
+    /// Replace a corrupt chunk by its mirror copy.
+    ///
+    /// The copy has to pass `check` first. Returns false if there is no copy to repair from.
+    pub fn repair_chunk(
+        &self,
+        digest: &[u8; 32],
+        check: &dyn Fn(&DataBlob) -> Result<(), Error>,
+    ) -> Result<bool, Error> {
+        let copy = match self.load_mirror_chunk(digest)? {
+            Some(copy) => copy,
+            None => return Ok(false),
+        };
+
+        check(&copy).map_err(|err| format_err!("mirror copy is corrupt too - {err}"))?;
+
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+        let _lock = self.inner.chunk_store.mutex().lock();
+        replace_file(&chunk_path, copy.raw_data(), CreateOptions::new(), true).map_err(|err| {
+            format_err!(
+                "restoring chunk {digest_str} on store '{}' failed - {err}",
+                self.name()
+            )
+        })?;
+
+        Ok(true)
+    }
+
     /// returns a list of chunks sorted by their inode number on disk chunks that couldn't get
     /// stat'ed are placed at the end of the list
     pub fn get_chunks_in_order<F, A>(
@@ -1355,6 +2328,15 @@ impl DataStore {
         let (mut config, _digest) = pbs_config::datastore::config()?;
         let mut datastore_config: DataStoreConfig = config.lookup("datastore", name)?;
 
+        if destroy_data {
+            ensure_datastore_is_mounted(&datastore_config)?;
+
+            let base = datastore_config.absolute_path();
+            if let Some(path) = find_retention_locked_snapshot(&base)? {
+                bail!("cannot destroy datastore, snapshot {path:?} has an active retention lock");
+            }
+        }
+
         datastore_config.maintenance_mode = Some("type=delete".to_string());
         config.set_data(name, "datastore", &datastore_config)?;
         pbs_config::datastore::save_config(&config)?;
@@ -1366,7 +2348,7 @@ impl DataStore {
             bail!("datastore is currently in use");
         }
 
-        let base = PathBuf::from(&datastore_config.path);
+        let base = datastore_config.absolute_path();
 
         let mut ok = true;
         if destroy_data {
@@ -1386,10 +2368,18 @@ impl DataStore {
             remove("host", &mut ok);
 
             if ok {
-                if let Err(err) = std::fs::remove_file(base.join(".gc-status")) {
-                    if err.kind() != io::ErrorKind::NotFound {
-                        task_warn!(worker, "failed to remove .gc-status file: {err}");
-                        ok = false;
+                for file in [
+                    ".gc-status",
+                    GC_CHECKPOINT_FILENAME,
+                    GC_PART_USAGE_FILENAME,
+                    LIVE_CHUNK_LIST_FILENAME,
+                    PARTIAL_LIVE_CHUNK_LIST_FILENAME,
+                ] {
+                    if let Err(err) = std::fs::remove_file(base.join(file)) {
+                        if err.kind() != io::ErrorKind::NotFound {
+                            task_warn!(worker, "failed to remove {file} file: {err}");
+                            ok = false;
+                        }
                     }
                 }
             }
@@ -1398,6 +2388,31 @@ impl DataStore {
             if ok {
                 remove(".chunks", &mut ok);
             }
+
+            if let (true, Some(path)) = (ok, &datastore_config.chunk_mirror) {
+                let mirror_dir = Path::new(path).join(".chunks");
+                if let Err(err) = std::fs::remove_dir_all(&mirror_dir) {
+                    if err.kind() != io::ErrorKind::NotFound {
+                        task_warn!(worker, "failed to remove {mirror_dir:?}: {err}");
+                        ok = false;
+                    }
+                }
+            }
+
+            if ok {
+                let backend = DatastoreBackend::from_config(&datastore_config)?;
+                if let DatastoreBackend::S3(client) = backend {
+                    task_log!(
+                        worker,
+                        "Deleting objects in bucket '{}'...",
+                        client.bucket()
+                    );
+                    if let Err(err) = proxmox_async::runtime::block_on(client.delete_prefix("")) {
+                        task_warn!(worker, "failed to remove objects from bucket: {err}");
+                        ok = false;
+                    }
+                }
+            }
         }
 
         // now the config
@@ -1448,3 +2463,387 @@ impl DataStore {
         Ok(())
     }
 }
+
+/// Removes the settings stored in a namespace directory if they are the only thing left in it.
+fn remove_namespace_settings(ns_path: &Path) {
+    let only_settings = match std::fs::read_dir(ns_path) {
+        Ok(mut entries) => entries.all(|entry| match entry {
+            Ok(entry) => entry.file_name() == NAMESPACE_RETENTION_LOCK_FILE_NAME,
+            Err(_) => false,
+        }),
+        Err(_) => false,
+    };
+
+    if only_settings {
+        let _ = std::fs::remove_file(ns_path.join(NAMESPACE_RETENTION_LOCK_FILE_NAME));
+    }
+}
+
+/// Returns the path of a snapshot below `base` whose retention lock has not expired yet.
+fn find_retention_locked_snapshot(base: &Path) -> Result<Option<PathBuf>, Error> {
+    let now = proxmox_time::epoch_i64();
+
+    for subdir in ["ns", "ct", "vm", "host"] {
+        let path = base.join(subdir);
+        if !path.exists() {
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(&path) {
+            let entry = entry?;
+            if entry.file_name() != RETENTION_LOCK_FILE_NAME || !entry.file_type().is_file() {
+                continue;
+            }
+
+            let locked_until = std::fs::read_to_string(entry.path())?
+                .trim()
+                .parse::<i64>()
+                .unwrap_or(i64::MAX);
+            if locked_until > now {
+                return Ok(entry.path().parent().map(Path::to_path_buf));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::convert::Infallible;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request, Response, StatusCode};
+
+    use crate::prune::{compute_prune_info, PruneMark};
+    use crate::BackupInfo;
+    use pbs_api_types::{KeepOptions, S3Client as S3ClientEntry, S3ClientConfig};
+
+    struct TestWorker;
+
+    impl WorkerTaskContext for TestWorker {
+        fn abort_requested(&self) -> bool {
+            false
+        }
+
+        fn shutdown_requested(&self) -> bool {
+            false
+        }
+
+        fn log(&self, _level: log::Level, message: &std::fmt::Arguments) {
+            println!("{message}");
+        }
+    }
+
+    type TestBucket = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    // minimal in-memory S3 server, only supporting path style requests for a single bucket
+    async fn handle_s3_request(bucket: TestBucket, req: Request<Body>) -> Response<Body> {
+        let method = req.method().clone();
+        let key = req
+            .uri()
+            .path()
+            .trim_start_matches("/test-bucket")
+            .trim_start_matches('/')
+            .to_string();
+        let query = req.uri().query().unwrap_or_default().to_string();
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+
+        let mut bucket = bucket.lock().unwrap();
+        let (status, body) = match (method, key.is_empty()) {
+            (Method::GET, true) => {
+                let prefix = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("prefix="))
+                    .unwrap_or_default();
+                let prefix = percent_encoding::percent_decode_str(prefix).decode_utf8_lossy();
+                let now = proxmox_time::epoch_to_rfc3339_utc(proxmox_time::epoch_i64()).unwrap();
+                let mut list = String::from("<ListBucketResult><IsTruncated>false</IsTruncated>");
+                for (key, data) in bucket.range(prefix.to_string()..) {
+                    if !key.starts_with(prefix.as_ref()) {
+                        break;
+                    }
+                    list.push_str(&format!(
+                        "<Contents><Key>{key}</Key><LastModified>{now}</LastModified><Size>{}</Size></Contents>",
+                        data.len(),
+                    ));
+                }
+                list.push_str("</ListBucketResult>");
+                (StatusCode::OK, Body::from(list))
+            }
+            (Method::PUT, false) => {
+                bucket.insert(key, body.to_vec());
+                (StatusCode::OK, Body::empty())
+            }
+            (Method::GET, false) => match bucket.get(&key) {
+                Some(data) => (StatusCode::OK, Body::from(data.clone())),
+                None => (StatusCode::NOT_FOUND, Body::empty()),
+            },
+            (Method::HEAD, false) if bucket.contains_key(&key) => (StatusCode::OK, Body::empty()),
+            (Method::HEAD, false) => (StatusCode::NOT_FOUND, Body::empty()),
+            (Method::DELETE, false) => {
+                bucket.remove(&key);
+                (StatusCode::NO_CONTENT, Body::empty())
+            }
+            _ => (StatusCode::BAD_REQUEST, Body::empty()),
+        };
+
+        Response::builder().status(status).body(body).unwrap()
+    }
+
+    fn test_s3_client(bucket: TestBucket) -> Result<Arc<S3Client>, Error> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        proxmox_async::runtime::get_runtime().spawn(async move {
+            let service = make_service_fn(move |_| {
+                let bucket = bucket.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        let bucket = bucket.clone();
+                        async move { Ok::<_, Infallible>(handle_s3_request(bucket, req).await) }
+                    }))
+                }
+            });
+            let server = hyper::Server::from_tcp(listener).unwrap().serve(service);
+            if let Err(err) = server.await {
+                eprintln!("test s3 server failed - {err}");
+            }
+        });
+
+        let entry = S3ClientEntry {
+            id: "test".to_string(),
+            secret_key: "secret".to_string(),
+            config: S3ClientConfig {
+                comment: None,
+                endpoint: "127.0.0.1".to_string(),
+                port: Some(port),
+                region: None,
+                access_key: "test".to_string(),
+                fingerprint: None,
+                path_style: Some(true),
+                use_http: Some(true),
+            },
+        };
+        S3Client::with_config(entry, "test-bucket")
+    }
+
+    fn test_datastore(path: &Path) -> Result<Arc<DataStore>, Error> {
+        test_datastore_with_backend(path, DatastoreBackend::Filesystem)
+    }
+
+    fn test_datastore_with_backend(
+        path: &Path,
+        backend: DatastoreBackend,
+    ) -> Result<Arc<DataStore>, Error> {
+        let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+        let chunk_store = ChunkStore::create(
+            "retention-lock-test",
+            path,
+            user.uid,
+            user.gid,
+            None,
+            DatastoreFSyncLevel::None,
+        )?;
+
+        let mut inner = unsafe { DataStoreImpl::new_test() };
+        let inner_mut = Arc::get_mut(&mut inner).unwrap();
+        inner_mut.chunk_store = Arc::new(chunk_store);
+        inner_mut.backend = backend;
+
+        Ok(Arc::new(DataStore {
+            inner,
+            operation: None,
+        }))
+    }
+
+    fn create_snapshot(datastore: &Arc<DataStore>, time: i64) -> Result<BackupDir, Error> {
+        let snapshot = datastore.backup_dir_from_parts(
+            BackupNamespace::root(),
+            BackupType::Vm,
+            "100",
+            time,
+        )?;
+        std::fs::create_dir_all(snapshot.full_path())?;
+        std::fs::write(snapshot.full_path().join(MANIFEST_BLOB_NAME), b"")?;
+        Ok(snapshot)
+    }
+
+    #[test]
+    fn test_gc_part_usage() {
+        let mut usage = GarbageCollectionPartUsage {
+            parts: vec![None; 4],
+        };
+        assert_eq!(usage.total(), (0, 0));
+
+        // parts not swept yet are extrapolated
+        usage.parts[1] = Some((1000, 10));
+        assert_eq!(usage.total(), (4000, 40));
+
+        usage.parts[2] = Some((3000, 20));
+        assert_eq!(usage.total(), (8000, 60));
+
+        usage.parts[0] = Some((2000, 30));
+        usage.parts[3] = Some((2000, 20));
+        assert_eq!(usage.total(), (8000, 80));
+    }
+
+    #[test]
+    fn test_s3_gc_sweep() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("s3-gc-sweep-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+
+        let bucket = TestBucket::default();
+        let client = test_s3_client(bucket.clone())?;
+        let datastore = test_datastore_with_backend(&path, DatastoreBackend::S3(client.clone()))?;
+
+        let encode_chunk = |data: &[u8]| -> Result<(DataBlob, [u8; 32]), Error> {
+            Ok((
+                DataBlob::encode(data, None, false)?,
+                openssl::sha::sha256(data),
+            ))
+        };
+
+        let (used, used_digest) = encode_chunk(b"used")?;
+        assert_eq!(
+            datastore.insert_chunk(&used, &used_digest)?,
+            (false, used.raw_size())
+        );
+        assert!(datastore.insert_chunk(&used, &used_digest)?.0);
+
+        // marker removed by the sweep of the local chunk store
+        let (unused, unused_digest) = encode_chunk(b"unused")?;
+        datastore.insert_chunk(&unused, &unused_digest)?;
+        datastore
+            .inner
+            .chunk_store
+            .remove_chunk_marker(&unused_digest)?;
+
+        // object which never had a marker, e.g. uploaded by an interrupted backup
+        let (orphan, orphan_digest) = encode_chunk(b"orphan")?;
+        let upload = client.put_object(
+            &s3::chunk_object_key(&orphan_digest),
+            orphan.raw_data().to_vec(),
+        );
+        proxmox_async::runtime::block_on(upload)?;
+
+        // objects modified after the cutoff are kept
+        let mut status = GarbageCollectionStatus::default();
+        let now = proxmox_time::epoch_i64();
+        datastore.sweep_unused_backend_chunks(&client, now - 3600, &mut status, &TestWorker)?;
+        assert_eq!(bucket.lock().unwrap().len(), 3);
+        assert_eq!(status.removed_bytes, 0);
+        assert_eq!(status.pending_bytes, unused.raw_size() + orphan.raw_size());
+
+        let mut status = GarbageCollectionStatus::default();
+        datastore.sweep_unused_backend_chunks(&client, now + 3600, &mut status, &TestWorker)?;
+        assert_eq!(
+            bucket.lock().unwrap().keys().cloned().collect::<Vec<_>>(),
+            vec![s3::chunk_object_key(&used_digest)],
+        );
+        assert_eq!(status.disk_bytes, used.raw_size());
+        assert_eq!(status.removed_bytes, unused.raw_size() + orphan.raw_size());
+
+        // a marker without object does not prevent uploading the chunk again
+        datastore
+            .inner
+            .chunk_store
+            .insert_chunk_marker(&unused_digest)?;
+        assert_eq!(
+            datastore.insert_chunk(&unused, &unused_digest)?,
+            (false, unused.raw_size())
+        );
+        assert!(bucket
+            .lock()
+            .unwrap()
+            .contains_key(&s3::chunk_object_key(&unused_digest)));
+
+        let _ = std::fs::remove_dir_all(&path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention_lock() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("retention-lock-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+
+        let datastore = test_datastore(&path)?;
+        let now = proxmox_time::epoch_i64();
+
+        let old = create_snapshot(&datastore, now - 7200)?;
+        let locked = create_snapshot(&datastore, now - 3600)?;
+        let _new = create_snapshot(&datastore, now - 60)?;
+
+        assert!(!locked.is_retention_locked());
+        assert_eq!(find_retention_locked_snapshot(&path)?, None);
+
+        // a lock has to end in the future
+        assert!(datastore.update_retention_lock(&locked, now - 1).is_err());
+        assert_eq!(locked.retention_locked_until()?, None);
+
+        // written directly, updating the lock would chown it to the backup user
+        std::fs::write(locked.retention_lock_file(), (now + 7200).to_string())?;
+        assert!(locked.is_retention_locked());
+        assert!(locked.is_protected());
+        assert_eq!(
+            find_retention_locked_snapshot(&path)?,
+            Some(locked.full_path())
+        );
+
+        // it can never be shortened
+        assert!(datastore
+            .update_retention_lock(&locked, now + 3600)
+            .is_err());
+        assert_eq!(locked.retention_locked_until()?, Some(now + 7200));
+
+        // neither forced removal nor prune remove the locked snapshot
+        assert!(locked.destroy(true).is_err());
+        assert!(locked.full_path().exists());
+
+        let group =
+            datastore.backup_group_from_parts(BackupNamespace::root(), BackupType::Vm, "100");
+        let list = group.list_backups()?;
+        let keep = KeepOptions {
+            keep_last: Some(1),
+            ..Default::default()
+        };
+        for (info, mark) in compute_prune_info(list, &keep)? {
+            let expected = match info.backup_dir.backup_time() {
+                time if time == old.backup_time() => PruneMark::Remove,
+                time if time == locked.backup_time() => PruneMark::Protected,
+                _ => PruneMark::Keep,
+            };
+            assert!(mark == expected);
+        }
+
+        // an unreadable lock counts as active
+        std::fs::write(locked.retention_lock_file(), b"garbage")?;
+        assert!(locked.is_retention_locked());
+        assert!(locked.destroy(true).is_err());
+        assert!(datastore
+            .update_retention_lock(&locked, now + 3600)
+            .is_err());
+        assert!(find_retention_locked_snapshot(&path)?.is_some());
+
+        // an expired lock frees the snapshot again
+        std::fs::write(locked.retention_lock_file(), (now - 1).to_string())?;
+        assert!(!locked.is_retention_locked());
+        assert!(!locked.is_protected());
+        assert_eq!(find_retention_locked_snapshot(&path)?, None);
+
+        let info = BackupInfo::new(locked.clone())?;
+        assert!(!info.protected);
+
+        locked.destroy(true)?;
+        assert!(!locked.full_path().exists());
+
+        let _ = std::fs::remove_dir_all(&path);
+
+        Ok(())
+    }
+}
@@ -17,6 +17,12 @@ pub const ENCRYPTED_BLOB_MAGIC_1_0: [u8; 8] = [123, 103, 133, 190, 34, 45, 76, 2
 // openssl::sha::sha256(b"Proxmox Backup zstd compressed encrypted blob v1.0")[0..8]
 pub const ENCR_COMPR_BLOB_MAGIC_1_0: [u8; 8] = [230, 89, 27, 191, 11, 191, 216, 11];
 
+// openssl::sha::sha256(b"Proxmox Backup compressed blob v2.0")[0..8]
+pub const COMPRESSED_BLOB_MAGIC_2_0: [u8; 8] = [85, 81, 186, 134, 248, 21, 212, 4];
+
+// openssl::sha::sha256(b"Proxmox Backup compressed encrypted blob v2.0")[0..8]
+pub const ENCR_COMPR_BLOB_MAGIC_2_0: [u8; 8] = [131, 123, 160, 189, 42, 247, 213, 132];
+
 // openssl::sha::sha256(b"Proxmox Backup fixed sized chunk index v1.0")[0..8]
 pub const FIXED_SIZED_CHUNK_INDEX_1_0: [u8; 8] = [47, 127, 65, 237, 145, 253, 15, 205];
 
@@ -59,6 +65,46 @@ pub struct EncryptedDataBlobHeader {
     pub tag: [u8; 16],
 }
 
+/// Compression algorithm id for zstd compressed v2.0 blobs
+pub const BLOB_COMPRESSION_ZSTD: u8 = 1;
+/// Compression algorithm id for lz4 compressed v2.0 blobs
+pub const BLOB_COMPRESSION_LZ4: u8 = 2;
+
+/// Compression information of v2.0 blobs
+///
+/// Version 2.0 blobs record the compression algorithm (and the level
+/// used to produce the data) in the header, so that algorithms other
+/// than zstd can be used:
+///
+/// (MAGIC || CRC32 || ALGORITHM || LEVEL || RESERVED || Data)
+#[derive(Endian, Clone, Copy)]
+#[repr(C, packed)]
+pub struct BlobCompressionHeader {
+    pub algorithm: u8,
+    pub level: u8,
+    pub reserved: [u8; 2],
+}
+
+/// Compressed data blob binary storage format (v2.0)
+#[derive(Endian)]
+#[repr(C, packed)]
+pub struct CompressedDataBlobHeader {
+    pub head: DataBlobHeader,
+    pub compression: BlobCompressionHeader,
+}
+
+/// Compressed and encrypted data blob binary storage format (v2.0)
+///
+/// (MAGIC || CRC32 || ALGORITHM || LEVEL || RESERVED || IV || TAG || EncryptedData).
+#[derive(Endian)]
+#[repr(C, packed)]
+pub struct EncrCompressedDataBlobHeader {
+    pub head: DataBlobHeader,
+    pub compression: BlobCompressionHeader,
+    pub iv: [u8; 16],
+    pub tag: [u8; 16],
+}
+
 /// Header size for different file types
 ///
 /// Panics on unknown magic numbers.
@@ -68,6 +114,8 @@ pub fn header_size(magic: &[u8; 8]) -> usize {
         COMPRESSED_BLOB_MAGIC_1_0 => std::mem::size_of::<DataBlobHeader>(),
         ENCRYPTED_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
         ENCR_COMPR_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
+        COMPRESSED_BLOB_MAGIC_2_0 => std::mem::size_of::<CompressedDataBlobHeader>(),
+        ENCR_COMPR_BLOB_MAGIC_2_0 => std::mem::size_of::<EncrCompressedDataBlobHeader>(),
         _ => panic!("unknown blob magic"),
     }
 }
@@ -172,6 +172,7 @@ pub mod cached_chunk_reader;
 pub mod catalog;
 pub mod checksum_reader;
 pub mod checksum_writer;
+pub mod chunk_mirror;
 pub mod chunk_stat;
 pub mod chunk_store;
 pub mod chunker;
@@ -182,12 +183,15 @@ pub mod data_blob_reader;
 pub mod data_blob_writer;
 pub mod file_formats;
 pub mod index;
+pub mod live_chunks;
 pub mod manifest;
 pub mod paperkey;
 pub mod prune;
 pub mod read_chunk;
+pub mod s3;
 pub mod store_progress;
 pub mod task_tracking;
+pub mod usage;
 
 pub mod dynamic_index;
 pub mod fixed_index;
@@ -195,6 +199,7 @@ pub mod fixed_index;
 pub use backup_info::{BackupDir, BackupGroup, BackupInfo};
 pub use checksum_reader::ChecksumReader;
 pub use checksum_writer::ChecksumWriter;
+pub use chunk_mirror::ChunkMirror;
 pub use chunk_store::ChunkStore;
 pub use chunker::Chunker;
 pub use crypt_reader::CryptReader;
@@ -206,7 +211,10 @@ pub use manifest::BackupManifest;
 pub use store_progress::StoreProgress;
 
 mod datastore;
-pub use datastore::{check_backup_owner, DataStore};
+pub use datastore::{
+    check_backup_owner, ensure_datastore_is_mounted, get_datastore_mount_status, DataStore,
+    DatastoreBackend,
+};
 
 mod hierarchy;
 pub use hierarchy::{
@@ -0,0 +1,340 @@
+//! Sorted on-disk list of the chunks referenced by index files
+//!
+//! Garbage collection in `digest-list` mode records the digests of all chunks which are still
+//! in use in this list during phase 1, instead of updating the chunks' access time. Phase 2
+//! then streams the list while iterating over the chunk store, which visits the chunk prefix
+//! directories in ascending order, so only the digests of one prefix are kept in memory.
+//!
+//! The file simply consists of the sorted, deduplicated 32 byte digests. To checkpoint an
+//! interrupted phase 1, the digests collected so far can be appended (unsorted) to a partial
+//! list, which gets merged when the final list is written. The checkpoint records the length of
+//! the partial list, a resumed run truncates it to that length before appending again.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::path::Path;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_sys::fs::{make_tmp_file, CreateOptions};
+
+/// File name of the live chunk list, relative to the datastore base directory.
+pub const LIVE_CHUNK_LIST_FILENAME: &str = ".gc-live-chunks";
+/// File name of the partial live chunk list, relative to the datastore base directory.
+pub const PARTIAL_LIVE_CHUNK_LIST_FILENAME: &str = ".gc-live-chunks.partial";
+
+/// Collects chunk digests in memory and writes them as sorted list.
+///
+/// Digests are deduplicated on insertion, so memory usage grows with the number of unique
+/// chunks since the last checkpoint, not with the number of references.
+#[derive(Default)]
+pub struct LiveChunkCollector {
+    digests: HashSet<[u8; 32]>,
+}
+
+impl LiveChunkCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `digest` as used.
+    pub fn insert(&mut self, digest: &[u8; 32]) {
+        self.digests.insert(*digest);
+    }
+
+    /// Append the digests collected so far to the partial list at `partial_path` and release
+    /// them from memory.
+    ///
+    /// Returns the length of the partial list, which has to be passed to [`truncate_partial`]
+    /// when resuming from this point.
+    pub fn persist_partial(&mut self, partial_path: &Path) -> Result<u64, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(partial_path)
+            .map_err(|err| format_err!("unable to open partial list {partial_path:?} - {err}"))?;
+
+        let mut writer = BufWriter::with_capacity(1024 * 1024, file);
+        for digest in self.digests.drain() {
+            writer.write_all(&digest)?;
+        }
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+
+        Ok(file.metadata()?.len())
+    }
+
+    /// Sort and deduplicate the collected digests and atomically write them to `path`.
+    ///
+    /// Digests from the partial list at `partial_path` are included, the partial list is
+    /// removed afterwards. Returns the number of unique digests.
+    pub fn write(
+        self,
+        path: &Path,
+        partial_path: &Path,
+        options: CreateOptions,
+    ) -> Result<usize, Error> {
+        let mut digests: Vec<[u8; 32]> = self.digests.into_iter().collect();
+
+        match std::fs::read(partial_path) {
+            Ok(data) if data.len() % 32 != 0 => {
+                bail!(
+                    "partial list {partial_path:?} has invalid size {}",
+                    data.len()
+                );
+            }
+            Ok(data) => digests.extend(
+                data.chunks_exact(32)
+                    .map(|digest| <[u8; 32]>::try_from(digest).unwrap()),
+            ),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => bail!("unable to read partial list {partial_path:?} - {err}"),
+        }
+
+        digests.sort_unstable();
+        digests.dedup();
+
+        let (fd, tmp_path) = make_tmp_file(path, options)?;
+        let file = unsafe { File::from_raw_fd(fd.into_raw_fd()) };
+
+        let try_block = || -> Result<(), Error> {
+            let mut writer = BufWriter::with_capacity(1024 * 1024, file);
+            for digest in digests.iter() {
+                writer.write_all(digest)?;
+            }
+            let file = writer.into_inner().map_err(|err| err.into_error())?;
+            file.sync_all()?;
+            Ok(())
+        };
+
+        if let Err(err) = try_block() {
+            let _ = nix::unistd::unlink(&tmp_path);
+            bail!("writing live chunk list {path:?} failed - {err}");
+        }
+
+        if let Err(err) = std::fs::rename(&tmp_path, path) {
+            let _ = nix::unistd::unlink(&tmp_path);
+            bail!("atomic rename of live chunk list {path:?} failed - {err}");
+        }
+
+        remove_partial(partial_path)?;
+
+        Ok(digests.len())
+    }
+}
+
+/// Remove a (stale) partial list.
+pub fn remove_partial(partial_path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(partial_path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => bail!("unable to remove partial list {partial_path:?} - {err}"),
+    }
+}
+
+/// Truncate the partial list to the `len` recorded by the checkpoint to resume from.
+///
+/// Digests appended after the checkpoint, including a torn last append, are dropped, they get
+/// collected again by the resumed run.
+pub fn truncate_partial(partial_path: &Path, len: u64) -> Result<(), Error> {
+    let file = match OpenOptions::new().write(true).open(partial_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && len == 0 => return Ok(()),
+        Err(err) => bail!("unable to open partial list {partial_path:?} - {err}"),
+    };
+
+    let size = file.metadata()?.len();
+    if size < len {
+        bail!("partial list {partial_path:?} is shorter than its checkpoint ({size} < {len})");
+    }
+
+    if size > len {
+        file.set_len(len)?;
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Reader for a live chunk list written by [`LiveChunkCollector`].
+pub struct LiveChunkList {
+    reader: BufReader<File>,
+    // first digest not yet assigned to a prefix
+    next: Option<[u8; 32]>,
+    // currently loaded chunk prefix and its digests
+    prefix: Option<u16>,
+    current: Vec<[u8; 32]>,
+}
+
+fn chunk_prefix(digest: &[u8; 32]) -> u16 {
+    u16::from_be_bytes([digest[0], digest[1]])
+}
+
+impl LiveChunkList {
+    /// Open the live chunk list at `path`.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)
+            .map_err(|err| format_err!("unable to open live chunk list {path:?} - {err}"))?;
+
+        let size = file.metadata()?.len();
+        if size % 32 != 0 {
+            bail!("live chunk list {path:?} has invalid size {size}");
+        }
+
+        let mut list = Self {
+            reader: BufReader::with_capacity(1024 * 1024, file),
+            next: None,
+            prefix: None,
+            current: Vec::new(),
+        };
+        list.next = list.read_digest()?;
+
+        Ok(list)
+    }
+
+    fn read_digest(&mut self) -> Result<Option<[u8; 32]>, Error> {
+        let mut digest = [0u8; 32];
+        match self.reader.read_exact(&mut digest) {
+            Ok(()) => Ok(Some(digest)),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns whether `digest` is part of the list.
+    ///
+    /// Lookups must happen in ascending chunk prefix order (as the chunk iterator does), the
+    /// order within a prefix does not matter.
+    pub fn contains(&mut self, digest: &[u8; 32]) -> Result<bool, Error> {
+        let prefix = chunk_prefix(digest);
+
+        if self.prefix != Some(prefix) {
+            if matches!(self.prefix, Some(current) if current > prefix) {
+                bail!("live chunk list lookup out of order");
+            }
+
+            self.current.clear();
+            while let Some(next) = self.next {
+                let next_prefix = chunk_prefix(&next);
+                if next_prefix > prefix {
+                    break;
+                }
+                if next_prefix == prefix {
+                    self.current.push(next);
+                }
+                self.next = self.read_digest()?;
+            }
+            self.prefix = Some(prefix);
+        }
+
+        Ok(self.current.binary_search(digest).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_live_chunk_list() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("live-chunks-test-{}", std::process::id()));
+        let partial_path = path.with_extension("partial");
+
+        let mut collector = LiveChunkCollector::new();
+        let mut digests = Vec::new();
+        for i in 0..1000u32 {
+            let digest = openssl::sha::sha256(&i.to_le_bytes());
+            collector.insert(&digest);
+            // insert some duplicates
+            if i % 3 == 0 {
+                collector.insert(&digest);
+            }
+            digests.push(digest);
+            // checkpoint in between, like an interrupted GC would
+            if i == 500 {
+                collector.persist_partial(&partial_path)?;
+                collector = LiveChunkCollector::new();
+            }
+        }
+        assert_eq!(
+            collector.write(&path, &partial_path, CreateOptions::new())?,
+            1000
+        );
+        assert!(!partial_path.exists());
+
+        digests.sort_unstable();
+
+        let mut list = LiveChunkList::open(&path)?;
+        for digest in digests.iter() {
+            assert!(list.contains(digest)?);
+            let mut missing = *digest;
+            missing[31] ^= 0xff;
+            assert!(!list.contains(&missing)?);
+        }
+
+        // going back to a lower prefix is not allowed
+        assert!(list.contains(&[0u8; 32]).is_err());
+
+        let _ = std::fs::remove_file(&path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_partial_list() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("live-chunks-torn-{}", std::process::id()));
+        let partial_path = path.with_extension("partial");
+        let _ = std::fs::remove_file(&partial_path);
+
+        let digests: Vec<[u8; 32]> = (0..100u32)
+            .map(|i| openssl::sha::sha256(&i.to_le_bytes()))
+            .collect();
+
+        let mut collector = LiveChunkCollector::new();
+        for digest in &digests[..50] {
+            collector.insert(digest);
+        }
+        let checkpoint_len = collector.persist_partial(&partial_path)?;
+        assert_eq!(checkpoint_len, 50 * 32);
+
+        // an interrupted append after the checkpoint leaves a torn record behind
+        let mut file = OpenOptions::new().append(true).open(&partial_path)?;
+        file.write_all(&digests[50])?;
+        file.write_all(&digests[51][..13])?;
+        drop(file);
+
+        // a partial list shorter than its checkpoint must not be used
+        assert!(truncate_partial(&partial_path, checkpoint_len + 64).is_err());
+
+        // the resumed run drops everything after the checkpoint and collects it again
+        truncate_partial(&partial_path, checkpoint_len)?;
+        assert_eq!(std::fs::metadata(&partial_path)?.len(), checkpoint_len);
+
+        let mut collector = LiveChunkCollector::new();
+        for digest in &digests[50..] {
+            collector.insert(digest);
+        }
+        assert_eq!(collector.persist_partial(&partial_path)?, 100 * 32);
+        assert_eq!(
+            LiveChunkCollector::new().write(&path, &partial_path, CreateOptions::new())?,
+            100
+        );
+
+        let mut sorted = digests.clone();
+        sorted.sort_unstable();
+        let mut list = LiveChunkList::open(&path)?;
+        for digest in sorted.iter() {
+            assert!(list.contains(digest)?);
+        }
+
+        // a missing partial list is fine for a checkpoint without one
+        truncate_partial(&partial_path, 0)?;
+        assert!(truncate_partial(&partial_path, 32).is_err());
+
+        let _ = std::fs::remove_file(&path);
+
+        Ok(())
+    }
+}
@@ -69,9 +69,7 @@ impl AsyncReadChunk for LocalChunkReader {
         digest: &'a [u8; 32],
     ) -> Pin<Box<dyn Future<Output = Result<DataBlob, Error>> + Send + 'a>> {
         Box::pin(async move {
-            let (path, _) = self.store.chunk_path(digest);
-
-            let raw_data = tokio::fs::read(&path).await?;
+            let raw_data = self.store.read_raw_chunk_data(digest).await?;
 
             let chunk = DataBlob::load_from_reader(&mut &raw_data[..])?;
             self.ensure_crypt_mode(chunk.crypt_mode()?)?;
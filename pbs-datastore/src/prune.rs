@@ -36,10 +36,14 @@ impl std::fmt::Display for PruneMark {
     }
 }
 
+type SelectFn = dyn Fn(&BackupInfo) -> Result<String, Error>;
+
+/// Marks the newest backup of up to `keep` selections, ignoring backups older than `oldest`.
 fn mark_selections<F: Fn(&BackupInfo) -> Result<String, Error>>(
     mark: &mut HashMap<PathBuf, PruneMark>,
     list: &[BackupInfo],
     keep: usize,
+    oldest: i64,
     select_id: F,
 ) -> Result<(), Error> {
     let mut include_hash = HashSet::new();
@@ -54,6 +58,9 @@ fn mark_selections<F: Fn(&BackupInfo) -> Result<String, Error>>(
     }
 
     for info in list {
+        if info.backup_dir.backup_time() < oldest {
+            break; // list is sorted newest first
+        }
         let backup_id = info.backup_dir.relative_path();
         if mark.get(&backup_id).is_some() {
             continue;
@@ -105,8 +112,17 @@ fn remove_incomplete_snapshots(mark: &mut HashMap<PathBuf, PruneMark>, list: &[B
 
 /// This filters incomplete and kept backups.
 pub fn compute_prune_info(
+    list: Vec<BackupInfo>,
+    options: &KeepOptions,
+) -> Result<Vec<(BackupInfo, PruneMark)>, Error> {
+    compute_prune_info_at(list, options, proxmox_time::epoch_i64())
+}
+
+/// Like [`compute_prune_info`], but evaluates the `keep-within` options relative to `now`.
+pub fn compute_prune_info_at(
     mut list: Vec<BackupInfo>,
     options: &KeepOptions,
+    now: i64,
 ) -> Result<Vec<(BackupInfo, PruneMark)>, Error> {
     let mut mark = HashMap::new();
 
@@ -114,44 +130,57 @@ pub fn compute_prune_info(
 
     remove_incomplete_snapshots(&mut mark, &list);
 
-    if let Some(keep_last) = options.keep_last {
-        mark_selections(&mut mark, &list, keep_last as usize, |info| {
-            Ok(info.backup_dir.backup_time_string().to_owned())
-        })?;
-    }
-
-    use proxmox_time::strftime_local;
+    let within: HashMap<&str, i64> = options.keep_within_options().collect();
+    let oldest_within = |key: &str| match within.get(key) {
+        Some(span) if *span > 0 => Some(now - span),
+        _ => None,
+    };
 
-    if let Some(keep_hourly) = options.keep_hourly {
-        mark_selections(&mut mark, &list, keep_hourly as usize, |info| {
-            strftime_local("%Y/%m/%d/%H", info.backup_dir.backup_time()).map_err(Error::from)
-        })?;
-    }
+    let select_time = |info: &BackupInfo| Ok(info.backup_dir.backup_time_string().to_owned());
 
-    if let Some(keep_daily) = options.keep_daily {
-        mark_selections(&mut mark, &list, keep_daily as usize, |info| {
-            strftime_local("%Y/%m/%d", info.backup_dir.backup_time()).map_err(Error::from)
-        })?;
+    if let Some(keep_last) = options.keep_last {
+        mark_selections(&mut mark, &list, keep_last as usize, i64::MIN, select_time)?;
     }
 
-    if let Some(keep_weekly) = options.keep_weekly {
-        mark_selections(&mut mark, &list, keep_weekly as usize, |info| {
-            // Note: Use iso-week year/week here. This year number
-            // might not match the calendar year number.
-            strftime_local("%G/%V", info.backup_dir.backup_time()).map_err(Error::from)
-        })?;
+    if let Some(oldest) = oldest_within("keep-within") {
+        mark_selections(&mut mark, &list, usize::MAX, oldest, select_time)?;
     }
 
-    if let Some(keep_monthly) = options.keep_monthly {
-        mark_selections(&mut mark, &list, keep_monthly as usize, |info| {
-            strftime_local("%Y/%m", info.backup_dir.backup_time()).map_err(Error::from)
-        })?;
-    }
+    use proxmox_time::strftime_local;
 
-    if let Some(keep_yearly) = options.keep_yearly {
-        mark_selections(&mut mark, &list, keep_yearly as usize, |info| {
-            strftime_local("%Y", info.backup_dir.backup_time()).map_err(Error::from)
-        })?;
+    let select_hour = |info: &BackupInfo| {
+        strftime_local("%Y/%m/%d/%H", info.backup_dir.backup_time()).map_err(Error::from)
+    };
+    let select_day = |info: &BackupInfo| {
+        strftime_local("%Y/%m/%d", info.backup_dir.backup_time()).map_err(Error::from)
+    };
+    let select_week = |info: &BackupInfo| {
+        // Note: Use iso-week year/week here. This year number
+        // might not match the calendar year number.
+        strftime_local("%G/%V", info.backup_dir.backup_time()).map_err(Error::from)
+    };
+    let select_month = |info: &BackupInfo| {
+        strftime_local("%Y/%m", info.backup_dir.backup_time()).map_err(Error::from)
+    };
+    let select_year = |info: &BackupInfo| {
+        strftime_local("%Y", info.backup_dir.backup_time()).map_err(Error::from)
+    };
+
+    let selections: [(Option<u64>, &str, &SelectFn); 5] = [
+        (options.keep_hourly, "keep-within-hourly", &select_hour),
+        (options.keep_daily, "keep-within-daily", &select_day),
+        (options.keep_weekly, "keep-within-weekly", &select_week),
+        (options.keep_monthly, "keep-within-monthly", &select_month),
+        (options.keep_yearly, "keep-within-yearly", &select_year),
+    ];
+
+    for (keep, within_key, select_id) in selections {
+        if let Some(keep) = keep {
+            mark_selections(&mut mark, &list, keep as usize, i64::MIN, select_id)?;
+        }
+        if let Some(oldest) = oldest_within(within_key) {
+            mark_selections(&mut mark, &list, usize::MAX, oldest, select_id)?;
+        }
     }
 
     let prune_info: Vec<(BackupInfo, PruneMark)> = list
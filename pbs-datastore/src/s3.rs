@@ -0,0 +1,506 @@
+//! Minimal client for S3 compatible object stores
+//!
+//! Datastores using the S3 backend keep chunks, index files, blobs and manifests as objects in a
+//! bucket. The object keys mirror the path of the file relative to the datastore base directory,
+//! e.g. `.chunks/0a1b/0a1b...` for chunks or `ns/foo/vm/100/<timestamp>/index.json.blob` for
+//! snapshot contents. Requests are authenticated using AWS signature version 4.
+
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use hyper::client::{Client, HttpConnector};
+use hyper::{Body, Method, Request, StatusCode};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::ssl::{SslConnector, SslMethod};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use proxmox_http::client::HttpsConnector;
+
+use pbs_api_types::{S3Client as S3ClientEntry, S3ClientConfig};
+
+/// Characters which are not percent encoded in query strings (RFC 3986 unreserved)
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Characters which are not percent encoded in object key paths
+const PATH_ENCODE_SET: &AsciiSet = &QUERY_ENCODE_SET.remove(b'/');
+
+const S3_TCP_KEEPALIVE_TIME: u32 = 120;
+const S3_DEFAULT_REGION: &str = "us-east-1";
+const EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Prefix of all chunk objects in the bucket
+pub const S3_CHUNK_PREFIX: &str = ".chunks/";
+
+/// Returns the object key used for the chunk with given digest.
+pub fn chunk_object_key(digest: &[u8; 32]) -> String {
+    let digest_str = hex::encode(digest);
+    format!("{S3_CHUNK_PREFIX}{}/{digest_str}", &digest_str[..4])
+}
+
+/// Information about a stored object, as returned by listing or HEAD requests.
+#[derive(Clone, Debug)]
+pub struct S3ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: i64,
+}
+
+/// Client for a single bucket of an S3 compatible object store
+pub struct S3Client {
+    client: Client<HttpsConnector>,
+    id: String,
+    bucket: String,
+    config: S3ClientConfig,
+    secret_key: String,
+}
+
+impl S3Client {
+    /// Create a client for `bucket` using the S3 client configuration with given `id`.
+    pub fn new(id: &str, bucket: &str) -> Result<Arc<Self>, Error> {
+        let (config, _digest) = pbs_config::s3::config()?;
+        let entry: S3ClientEntry = config
+            .lookup("s3client", id)
+            .map_err(|err| format_err!("unable to lookup s3 client '{id}' - {err}"))?;
+        Self::with_config(entry, bucket)
+    }
+
+    /// Create a client for `bucket` from an already loaded S3 client configuration.
+    pub fn with_config(entry: S3ClientEntry, bucket: &str) -> Result<Arc<Self>, Error> {
+        let mut ssl_connector_builder = SslConnector::builder(SslMethod::tls())?;
+
+        if let Some(expected) = entry.config.fingerprint.clone() {
+            let expected = expected.to_lowercase();
+            ssl_connector_builder.set_verify_callback(
+                openssl::ssl::SslVerifyMode::PEER,
+                move |valid, ctx| {
+                    if valid {
+                        return true;
+                    }
+                    if ctx.error_depth() != 0 {
+                        return false;
+                    }
+                    let cert = match ctx.current_cert() {
+                        Some(cert) => cert,
+                        None => return false,
+                    };
+                    match cert.digest(MessageDigest::sha256()) {
+                        Ok(fp) => {
+                            let fp = hex::encode(fp)
+                                .as_bytes()
+                                .chunks(2)
+                                .map(|v| std::str::from_utf8(v).unwrap())
+                                .collect::<Vec<&str>>()
+                                .join(":");
+                            fp == expected
+                        }
+                        Err(_) => false,
+                    }
+                },
+            );
+        }
+
+        let mut httpc = HttpConnector::new();
+        httpc.set_nodelay(true);
+        httpc.enforce_http(false);
+        httpc.set_connect_timeout(Some(std::time::Duration::new(10, 0)));
+
+        let https = HttpsConnector::with_connector(
+            httpc,
+            ssl_connector_builder.build(),
+            S3_TCP_KEEPALIVE_TIME,
+        );
+
+        let client = Client::builder().build::<_, Body>(https);
+
+        Ok(Arc::new(Self {
+            client,
+            id: entry.id,
+            bucket: bucket.to_string(),
+            config: entry.config,
+            secret_key: entry.secret_key,
+        }))
+    }
+
+    /// The ID of the S3 client configuration in use.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The bucket this client operates on.
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    fn host(&self) -> String {
+        let host = if self.config.path_style.unwrap_or(false) {
+            self.config.endpoint.clone()
+        } else {
+            format!("{}.{}", self.bucket, self.config.endpoint)
+        };
+        match self.config.port {
+            Some(port) => format!("{host}:{port}"),
+            None => host,
+        }
+    }
+
+    fn region(&self) -> &str {
+        self.config.region.as_deref().unwrap_or(S3_DEFAULT_REGION)
+    }
+
+    fn encoded_path(&self, key: &str) -> String {
+        let key = utf8_percent_encode(key, PATH_ENCODE_SET).to_string();
+        match (self.config.path_style.unwrap_or(false), key.is_empty()) {
+            (true, true) => format!("/{}", self.bucket),
+            (true, false) => format!("/{}/{key}", self.bucket),
+            (false, _) => format!("/{key}"),
+        }
+    }
+
+    fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+        let key = PKey::hmac(key)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(data)?;
+        Ok(signer.sign_to_vec()?)
+    }
+
+    /// Build and sign a request using AWS signature version 4.
+    fn build_request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Option<Vec<u8>>,
+    ) -> Result<Request<Body>, Error> {
+        let host = self.host();
+        let path = self.encoded_path(key);
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| {
+                (
+                    utf8_percent_encode(k, QUERY_ENCODE_SET).to_string(),
+                    utf8_percent_encode(v, QUERY_ENCODE_SET).to_string(),
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<String>>()
+            .join("&");
+
+        let payload_hash = match body {
+            Some(ref data) => hex::encode(openssl::sha::sha256(data)),
+            None => EMPTY_PAYLOAD_SHA256.to_string(),
+        };
+
+        let now = proxmox_time::epoch_i64();
+        let amz_date = proxmox_time::strftime_utc("%Y%m%dT%H%M%SZ", now)?;
+        let date = &amz_date[..8];
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+        );
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.region());
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(openssl::sha::sha256(canonical_request.as_bytes())),
+        );
+
+        let secret = format!("AWS4{}", self.secret_key);
+        let signing_key = Self::hmac_sha256(secret.as_bytes(), date.as_bytes())?;
+        let signing_key = Self::hmac_sha256(&signing_key, self.region().as_bytes())?;
+        let signing_key = Self::hmac_sha256(&signing_key, b"s3")?;
+        let signing_key = Self::hmac_sha256(&signing_key, b"aws4_request")?;
+        let signature = hex::encode(Self::hmac_sha256(&signing_key, string_to_sign.as_bytes())?);
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.config.access_key,
+        );
+
+        let scheme = if self.config.use_http.unwrap_or(false) {
+            "http"
+        } else {
+            "https"
+        };
+        let uri = if query.is_empty() {
+            format!("{scheme}://{host}{path}")
+        } else {
+            format!("{scheme}://{host}{path}?{query}")
+        };
+
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(hyper::header::AUTHORIZATION, authorization)
+            .body(body.map(Body::from).unwrap_or_else(Body::empty))?;
+
+        Ok(request)
+    }
+
+    async fn send(&self, request: Request<Body>) -> Result<(StatusCode, Vec<u8>), Error> {
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        let data = hyper::body::to_bytes(response.into_body()).await?;
+        Ok((status, data.to_vec()))
+    }
+
+    fn request_error(&self, what: &str, key: &str, status: StatusCode, body: &[u8]) -> Error {
+        let message = extract_xml_tag(&String::from_utf8_lossy(body), "Message")
+            .map(|msg| format!(" - {msg}"))
+            .unwrap_or_default();
+        format_err!(
+            "s3 {what} of '{key}' in bucket '{}' failed: {status}{message}",
+            self.bucket
+        )
+    }
+
+    /// Check that the bucket exists and is accessible with the configured credentials.
+    pub async fn head_bucket(&self) -> Result<(), Error> {
+        let request = self.build_request(Method::HEAD, "", &[], None)?;
+        let (status, body) = self.send(request).await?;
+        if !status.is_success() {
+            return Err(self.request_error("bucket access", "", status, &body));
+        }
+        Ok(())
+    }
+
+    /// Upload an object, replacing any existing object with the same key.
+    pub async fn put_object(&self, key: &str, data: Vec<u8>) -> Result<(), Error> {
+        let request = self.build_request(Method::PUT, key, &[], Some(data))?;
+        let (status, body) = self.send(request).await?;
+        if !status.is_success() {
+            return Err(self.request_error("upload", key, status, &body));
+        }
+        Ok(())
+    }
+
+    /// Download an object, returns `None` if the object does not exist.
+    pub async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let request = self.build_request(Method::GET, key, &[], None)?;
+        let (status, body) = self.send(request).await?;
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(body)),
+            status => Err(self.request_error("download", key, status, &body)),
+        }
+    }
+
+    /// Get the object metadata, returns `None` if the object does not exist.
+    pub async fn head_object(&self, key: &str) -> Result<Option<S3ObjectInfo>, Error> {
+        let request = self.build_request(Method::HEAD, key, &[], None)?;
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        } else if !status.is_success() {
+            return Err(self.request_error("stat", key, status, &[]));
+        }
+
+        let headers = response.headers();
+        let size = headers
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let last_modified = headers
+            .get(hyper::header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate_to_epoch(v).ok())
+            .unwrap_or(0);
+
+        Ok(Some(S3ObjectInfo {
+            key: key.to_string(),
+            size,
+            last_modified,
+        }))
+    }
+
+    /// Delete an object, deleting non-existing objects is not an error.
+    pub async fn delete_object(&self, key: &str) -> Result<(), Error> {
+        let request = self.build_request(Method::DELETE, key, &[], None)?;
+        let (status, body) = self.send(request).await?;
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            return Err(self.request_error("delete", key, status, &body));
+        }
+        Ok(())
+    }
+
+    /// List all objects with keys starting with `prefix`.
+    pub async fn list_objects(&self, prefix: &str) -> Result<Vec<S3ObjectInfo>, Error> {
+        let mut list = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(ref token) = continuation_token {
+                query.push(("continuation-token", token.as_str()));
+            }
+
+            let request = self.build_request(Method::GET, "", &query, None)?;
+            let (status, body) = self.send(request).await?;
+            if !status.is_success() {
+                return Err(self.request_error("listing", prefix, status, &body));
+            }
+            let body = String::from_utf8(body)
+                .map_err(|err| format_err!("got invalid listing from s3 - {err}"))?;
+
+            for contents in extract_xml_tags(&body, "Contents") {
+                let key = match extract_xml_tag(contents, "Key") {
+                    Some(key) => xml_unescape(key),
+                    None => bail!("got object listing entry without key"),
+                };
+                let size = extract_xml_tag(contents, "Size")
+                    .and_then(|size| size.parse().ok())
+                    .unwrap_or(0);
+                let last_modified = match extract_xml_tag(contents, "LastModified") {
+                    Some(time) => rfc3339_to_epoch(time)?,
+                    None => 0,
+                };
+                list.push(S3ObjectInfo {
+                    key,
+                    size,
+                    last_modified,
+                });
+            }
+
+            if extract_xml_tag(&body, "IsTruncated") != Some("true") {
+                break;
+            }
+            continuation_token = match extract_xml_tag(&body, "NextContinuationToken") {
+                Some(token) => Some(xml_unescape(token)),
+                None => bail!("truncated object listing without continuation token"),
+            };
+        }
+
+        Ok(list)
+    }
+
+    /// Delete all objects with keys starting with `prefix`.
+    pub async fn delete_prefix(&self, prefix: &str) -> Result<(), Error> {
+        for object in self.list_objects(prefix).await? {
+            self.delete_object(&object.key).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the inner text of all `<tag>...</tag>` elements.
+fn extract_xml_tags<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+
+    let mut list = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        match rest.find(&close) {
+            Some(end) => {
+                list.push(&rest[..end]);
+                rest = &rest[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    list
+}
+
+/// Returns the inner text of the first `<tag>...</tag>` element.
+fn extract_xml_tag<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    extract_xml_tags(xml, tag).into_iter().next()
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Parse timestamps like `2023-10-12T17:50:30.000Z`, dropping the fractional seconds.
+fn rfc3339_to_epoch(time: &str) -> Result<i64, Error> {
+    let time = match time.find('.') {
+        Some(pos) => format!("{}Z", &time[..pos]),
+        None => time.to_string(),
+    };
+    proxmox_time::parse_rfc3339(&time)
+}
+
+/// Parse HTTP dates like `Wed, 12 Oct 2023 17:50:30 GMT`.
+fn httpdate_to_epoch(date: &str) -> Result<i64, Error> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        bail!("unable to parse http date '{date}'");
+    }
+    let month = MONTHS
+        .iter()
+        .position(|m| *m == parts[2])
+        .ok_or_else(|| format_err!("unable to parse month in http date '{date}'"))?;
+
+    proxmox_time::parse_rfc3339(&format!(
+        "{}-{:02}-{:0>2}T{}Z",
+        parts[3],
+        month + 1,
+        parts[1],
+        parts[4]
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_listing_parser() {
+        let xml = "<ListBucketResult><IsTruncated>false</IsTruncated>\
+            <Contents><Key>.chunks/0000/a&amp;b</Key><LastModified>2023-10-12T17:50:30.000Z</LastModified><Size>42</Size></Contents>\
+            <Contents><Key>vm/100/owner</Key><LastModified>2023-10-12T17:50:31.000Z</LastModified><Size>7</Size></Contents>\
+            </ListBucketResult>";
+
+        let contents = extract_xml_tags(xml, "Contents");
+        assert_eq!(contents.len(), 2);
+        assert_eq!(
+            xml_unescape(extract_xml_tag(contents[0], "Key").unwrap()),
+            ".chunks/0000/a&b"
+        );
+        assert_eq!(extract_xml_tag(contents[1], "Size"), Some("7"));
+        assert_eq!(extract_xml_tag(xml, "IsTruncated"), Some("false"));
+        assert_eq!(
+            rfc3339_to_epoch(extract_xml_tag(contents[0], "LastModified").unwrap()).unwrap(),
+            1697133030
+        );
+    }
+
+    #[test]
+    fn test_httpdate_parser() {
+        assert_eq!(
+            httpdate_to_epoch("Thu, 12 Oct 2023 17:50:30 GMT").unwrap(),
+            1697133030
+        );
+    }
+
+    #[test]
+    fn test_chunk_object_key() {
+        let digest = [0xabu8; 32];
+        let key = chunk_object_key(&digest);
+        assert!(key.starts_with(".chunks/abab/abababab"));
+    }
+}
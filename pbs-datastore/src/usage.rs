@@ -0,0 +1,324 @@
+//! Storage usage accounting for sets of snapshots
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+use proxmox_sys::task_log;
+use proxmox_sys::WorkerTaskContext;
+
+use pbs_api_types::{
+    print_ns_and_snapshot, BackupNamespace, NamespaceSpaceUsage, SpaceAccountingStatus, SpaceUsage,
+    StorageUsage,
+};
+
+use crate::manifest::{archive_type, ArchiveType};
+use crate::{BackupDir, DataStore};
+
+/// Sums up the storage usage of snapshots.
+///
+/// The logical size is the sum of the file sizes recorded in the manifests. The unique size
+/// counts every referenced chunk only once, using the size of the chunk file on disk, or the
+/// uncompressed chunk size if the chunk is not available locally.
+pub struct UsageCollector {
+    datastore: Arc<DataStore>,
+    chunks: HashSet<[u8; 32]>,
+    usage: StorageUsage,
+}
+
+impl UsageCollector {
+    pub fn new(datastore: Arc<DataStore>) -> Self {
+        Self {
+            datastore,
+            chunks: HashSet::new(),
+            usage: StorageUsage::default(),
+        }
+    }
+
+    /// Account a finished snapshot.
+    pub fn add_snapshot(&mut self, snapshot: &BackupDir) -> Result<(), Error> {
+        let (manifest, _) = snapshot.load_manifest()?;
+
+        self.usage.snapshots += 1;
+
+        for info in manifest.files() {
+            self.usage.logical_size += info.size;
+
+            match archive_type(&info.filename)? {
+                ArchiveType::FixedIndex | ArchiveType::DynamicIndex => (),
+                ArchiveType::Blob => {
+                    self.usage.unique_size += info.size;
+                    continue;
+                }
+            }
+
+            let mut path = snapshot.relative_path();
+            path.push(&info.filename);
+            let index = self.datastore.open_index(&path)?;
+
+            for pos in 0..index.index_count() {
+                let info = index.chunk_info(pos).unwrap();
+                if !self.chunks.insert(info.digest) {
+                    continue;
+                }
+                let (chunk_path, _) = self.datastore.chunk_path(&info.digest);
+                self.usage.unique_size += match std::fs::metadata(chunk_path) {
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => info.size(),
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Usage of all snapshots accounted so far.
+    pub fn usage(&self) -> StorageUsage {
+        self.usage
+    }
+}
+
+/// File name of the last space accounting result, relative to the datastore base directory.
+pub const SPACE_ACCOUNTING_FILENAME: &str = ".space-accounting";
+
+const SNAPSHOT_LEVEL: usize = 0;
+const GROUP_LEVEL: usize = 1;
+const NAMESPACE_LEVEL: usize = 2;
+
+const NO_ENTITY: u32 = u32::MAX;
+
+struct ChunkRef {
+    size: u64,
+    // last snapshot, group and namespace referencing the chunk
+    last: [u32; 3],
+    shared: [bool; 3],
+}
+
+#[derive(Default)]
+struct Entities {
+    keys: Vec<String>,
+    referenced: Vec<u64>,
+    unique: Vec<u64>,
+}
+
+impl Entities {
+    fn add(&mut self, key: String) -> u32 {
+        self.keys.push(key);
+        self.referenced.push(0);
+        self.unique.push(0);
+        (self.keys.len() - 1) as u32
+    }
+
+    fn into_map(self) -> HashMap<String, SpaceUsage> {
+        self.keys
+            .into_iter()
+            .zip(self.referenced.into_iter().zip(self.unique))
+            .map(|(key, (referenced_size, unique_size))| {
+                let usage = SpaceUsage {
+                    referenced_size,
+                    unique_size,
+                    shared_size: referenced_size - unique_size,
+                };
+                (key, usage)
+            })
+            .collect()
+    }
+}
+
+fn group_key(ns: &BackupNamespace, group: &pbs_api_types::BackupGroup) -> String {
+    if ns.is_root() {
+        group.to_string()
+    } else {
+        format!("{}/{}", ns.display_as_path(), group)
+    }
+}
+
+/// Referenced, unique and shared space of all snapshots, groups and namespaces of a datastore.
+///
+/// A chunk is unique to a snapshot (group, namespace) if no other snapshot (group, namespace)
+/// references it, so its size would be freed by a garbage collection after removing the
+/// snapshot. Namespaces only account the snapshots directly in them, not those of their
+/// sub-namespaces.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SpaceAccounting {
+    /// Task which computed the result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upid: Option<String>,
+    /// Time the accounting finished.
+    pub time: i64,
+    pub namespaces: HashMap<String, SpaceUsage>,
+    pub groups: HashMap<String, SpaceUsage>,
+    pub snapshots: HashMap<String, SpaceUsage>,
+}
+
+impl SpaceAccounting {
+    /// Walk all finished snapshots of the datastore and account their space.
+    pub fn compute(
+        datastore: &Arc<DataStore>,
+        worker: &dyn WorkerTaskContext,
+        upid: Option<String>,
+    ) -> Result<Self, Error> {
+        let mut chunks: HashMap<[u8; 32], ChunkRef> = HashMap::new();
+        let mut entities: [Entities; 3] = Default::default();
+
+        for ns in datastore.recursive_iter_backup_ns_ok(BackupNamespace::root(), None)? {
+            task_log!(worker, "accounting namespace '{ns}'");
+            let ns_id = entities[NAMESPACE_LEVEL].add(ns.to_string());
+
+            for group in datastore.iter_backup_groups_ok(ns.clone())? {
+                let group_id = entities[GROUP_LEVEL].add(group_key(&ns, group.as_ref()));
+
+                for info in group.list_backups()? {
+                    worker.check_abort()?;
+                    worker.fail_on_shutdown()?;
+
+                    if !info.is_finished() {
+                        continue;
+                    }
+                    let snapshot = &info.backup_dir;
+                    let snapshot_id =
+                        entities[SNAPSHOT_LEVEL].add(print_ns_and_snapshot(&ns, snapshot.as_ref()));
+                    let ids = [snapshot_id, group_id, ns_id];
+
+                    let (manifest, _) = match snapshot.load_manifest() {
+                        Ok(manifest) => manifest,
+                        Err(err) => {
+                            task_log!(worker, "skipping {snapshot:?} - {err}");
+                            continue;
+                        }
+                    };
+
+                    for file in manifest.files() {
+                        match archive_type(&file.filename)? {
+                            ArchiveType::FixedIndex | ArchiveType::DynamicIndex => (),
+                            ArchiveType::Blob => {
+                                for (level, id) in ids.iter().enumerate() {
+                                    entities[level].referenced[*id as usize] += file.size;
+                                    entities[level].unique[*id as usize] += file.size;
+                                }
+                                continue;
+                            }
+                        }
+
+                        let mut path = snapshot.relative_path();
+                        path.push(&file.filename);
+                        let index = datastore
+                            .open_index(&path)
+                            .map_err(|err| format_err!("unable to open index {path:?} - {err}"))?;
+
+                        for pos in 0..index.index_count() {
+                            let info = index.chunk_info(pos).unwrap();
+                            let chunk = chunks.entry(info.digest).or_insert_with(|| {
+                                let (chunk_path, _) = datastore.chunk_path(&info.digest);
+                                let size = match std::fs::metadata(chunk_path) {
+                                    Ok(metadata) => metadata.len(),
+                                    Err(_) => info.size(),
+                                };
+                                ChunkRef {
+                                    size,
+                                    last: [NO_ENTITY; 3],
+                                    shared: [false; 3],
+                                }
+                            });
+
+                            // snapshots, groups and namespaces are walked one after another, so
+                            // a different last entity means the chunk is referenced by several
+                            for (level, id) in ids.iter().enumerate() {
+                                if chunk.last[level] == *id {
+                                    continue;
+                                }
+                                if chunk.last[level] != NO_ENTITY {
+                                    chunk.shared[level] = true;
+                                }
+                                chunk.last[level] = *id;
+                                entities[level].referenced[*id as usize] += chunk.size;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for chunk in chunks.values() {
+            for level in 0..3 {
+                if !chunk.shared[level] {
+                    entities[level].unique[chunk.last[level] as usize] += chunk.size;
+                }
+            }
+        }
+
+        let [snapshots, groups, namespaces] = entities;
+
+        Ok(Self {
+            upid,
+            time: proxmox_time::epoch_i64(),
+            namespaces: namespaces.into_map(),
+            groups: groups.into_map(),
+            snapshots: snapshots.into_map(),
+        })
+    }
+
+    /// Load the last result of `datastore`, if any.
+    pub fn load(datastore: &DataStore) -> Result<Option<Self>, Error> {
+        let path = datastore.base_path().join(SPACE_ACCOUNTING_FILENAME);
+        match file_read_optional_string(path)? {
+            Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the result in the datastore base directory.
+    pub fn save(&self, datastore: &DataStore) -> Result<(), Error> {
+        let path = datastore.base_path().join(SPACE_ACCOUNTING_FILENAME);
+        let data = serde_json::to_vec(self)?;
+
+        let backup_user = pbs_config::backup_user()?;
+        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+        let options = CreateOptions::new()
+            .perm(mode)
+            .owner(backup_user.uid)
+            .group(backup_user.gid);
+
+        replace_file(path, &data, options, false)
+    }
+
+    /// Space used by a backup group.
+    pub fn group(
+        &self,
+        ns: &BackupNamespace,
+        group: &pbs_api_types::BackupGroup,
+    ) -> Option<SpaceUsage> {
+        self.groups.get(&group_key(ns, group)).copied()
+    }
+
+    /// Space used by a snapshot.
+    pub fn snapshot(
+        &self,
+        ns: &BackupNamespace,
+        dir: &pbs_api_types::BackupDir,
+    ) -> Option<SpaceUsage> {
+        self.snapshots.get(&print_ns_and_snapshot(ns, dir)).copied()
+    }
+
+    /// Summary of the result, sorted by namespace.
+    pub fn status(&self) -> Result<SpaceAccountingStatus, Error> {
+        let mut namespaces = Vec::with_capacity(self.namespaces.len());
+        for (ns, usage) in self.namespaces.iter() {
+            namespaces.push(NamespaceSpaceUsage {
+                ns: ns.parse()?,
+                usage: *usage,
+            });
+        }
+        namespaces.sort_unstable_by(|a, b| a.ns.cmp(&b.ns));
+
+        Ok(SpaceAccountingStatus {
+            upid: self.upid.clone(),
+            time: self.time,
+            namespaces,
+        })
+    }
+}
@@ -24,13 +24,15 @@ use proxmox_time::{epoch_i64, strftime_local};
 use pxar::accessor::{MaybeReady, ReadAt, ReadAtOperation};
 
 use pbs_api_types::{
-    Authid, BackupDir, BackupGroup, BackupNamespace, BackupPart, BackupType, CryptMode,
-    Fingerprint, GroupListItem, PruneJobOptions, PruneListItem, RateLimitConfig, SnapshotListItem,
-    StorageStatus, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
-    BACKUP_TYPE_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA, TRAFFIC_CONTROL_RATE_SCHEMA,
+    Authid, BackupDir, BackupGroup, BackupNamespace, BackupPart, BackupType, ChunkCompression,
+    ChunkCompressionAlgorithm, CryptMode, Fingerprint, GroupListItem, PruneJobOptions,
+    PruneListItem, RateLimitConfig, SnapshotListItem, StorageStatus, BACKUP_ID_SCHEMA,
+    BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA,
+    CHUNK_COMPRESSION_LEVEL_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA, TRAFFIC_CONTROL_RATE_SCHEMA,
 };
 use pbs_client::catalog_shell::Shell;
 use pbs_client::pxar::ErrorHandler as PxarErrorHandler;
+use pbs_client::pxar::PxarChangeInfo;
 use pbs_client::tools::{
     complete_archive_name, complete_auth_id, complete_backup_group, complete_backup_snapshot,
     complete_backup_source, complete_chunk_size, complete_group_or_snapshot,
@@ -43,14 +45,14 @@ use pbs_client::tools::{
     CHUNK_SIZE_SCHEMA, REPO_URL_SCHEMA,
 };
 use pbs_client::{
-    delete_ticket_info, parse_backup_specification, view_task_result, BackupReader,
-    BackupRepository, BackupSpecificationType, BackupStats, BackupWriter, ChunkStream,
-    FixedChunkStream, HttpClient, PxarBackupStream, RemoteChunkReader, UploadOptions,
-    BACKUP_SOURCE_SCHEMA,
+    delete_ticket_info, parse_backup_specification, view_task_result, BackupDetectionMode,
+    BackupReader, BackupRepository, BackupSpecificationType, BackupStats, BackupWriter,
+    ChunkStream, FixedChunkStream, HttpClient, InjectionData, PxarBackupStream, RemoteChunkReader,
+    UploadOptions, BACKUP_SOURCE_SCHEMA,
 };
 use pbs_datastore::catalog::{BackupCatalogWriter, CatalogReader, CatalogWriter};
 use pbs_datastore::chunk_store::verify_chunk_size;
-use pbs_datastore::dynamic_index::{BufferedDynamicReader, DynamicIndexReader};
+use pbs_datastore::dynamic_index::{BufferedDynamicReader, DynamicIndexReader, LocalDynamicReadAt};
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
 use pbs_datastore::manifest::{
@@ -66,6 +68,8 @@ mod benchmark;
 pub use benchmark::*;
 mod mount;
 pub use mount::*;
+mod nbd;
+pub use nbd::*;
 mod task;
 pub use task::*;
 mod catalog;
@@ -191,9 +195,27 @@ async fn backup_directory<P: AsRef<Path>>(
     catalog: Arc<Mutex<CatalogWriter<TokioWriterAdapter<StdChannelWriter<Error>>>>>,
     pxar_create_options: pbs_client::pxar::PxarCreateOptions,
     upload_options: UploadOptions,
+    detection_mode: BackupDetectionMode,
 ) -> Result<BackupStats, Error> {
-    let pxar_stream = PxarBackupStream::open(dir_path.as_ref(), catalog, pxar_create_options)?;
-    let mut chunk_stream = ChunkStream::new(pxar_stream, chunk_size);
+    let mut pxar_stream = PxarBackupStream::open(
+        dir_path.as_ref(),
+        catalog,
+        pxar_create_options,
+        detection_mode,
+    )?;
+
+    let (injection_data, injections) = match pxar_stream.take_boundaries() {
+        Some(boundaries) => {
+            let (injections_tx, injections_rx) = std::sync::mpsc::channel();
+            (
+                Some(InjectionData::new(boundaries, injections_tx)),
+                Some(injections_rx),
+            )
+        }
+        None => (None, None),
+    };
+
+    let mut chunk_stream = ChunkStream::new(pxar_stream, chunk_size, injection_data);
 
     let (tx, rx) = mpsc::channel(10); // allow to buffer 10 chunks
 
@@ -211,12 +233,68 @@ async fn backup_directory<P: AsRef<Path>>(
     }
 
     let stats = client
-        .upload_stream(archive_name, stream, upload_options)
+        .upload_stream(archive_name, stream, upload_options, injections)
         .await?;
 
     Ok(stats)
 }
 
+async fn open_previous_ref(
+    reader: &Arc<BackupReader>,
+    manifest: &BackupManifest,
+    archive_name: &str,
+    crypt_config: Option<Arc<CryptConfig>>,
+    crypt_mode: CryptMode,
+) -> Result<Option<pbs_client::pxar::PxarPrevRef>, Error> {
+    let file_info = match manifest.lookup_file_info(archive_name) {
+        Ok(file_info) => file_info,
+        Err(_) => return Ok(None),
+    };
+
+    if file_info.crypt_mode != crypt_mode {
+        log::info!("Crypt mode of '{archive_name}' changed, reading all files");
+        return Ok(None);
+    }
+
+    let index = reader
+        .download_dynamic_index(manifest, archive_name)
+        .await?;
+    let most_used = index.find_most_used_chunks(8);
+
+    let lookup_index = reader
+        .download_dynamic_index(manifest, archive_name)
+        .await?;
+
+    // archives of older clients have no change information, all their files are read again
+    let change_info_name = PxarChangeInfo::blob_name(archive_name);
+    let change_info = if manifest.lookup_file_info(&change_info_name).is_ok() {
+        let mut blob = reader.download_blob(manifest, &change_info_name).await?;
+        let mut data = Vec::new();
+        blob.read_to_end(&mut data)?;
+        PxarChangeInfo::decode(&data)?
+    } else {
+        PxarChangeInfo::default()
+    };
+
+    let chunk_reader = RemoteChunkReader::new(
+        reader.clone(),
+        crypt_config,
+        file_info.chunk_crypt_mode(),
+        most_used,
+    );
+
+    let reader = BufferedDynamicReader::new(index, chunk_reader);
+    let archive_size = reader.archive_size();
+    let reader: Arc<dyn ReadAt + Send + Sync> = Arc::new(LocalDynamicReadAt::new(reader));
+    let accessor = pxar::accessor::aio::Accessor::new(reader, archive_size).await?;
+
+    Ok(Some(pbs_client::pxar::PxarPrevRef {
+        accessor: Arc::new(accessor),
+        index: Arc::new(lookup_index),
+        change_info: Arc::new(change_info),
+    }))
+}
+
 async fn backup_image<P: AsRef<Path>>(
     client: &BackupWriter,
     image_path: P,
@@ -238,7 +316,7 @@ async fn backup_image<P: AsRef<Path>>(
     }
 
     let stats = client
-        .upload_stream(archive_name, stream, upload_options)
+        .upload_stream(archive_name, stream, upload_options, None)
         .await?;
 
     Ok(stats)
@@ -327,6 +405,10 @@ async fn list_backup_groups(param: Value) -> Result<Value, Error> {
                 .right_align(false),
         )
         .column(ColumnConfig::new("backup-count"))
+        .column(
+            ColumnConfig::new("unique-size")
+                .renderer(pbs_tools::format::render_bytes_human_readable),
+        )
         .column(ColumnConfig::new("files").renderer(render_files));
 
     let mut data: Value = result["data"].take();
@@ -529,7 +611,7 @@ fn spawn_catalog_upload(
     let (catalog_tx, catalog_rx) = std::sync::mpsc::sync_channel(10); // allow to buffer 10 writes
     let catalog_stream = proxmox_async::blocking::StdChannelStream(catalog_rx);
     let catalog_chunk_size = 512 * 1024;
-    let catalog_chunk_stream = ChunkStream::new(catalog_stream, Some(catalog_chunk_size));
+    let catalog_chunk_stream = ChunkStream::new(catalog_stream, Some(catalog_chunk_size), None);
 
     let catalog_writer = Arc::new(Mutex::new(CatalogWriter::new(TokioWriterAdapter::new(
         StdChannelWriter::new(catalog_tx),
@@ -545,7 +627,7 @@ fn spawn_catalog_upload(
 
     tokio::spawn(async move {
         let catalog_upload_result = client
-            .upload_stream(CATALOG_NAME, catalog_chunk_stream, upload_options)
+            .upload_stream(CATALOG_NAME, catalog_chunk_stream, upload_options, None)
             .await;
 
         if let Err(ref err) = catalog_upload_result {
@@ -665,6 +747,18 @@ fn spawn_catalog_upload(
                optional: true,
                default: false,
            },
+           "change-detection-mode": {
+               type: BackupDetectionMode,
+               optional: true,
+           },
+           compression: {
+               type: ChunkCompressionAlgorithm,
+               optional: true,
+           },
+           "compression-level": {
+               schema: CHUNK_COMPRESSION_LEVEL_SCHEMA,
+               optional: true,
+           },
        }
    }
 )]
@@ -674,6 +768,7 @@ async fn create_backup(
     all_file_systems: bool,
     skip_lost_and_found: bool,
     dry_run: bool,
+    change_detection_mode: Option<BackupDetectionMode>,
     _info: &ApiMethod,
     _rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Value, Error> {
@@ -700,6 +795,18 @@ async fn create_backup(
 
     let rate_limit = RateLimitConfig::with_same_inout(rate, burst);
 
+    let compression = match param["compression"].as_str() {
+        Some(algorithm) => Some(ChunkCompression::new(
+            serde_json::from_value(algorithm.into())?,
+            param["compression-level"].as_u64(),
+        )),
+        None if param["compression-level"].is_u64() => Some(ChunkCompression::new(
+            ChunkCompressionAlgorithm::Zstd,
+            param["compression-level"].as_u64(),
+        )),
+        None => None,
+    };
+
     let crypto = crypto_parameters(&param)?;
 
     let backup_id = param["backup-id"]
@@ -827,7 +934,7 @@ async fn create_backup(
 
     let backup_time = backup_time_opt.unwrap_or_else(epoch_i64);
 
-    let client = connect_rate_limited(&repo, rate_limit)?;
+    let http_client = connect_rate_limited(&repo, rate_limit)?;
     record_repository(&repo);
 
     let snapshot = BackupDir::from((backup_type, backup_id.to_owned(), backup_time));
@@ -878,8 +985,8 @@ async fn create_backup(
         }
     };
 
-    let client = BackupWriter::start(
-        client,
+    let client = BackupWriter::start_shared(
+        &http_client,
         crypt_config.clone(),
         repo.store(),
         &backup_ns,
@@ -889,12 +996,14 @@ async fn create_backup(
     )
     .await?;
 
+    let mut previous_backup_time = None;
     let download_previous_manifest = match client.previous_backup_time().await {
         Ok(Some(backup_time)) => {
             log::info!(
                 "Downloading previous manifest ({})",
                 strftime_local("%c", backup_time)?
             );
+            previous_backup_time = Some(backup_time);
             true
         }
         Ok(None) => {
@@ -927,6 +1036,32 @@ async fn create_backup(
         None
     };
 
+    let detection_mode = change_detection_mode.unwrap_or_default();
+
+    let previous_reader = match (detection_mode, previous_backup_time, &previous_manifest) {
+        (BackupDetectionMode::Metadata, Some(previous_time), Some(_)) => {
+            let previous_snapshot =
+                BackupDir::from((backup_type, backup_id.to_owned(), previous_time));
+            match BackupReader::start(
+                &http_client,
+                crypt_config.clone(),
+                repo.store(),
+                &backup_ns,
+                &previous_snapshot,
+                false,
+            )
+            .await
+            {
+                Ok(reader) => Some(reader),
+                Err(err) => {
+                    log::warn!("Unable to open previous snapshot, reading all files - {err}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
     let mut manifest = BackupManifest::new(snapshot);
 
     let mut catalog = None;
@@ -988,16 +1123,43 @@ async fn create_backup(
                     .unwrap()
                     .start_directory(std::ffi::CString::new(target.as_str())?.as_c_str())?;
 
-                let pxar_options = pbs_client::pxar::PxarCreateOptions {
+                let mut pxar_options = pbs_client::pxar::PxarCreateOptions {
                     device_set: devices.clone(),
                     patterns: pattern_list.clone(),
                     entries_max: entries_max as usize,
                     skip_lost_and_found,
+                    previous_ref: None,
+                    change_info: None,
                 };
 
+                let change_info = detection_mode
+                    .is_metadata()
+                    .then(|| Arc::new(Mutex::new(PxarChangeInfo::default())));
+                pxar_options.change_info = change_info.clone();
+
+                if let (Some(reader), Some(previous_manifest)) =
+                    (&previous_reader, &previous_manifest)
+                {
+                    match open_previous_ref(
+                        reader,
+                        previous_manifest,
+                        &target,
+                        crypt_config.clone(),
+                        crypto.mode,
+                    )
+                    .await
+                    {
+                        Ok(previous_ref) => pxar_options.previous_ref = previous_ref,
+                        Err(err) => log::warn!(
+                            "Unable to open previous archive '{target}', reading all files - {err}"
+                        ),
+                    }
+                }
+
                 let upload_options = UploadOptions {
                     previous_manifest: previous_manifest.clone(),
                     compress: true,
+                    compression,
                     encrypt: crypto.mode == CryptMode::Encrypt,
                     ..UploadOptions::default()
                 };
@@ -1010,8 +1172,24 @@ async fn create_backup(
                     catalog.clone(),
                     pxar_options,
                     upload_options,
+                    detection_mode,
                 )
                 .await?;
+
+                if let Some(change_info) = change_info {
+                    let change_info_name = PxarChangeInfo::blob_name(&target);
+                    let data = change_info.lock().unwrap().encode();
+                    let upload_options = UploadOptions {
+                        compress: true,
+                        encrypt: crypto.mode == CryptMode::Encrypt,
+                        ..UploadOptions::default()
+                    };
+                    let stats = client
+                        .upload_blob_from_data(data, &change_info_name, upload_options)
+                        .await?;
+                    manifest.add_file(change_info_name, stats.size, stats.csum, crypto.mode)?;
+                }
+
                 manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
                 catalog.lock().unwrap().end_directory()?;
             }
@@ -1022,6 +1200,7 @@ async fn create_backup(
                     previous_manifest: previous_manifest.clone(),
                     fixed_size: Some(size),
                     compress: true,
+                    compression,
                     encrypt: crypto.mode == CryptMode::Encrypt,
                 };
 
@@ -1777,6 +1956,7 @@ fn main() {
         .insert("mount", mount_cmd_def())
         .insert("map", map_cmd_def())
         .insert("unmap", unmap_cmd_def())
+        .insert("nbd", nbd_cmd_def())
         .insert("catalog", catalog_mgmt_cli())
         .insert("task", task_mgmt_cli())
         .insert("version", version_cmd_def)
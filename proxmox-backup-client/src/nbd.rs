@@ -0,0 +1,954 @@
+//! Export drive images of a backup snapshot via the Network Block Device (NBD) protocol.
+//!
+//! Only the fixed newstyle negotiation with a single export is implemented. Writes are only
+//! accepted in copy-on-write mode and get stored in a temporary overlay file, which is dropped
+//! when the server exits - the backup itself is never modified.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use futures::future::{BoxFuture, FutureExt};
+use futures::select;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::signal::unix::{signal, SignalKind};
+
+use proxmox_router::cli::{complete_file_name, CliCommand};
+use proxmox_schema::api;
+
+use pbs_api_types::BackupNamespace;
+use pbs_client::tools::key_source::get_encryption_key_password;
+use pbs_client::{BackupReader, RemoteChunkReader};
+use pbs_datastore::cached_chunk_reader::CachedChunkReader;
+use pbs_datastore::fixed_index::FixedIndexReader;
+use pbs_datastore::index::IndexFile;
+use pbs_key_config::load_and_decrypt_key;
+use pbs_tools::crypt_config::CryptConfig;
+
+use crate::{
+    complete_group_or_snapshot, complete_img_archive_name, complete_namespace, complete_repository,
+    connect, dir_or_last_from_group, extract_repository_from_value, optional_ns_param,
+    record_repository, REPO_URL_SCHEMA,
+};
+
+/// Default NBD port as registered with IANA
+const NBD_DEFAULT_LISTEN: &str = "127.0.0.1:10809";
+
+const NBD_MAGIC: u64 = 0x4e42_444d_4147_4943; // "NBDMAGIC"
+const NBD_IHAVEOPT: u64 = 0x4948_4156_454f_5054; // "IHAVEOPT"
+const NBD_OPTION_REPLY_MAGIC: u64 = 0x0003_e889_0455_65a9;
+const NBD_REQUEST_MAGIC: u32 = 0x2560_9513;
+const NBD_SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+
+// handshake flags
+const NBD_FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+const NBD_FLAG_NO_ZEROES: u16 = 1 << 1;
+const NBD_FLAG_C_FIXED_NEWSTYLE: u32 = 1 << 0;
+const NBD_FLAG_C_NO_ZEROES: u32 = 1 << 1;
+
+// options
+const NBD_OPT_EXPORT_NAME: u32 = 1;
+const NBD_OPT_ABORT: u32 = 2;
+const NBD_OPT_LIST: u32 = 3;
+const NBD_OPT_INFO: u32 = 6;
+const NBD_OPT_GO: u32 = 7;
+
+// option replies
+const NBD_REP_ACK: u32 = 1;
+const NBD_REP_SERVER: u32 = 2;
+const NBD_REP_INFO: u32 = 3;
+const NBD_REP_ERR_UNSUP: u32 = (1 << 31) | 1;
+const NBD_REP_ERR_INVALID: u32 = (1 << 31) | 3;
+const NBD_REP_ERR_UNKNOWN: u32 = (1 << 31) | 6;
+
+const NBD_INFO_EXPORT: u16 = 0;
+const NBD_INFO_BLOCK_SIZE: u16 = 3;
+
+// transmission flags
+const NBD_FLAG_HAS_FLAGS: u16 = 1 << 0;
+const NBD_FLAG_READ_ONLY: u16 = 1 << 1;
+const NBD_FLAG_SEND_FLUSH: u16 = 1 << 2;
+const NBD_FLAG_CAN_MULTI_CONN: u16 = 1 << 8;
+
+// commands
+const NBD_CMD_READ: u16 = 0;
+const NBD_CMD_WRITE: u16 = 1;
+const NBD_CMD_DISC: u16 = 2;
+const NBD_CMD_FLUSH: u16 = 3;
+
+// errors
+const NBD_EPERM: u32 = 1;
+const NBD_EIO: u32 = 5;
+const NBD_EINVAL: u32 = 22;
+const NBD_ENOSPC: u32 = 28;
+
+/// Maximum length of option data we accept during negotiation
+const MAX_OPTION_LENGTH: u32 = 64 * 1024;
+/// Maximum payload size of a single read or write request
+const MAX_REQUEST_SIZE: u32 = 32 * 1024 * 1024;
+/// Granularity of the copy-on-write overlay
+const COW_BLOCK_SIZE: u64 = 4096;
+
+/// Temporary file holding the blocks written by NBD clients.
+struct CowOverlay {
+    file: File,
+    dirty: HashSet<u64>,
+}
+
+impl CowOverlay {
+    fn new() -> Result<Self, Error> {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .custom_flags(libc::O_TMPFILE)
+            .open(std::env::temp_dir())
+            .map_err(|err| format_err!("unable to create copy-on-write overlay - {err}"))?;
+
+        Ok(Self {
+            file,
+            dirty: HashSet::new(),
+        })
+    }
+
+    /// Replace the parts of `buf` which got written to the overlay.
+    fn patch(&self, buf: &mut [u8], offset: u64) -> Result<(), Error> {
+        let end = offset + buf.len() as u64;
+        for block in (offset / COW_BLOCK_SIZE)..end.div_ceil(COW_BLOCK_SIZE) {
+            if !self.dirty.contains(&block) {
+                continue;
+            }
+            let start = (block * COW_BLOCK_SIZE).max(offset);
+            let stop = ((block + 1) * COW_BLOCK_SIZE).min(end);
+            let range = (start - offset) as usize..(stop - offset) as usize;
+            self.file.read_exact_at(&mut buf[range], start)?;
+        }
+        Ok(())
+    }
+}
+
+/// Random read access to the exported image.
+trait ImageReader: Send + Sync {
+    fn read_at<'a>(&'a self, buf: &'a mut [u8], offset: u64)
+        -> BoxFuture<'a, Result<usize, Error>>;
+}
+
+impl ImageReader for CachedChunkReader<FixedIndexReader, RemoteChunkReader> {
+    fn read_at<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+        offset: u64,
+    ) -> BoxFuture<'a, Result<usize, Error>> {
+        CachedChunkReader::read_at(self, buf, offset).boxed()
+    }
+}
+
+/// A fixed index archive exported via NBD.
+struct NbdExport {
+    name: String,
+    size: u64,
+    reader: Box<dyn ImageReader>,
+    overlay: Option<tokio::sync::Mutex<CowOverlay>>,
+}
+
+impl NbdExport {
+    /// An empty export name selects the default (and only) export.
+    fn matches(&self, name: &[u8]) -> bool {
+        name.is_empty() || name == self.name.as_bytes()
+    }
+
+    fn transmission_flags(&self) -> u16 {
+        let mut flags = NBD_FLAG_HAS_FLAGS | NBD_FLAG_SEND_FLUSH | NBD_FLAG_CAN_MULTI_CONN;
+        if self.overlay.is_none() {
+            flags |= NBD_FLAG_READ_ONLY;
+        }
+        flags
+    }
+
+    async fn read_base(&self, buf: &mut [u8], offset: u64) -> Result<(), Error> {
+        let read = self.reader.read_at(buf, offset).await?;
+        if read != buf.len() {
+            bail!(
+                "short read at offset {offset} ({read} of {} bytes)",
+                buf.len()
+            );
+        }
+        Ok(())
+    }
+
+    async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), Error> {
+        self.read_base(buf, offset).await?;
+        if let Some(overlay) = &self.overlay {
+            overlay.lock().await.patch(buf, offset)?;
+        }
+        Ok(())
+    }
+
+    async fn write_at(&self, data: &[u8], offset: u64) -> Result<(), Error> {
+        let overlay = match &self.overlay {
+            Some(overlay) => overlay,
+            None => bail!("export is read-only"),
+        };
+        let mut overlay = overlay.lock().await;
+
+        let end = offset + data.len() as u64;
+        for block in (offset / COW_BLOCK_SIZE)..end.div_ceil(COW_BLOCK_SIZE) {
+            let block_start = block * COW_BLOCK_SIZE;
+            let block_end = (block_start + COW_BLOCK_SIZE).min(self.size);
+            let start = block_start.max(offset);
+            let stop = block_end.min(end);
+
+            // partially written blocks need the original data first
+            if !overlay.dirty.contains(&block) && (start != block_start || stop != block_end) {
+                let mut base = vec![0u8; (block_end - block_start) as usize];
+                self.read_base(&mut base, block_start).await?;
+                overlay.file.write_all_at(&base, block_start)?;
+            }
+
+            let range = (start - offset) as usize..(stop - offset) as usize;
+            overlay.file.write_all_at(&data[range], start)?;
+            overlay.dirty.insert(block);
+        }
+
+        Ok(())
+    }
+}
+
+async fn send_option_reply<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    option: u32,
+    reply: u32,
+    data: &[u8],
+) -> Result<(), Error> {
+    stream.write_u64(NBD_OPTION_REPLY_MAGIC).await?;
+    stream.write_u32(option).await?;
+    stream.write_u32(reply).await?;
+    stream.write_u32(data.len() as u32).await?;
+    stream.write_all(data).await?;
+    Ok(())
+}
+
+async fn send_simple_reply<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    error: u32,
+    handle: u64,
+    data: Option<&[u8]>,
+) -> Result<(), Error> {
+    stream.write_u32(NBD_SIMPLE_REPLY_MAGIC).await?;
+    stream.write_u32(error).await?;
+    stream.write_u64(handle).await?;
+    if let Some(data) = data {
+        stream.write_all(data).await?;
+    }
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Parse the data of `NBD_OPT_INFO` and `NBD_OPT_GO` and return the requested export name.
+fn parse_info_request(data: &[u8]) -> Option<&[u8]> {
+    let name_len = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+    let name = data.get(4..(4 + name_len))?;
+    let request_count =
+        u16::from_be_bytes(data.get((4 + name_len)..(6 + name_len))?.try_into().ok()?) as usize;
+    if data.len() != 6 + name_len + request_count * 2 {
+        return None;
+    }
+    Some(name)
+}
+
+/// Option haggling phase. Returns `false` if the client aborted the negotiation.
+async fn negotiate<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    export: &NbdExport,
+) -> Result<bool, Error> {
+    stream.write_u64(NBD_MAGIC).await?;
+    stream.write_u64(NBD_IHAVEOPT).await?;
+    stream
+        .write_u16(NBD_FLAG_FIXED_NEWSTYLE | NBD_FLAG_NO_ZEROES)
+        .await?;
+    stream.flush().await?;
+
+    let client_flags = stream.read_u32().await?;
+    if client_flags & NBD_FLAG_C_FIXED_NEWSTYLE == 0 {
+        bail!("client does not support the fixed newstyle negotiation");
+    }
+    let no_zeroes = client_flags & NBD_FLAG_C_NO_ZEROES != 0;
+
+    loop {
+        let magic = stream.read_u64().await?;
+        if magic != NBD_IHAVEOPT {
+            bail!("got invalid option magic {magic:#x}");
+        }
+        let option = stream.read_u32().await?;
+        let length = stream.read_u32().await?;
+        if length > MAX_OPTION_LENGTH {
+            bail!("option data too large ({length} bytes)");
+        }
+        let mut data = vec![0u8; length as usize];
+        stream.read_exact(&mut data).await?;
+
+        match option {
+            NBD_OPT_EXPORT_NAME => {
+                if !export.matches(&data) {
+                    // this option has no error reply, just drop the connection
+                    bail!(
+                        "client requested unknown export '{}'",
+                        String::from_utf8_lossy(&data)
+                    );
+                }
+                stream.write_u64(export.size).await?;
+                stream.write_u16(export.transmission_flags()).await?;
+                if !no_zeroes {
+                    stream.write_all(&[0u8; 124]).await?;
+                }
+                stream.flush().await?;
+                return Ok(true);
+            }
+            NBD_OPT_ABORT => {
+                send_option_reply(stream, option, NBD_REP_ACK, &[]).await?;
+                stream.flush().await?;
+                return Ok(false);
+            }
+            NBD_OPT_LIST => {
+                let mut reply = Vec::with_capacity(4 + export.name.len());
+                reply.extend_from_slice(&(export.name.len() as u32).to_be_bytes());
+                reply.extend_from_slice(export.name.as_bytes());
+                send_option_reply(stream, option, NBD_REP_SERVER, &reply).await?;
+                send_option_reply(stream, option, NBD_REP_ACK, &[]).await?;
+            }
+            NBD_OPT_INFO | NBD_OPT_GO => match parse_info_request(&data) {
+                None => send_option_reply(stream, option, NBD_REP_ERR_INVALID, &[]).await?,
+                Some(name) if !export.matches(name) => {
+                    send_option_reply(stream, option, NBD_REP_ERR_UNKNOWN, &[]).await?
+                }
+                Some(_) => {
+                    let mut info = Vec::with_capacity(12);
+                    info.extend_from_slice(&NBD_INFO_EXPORT.to_be_bytes());
+                    info.extend_from_slice(&export.size.to_be_bytes());
+                    info.extend_from_slice(&export.transmission_flags().to_be_bytes());
+                    send_option_reply(stream, option, NBD_REP_INFO, &info).await?;
+
+                    let mut info = Vec::with_capacity(14);
+                    info.extend_from_slice(&NBD_INFO_BLOCK_SIZE.to_be_bytes());
+                    info.extend_from_slice(&1u32.to_be_bytes());
+                    info.extend_from_slice(&(COW_BLOCK_SIZE as u32).to_be_bytes());
+                    info.extend_from_slice(&MAX_REQUEST_SIZE.to_be_bytes());
+                    send_option_reply(stream, option, NBD_REP_INFO, &info).await?;
+
+                    send_option_reply(stream, option, NBD_REP_ACK, &[]).await?;
+                    if option == NBD_OPT_GO {
+                        stream.flush().await?;
+                        return Ok(true);
+                    }
+                }
+            },
+            _ => send_option_reply(stream, option, NBD_REP_ERR_UNSUP, &[]).await?,
+        }
+        stream.flush().await?;
+    }
+}
+
+/// Transmission phase, handles requests until the client disconnects.
+async fn transmit<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    export: &NbdExport,
+) -> Result<(), Error> {
+    loop {
+        let magic = match stream.read_u32().await {
+            Ok(magic) => magic,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        if magic != NBD_REQUEST_MAGIC {
+            bail!("got invalid request magic {magic:#x}");
+        }
+        let _flags = stream.read_u16().await?;
+        let command = stream.read_u16().await?;
+        let handle = stream.read_u64().await?;
+        let offset = stream.read_u64().await?;
+        let length = stream.read_u32().await?;
+
+        let in_range = offset
+            .checked_add(length as u64)
+            .map(|end| end <= export.size)
+            .unwrap_or(false);
+
+        match command {
+            NBD_CMD_READ => {
+                if length > MAX_REQUEST_SIZE || !in_range {
+                    send_simple_reply(stream, NBD_EINVAL, handle, None).await?;
+                    continue;
+                }
+                let mut buf = vec![0u8; length as usize];
+                match export.read_at(&mut buf, offset).await {
+                    Ok(()) => send_simple_reply(stream, 0, handle, Some(&buf)).await?,
+                    Err(err) => {
+                        log::error!("read of {length} bytes at offset {offset} failed - {err}");
+                        send_simple_reply(stream, NBD_EIO, handle, None).await?;
+                    }
+                }
+            }
+            NBD_CMD_WRITE => {
+                if length > MAX_REQUEST_SIZE {
+                    // we cannot skip the payload reliably, drop the connection
+                    bail!("write request too large ({length} bytes)");
+                }
+                let mut buf = vec![0u8; length as usize];
+                stream.read_exact(&mut buf).await?;
+
+                let error = if export.overlay.is_none() {
+                    NBD_EPERM
+                } else if !in_range {
+                    NBD_ENOSPC
+                } else if let Err(err) = export.write_at(&buf, offset).await {
+                    log::error!("write of {length} bytes at offset {offset} failed - {err}");
+                    NBD_EIO
+                } else {
+                    0
+                };
+                send_simple_reply(stream, error, handle, None).await?;
+            }
+            NBD_CMD_FLUSH => {
+                // the overlay is temporary, nothing to persist
+                send_simple_reply(stream, 0, handle, None).await?;
+            }
+            NBD_CMD_DISC => return Ok(()),
+            _ => send_simple_reply(stream, NBD_EINVAL, handle, None).await?,
+        }
+    }
+}
+
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    export: Arc<NbdExport>,
+) -> Result<(), Error> {
+    let mut stream = tokio::io::BufStream::new(stream);
+    if negotiate(&mut stream, &export).await? {
+        transmit(&mut stream, &export).await?;
+    }
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            repository: {
+                schema: REPO_URL_SCHEMA,
+                optional: true,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            snapshot: {
+                type: String,
+                description: "Group/Snapshot path.",
+            },
+            "archive-name": {
+                type: String,
+                description: "Drive image archive name.",
+            },
+            keyfile: {
+                type: String,
+                description: "Path to encryption key.",
+                optional: true,
+            },
+            listen: {
+                type: String,
+                description: "TCP address and port to listen on.",
+                optional: true,
+                default: NBD_DEFAULT_LISTEN,
+            },
+            socket: {
+                type: String,
+                description: "Listen on this unix socket path instead of a TCP address.",
+                optional: true,
+            },
+            "export-name": {
+                type: String,
+                description: "Name of the NBD export, defaults to the archive name.",
+                optional: true,
+            },
+            "copy-on-write": {
+                type: Boolean,
+                description: "Accept writes and store them in a temporary overlay, which is \
+                    discarded on exit.",
+                optional: true,
+                default: false,
+            },
+        }
+    }
+)]
+/// Export a drive image from a VM backup via NBD. Runs in the foreground until interrupted.
+#[allow(clippy::too_many_arguments)]
+async fn nbd_export(
+    param: Value,
+    snapshot: String,
+    archive_name: String,
+    keyfile: Option<String>,
+    listen: Option<String>,
+    socket: Option<String>,
+    export_name: Option<String>,
+    copy_on_write: bool,
+) -> Result<(), Error> {
+    let repo = extract_repository_from_value(&param)?;
+    let client = connect(&repo)?;
+    record_repository(&repo);
+
+    let backup_ns = optional_ns_param(&param)?;
+    let backup_dir = dir_or_last_from_group(&client, &repo, &backup_ns, &snapshot).await?;
+
+    let crypt_config = match keyfile.map(PathBuf::from) {
+        None => None,
+        Some(path) => {
+            log::info!("Encryption key file: '{:?}'", path);
+            let (key, _, fingerprint) = load_and_decrypt_key(&path, &get_encryption_key_password)?;
+            log::info!("Encryption key fingerprint: '{}'", fingerprint);
+            Some(Arc::new(CryptConfig::new(key)?))
+        }
+    };
+
+    let archive_name = archive_name
+        .strip_suffix(".fidx")
+        .unwrap_or(&archive_name)
+        .to_string();
+    if !archive_name.ends_with(".img") {
+        bail!("can only export drive images (.img archives) via NBD");
+    }
+    let server_archive_name = format!("{archive_name}.fidx");
+
+    let client = BackupReader::start(
+        &client,
+        crypt_config.clone(),
+        repo.store(),
+        &backup_ns,
+        &backup_dir,
+        true,
+    )
+    .await?;
+
+    let (manifest, _) = client.download_manifest().await?;
+    manifest.check_fingerprint(crypt_config.as_ref().map(Arc::as_ref))?;
+
+    let file_info = manifest.lookup_file_info(&server_archive_name)?;
+    let index = client
+        .download_fixed_index(&manifest, &server_archive_name)
+        .await?;
+    let size = index.index_bytes();
+    let chunk_reader = RemoteChunkReader::new(
+        client.clone(),
+        crypt_config,
+        file_info.chunk_crypt_mode(),
+        HashMap::new(),
+    );
+
+    let overlay = if copy_on_write {
+        Some(tokio::sync::Mutex::new(CowOverlay::new()?))
+    } else {
+        None
+    };
+
+    let export = Arc::new(NbdExport {
+        name: export_name.unwrap_or_else(|| archive_name.clone()),
+        size,
+        reader: Box::new(CachedChunkReader::new(chunk_reader, index, 8)),
+        overlay,
+    });
+
+    // handle SIGINT and SIGTERM
+    let mut interrupt_int = signal(SignalKind::interrupt())?;
+    let mut interrupt_term = signal(SignalKind::terminate())?;
+    let mut interrupt =
+        futures::future::select(interrupt_int.recv().boxed(), interrupt_term.recv().boxed()).fuse();
+
+    let mode = if copy_on_write {
+        "copy-on-write"
+    } else {
+        "read-only"
+    };
+
+    if let Some(socket) = socket {
+        let listener = UnixListener::bind(&socket)
+            .map_err(|err| format_err!("unable to listen on socket {socket:?} - {err}"))?;
+        log::info!(
+            "Exporting '{}' ({size} bytes, {mode}) as 'nbd+unix:///{}?socket={socket}'",
+            backup_dir,
+            export.name
+        );
+
+        loop {
+            select! {
+                res = listener.accept().fuse() => {
+                    let (stream, _) = res?;
+                    let export = Arc::clone(&export);
+                    tokio::spawn(async move {
+                        if let Err(err) = handle_connection(stream, export).await {
+                            log::error!("NBD connection failed - {err}");
+                        }
+                    });
+                }
+                _ = interrupt => break,
+            }
+        }
+
+        let _ = std::fs::remove_file(&socket);
+    } else {
+        let listen = listen.unwrap_or_else(|| NBD_DEFAULT_LISTEN.to_string());
+        let listener = TcpListener::bind(&listen)
+            .await
+            .map_err(|err| format_err!("unable to listen on {listen} - {err}"))?;
+        log::info!(
+            "Exporting '{}' ({size} bytes, {mode}) as 'nbd://{listen}/{}'",
+            backup_dir,
+            export.name
+        );
+
+        loop {
+            select! {
+                res = listener.accept().fuse() => {
+                    let (stream, peer) = res?;
+                    log::info!("NBD connection from {peer}");
+                    let export = Arc::clone(&export);
+                    tokio::spawn(async move {
+                        if let Err(err) = handle_connection(stream, export).await {
+                            log::error!("NBD connection from {peer} failed - {err}");
+                        }
+                    });
+                }
+                _ = interrupt => break,
+            }
+        }
+    }
+
+    log::info!("NBD export stopped");
+
+    Ok(())
+}
+
+pub fn nbd_cmd_def() -> CliCommand {
+    CliCommand::new(&API_METHOD_NBD_EXPORT)
+        .arg_param(&["snapshot", "archive-name"])
+        .completion_cb("repository", complete_repository)
+        .completion_cb("ns", complete_namespace)
+        .completion_cb("snapshot", complete_group_or_snapshot)
+        .completion_cb("archive-name", complete_img_archive_name)
+        .completion_cb("keyfile", complete_file_name)
+        .completion_cb("socket", complete_file_name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::io::DuplexStream;
+
+    const TEST_SIZE: u64 = 3 * COW_BLOCK_SIZE;
+
+    impl ImageReader for Vec<u8> {
+        fn read_at<'a>(
+            &'a self,
+            buf: &'a mut [u8],
+            offset: u64,
+        ) -> BoxFuture<'a, Result<usize, Error>> {
+            let start = (offset as usize).min(self.len());
+            let len = buf.len().min(self.len() - start);
+            buf[..len].copy_from_slice(&self[start..(start + len)]);
+            futures::future::ready(Ok(len)).boxed()
+        }
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..TEST_SIZE).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn test_export(copy_on_write: bool) -> Result<Arc<NbdExport>, Error> {
+        let overlay = if copy_on_write {
+            Some(tokio::sync::Mutex::new(CowOverlay::new()?))
+        } else {
+            None
+        };
+        Ok(Arc::new(NbdExport {
+            name: "drive-scsi0.img".to_string(),
+            size: TEST_SIZE,
+            reader: Box::new(test_data()),
+            overlay,
+        }))
+    }
+
+    /// Run `client` against a server handling a single connection to `export`.
+    fn run_client<F, Fut>(export: Arc<NbdExport>, client: F) -> Result<(), Error>
+    where
+        F: FnOnce(DuplexStream) -> Fut,
+        Fut: std::future::Future<Output = Result<(), Error>>,
+    {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async move {
+            let (client_stream, server_stream) = tokio::io::duplex(1024 * 1024);
+            let server = tokio::spawn(handle_connection(server_stream, export));
+            client(client_stream).await?;
+            server.await?
+        })
+    }
+
+    async fn read_option_reply(stream: &mut DuplexStream) -> Result<(u32, u32, Vec<u8>), Error> {
+        assert_eq!(stream.read_u64().await?, NBD_OPTION_REPLY_MAGIC);
+        let option = stream.read_u32().await?;
+        let reply = stream.read_u32().await?;
+        let mut data = vec![0u8; stream.read_u32().await? as usize];
+        stream.read_exact(&mut data).await?;
+        Ok((option, reply, data))
+    }
+
+    async fn send_option(stream: &mut DuplexStream, option: u32, data: &[u8]) -> Result<(), Error> {
+        stream.write_u64(NBD_IHAVEOPT).await?;
+        stream.write_u32(option).await?;
+        stream.write_u32(data.len() as u32).await?;
+        stream.write_all(data).await?;
+        Ok(())
+    }
+
+    /// Negotiate the default export via `NBD_OPT_GO`, returns its size and transmission flags.
+    async fn handshake(stream: &mut DuplexStream) -> Result<(u64, u16), Error> {
+        assert_eq!(stream.read_u64().await?, NBD_MAGIC);
+        assert_eq!(stream.read_u64().await?, NBD_IHAVEOPT);
+        let flags = stream.read_u16().await?;
+        assert_eq!(flags, NBD_FLAG_FIXED_NEWSTYLE | NBD_FLAG_NO_ZEROES);
+        stream
+            .write_u32(NBD_FLAG_C_FIXED_NEWSTYLE | NBD_FLAG_C_NO_ZEROES)
+            .await?;
+
+        // empty export name, no information requests
+        send_option(stream, NBD_OPT_GO, &[0, 0, 0, 0, 0, 0]).await?;
+
+        let (option, reply, info) = read_option_reply(stream).await?;
+        assert_eq!((option, reply), (NBD_OPT_GO, NBD_REP_INFO));
+        assert_eq!(info[0..2], NBD_INFO_EXPORT.to_be_bytes());
+        let size = u64::from_be_bytes(info[2..10].try_into()?);
+        let flags = u16::from_be_bytes(info[10..12].try_into()?);
+
+        let (_, reply, info) = read_option_reply(stream).await?;
+        assert_eq!(reply, NBD_REP_INFO);
+        assert_eq!(info[0..2], NBD_INFO_BLOCK_SIZE.to_be_bytes());
+
+        let (_, reply, _) = read_option_reply(stream).await?;
+        assert_eq!(reply, NBD_REP_ACK);
+
+        Ok((size, flags))
+    }
+
+    async fn send_request(
+        stream: &mut DuplexStream,
+        command: u16,
+        handle: u64,
+        offset: u64,
+        length: u32,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        stream.write_u32(NBD_REQUEST_MAGIC).await?;
+        stream.write_u16(0).await?;
+        stream.write_u16(command).await?;
+        stream.write_u64(handle).await?;
+        stream.write_u64(offset).await?;
+        stream.write_u32(length).await?;
+        stream.write_all(data).await?;
+        Ok(())
+    }
+
+    /// Read a simple reply, including `length` bytes of data if it was successful.
+    async fn read_reply(
+        stream: &mut DuplexStream,
+        length: usize,
+    ) -> Result<(u32, u64, Vec<u8>), Error> {
+        assert_eq!(stream.read_u32().await?, NBD_SIMPLE_REPLY_MAGIC);
+        let error = stream.read_u32().await?;
+        let handle = stream.read_u64().await?;
+        let mut data = Vec::new();
+        if error == 0 {
+            data.resize(length, 0);
+            stream.read_exact(&mut data).await?;
+        }
+        Ok((error, handle, data))
+    }
+
+    #[test]
+    fn test_parse_info_request() {
+        assert_eq!(parse_info_request(&[0, 0, 0, 0, 0, 0]), Some(&b""[..]));
+        assert_eq!(
+            parse_info_request(&[0, 0, 0, 3, b'f', b'o', b'o', 0, 1, 0, 3]),
+            Some(&b"foo"[..])
+        );
+        // truncated name, missing or extra information requests
+        assert_eq!(parse_info_request(&[0, 0, 0, 4, b'f', b'o', b'o']), None);
+        assert_eq!(parse_info_request(&[0, 0, 0, 0, 0, 1]), None);
+        assert_eq!(parse_info_request(&[0, 0, 0, 0, 0, 0, 0, 3]), None);
+        assert_eq!(parse_info_request(&[]), None);
+    }
+
+    #[test]
+    fn test_handshake() -> Result<(), Error> {
+        run_client(test_export(false)?, |mut stream| async move {
+            assert_eq!(stream.read_u64().await?, NBD_MAGIC);
+            assert_eq!(stream.read_u64().await?, NBD_IHAVEOPT);
+            stream.read_u16().await?;
+            stream.write_u32(NBD_FLAG_C_FIXED_NEWSTYLE).await?;
+
+            send_option(&mut stream, NBD_OPT_LIST, &[]).await?;
+            let (option, reply, data) = read_option_reply(&mut stream).await?;
+            assert_eq!((option, reply), (NBD_OPT_LIST, NBD_REP_SERVER));
+            assert_eq!(&data[4..], b"drive-scsi0.img");
+            let (_, reply, _) = read_option_reply(&mut stream).await?;
+            assert_eq!(reply, NBD_REP_ACK);
+
+            let mut request = 5u32.to_be_bytes().to_vec();
+            request.extend_from_slice(b"other");
+            request.extend_from_slice(&0u16.to_be_bytes());
+            send_option(&mut stream, NBD_OPT_INFO, &request).await?;
+            let (_, reply, _) = read_option_reply(&mut stream).await?;
+            assert_eq!(reply, NBD_REP_ERR_UNKNOWN);
+
+            send_option(&mut stream, NBD_OPT_INFO, &[0, 0]).await?;
+            let (_, reply, _) = read_option_reply(&mut stream).await?;
+            assert_eq!(reply, NBD_REP_ERR_INVALID);
+
+            send_option(&mut stream, 42, &[]).await?;
+            let (_, reply, _) = read_option_reply(&mut stream).await?;
+            assert_eq!(reply, NBD_REP_ERR_UNSUP);
+
+            // without NBD_FLAG_C_NO_ZEROES, the export info is padded
+            send_option(&mut stream, NBD_OPT_EXPORT_NAME, b"drive-scsi0.img").await?;
+            assert_eq!(stream.read_u64().await?, TEST_SIZE);
+            let flags = stream.read_u16().await?;
+            assert_ne!(flags & NBD_FLAG_READ_ONLY, 0);
+            let mut zeroes = [1u8; 124];
+            stream.read_exact(&mut zeroes).await?;
+            assert_eq!(zeroes, [0u8; 124]);
+
+            send_request(&mut stream, NBD_CMD_DISC, 1, 0, 0, &[]).await
+        })
+    }
+
+    #[test]
+    fn test_unknown_export_name() {
+        let result = run_client(test_export(false).unwrap(), |mut stream| async move {
+            stream.read_exact(&mut [0u8; 18]).await?;
+            stream.write_u32(NBD_FLAG_C_FIXED_NEWSTYLE).await?;
+            send_option(&mut stream, NBD_OPT_EXPORT_NAME, b"other").await?;
+            Ok(())
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read() -> Result<(), Error> {
+        let data = test_data();
+        run_client(test_export(false)?, |mut stream| async move {
+            let (size, flags) = handshake(&mut stream).await?;
+            assert_eq!(size, TEST_SIZE);
+            assert_ne!(flags & NBD_FLAG_READ_ONLY, 0);
+
+            send_request(&mut stream, NBD_CMD_READ, 7, 4000, 200, &[]).await?;
+            let (error, handle, buf) = read_reply(&mut stream, 200).await?;
+            assert_eq!((error, handle), (0, 7));
+            assert_eq!(buf, data[4000..4200]);
+
+            // the whole image in one request
+            send_request(&mut stream, NBD_CMD_READ, 8, 0, TEST_SIZE as u32, &[]).await?;
+            let (error, handle, buf) = read_reply(&mut stream, TEST_SIZE as usize).await?;
+            assert_eq!((error, handle), (0, 8));
+            assert_eq!(buf, data);
+
+            // writes are refused on read-only exports
+            send_request(&mut stream, NBD_CMD_WRITE, 9, 0, 4, b"test").await?;
+            let (error, handle, _) = read_reply(&mut stream, 0).await?;
+            assert_eq!((error, handle), (NBD_EPERM, 9));
+
+            send_request(&mut stream, NBD_CMD_DISC, 10, 0, 0, &[]).await
+        })
+    }
+
+    #[test]
+    fn test_out_of_range_read() -> Result<(), Error> {
+        run_client(test_export(false)?, |mut stream| async move {
+            handshake(&mut stream).await?;
+
+            for (handle, offset, length) in [
+                (1, TEST_SIZE - 4, 8),
+                (2, TEST_SIZE, 1),
+                (3, u64::MAX, 2),
+                (4, 0, MAX_REQUEST_SIZE + 1),
+            ] {
+                send_request(&mut stream, NBD_CMD_READ, handle, offset, length, &[]).await?;
+                let (error, reply_handle, _) = read_reply(&mut stream, 0).await?;
+                assert_eq!((error, reply_handle), (NBD_EINVAL, handle));
+            }
+
+            // the connection is still usable afterwards
+            send_request(&mut stream, NBD_CMD_READ, 5, TEST_SIZE - 4, 4, &[]).await?;
+            let (error, handle, _) = read_reply(&mut stream, 4).await?;
+            assert_eq!((error, handle), (0, 5));
+
+            send_request(&mut stream, NBD_CMD_DISC, 6, 0, 0, &[]).await
+        })
+    }
+
+    #[test]
+    fn test_disconnect() -> Result<(), Error> {
+        // the server returns after NBD_CMD_DISC, without sending a reply
+        run_client(test_export(false)?, |mut stream| async move {
+            handshake(&mut stream).await?;
+            send_request(&mut stream, NBD_CMD_DISC, 1, 0, 0, &[]).await?;
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await?;
+            assert!(buf.is_empty());
+            Ok(())
+        })?;
+
+        // closing the connection is not an error either
+        run_client(test_export(false)?, |mut stream| async move {
+            handshake(&mut stream).await?;
+            drop(stream);
+            Ok(())
+        })?;
+
+        // neither is aborting the negotiation
+        run_client(test_export(false)?, |mut stream| async move {
+            stream.read_exact(&mut [0u8; 18]).await?;
+            stream.write_u32(NBD_FLAG_C_FIXED_NEWSTYLE).await?;
+            send_option(&mut stream, NBD_OPT_ABORT, &[]).await?;
+            let (_, reply, _) = read_option_reply(&mut stream).await?;
+            assert_eq!(reply, NBD_REP_ACK);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_copy_on_write() -> Result<(), Error> {
+        let mut expected = test_data();
+        expected[4090..4100].copy_from_slice(&[0xff; 10]);
+
+        run_client(test_export(true)?, |mut stream| async move {
+            let (_, flags) = handshake(&mut stream).await?;
+            assert_eq!(flags & NBD_FLAG_READ_ONLY, 0);
+
+            // crosses a block boundary, both blocks are only partially written
+            send_request(&mut stream, NBD_CMD_WRITE, 1, 4090, 10, &[0xff; 10]).await?;
+            let (error, _, _) = read_reply(&mut stream, 0).await?;
+            assert_eq!(error, 0);
+
+            send_request(&mut stream, NBD_CMD_WRITE, 2, TEST_SIZE - 2, 4, &[0; 4]).await?;
+            let (error, _, _) = read_reply(&mut stream, 0).await?;
+            assert_eq!(error, NBD_ENOSPC);
+
+            send_request(&mut stream, NBD_CMD_READ, 3, 0, TEST_SIZE as u32, &[]).await?;
+            let (error, _, buf) = read_reply(&mut stream, TEST_SIZE as usize).await?;
+            assert_eq!(error, 0);
+            assert_eq!(buf, expected);
+
+            send_request(&mut stream, NBD_CMD_DISC, 4, 0, 0, &[]).await
+        })
+    }
+}
@@ -96,6 +96,10 @@ async fn list_snapshots(param: Value) -> Result<Value, Error> {
                 .header("snapshot"),
         )
         .column(ColumnConfig::new("size").renderer(pbs_tools::format::render_bytes_human_readable))
+        .column(
+            ColumnConfig::new("unique-size")
+                .renderer(pbs_tools::format::render_bytes_human_readable),
+        )
         .column(ColumnConfig::new("files").renderer(render_files));
 
     let return_type = &pbs_api_types::ADMIN_DATASTORE_LIST_SNAPSHOTS_RETURN_TYPE;
@@ -493,6 +497,126 @@ fn protected_cli() -> CliCommandMap {
         )
 }
 
+#[api(
+    input: {
+        properties: {
+            repository: {
+                schema: REPO_URL_SCHEMA,
+                optional: true,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            snapshot: {
+                type: String,
+                description: "Snapshot path.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show the retention lock of the specified snapshot
+async fn show_retention_lock(param: Value) -> Result<(), Error> {
+    let repo = extract_repository_from_value(&param)?;
+    let path = required_string_param(&param, "snapshot")?;
+
+    let backup_ns = optional_ns_param(&param)?;
+    let snapshot: BackupDir = path.parse()?;
+    let client = connect(&repo)?;
+
+    let path = format!("api2/json/admin/datastore/{}/retention-lock", repo.store());
+
+    let args = snapshot_args(&backup_ns, &snapshot)?;
+
+    let output_format = get_output_format(&param);
+
+    let mut result = client.get(&path, Some(args)).await?;
+
+    let locked_until = result["data"].take();
+
+    if output_format == "text" {
+        match locked_until.as_i64() {
+            Some(locked_until) if locked_until > proxmox_time::epoch_i64() => {
+                println!("{}", proxmox_time::epoch_to_rfc3339(locked_until)?)
+            }
+            _ => println!("not locked"),
+        }
+    } else {
+        format_and_print_result(
+            &json!({
+                "locked-until": locked_until,
+            }),
+            &output_format,
+        );
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            repository: {
+                schema: REPO_URL_SCHEMA,
+                optional: true,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            snapshot: {
+                type: String,
+                description: "Snapshot path.",
+            },
+            "lock-for": {
+                type: String,
+                description: "Lock the snapshot against removal for this time span from now on.",
+            },
+        }
+    }
+)]
+/// Lock a snapshot against removal, an active lock can only be extended
+async fn extend_retention_lock(lock_for: String, param: Value) -> Result<(), Error> {
+    let repo = extract_repository_from_value(&param)?;
+    let path = required_string_param(&param, "snapshot")?;
+
+    let backup_ns = optional_ns_param(&param)?;
+    let snapshot: BackupDir = path.parse()?;
+    let lock_for: proxmox_time::TimeSpan = lock_for.parse()?;
+    let client = connect(&repo)?;
+
+    let path = format!("api2/json/admin/datastore/{}/retention-lock", repo.store());
+
+    let mut args = snapshot_args(&backup_ns, &snapshot)?;
+    args["locked-until"] = Value::from(proxmox_time::epoch_i64() + f64::from(lock_for) as i64);
+
+    client.put(&path, Some(args)).await?;
+
+    Ok(())
+}
+
+fn retention_lock_cli() -> CliCommandMap {
+    CliCommandMap::new()
+        .insert(
+            "show",
+            CliCommand::new(&API_METHOD_SHOW_RETENTION_LOCK)
+                .arg_param(&["snapshot"])
+                .completion_cb("ns", complete_namespace)
+                .completion_cb("snapshot", complete_backup_snapshot),
+        )
+        .insert(
+            "extend",
+            CliCommand::new(&API_METHOD_EXTEND_RETENTION_LOCK)
+                .arg_param(&["snapshot", "lock-for"])
+                .completion_cb("ns", complete_namespace)
+                .completion_cb("snapshot", complete_backup_snapshot),
+        )
+}
+
 fn notes_cli() -> CliCommandMap {
     CliCommandMap::new()
         .insert(
@@ -515,6 +639,7 @@ pub fn snapshot_mgtm_cli() -> CliCommandMap {
     CliCommandMap::new()
         .insert("notes", notes_cli())
         .insert("protected", protected_cli())
+        .insert("retention-lock", retention_lock_cli())
         .insert(
             "list",
             CliCommand::new(&API_METHOD_LIST_SNAPSHOTS)
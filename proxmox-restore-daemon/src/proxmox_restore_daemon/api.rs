@@ -352,6 +352,8 @@ fn extract(
                         device_set: None,
                         patterns,
                         skip_lost_and_found: false,
+                        previous_ref: None,
+                        change_info: None,
                     };
 
                     let pxar_writer = TokioWriter::new(writer);
@@ -335,6 +335,8 @@ async fn create_archive(
         device_set,
         patterns,
         skip_lost_and_found: false,
+        previous_ref: None,
+        change_info: None,
     };
 
     let source = PathBuf::from(source);
@@ -7,8 +7,8 @@ use proxmox_router::{Permission, Router, RpcEnvironment};
 use proxmox_schema::api;
 
 use pbs_api_types::{
-    AclListItem, Authid, Role, ACL_PATH_SCHEMA, ACL_PROPAGATE_SCHEMA, PRIV_PERMISSIONS_MODIFY,
-    PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA, PROXMOX_GROUP_ID_SCHEMA,
+    AclListItem, Authid, Group, ACL_PATH_SCHEMA, ACL_PROPAGATE_SCHEMA, PRIV_PERMISSIONS_MODIFY,
+    PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA, PROXMOX_GROUP_ID_SCHEMA, ROLE_ID_SCHEMA,
 };
 
 use pbs_config::acl::AclTreeNode;
@@ -146,7 +146,7 @@ pub fn read_acl(
                 schema: ACL_PATH_SCHEMA,
             },
 	    role: {
-                type: Role,
+                schema: ROLE_ID_SCHEMA,
             },
             propagate: {
                 optional: true,
@@ -227,8 +227,14 @@ pub fn update_acl(
 
     let delete = delete.unwrap_or(false);
 
-    if let Some(ref _group) = group {
-        bail!("parameter 'group' - groups are currently not supported.");
+    if let Some(ref group) = group {
+        if !delete {
+            // Note: we allow to delete non-existent groups
+            let user_cfg = pbs_config::user::cached_config()?;
+            if user_cfg.lookup::<Group>("group", group).is_err() {
+                bail!("no such group.");
+            }
+        }
     } else if let Some(ref auth_id) = auth_id {
         if !delete {
             // Note: we allow to delete non-existent users
@@ -249,8 +255,9 @@ pub fn update_acl(
     }
 
     if !delete {
-        // Note: we allow to delete entries with invalid path
+        // Note: we allow to delete entries with invalid path or unknown roles
         pbs_config::acl::check_acl_path(&path)?;
+        pbs_config::role::check_role_exists(&role)?;
     }
 
     if let Some(auth_id) = auth_id {
@@ -7,7 +7,8 @@ use proxmox_router::{Permission, Router, RpcEnvironment, RpcEnvironmentType, Sub
 use proxmox_schema::api;
 
 use pbs_api_types::{
-    Authid, BasicRealmInfo, Realm, PRIV_PERMISSIONS_MODIFY, REMOVE_VANISHED_SCHEMA, UPID_SCHEMA,
+    Authid, BasicRealmInfo, Realm, SyncScope, PRIV_PERMISSIONS_MODIFY, REMOVE_VANISHED_SCHEMA,
+    UPID_SCHEMA,
 };
 
 use crate::server::jobstate::Job;
@@ -74,7 +75,11 @@ fn list_domains(rpcenv: &mut dyn RpcEnvironment) -> Result<Vec<BasicRealmInfo>,
             "enable-new": {
                 description: "Enable newly synced users immediately",
                 optional: true,
-            }
+            },
+            scope: {
+                type: SyncScope,
+                optional: true,
+            },
          },
     },
     returns: {
@@ -84,12 +89,13 @@ fn list_domains(rpcenv: &mut dyn RpcEnvironment) -> Result<Vec<BasicRealmInfo>,
         permission: &Permission::Privilege(&["access", "users"], PRIV_PERMISSIONS_MODIFY, false),
     },
 )]
-/// Synchronize users of a given realm
+/// Synchronize users and groups of a given realm
 pub fn sync_realm(
     realm: Realm,
     dry_run: bool,
     remove_vanished: Option<String>,
     enable_new: Option<bool>,
+    scope: Option<SyncScope>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Value, Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
@@ -108,6 +114,7 @@ pub fn sync_realm(
         dry_run,
         remove_vanished,
         enable_new,
+        scope,
     )
     .map_err(|err| {
         format_err!(
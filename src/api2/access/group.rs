@@ -0,0 +1,289 @@
+//! Group Management
+
+use anyhow::{bail, Error};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+
+use proxmox_router::{Permission, Router, RpcEnvironment};
+use proxmox_schema::api;
+use proxmox_section_config::SectionConfigData;
+
+use pbs_api_types::{
+    Group, GroupUpdater, Userid, GROUP_MEMBER_LIST_SCHEMA, PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT,
+    PROXMOX_CONFIG_DIGEST_SCHEMA, PROXMOX_GROUP_ID_SCHEMA,
+};
+
+fn check_members_exist(config: &SectionConfigData, members: &[Userid]) -> Result<(), Error> {
+    for userid in members {
+        if config
+            .lookup::<pbs_api_types::User>("user", userid.as_str())
+            .is_err()
+        {
+            bail!("user '{userid}' does not exist.");
+        }
+    }
+    Ok(())
+}
+
+fn sort_members(group: &mut Group) {
+    if let Some(members) = group.members.as_mut() {
+        members.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    }
+}
+
+#[api(
+    returns: {
+        description: "List of groups.",
+        type: Array,
+        items: { type: Group },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "groups"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List groups.
+pub fn list_groups(rpcenv: &mut dyn RpcEnvironment) -> Result<Vec<Group>, Error> {
+    let (config, digest) = pbs_config::user::config()?;
+
+    let list = config.convert_to_typed_array("group")?;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: Group,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "groups"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Create a new group.
+pub fn create_group(config: Group) -> Result<(), Error> {
+    let _lock = pbs_config::user::lock_config()?;
+
+    let (mut section_config, _digest) = pbs_config::user::config()?;
+
+    if section_config.sections.get(&config.groupid).is_some() {
+        bail!("group '{}' already exists.", config.groupid);
+    }
+
+    let mut data = Group {
+        members: None,
+        ..config
+    };
+    if let Some(members) = config.members {
+        check_members_exist(&section_config, &members)?;
+        for userid in members {
+            data.add_member(userid);
+        }
+        sort_members(&mut data);
+    }
+
+    section_config.set_data(&data.groupid, "group", &data)?;
+
+    pbs_config::user::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            groupid: {
+                schema: PROXMOX_GROUP_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: Group },
+    access: {
+        permission: &Permission::Privilege(&["access", "groups"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Read group configuration data.
+pub fn read_group(groupid: String, rpcenv: &mut dyn RpcEnvironment) -> Result<Group, Error> {
+    let (config, digest) = pbs_config::user::config()?;
+    let group = config.lookup("group", &groupid)?;
+    rpcenv["digest"] = hex::encode(digest).into();
+    Ok(group)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the comment property.
+    Comment,
+    /// Remove all members.
+    Members,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            groupid: {
+                schema: PROXMOX_GROUP_ID_SCHEMA,
+            },
+            update: {
+                type: GroupUpdater,
+                flatten: true,
+            },
+            "add-members": {
+                optional: true,
+                schema: GROUP_MEMBER_LIST_SCHEMA,
+            },
+            "remove-members": {
+                optional: true,
+                schema: GROUP_MEMBER_LIST_SCHEMA,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "groups"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Update group configuration and membership.
+///
+/// 'members' replaces the member list, 'add-members' and 'remove-members' are applied
+/// afterwards.
+pub fn update_group(
+    groupid: String,
+    update: GroupUpdater,
+    add_members: Option<Vec<Userid>>,
+    remove_members: Option<Vec<Userid>>,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+) -> Result<(), Error> {
+    let _lock = pbs_config::user::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::user::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut data: Group = config.lookup("group", &groupid)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Comment => data.comment = None,
+                DeletableProperty::Members => data.members = None,
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            data.comment = None;
+        } else {
+            data.comment = Some(comment);
+        }
+    }
+
+    if let Some(members) = update.members {
+        check_members_exist(&config, &members)?;
+        data.members = None;
+        for userid in members {
+            data.add_member(userid);
+        }
+    }
+
+    if let Some(add_members) = add_members {
+        check_members_exist(&config, &add_members)?;
+        for userid in add_members {
+            data.add_member(userid);
+        }
+    }
+
+    for userid in remove_members.iter().flatten() {
+        data.remove_member(userid);
+    }
+
+    sort_members(&mut data);
+
+    config.set_data(&groupid, "group", &data)?;
+
+    pbs_config::user::save_config(&config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            groupid: {
+                schema: PROXMOX_GROUP_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "groups"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Remove a group from the configuration file, including all of its ACL entries.
+pub fn delete_group(groupid: String, digest: Option<String>) -> Result<(), Error> {
+    let _lock = pbs_config::user::lock_config()?;
+    let _acl_lock = pbs_config::acl::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::user::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    match config.sections.get(&groupid) {
+        Some((section_type, _)) if section_type == "group" => {
+            config.sections.remove(&groupid);
+        }
+        _ => bail!("group '{groupid}' does not exist."),
+    }
+
+    let (mut tree, _digest) = pbs_config::acl::config()?;
+    tree.delete_group(&groupid);
+
+    pbs_config::user::save_config(&config)?;
+    pbs_config::acl::save_config(&tree)?;
+
+    Ok(())
+}
+
+const GROUP_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_GROUP)
+    .put(&API_METHOD_UPDATE_GROUP)
+    .delete(&API_METHOD_DELETE_GROUP);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_GROUPS)
+    .post(&API_METHOD_CREATE_GROUP)
+    .match_all("groupid", &GROUP_ROUTER);
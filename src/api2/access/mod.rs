@@ -19,6 +19,7 @@ use pbs_config::CachedUserInfo;
 
 pub mod acl;
 pub mod domain;
+pub mod group;
 pub mod openid;
 pub mod role;
 pub mod tfa;
@@ -218,6 +219,7 @@ const SUBDIRS: SubdirMap = &sorted!([
     ),
     ("openid", &openid::ROUTER),
     ("domains", &domain::ROUTER),
+    ("groups", &group::ROUTER),
     ("roles", &role::ROUTER),
     ("users", &user::ROUTER),
     ("tfa", &tfa::ROUTER),
@@ -1,4 +1,6 @@
 //! OpenID redirect/login API
+use std::collections::HashSet;
+
 use anyhow::{bail, format_err, Error};
 use serde_json::{json, Value};
 
@@ -13,8 +15,8 @@ use proxmox_sortable_macro::sortable;
 use proxmox_openid::{OpenIdAuthenticator, OpenIdConfig};
 
 use pbs_api_types::{
-    OpenIdRealmConfig, User, Userid, EMAIL_SCHEMA, FIRST_NAME_SCHEMA, LAST_NAME_SCHEMA,
-    OPENID_DEFAILT_SCOPE_LIST, REALM_ID_SCHEMA,
+    Group, OpenIdRealmConfig, User, Userid, EMAIL_SCHEMA, FIRST_NAME_SCHEMA, LAST_NAME_SCHEMA,
+    OPENID_DEFAILT_SCOPE_LIST, PROXMOX_GROUP_ID_SCHEMA, REALM_ID_SCHEMA,
 };
 use pbs_buildcfg::PROXMOX_BACKUP_RUN_DIR_M;
 
@@ -58,6 +60,79 @@ fn openid_authenticator(
     OpenIdAuthenticator::discover(&config, redirect_url)
 }
 
+/// Set the memberships of `userid` in the groups of `realm` to the groups listed in the
+/// configured groups claim.
+fn update_group_memberships(
+    realm_config: &OpenIdRealmConfig,
+    realm: &str,
+    userid: &Userid,
+    info: &Value,
+) -> Result<(), Error> {
+    let claim = match realm_config.groups_claim.as_deref() {
+        Some(claim) => claim,
+        None => return Ok(()),
+    };
+
+    let names: Vec<&str> = match &info[claim] {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(list) => list.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+
+    let suffix = format!("-{realm}");
+    let claimed_groups: HashSet<String> = names
+        .into_iter()
+        .map(|name| format!("{name}{suffix}"))
+        .filter(|groupid| {
+            PROXMOX_GROUP_ID_SCHEMA
+                .unwrap_string_schema()
+                .check_constraints(groupid)
+                .is_ok()
+        })
+        .collect();
+
+    let _lock = pbs_config::user::lock_config()?;
+    let (mut config, _digest) = pbs_config::user::config()?;
+
+    let mut changed = false;
+
+    for mut group in config.convert_to_typed_array::<Group>("group")? {
+        if !group.groupid.ends_with(&suffix) {
+            continue;
+        }
+        let modified = if claimed_groups.contains(&group.groupid) {
+            group.add_member(userid.clone())
+        } else {
+            group.remove_member(userid)
+        };
+        if modified {
+            config.set_data(&group.groupid.clone(), "group", &group)?;
+            changed = true;
+        }
+    }
+
+    if realm_config.groups_autocreate.unwrap_or(false) {
+        for groupid in claimed_groups {
+            if config.sections.contains_key(&groupid) {
+                continue;
+            }
+            let group = Group {
+                groupid: groupid.clone(),
+                comment: None,
+                members: Some(vec![userid.clone()]),
+            };
+            config.set_data(&groupid, "group", &group)?;
+            changed = true;
+        }
+    }
+
+    if changed {
+        pbs_config::user::save_config(&config)?;
+    }
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -199,6 +274,8 @@ pub fn openid_login(
             }
         }
 
+        update_group_memberships(&config, &realm, &user_id, &info)?;
+
         let api_ticket = ApiTicket::Full(user_id.clone());
         let ticket = Ticket::new("PBS", &api_ticket)?.sign(private_auth_keyring(), None)?;
         let token = assemble_csrf_prevention_token(csrf_secret(), &user_id);
@@ -1,13 +1,17 @@
 //! Manage Roles with privileges
 
-use anyhow::Error;
-
+use anyhow::{bail, Error};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use proxmox_router::{Permission, Router};
+use proxmox_router::{Permission, Router, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::{Role, PRIVILEGES, SINGLE_LINE_COMMENT_SCHEMA};
+use pbs_api_types::{
+    privs_to_priv_names, RoleConfig, RoleConfigUpdater, PRIVILEGES, PRIV_PERMISSIONS_MODIFY,
+    PROXMOX_CONFIG_DIGEST_SCHEMA, ROLE_ID_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
+};
 use pbs_config::acl::ROLE_NAMES;
 
 #[api(
@@ -19,7 +23,7 @@ use pbs_config::acl::ROLE_NAMES;
             description: "Role with description and privileges.",
             properties: {
                 roleid: {
-                    type: Role,
+                    schema: ROLE_ID_SCHEMA,
                 },
                 privs: {
                     type: Array,
@@ -33,6 +37,10 @@ use pbs_config::acl::ROLE_NAMES;
                     schema: SINGLE_LINE_COMMENT_SCHEMA,
                     optional: true,
                 },
+                special: {
+                    type: bool,
+                    description: "Built-in role, cannot be modified.",
+                },
             },
         }
     },
@@ -41,7 +49,7 @@ use pbs_config::acl::ROLE_NAMES;
     }
 )]
 /// Role list
-fn list_roles() -> Result<Value, Error> {
+pub fn list_roles() -> Result<Value, Error> {
     let mut list = Vec::new();
 
     for (role, (privs, comment)) in ROLE_NAMES.iter() {
@@ -51,9 +59,217 @@ fn list_roles() -> Result<Value, Error> {
                 priv_list.push(name);
             }
         }
-        list.push(json!({ "roleid": role, "privs": priv_list, "comment": comment }));
+        list.push(json!({
+            "roleid": role,
+            "privs": priv_list,
+            "comment": comment,
+            "special": true,
+        }));
     }
+
+    let (config, _digest) = pbs_config::role::config()?;
+    for role in config.convert_to_typed_array::<RoleConfig>("role")? {
+        let privs = role.privileges().unwrap_or(0);
+        list.push(json!({
+            "roleid": role.roleid,
+            "privs": privs_to_priv_names(privs),
+            "comment": role.comment,
+            "special": false,
+        }));
+    }
+
     Ok(list.into())
 }
 
-pub const ROUTER: Router = Router::new().get(&API_METHOD_LIST_ROLES);
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: RoleConfig,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "acl"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Create a new custom role.
+pub fn create_role(config: RoleConfig) -> Result<(), Error> {
+    let _lock = pbs_config::role::lock_config()?;
+
+    let (mut section_config, _digest) = pbs_config::role::config()?;
+
+    if ROLE_NAMES.contains_key(config.roleid.as_str()) {
+        bail!("role '{}' is a built-in role.", config.roleid);
+    }
+
+    if section_config.sections.get(&config.roleid).is_some() {
+        bail!("role '{}' already exists.", config.roleid);
+    }
+
+    section_config.set_data(&config.roleid, "role", &config)?;
+
+    pbs_config::role::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            roleid: {
+                schema: ROLE_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: RoleConfig },
+    access: {
+        permission: &Permission::Anybody,
+    },
+)]
+/// Read a custom role.
+pub fn read_role(roleid: String, rpcenv: &mut dyn RpcEnvironment) -> Result<RoleConfig, Error> {
+    let (config, digest) = pbs_config::role::config()?;
+    let role = config.lookup("role", &roleid)?;
+    rpcenv["digest"] = hex::encode(digest).into();
+    Ok(role)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the comment property.
+    Comment,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            roleid: {
+                schema: ROLE_ID_SCHEMA,
+            },
+            update: {
+                type: RoleConfigUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "acl"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Update a custom role.
+pub fn update_role(
+    roleid: String,
+    update: RoleConfigUpdater,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+) -> Result<(), Error> {
+    let _lock = pbs_config::role::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::role::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut data: RoleConfig = config.lookup("role", &roleid)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Comment => data.comment = None,
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            data.comment = None;
+        } else {
+            data.comment = Some(comment);
+        }
+    }
+
+    if let Some(privs) = update.privs {
+        data.privs = privs;
+    }
+
+    config.set_data(&roleid, "role", &data)?;
+
+    pbs_config::role::save_config(&config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            roleid: {
+                schema: ROLE_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "acl"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Remove a custom role, including all ACL entries using it.
+pub fn delete_role(roleid: String, digest: Option<String>) -> Result<(), Error> {
+    let _lock = pbs_config::role::lock_config()?;
+    let _acl_lock = pbs_config::acl::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::role::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    if config.sections.remove(&roleid).is_none() {
+        bail!("role '{roleid}' does not exist.");
+    }
+
+    let (mut tree, _digest) = pbs_config::acl::config()?;
+    tree.delete_role(&roleid);
+
+    // drop the ACL entries first, the ACL config must not reference unknown roles
+    pbs_config::acl::save_config(&tree)?;
+    pbs_config::role::save_config(&config)?;
+
+    Ok(())
+}
+
+const ROLE_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_ROLE)
+    .put(&API_METHOD_UPDATE_ROLE)
+    .delete(&API_METHOD_DELETE_ROLE);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_ROLES)
+    .post(&API_METHOD_CREATE_ROLE)
+    .match_all("roleid", &ROLE_ROUTER);
@@ -11,9 +11,10 @@ use proxmox_schema::api;
 use proxmox_tfa::api::TfaConfig;
 
 use pbs_api_types::{
-    ApiToken, Authid, Tokenname, User, UserUpdater, UserWithTokens, Userid, ENABLE_USER_SCHEMA,
-    EXPIRE_USER_SCHEMA, PBS_PASSWORD_SCHEMA, PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT,
-    PROXMOX_CONFIG_DIGEST_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
+    ApiToken, Authid, Tokenname, User, UserUpdater, UserWithTokens, Userid,
+    APPEND_ONLY_TOKEN_SCHEMA, ENABLE_USER_SCHEMA, EXPIRE_USER_SCHEMA, PBS_PASSWORD_SCHEMA,
+    PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    SINGLE_LINE_COMMENT_SCHEMA,
 };
 use pbs_config::token_shadow;
 
@@ -378,6 +379,8 @@ pub fn delete_user(userid: Userid, digest: Option<String>) -> Result<(), Error>
         None => bail!("user '{}' does not exist.", userid),
     }
 
+    pbs_config::user::remove_user_from_groups(&mut config, &userid)?;
+
     pbs_config::user::save_config(&config)?;
 
     let authenticator = crate::auth::lookup_authenticator(userid.realm())?;
@@ -464,6 +467,10 @@ pub fn read_token(
                 schema: EXPIRE_USER_SCHEMA,
                 optional: true,
             },
+            "append-only": {
+                schema: APPEND_ONLY_TOKEN_SCHEMA,
+                optional: true,
+            },
             digest: {
                 optional: true,
                 schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
@@ -497,6 +504,7 @@ pub fn generate_token(
     comment: Option<String>,
     enable: Option<bool>,
     expire: Option<i64>,
+    append_only: Option<bool>,
     digest: Option<String>,
 ) -> Result<Value, Error> {
     let _lock = pbs_config::user::lock_config()?;
@@ -527,6 +535,7 @@ pub fn generate_token(
         comment,
         enable,
         expire,
+        append_only: append_only.filter(|append_only| *append_only),
     };
 
     config.set_data(&tokenid_string, "token", &token)?;
@@ -561,6 +570,10 @@ pub fn generate_token(
                 schema: EXPIRE_USER_SCHEMA,
                 optional: true,
             },
+            "append-only": {
+                schema: APPEND_ONLY_TOKEN_SCHEMA,
+                optional: true,
+            },
             digest: {
                 optional: true,
                 schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
@@ -572,16 +585,20 @@ pub fn generate_token(
             &Permission::Privilege(&["access", "users"], PRIV_PERMISSIONS_MODIFY, false),
             &Permission::UserParam("userid"),
         ]),
+        description: "Removing the append-only flag requires Permissions.Modify on '/access/users'.",
     },
 )]
 /// Update user's API token metadata
+#[allow(clippy::too_many_arguments)]
 pub fn update_token(
     userid: Userid,
     token_name: Tokenname,
     comment: Option<String>,
     enable: Option<bool>,
     expire: Option<i64>,
+    append_only: Option<bool>,
     digest: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<(), Error> {
     let _lock = pbs_config::user::lock_config()?;
 
@@ -614,6 +631,21 @@ pub fn update_token(
         data.expire = if expire > 0 { Some(expire) } else { None };
     }
 
+    if let Some(append_only) = append_only {
+        if !append_only && data.is_append_only() {
+            // lifting the restriction must not be possible with just the user's credentials
+            let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+            let user_info = CachedUserInfo::new()?;
+            user_info.check_privs(
+                &auth_id,
+                &["access", "users"],
+                PRIV_PERMISSIONS_MODIFY,
+                false,
+            )?;
+        }
+        data.append_only = if append_only { Some(true) } else { None };
+    }
+
     config.set_data(&tokenid_string, "token", &data)?;
 
     pbs_config::user::save_config(&config)?;
@@ -5,6 +5,7 @@ use std::ffi::OsStr;
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
 use futures::*;
@@ -18,28 +19,30 @@ use proxmox_async::blocking::WrappedReaderStream;
 use proxmox_async::{io::AsyncChannelWriter, stream::AsyncReaderStream};
 use proxmox_compression::zstd::ZstdEncoder;
 use proxmox_router::{
-    http_err, list_subdirs_api_method, ApiHandler, ApiMethod, ApiResponseFuture, Permission,
-    Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+    http_bail, http_err, list_subdirs_api_method, ApiHandler, ApiMethod, ApiResponseFuture,
+    Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
 };
 use proxmox_schema::*;
 use proxmox_sortable_macro::sortable;
 use proxmox_sys::fs::{
     file_read_firstline, file_read_optional_string, replace_file, CreateOptions,
 };
-use proxmox_sys::{task_log, task_warn};
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
 
 use pxar::accessor::aio::Accessor;
 use pxar::EntryKind;
 
 use pbs_api_types::{
     print_ns_and_snapshot, print_store_and_ns, Authid, BackupContent, BackupNamespace, BackupType,
-    Counts, CryptMode, DataStoreListItem, DataStoreStatus, GarbageCollectionStatus, GroupListItem,
-    KeepOptions, Operation, PruneJobOptions, RRDMode, RRDTimeFrame, SnapshotListItem,
-    SnapshotVerifyState, BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA,
-    BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, DATASTORE_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA,
-    MAX_NAMESPACE_DEPTH, NS_MAX_DEPTH_SCHEMA, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP,
-    PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_READ, PRIV_DATASTORE_VERIFY,
-    UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
+    Counts, CryptMode, DataStoreConfig, DataStoreListItem, DataStoreStatus, DatastoreQuota,
+    DatastoreQuotaStatus, GarbageCollectionStatus, GroupListItem, KeepOptions, MaintenanceType,
+    NamespaceUsage, Operation, PruneJobOptions, RRDMode, RRDTimeFrame, ScrubStatus,
+    SnapshotListItem, SnapshotVerifyState, SpaceAccountingStatus, BACKUP_ARCHIVE_NAME_SCHEMA,
+    BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA,
+    DATASTORE_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA, MAX_NAMESPACE_DEPTH, NS_MAX_DEPTH_SCHEMA,
+    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE,
+    PRIV_DATASTORE_READ, PRIV_DATASTORE_VERIFY, PRIV_SYS_MODIFY, RETENTION_LOCKED_UNTIL_SCHEMA,
+    RETENTION_LOCK_SCHEMA, UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
 };
 use pbs_client::pxar::{create_tar, create_zip};
 use pbs_config::CachedUserInfo;
@@ -53,17 +56,19 @@ use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
 use pbs_datastore::manifest::{BackupManifest, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME};
 use pbs_datastore::prune::compute_prune_info;
+use pbs_datastore::usage::SpaceAccounting;
 use pbs_datastore::{
-    check_backup_owner, task_tracking, BackupDir, BackupGroup, DataStore, LocalChunkReader,
-    StoreProgress, CATALOG_NAME,
+    check_backup_owner, ensure_datastore_is_mounted, get_datastore_mount_status, task_tracking,
+    BackupDir, BackupGroup, DataStore, LocalChunkReader, StoreProgress, CATALOG_NAME,
 };
 use pbs_tools::json::required_string_param;
-use proxmox_rest_server::{formatter, WorkerTask};
+use proxmox_rest_server::{formatter, TaskListInfoIterator, WorkerTask};
 
 use crate::api2::backup::optional_ns_param;
 use crate::api2::node::rrd::create_value_from_rrd;
 use crate::backup::{
-    check_ns_privs_full, verify_all_backups, verify_backup_dir, verify_backup_group, verify_filter,
+    check_not_append_only, check_ns_privs, check_ns_privs_full, load_scrub_status,
+    verify_all_backups, verify_backup_dir, verify_backup_group, verify_filter,
     ListAccessibleBackupGroups, NS_PRIVS_OK,
 };
 
@@ -194,6 +199,8 @@ pub fn list_groups(
 
     let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
 
+    let space_accounting = load_space_accounting(&datastore);
+
     datastore
         .iter_backup_groups(ns.clone())? // FIXME: Namespaces and recursion parameters!
         .try_fold(Vec::new(), |mut group_info, group| {
@@ -239,6 +246,10 @@ pub fn list_groups(
             let note_path = get_group_note_path(&datastore, &ns, group.as_ref());
             let comment = file_read_firstline(note_path).ok();
 
+            let space = space_accounting
+                .as_ref()
+                .and_then(|accounting| accounting.group(&ns, group.as_ref()));
+
             group_info.push(GroupListItem {
                 backup: group.into(),
                 last_backup: last_backup.backup_dir.backup_time(),
@@ -246,6 +257,9 @@ pub fn list_groups(
                 backup_count,
                 files: last_backup.files,
                 comment,
+                referenced_size: space.map(|space| space.referenced_size),
+                unique_size: space.map(|space| space.unique_size),
+                shared_size: space.map(|space| space.shared_size),
             });
 
             Ok(group_info)
@@ -284,6 +298,8 @@ pub async fn delete_group(
     tokio::task::spawn_blocking(move || {
         let ns = ns.unwrap_or_default();
 
+        check_not_append_only(&auth_id)?;
+
         let datastore = check_privs_and_load_store(
             &store,
             &ns,
@@ -391,6 +407,8 @@ pub async fn delete_snapshot(
     tokio::task::spawn_blocking(move || {
         let ns = ns.unwrap_or_default();
 
+        check_not_append_only(&auth_id)?;
+
         let datastore = check_privs_and_load_store(
             &store,
             &ns,
@@ -497,12 +515,23 @@ unsafe fn list_snapshots_blocking(
         (None, None) => datastore.list_backup_groups(ns.clone())?,
     };
 
+    let space_accounting = load_space_accounting(&datastore);
+
     let info_to_snapshot_list_item = |group: &BackupGroup, owner, info: BackupInfo| {
         let backup = pbs_api_types::BackupDir {
             group: group.into(),
             time: info.backup_dir.backup_time(),
         };
         let protected = info.backup_dir.is_protected();
+        let locked_until = info
+            .backup_dir
+            .retention_locked_until()
+            .ok()
+            .flatten()
+            .filter(|locked_until| *locked_until > proxmox_time::epoch_i64());
+        let space = space_accounting
+            .as_ref()
+            .and_then(|accounting| accounting.snapshot(&ns, &backup));
 
         match get_all_snapshot_files(&info) {
             Ok((manifest, files)) => {
@@ -541,6 +570,10 @@ unsafe fn list_snapshots_blocking(
                     size,
                     owner,
                     protected,
+                    locked_until,
+                    referenced_size: space.map(|space| space.referenced_size),
+                    unique_size: space.map(|space| space.unique_size),
+                    shared_size: space.map(|space| space.shared_size),
                 }
             }
             Err(err) => {
@@ -564,6 +597,10 @@ unsafe fn list_snapshots_blocking(
                     size: None,
                     owner,
                     protected,
+                    locked_until,
+                    referenced_size: space.map(|space| space.referenced_size),
+                    unique_size: space.map(|space| space.unique_size),
+                    shared_size: space.map(|space| space.shared_size),
                 }
             }
         }
@@ -640,6 +677,134 @@ async fn get_snapshots_count(
     .await?
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            "max-depth": {
+                schema: NS_MAX_DEPTH_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "Storage usage per namespace.",
+        type: Array,
+        items: { type: NamespaceUsage },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Only namespaces with DATASTORE_AUDIT on /datastore/{store}[/{namespace}] \
+            are included.",
+    },
+)]
+/// Get the storage usage of the namespaces of a datastore.
+///
+/// The usage of every namespace only covers the snapshots directly in it. This reads all index
+/// files of the included snapshots and can take a while on large datastores.
+pub async fn namespace_usage(
+    store: String,
+    ns: Option<BackupNamespace>,
+    max_depth: Option<usize>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<NamespaceUsage>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+    let ns = ns.unwrap_or_default();
+
+    let parent_access = check_ns_privs(&store, &ns, &auth_id, PRIV_DATASTORE_AUDIT);
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    tokio::task::spawn_blocking(move || {
+        let iter = match datastore.recursive_iter_backup_ns_ok(ns, max_depth) {
+            Ok(iter) => iter,
+            Err(_) if parent_access.is_err() => http_bail!(FORBIDDEN, "permission check failed"),
+            Err(err) => return Err(err),
+        };
+
+        let mut list = Vec::new();
+        for ns in iter {
+            let privs = user_info.lookup_privs(&auth_id, &ns.acl_path(&store));
+            if privs & PRIV_DATASTORE_AUDIT == 0 {
+                continue;
+            }
+            let usage = crate::server::quota::compute_namespace_usage(&datastore, ns.clone())?;
+            list.push(NamespaceUsage { ns, usage });
+        }
+
+        if list.is_empty() && parent_access.is_err() {
+            http_bail!(FORBIDDEN, "permission check failed"); // avoid leakage
+        }
+
+        Ok(list)
+    })
+    .await?
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        description: "Quotas of the datastore with their current usage.",
+        type: Array,
+        items: { type: DatastoreQuotaStatus },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Only quotas with DATASTORE_AUDIT on their namespace are included.",
+    },
+)]
+/// Get the configured quotas of a datastore and their current usage.
+pub async fn quota_status(
+    store: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<DatastoreQuotaStatus>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, digest) = pbs_config::quota::config()?;
+    let quotas: Vec<DatastoreQuota> = config.convert_to_typed_array("quota")?;
+
+    let quotas: Vec<DatastoreQuota> = quotas
+        .into_iter()
+        .filter(|quota| {
+            quota.store == store
+                && user_info.lookup_privs(&auth_id, &quota.acl_path()) & PRIV_DATASTORE_AUDIT != 0
+        })
+        .collect();
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    if quotas.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    tokio::task::spawn_blocking(move || {
+        quotas
+            .into_iter()
+            .map(|config| {
+                let usage = crate::server::quota::quota_usage(&datastore, &config)?;
+                Ok(DatastoreQuotaStatus { config, usage })
+            })
+            .collect()
+    })
+    .await?
+}
+
 #[api(
     input: {
         properties: {
@@ -965,6 +1130,9 @@ pub fn prune(
 ) -> Result<Value, Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let ns = ns.unwrap_or_default();
+    if !dry_run {
+        check_not_append_only(&auth_id)?;
+    }
     let datastore = check_privs_and_load_store(
         &store,
         &ns,
@@ -1110,6 +1278,10 @@ pub fn prune_datastore(
         true,
     )?;
 
+    if !dry_run {
+        check_not_append_only(&auth_id)?;
+    }
+
     let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
     let ns = prune_options.ns.clone().unwrap_or_default();
     let worker_id = format!("{}:{}", store, ns);
@@ -1199,6 +1371,159 @@ pub fn garbage_collection_status(
     Ok(status)
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Start checking all chunks of the chunk store.
+pub fn start_scrub(store: String, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let job = Job::new("scrub", &store).map_err(|_| format_err!("scrub already running"))?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = crate::server::do_scrub_job(job, datastore, &auth_id, None, to_stdout)
+        .map_err(|err| format_err!("unable to start scrub job on datastore {store} - {err}"))?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        type: ScrubStatus,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+    },
+)]
+/// Result of the last chunk store scrub.
+pub fn scrub_status(store: String) -> Result<ScrubStatus, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    load_scrub_status(&datastore)
+}
+
+/// Load the last space accounting result, errors are only logged.
+fn load_space_accounting(datastore: &DataStore) -> Option<SpaceAccounting> {
+    match SpaceAccounting::load(datastore) {
+        Ok(accounting) => accounting,
+        Err(err) => {
+            log::error!(
+                "unable to load space accounting of datastore {} - {err}",
+                datastore.name()
+            );
+            None
+        }
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Start computing the referenced, unique and shared space of all snapshots, groups and
+/// namespaces.
+pub fn start_space_accounting(
+    store: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "space-accounting",
+        Some(store),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            let accounting =
+                SpaceAccounting::compute(&datastore, &*worker, Some(worker.upid().to_string()))?;
+            accounting.save(&datastore)?;
+            task_log!(
+                worker,
+                "accounted {} snapshots in {} groups",
+                accounting.snapshots.len(),
+                accounting.groups.len()
+            );
+            Ok(())
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        type: SpaceAccountingStatus,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Only namespaces with DATASTORE_AUDIT on /datastore/{store}[/{namespace}] \
+            are included.",
+    },
+)]
+/// Result of the last space accounting run.
+pub fn space_accounting_status(
+    store: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<SpaceAccountingStatus, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    let mut status = match SpaceAccounting::load(&datastore)? {
+        Some(accounting) => accounting.status()?,
+        None => SpaceAccountingStatus::default(),
+    };
+
+    status.namespaces.retain(|usage| {
+        let privs = user_info.lookup_privs(&auth_id, &usage.ns.acl_path(&store));
+        privs & PRIV_DATASTORE_AUDIT != 0
+    });
+
+    Ok(status)
+}
+
 #[api(
     returns: {
         description: "List the accessible datastores.",
@@ -1539,6 +1864,7 @@ pub fn upload_backup_log(
         let blob = DataBlob::load_from_reader(&mut &data[..])?;
 
         replace_file(&path, blob.raw_data(), CreateOptions::new(), false)?;
+        proxmox_async::runtime::block_in_place(|| datastore.upload_file_to_backend(&path))?;
 
         // fixme: use correct formatter
         Ok(formatter::JSON_FORMATTER.format_data(Value::Null, &*rpcenv))
@@ -1920,6 +2246,8 @@ pub fn set_group_notes(
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let ns = ns.unwrap_or_default();
 
+    check_not_append_only(&auth_id)?;
+
     let datastore = check_privs_and_load_store(
         &store,
         &ns,
@@ -1931,7 +2259,8 @@ pub fn set_group_notes(
     )?;
 
     let note_path = get_group_note_path(&datastore, &ns, &backup_group);
-    replace_file(note_path, notes.as_bytes(), CreateOptions::new(), false)?;
+    replace_file(&note_path, notes.as_bytes(), CreateOptions::new(), false)?;
+    datastore.upload_file_to_backend(&note_path)?;
 
     Ok(())
 }
@@ -2019,6 +2348,8 @@ pub fn set_notes(
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let ns = ns.unwrap_or_default();
 
+    check_not_append_only(&auth_id)?;
+
     let datastore = check_privs_and_load_store(
         &store,
         &ns,
@@ -2119,6 +2450,12 @@ pub async fn set_protection(
 
     tokio::task::spawn_blocking(move || {
         let ns = ns.unwrap_or_default();
+
+        if !protected {
+            // adding protection is fine, removing it would allow deletion
+            check_not_append_only(&auth_id)?;
+        }
+
         let datastore = check_privs_and_load_store(
             &store,
             &ns,
@@ -2136,6 +2473,171 @@ pub async fn set_protection(
     .await?
 }
 
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+        },
+    },
+    returns: {
+        schema: RETENTION_LOCKED_UNTIL_SCHEMA,
+        optional: true,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_AUDIT for any \
+            or DATASTORE_BACKUP and being the owner of the group",
+    },
+)]
+/// Query the retention lock of a specific backup
+pub fn get_retention_lock(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Option<i64>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+    let datastore = check_privs_and_load_store(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_AUDIT,
+        PRIV_DATASTORE_BACKUP,
+        Some(Operation::Read),
+        &backup_dir.group,
+    )?;
+
+    let backup_dir = datastore.backup_dir(ns, backup_dir)?;
+
+    backup_dir.retention_locked_until()
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+            "locked-until": {
+                schema: RETENTION_LOCKED_UNTIL_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires DATASTORE_MODIFY on /datastore/{store}[/{namespace}]",
+    },
+)]
+/// Lock a specific backup against removal until the given time.
+///
+/// An active retention lock can only be extended, never shortened or removed.
+pub async fn set_retention_lock(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    locked_until: i64,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    tokio::task::spawn_blocking(move || {
+        let ns = ns.unwrap_or_default();
+
+        // the lock cannot be reverted, so owners with Datastore.Backup must not set it
+        check_ns_privs(&store, &ns, &auth_id, PRIV_DATASTORE_MODIFY)?;
+
+        let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+        let backup_dir = datastore.backup_dir(ns, backup_dir)?;
+
+        datastore.update_retention_lock(&backup_dir, locked_until)
+    })
+    .await?
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+            },
+        },
+    },
+    returns: {
+        schema: RETENTION_LOCK_SCHEMA,
+        optional: true,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires DATASTORE_AUDIT on /datastore/{store}/{namespace}",
+    },
+)]
+/// Get the default retention lock configured on a namespace.
+pub fn get_namespace_retention_lock(
+    store: String,
+    ns: BackupNamespace,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Option<String>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    check_ns_privs(&store, &ns, &auth_id, PRIV_DATASTORE_AUDIT)?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    datastore.namespace_retention_lock(&ns)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+            },
+            "retention-lock": {
+                schema: RETENTION_LOCK_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires DATASTORE_MODIFY on /datastore/{store}/{namespace}",
+    },
+)]
+/// Set the default retention lock for new snapshots in a namespace and its sub-namespaces.
+///
+/// Without `retention-lock`, the setting is removed and the one of the parent namespace or
+/// datastore applies again.
+pub fn set_namespace_retention_lock(
+    store: String,
+    ns: BackupNamespace,
+    retention_lock: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    check_ns_privs(&store, &ns, &auth_id, PRIV_DATASTORE_MODIFY)?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+    datastore.set_namespace_retention_lock(&ns, retention_lock.as_deref())
+}
+
 #[api(
     input: {
         properties: {
@@ -2171,6 +2673,9 @@ pub async fn set_backup_owner(
 
     tokio::task::spawn_blocking(move || {
         let ns = ns.unwrap_or_default();
+
+        check_not_append_only(&auth_id)?;
+
         let owner_check_required = check_ns_privs_full(
             &store,
             &ns,
@@ -2239,6 +2744,259 @@ pub async fn set_backup_owner(
     .await?
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Refresh the local datastore cache from the contents of the S3 backend.
+pub fn s3_refresh(store: String, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "s3-refresh",
+        Some(store),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| datastore.refresh_from_backend(&*worker),
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+/// Mount the backing device of a removable datastore, if it is not mounted already.
+pub(crate) fn mount_backing_device(datastore: &DataStoreConfig) -> Result<(), Error> {
+    let (mount_point, uuid) = match (datastore.mount_point(), datastore.backing_device.as_deref()) {
+        (Some(mount_point), Some(uuid)) => (mount_point, uuid),
+        _ => bail!("datastore '{}' is not removable", datastore.name),
+    };
+
+    if get_datastore_mount_status(datastore) == Some(true) {
+        return Ok(());
+    }
+
+    let device = std::path::Path::new("/dev/disk/by-uuid").join(uuid);
+    if !device.exists() {
+        bail!(
+            "backing device '{uuid}' of datastore '{}' not found - is it plugged in?",
+            datastore.name
+        );
+    }
+
+    proxmox_sys::fs::create_path(&mount_point, None, None)?;
+    crate::tools::disks::mount_by_uuid(uuid, &mount_point)
+}
+
+fn do_mount_device(datastore: &DataStoreConfig) -> Result<(), Error> {
+    if get_datastore_mount_status(datastore) == Some(true) {
+        bail!("datastore '{}' is already mounted", datastore.name);
+    }
+
+    mount_backing_device(datastore)?;
+
+    let path = datastore.absolute_path();
+    if !path.join(".chunks").is_dir() {
+        // do not leave some unrelated filesystem mounted
+        if let Some(mount_point) = datastore.mount_point() {
+            let _ = crate::tools::disks::unmount_by_mountpoint(&mount_point);
+        }
+        bail!(
+            "no datastore found at {path:?} on the device of datastore '{}'",
+            datastore.name
+        );
+    }
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::And(&[
+            &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+            &Permission::Privilege(&["system", "disks"], PRIV_SYS_MODIFY, false),
+        ]),
+    },
+)]
+/// Mount the backing device of a removable datastore.
+pub fn mount(store: String, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let (section_config, _digest) = pbs_config::datastore::config()?;
+    let datastore: DataStoreConfig = section_config.lookup("datastore", &store)?;
+
+    if !datastore.is_removable() {
+        bail!("datastore '{store}' is not removable");
+    }
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid = WorkerTask::new_thread(
+        "mount-device",
+        Some(store),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            task_log!(
+                worker,
+                "mounting device '{}' of datastore '{}'",
+                datastore.backing_device.as_deref().unwrap_or_default(),
+                datastore.name,
+            );
+            do_mount_device(&datastore)
+        },
+    )?;
+
+    Ok(json!(upid))
+}
+
+fn do_unmount_device(
+    datastore: &DataStoreConfig,
+    worker: &dyn WorkerTaskContext,
+) -> Result<(), Error> {
+    let mut waited = 0;
+    loop {
+        let active = task_tracking::get_active_operations(&datastore.name)?;
+        if active.read == 0 && active.write == 0 {
+            break;
+        }
+        if waited % 10 == 0 {
+            task_log!(
+                worker,
+                "waiting for {} read and {} write operation(s) to finish",
+                active.read,
+                active.write,
+            );
+        }
+        worker.check_abort()?;
+        std::thread::sleep(Duration::from_secs(1));
+        waited += 1;
+    }
+
+    // make sure that no cached datastore instance keeps files on the device open
+    DataStore::remove_unused_datastores()?;
+    if let Err(err) = proxmox_async::runtime::block_on(crate::server::notify_datastore_removed()) {
+        task_warn!(worker, "failed to notify proxy about unmount: {err}");
+    }
+
+    // unwrap: only called for removable datastores
+    let mount_point = datastore.mount_point().unwrap();
+    task_log!(worker, "unmounting {mount_point:?}");
+    crate::tools::disks::unmount_by_mountpoint(&mount_point)
+}
+
+/// Returns whether an unmount task for `store` is still running.
+///
+/// The `unmount` maintenance mode of a datastore is stale if not, for example after the daemon
+/// was restarted during the unmount.
+pub fn unmount_task_running(store: &str) -> Result<bool, Error> {
+    for info in TaskListInfoIterator::new(true)? {
+        let upid = info?.upid;
+        if upid.worker_type == "unmount-device"
+            && upid.worker_id.as_deref() == Some(store)
+            && proxmox_async::runtime::block_on(proxmox_rest_server::worker_is_active(&upid))?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::And(&[
+            &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+            &Permission::Privilege(&["system", "disks"], PRIV_SYS_MODIFY, false),
+        ]),
+    },
+)]
+/// Unmount a removable datastore once all running operations on it have finished.
+pub fn unmount(store: String, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let lock = pbs_config::datastore::lock_config()?;
+    let (mut section_config, _digest) = pbs_config::datastore::config()?;
+    let mut datastore: DataStoreConfig = section_config.lookup("datastore", &store)?;
+
+    if !datastore.is_removable() {
+        bail!("datastore '{store}' is not removable");
+    }
+    ensure_datastore_is_mounted(&datastore)?;
+
+    if let Some(mode) = datastore.get_maintenance_mode() {
+        if *mode.ty() == MaintenanceType::Unmount {
+            if unmount_task_running(&store)? {
+                bail!("datastore '{store}' is already being unmounted");
+            }
+            // left over by an interrupted unmount, the previous mode is lost
+            datastore.maintenance_mode = None;
+        }
+    }
+
+    // block new operations while waiting for the running ones
+    let old_maintenance_mode = datastore.maintenance_mode.take();
+    datastore.maintenance_mode = Some("type=unmount".to_string());
+    section_config.set_data(&store, "datastore", &datastore)?;
+    pbs_config::datastore::save_config(&section_config)?;
+    drop(lock);
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid = WorkerTask::new_thread(
+        "unmount-device",
+        Some(store.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            let result = do_unmount_device(&datastore, &*worker);
+
+            // restore the previous maintenance mode in any case
+            let _lock = pbs_config::datastore::lock_config()?;
+            let (mut section_config, _digest) = pbs_config::datastore::config()?;
+            let mut datastore: DataStoreConfig = section_config.lookup("datastore", &store)?;
+            datastore.maintenance_mode = old_maintenance_mode;
+            section_config.set_data(&store, "datastore", &datastore)?;
+            pbs_config::datastore::save_config(&section_config)?;
+
+            result
+        },
+    )?;
+
+    Ok(json!(upid))
+}
+
 #[sortable]
 const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
     (
@@ -2259,6 +3017,7 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         &Router::new().download(&API_METHOD_DOWNLOAD_FILE_DECODED),
     ),
     ("files", &Router::new().get(&API_METHOD_LIST_SNAPSHOT_FILES)),
+    ("mount", &Router::new().post(&API_METHOD_MOUNT)),
     (
         "gc",
         &Router::new()
@@ -2282,6 +3041,16 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         // FIXME: move into datastore:: sub-module?!
         &crate::api2::admin::namespace::ROUTER,
     ),
+    (
+        "namespace-retention-lock",
+        &Router::new()
+            .get(&API_METHOD_GET_NAMESPACE_RETENTION_LOCK)
+            .put(&API_METHOD_SET_NAMESPACE_RETENTION_LOCK),
+    ),
+    (
+        "namespace-usage",
+        &Router::new().get(&API_METHOD_NAMESPACE_USAGE),
+    ),
     (
         "notes",
         &Router::new()
@@ -2303,7 +3072,27 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         "pxar-file-download",
         &Router::new().download(&API_METHOD_PXAR_FILE_DOWNLOAD),
     ),
+    ("quota", &Router::new().get(&API_METHOD_QUOTA_STATUS)),
+    (
+        "retention-lock",
+        &Router::new()
+            .get(&API_METHOD_GET_RETENTION_LOCK)
+            .put(&API_METHOD_SET_RETENTION_LOCK),
+    ),
     ("rrd", &Router::new().get(&API_METHOD_GET_RRD_STATS)),
+    ("s3-refresh", &Router::new().post(&API_METHOD_S3_REFRESH)),
+    (
+        "scrub",
+        &Router::new()
+            .get(&API_METHOD_SCRUB_STATUS)
+            .post(&API_METHOD_START_SCRUB),
+    ),
+    (
+        "space-accounting",
+        &Router::new()
+            .get(&API_METHOD_SPACE_ACCOUNTING_STATUS)
+            .post(&API_METHOD_START_SPACE_ACCOUNTING),
+    ),
     (
         "snapshots",
         &Router::new()
@@ -2311,6 +3100,7 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
             .delete(&API_METHOD_DELETE_SNAPSHOT),
     ),
     ("status", &Router::new().get(&API_METHOD_STATUS)),
+    ("unmount", &Router::new().post(&API_METHOD_UNMOUNT)),
     (
         "upload-backup-log",
         &Router::new().upload(&API_METHOD_UPLOAD_BACKUP_LOG),
@@ -12,7 +12,9 @@ use pbs_api_types::{
 
 use pbs_datastore::DataStore;
 
-use crate::backup::{check_ns_modification_privs, check_ns_privs, NS_PRIVS_OK};
+use crate::backup::{
+    check_not_append_only, check_ns_modification_privs, check_ns_privs, NS_PRIVS_OK,
+};
 
 #[api(
     input: {
@@ -154,6 +156,7 @@ pub fn delete_namespace(
 ) -> Result<Value, Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
 
+    check_not_append_only(&auth_id)?;
     check_ns_modification_privs(&store, &ns, &auth_id)?;
 
     let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
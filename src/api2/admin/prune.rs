@@ -16,6 +16,7 @@ use pbs_api_types::{
 use pbs_config::prune;
 use pbs_config::CachedUserInfo;
 
+use crate::api2::config::prune::check_prune_job_modify_access;
 use crate::server::{
     do_prune_job,
     jobstate::{compute_schedule_status, Job, JobState},
@@ -117,7 +118,7 @@ pub fn run_prune_job(
     let (config, _digest) = prune::config()?;
     let prune_job: PruneJobConfig = config.lookup("prune", &id)?;
 
-    user_info.check_privs(&auth_id, &prune_job.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+    check_prune_job_modify_access(&user_info, &auth_id, &prune_job)?;
 
     let job = Job::new("prunejob", &id)?;
 
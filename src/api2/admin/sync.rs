@@ -10,13 +10,18 @@ use proxmox_router::{
 use proxmox_schema::api;
 use proxmox_sortable_macro::sortable;
 
-use pbs_api_types::{Authid, SyncJobConfig, SyncJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA};
+use pbs_api_types::{
+    Authid, SyncJobConfig, SyncJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA, SYNC_DRY_RUN_SCHEMA,
+};
 use pbs_config::sync;
 use pbs_config::CachedUserInfo;
 
 use crate::{
     api2::{
-        config::sync::{check_sync_job_modify_access, check_sync_job_read_access},
+        config::sync::{
+            check_sync_job_modify_access, check_sync_job_not_append_only,
+            check_sync_job_read_access,
+        },
         pull::do_sync_job,
     },
     server::jobstate::{compute_schedule_status, Job, JobState},
@@ -88,7 +93,11 @@ pub fn list_sync_jobs(
         properties: {
             id: {
                 schema: JOB_ID_SCHEMA,
-            }
+            },
+            "dry-run": {
+                schema: SYNC_DRY_RUN_SCHEMA,
+                optional: true,
+            },
         }
     },
     access: {
@@ -99,6 +108,7 @@ pub fn list_sync_jobs(
 /// Runs the sync jobs manually.
 pub fn run_sync_job(
     id: String,
+    dry_run: Option<bool>,
     _info: &ApiMethod,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
@@ -108,6 +118,8 @@ pub fn run_sync_job(
     let (config, _digest) = sync::config()?;
     let sync_job: SyncJobConfig = config.lookup("sync", &id)?;
 
+    check_sync_job_not_append_only(&user_info, &auth_id, &sync_job)?;
+
     if !check_sync_job_modify_access(&user_info, &auth_id, &sync_job) {
         bail!("permission check failed");
     }
@@ -116,7 +128,8 @@ pub fn run_sync_job(
 
     let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
 
-    let upid_str = do_sync_job(job, sync_job, &auth_id, None, to_stdout)?;
+    let dry_run = dry_run.unwrap_or(false);
+    let upid_str = do_sync_job(job, sync_job, &auth_id, None, to_stdout, dry_run)?;
 
     Ok(upid_str)
 }
@@ -17,6 +17,7 @@ use pbs_datastore::{DataBlob, DataStore};
 use proxmox_rest_server::{formatter::*, WorkerTask};
 
 use crate::backup::verify_backup_dir_with_lock;
+use crate::server::quota::QuotaTracker;
 
 use hyper::{Body, Response};
 
@@ -83,6 +84,7 @@ struct SharedBackupState {
     known_chunks: KnownChunksMap,
     backup_size: u64, // sums up size of all files
     backup_stat: UploadStatistic,
+    quota: QuotaTracker,
 }
 
 impl SharedBackupState {
@@ -123,6 +125,7 @@ impl BackupEnvironment {
         worker: Arc<WorkerTask>,
         datastore: Arc<DataStore>,
         backup_dir: BackupDir,
+        quota: QuotaTracker,
     ) -> Self {
         let state = SharedBackupState {
             finished: false,
@@ -133,6 +136,7 @@ impl BackupEnvironment {
             known_chunks: HashMap::new(),
             backup_size: 0,
             backup_stat: UploadStatistic::new(),
+            quota,
         };
 
         Self {
@@ -214,6 +218,10 @@ impl BackupEnvironment {
         // register chunk
         state.known_chunks.insert(digest, size);
 
+        if !is_duplicate {
+            state.quota.add_unique(compressed_size as u64)?;
+        }
+
         Ok(())
     }
 
@@ -249,9 +257,20 @@ impl BackupEnvironment {
         // register chunk
         state.known_chunks.insert(digest, size);
 
+        if !is_duplicate {
+            state.quota.add_unique(compressed_size as u64)?;
+        }
+
         Ok(())
     }
 
+    /// Fail if a quota covering the backup is exceeded.
+    pub fn check_quota(&self) -> Result<(), Error> {
+        let state = self.state.lock().unwrap();
+
+        state.quota.check()
+    }
+
     pub fn lookup_chunk(&self, digest: &[u8; 32]) -> Option<u32> {
         let state = self.state.lock().unwrap();
 
@@ -347,6 +366,8 @@ impl BackupEnvironment {
 
         data.index.add_chunk(data.offset, digest)?;
 
+        state.quota.add_logical(size as u64)?;
+
         Ok(())
     }
 
@@ -374,6 +395,8 @@ impl BackupEnvironment {
 
         data.index.add_digest(idx, digest)?;
 
+        state.quota.add_logical(size as u64)?;
+
         Ok(())
     }
 
@@ -587,6 +610,9 @@ impl BackupEnvironment {
         state.backup_size += orig_len as u64;
         state.backup_stat.size += blob_len as u64;
 
+        state.quota.add_logical(orig_len as u64)?;
+        state.quota.add_unique(blob_len as u64)?;
+
         Ok(())
     }
 
@@ -608,11 +634,15 @@ impl BackupEnvironment {
         // check for valid manifest and store stats
         let stats = serde_json::to_value(state.backup_stat)?;
         self.backup_dir
-            .update_manifest(|manifest| {
+            .update_local_manifest(|manifest| {
                 manifest.unprotected["chunk_upload_stats"] = stats;
             })
             .map_err(|err| format_err!("unable to update manifest blob - {}", err))?;
 
+        self.datastore
+            .upload_snapshot_to_backend(&self.backup_dir)
+            .map_err(|err| format_err!("unable to upload snapshot to backend - {}", err))?;
+
         if let Some(base) = &self.last_backup {
             let path = base.backup_dir.full_path();
             if !path.exists() {
@@ -623,11 +653,17 @@ impl BackupEnvironment {
             }
         }
 
+        self.datastore
+            .apply_default_retention_lock(&self.backup_dir)
+            .map_err(|err| format_err!("unable to apply retention lock - {}", err))?;
+
         self.datastore.try_ensure_sync_level()?;
 
         // marks the backup as successful
         state.finished = true;
 
+        state.quota.finish();
+
         Ok(())
     }
 
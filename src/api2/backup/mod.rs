@@ -29,6 +29,8 @@ use pbs_tools::json::{required_array_param, required_integer_param, required_str
 use proxmox_rest_server::{H2Service, WorkerTask};
 use proxmox_sys::fs::lock_dir_noblock_shared;
 
+use crate::server::quota::QuotaTracker;
+
 mod environment;
 use environment::*;
 
@@ -58,6 +60,9 @@ pub const API_METHOD_UPGRADE_BACKUP: ApiMethod = ApiMethod::new(
     &Permission::Anybody
 );
 
+/// Maximum tolerated clock skew (in seconds) for backup timestamps of append-only API tokens.
+const APPEND_ONLY_MAX_CLOCK_SKEW: i64 = 3600;
+
 pub(crate) fn optional_ns_param(param: &Value) -> Result<BackupNamespace, Error> {
     match param.get("ns") {
         Some(Value::String(ns)) => ns.parse(),
@@ -153,6 +158,24 @@ fn upgrade_to_backup_protocol(
             bail!("backup owner check failed ({} != {})", auth_id, owner);
         }
 
+        if user_info.is_append_only(&auth_id) {
+            // a snapshot from the far future would block all further backups of the group
+            if backup_dir_arg.time > proxmox_time::epoch_i64() + APPEND_ONLY_MAX_CLOCK_SKEW {
+                bail!("backup timestamp lies in the future (append-only API token).");
+            }
+        }
+
+        let quota = if worker_type == "benchmark" {
+            QuotaTracker::default()
+        } else {
+            let mut quota = proxmox_async::runtime::block_in_place(|| {
+                QuotaTracker::new(&datastore, backup_group.backup_ns(), &owner)
+            })?;
+            quota.warn_soft_limits();
+            quota.check()?;
+            quota
+        };
+
         let last_backup = {
             let info = backup_group.last_backup(true).unwrap_or(None);
             if let Some(info) = info {
@@ -209,6 +232,7 @@ fn upgrade_to_backup_protocol(
                     worker.clone(),
                     datastore,
                     backup_dir,
+                    quota,
                 );
 
                 env.debug = debug;
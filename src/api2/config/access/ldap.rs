@@ -187,6 +187,14 @@ pub enum DeletableProperty {
     SyncAttributes,
     /// User classes
     UserClasses,
+    /// Group search base domain
+    GroupDn,
+    /// Group filter
+    GroupFilter,
+    /// Group classes
+    GroupClasses,
+    /// Group name attribute
+    GroupNameAttr,
 }
 
 #[api(
@@ -279,6 +287,18 @@ pub fn update_ldap_realm(
                 DeletableProperty::UserClasses => {
                     config.user_classes = None;
                 }
+                DeletableProperty::GroupDn => {
+                    config.group_dn = None;
+                }
+                DeletableProperty::GroupFilter => {
+                    config.group_filter = None;
+                }
+                DeletableProperty::GroupClasses => {
+                    config.group_classes = None;
+                }
+                DeletableProperty::GroupNameAttr => {
+                    config.group_name_attr = None;
+                }
             }
         }
     }
@@ -336,6 +356,18 @@ pub fn update_ldap_realm(
     if let Some(user_classes) = update.user_classes {
         config.user_classes = Some(user_classes);
     }
+    if let Some(group_dn) = update.group_dn {
+        config.group_dn = Some(group_dn);
+    }
+    if let Some(group_filter) = update.group_filter {
+        config.group_filter = Some(group_filter);
+    }
+    if let Some(group_classes) = update.group_classes {
+        config.group_classes = Some(group_classes);
+    }
+    if let Some(group_name_attr) = update.group_name_attr {
+        config.group_name_attr = Some(group_name_attr);
+    }
 
     let ldap_config = if let Some(_) = password {
         LdapAuthenticator::api_type_to_config_with_password(&config, password.clone())?
@@ -157,6 +157,10 @@ pub enum DeletableProperty {
     Prompt,
     /// Delete the acr_values property
     AcrValues,
+    /// Delete the groups-claim property
+    GroupsClaim,
+    /// Delete the groups-autocreate property
+    GroupsAutocreate,
 }
 
 #[api(
@@ -229,6 +233,12 @@ pub fn update_openid_realm(
                 DeletableProperty::AcrValues => {
                     config.acr_values = None;
                 }
+                DeletableProperty::GroupsClaim => {
+                    config.groups_claim = None;
+                }
+                DeletableProperty::GroupsAutocreate => {
+                    config.groups_autocreate = None;
+                }
             }
         }
     }
@@ -258,6 +268,12 @@ pub fn update_openid_realm(
     if update.scopes.is_some() {
         config.scopes = update.scopes;
     }
+    if update.groups_claim.is_some() {
+        config.groups_claim = update.groups_claim;
+    }
+    if update.groups_autocreate.is_some() {
+        config.groups_autocreate = update.groups_autocreate;
+    }
     if update.prompt.is_some() {
         config.prompt = update.prompt;
     }
@@ -1,7 +1,7 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use ::serde::{Deserialize, Serialize};
-use anyhow::Error;
+use anyhow::{bail, Error};
 use hex::FromHex;
 use serde_json::Value;
 
@@ -12,20 +12,27 @@ use proxmox_sys::{task_warn, WorkerTaskContext};
 use proxmox_uuid::Uuid;
 
 use pbs_api_types::{
-    Authid, DataStoreConfig, DataStoreConfigUpdater, DatastoreNotify, DatastoreTuning, KeepOptions,
-    PruneJobConfig, PruneJobOptions, DATASTORE_SCHEMA, PRIV_DATASTORE_ALLOCATE,
-    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA, UPID_SCHEMA,
+    Authid, DataStoreConfig, DataStoreConfigUpdater, DatastoreBackendType, DatastoreNotify,
+    DatastoreTuning, KeepOptions, MaintenanceMode, MaintenanceType, PruneJobConfig,
+    PruneJobOptions, DATASTORE_SCHEMA, PRIV_DATASTORE_ALLOCATE, PRIV_DATASTORE_AUDIT,
+    PRIV_DATASTORE_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA, UPID_SCHEMA,
 };
 use pbs_config::BackupLockGuard;
+use pbs_datastore::chunk_mirror::ChunkMirror;
 use pbs_datastore::chunk_store::ChunkStore;
+use pbs_datastore::s3::S3Client;
 
 use crate::api2::admin::{
-    prune::list_prune_jobs, sync::list_sync_jobs, verify::list_verification_jobs,
+    datastore::{mount_backing_device, unmount_task_running},
+    prune::list_prune_jobs,
+    sync::list_sync_jobs,
+    verify::list_verification_jobs,
 };
 use crate::api2::config::prune::{delete_prune_job, do_create_prune_job};
 use crate::api2::config::sync::delete_sync_job;
 use crate::api2::config::tape_backup_job::{delete_tape_backup_job, list_tape_backup_jobs};
 use crate::api2::config::verify::delete_verification_job;
+use crate::backup::check_user_not_append_only;
 use pbs_config::CachedUserInfo;
 
 use proxmox_rest_server::WorkerTask;
@@ -66,18 +73,55 @@ pub fn list_datastores(
     Ok(list.into_iter().filter(filter_by_privs).collect())
 }
 
+/// Check the chunk mirror path of a datastore and create its chunk directory.
+fn setup_chunk_mirror(datastore: &DataStoreConfig) -> Result<(), Error> {
+    let path = match datastore.chunk_mirror {
+        Some(ref path) => Path::new(path),
+        None => return Ok(()),
+    };
+
+    if datastore.backend_config()?.ty.unwrap_or_default() == DatastoreBackendType::S3 {
+        param_bail!("chunk-mirror", "not supported with the S3 backend");
+    }
+    if !path.is_absolute() {
+        param_bail!("chunk-mirror", "expected an absolute path");
+    }
+    let store_path = datastore.absolute_path();
+    if path.starts_with(&store_path) || store_path.starts_with(path) {
+        param_bail!("chunk-mirror", "must not overlap with the datastore path");
+    }
+
+    let backup_user = pbs_config::backup_user()?;
+    ChunkMirror::create(path, backup_user.uid, backup_user.gid)
+}
+
 pub(crate) fn do_create_datastore(
     _lock: BackupLockGuard,
     mut config: SectionConfigData,
     datastore: DataStoreConfig,
     worker: Option<&dyn WorkerTaskContext>,
 ) -> Result<(), Error> {
-    let path: PathBuf = datastore.path.clone().into();
+    let path: PathBuf = datastore.absolute_path();
+
+    if datastore.is_removable() {
+        mount_backing_device(&datastore)?;
+    }
 
     let tuning: DatastoreTuning = serde_json::from_value(
         DatastoreTuning::API_SCHEMA
             .parse_property_string(datastore.tuning.as_deref().unwrap_or(""))?,
     )?;
+
+    let backend_config = datastore.backend_config()?;
+    if backend_config.ty.unwrap_or_default() == DatastoreBackendType::S3 {
+        // unwrap: checked by backend_config()
+        let client = S3Client::new(
+            backend_config.client.as_deref().unwrap(),
+            backend_config.bucket.as_deref().unwrap(),
+        )?;
+        proxmox_async::runtime::block_on(client.head_bucket())?;
+    }
+
     let backup_user = pbs_config::backup_user()?;
     let _store = ChunkStore::create(
         &datastore.name,
@@ -88,6 +132,8 @@ pub(crate) fn do_create_datastore(
         tuning.sync_level.unwrap_or_default(),
     )?;
 
+    setup_chunk_mirror(&datastore)?;
+
     config.set_data(&datastore.name, "datastore", &datastore)?;
 
     pbs_config::datastore::save_config(&config)?;
@@ -204,6 +250,8 @@ pub enum DeletableProperty {
     GcSchedule,
     /// Delete the prune job schedule.
     PruneSchedule,
+    /// Delete the chunk store scrub schedule.
+    ScrubSchedule,
     /// Delete the keep-last property
     KeepLast,
     /// Delete the keep-hourly property
@@ -216,12 +264,30 @@ pub enum DeletableProperty {
     KeepMonthly,
     /// Delete the keep-yearly property
     KeepYearly,
+    /// Delete the keep-within property
+    KeepWithin,
+    /// Delete the keep-within-hourly property
+    KeepWithinHourly,
+    /// Delete the keep-within-daily property
+    KeepWithinDaily,
+    /// Delete the keep-within-weekly property
+    KeepWithinWeekly,
+    /// Delete the keep-within-monthly property
+    KeepWithinMonthly,
+    /// Delete the keep-within-yearly property
+    KeepWithinYearly,
     /// Delete the verify-new property
     VerifyNew,
+    /// Delete the retention-lock property
+    RetentionLock,
+    /// Delete the chunk-mirror property, existing copies are kept
+    ChunkMirror,
     /// Delete the notify-user property
     NotifyUser,
     /// Delete the notify property
     Notify,
+    /// Delete the notification-mode property
+    NotificationMode,
     /// Delete the tuning property
     Tuning,
     /// Delete the maintenance-mode property
@@ -276,6 +342,12 @@ pub fn update_datastore(
 
     let mut data: DataStoreConfig = config.lookup("datastore", &name)?;
 
+    // a stale 'unmount' mode of an interrupted unmount can be changed again
+    let unmounting = match data.get_maintenance_mode() {
+        Some(mode) if *mode.ty() == MaintenanceType::Unmount => unmount_task_running(&name)?,
+        _ => false,
+    };
+
     if let Some(delete) = delete {
         for delete_prop in delete {
             match delete_prop {
@@ -288,6 +360,9 @@ pub fn update_datastore(
                 DeletableProperty::PruneSchedule => {
                     data.prune_schedule = None;
                 }
+                DeletableProperty::ScrubSchedule => {
+                    data.scrub_schedule = None;
+                }
                 DeletableProperty::KeepLast => {
                     data.keep.keep_last = None;
                 }
@@ -306,19 +381,49 @@ pub fn update_datastore(
                 DeletableProperty::KeepYearly => {
                     data.keep.keep_yearly = None;
                 }
+                DeletableProperty::KeepWithin => {
+                    data.keep.keep_within = None;
+                }
+                DeletableProperty::KeepWithinHourly => {
+                    data.keep.keep_within_hourly = None;
+                }
+                DeletableProperty::KeepWithinDaily => {
+                    data.keep.keep_within_daily = None;
+                }
+                DeletableProperty::KeepWithinWeekly => {
+                    data.keep.keep_within_weekly = None;
+                }
+                DeletableProperty::KeepWithinMonthly => {
+                    data.keep.keep_within_monthly = None;
+                }
+                DeletableProperty::KeepWithinYearly => {
+                    data.keep.keep_within_yearly = None;
+                }
                 DeletableProperty::VerifyNew => {
                     data.verify_new = None;
                 }
+                DeletableProperty::RetentionLock => {
+                    data.retention_lock = None;
+                }
+                DeletableProperty::ChunkMirror => {
+                    data.chunk_mirror = None;
+                }
                 DeletableProperty::Notify => {
                     data.notify = None;
                 }
                 DeletableProperty::NotifyUser => {
                     data.notify_user = None;
                 }
+                DeletableProperty::NotificationMode => {
+                    data.notification_mode = None;
+                }
                 DeletableProperty::Tuning => {
                     data.tuning = None;
                 }
                 DeletableProperty::MaintenanceMode => {
+                    if unmounting {
+                        bail!("datastore '{name}' is being unmounted");
+                    }
                     data.maintenance_mode = None;
                 }
             }
@@ -340,6 +445,12 @@ pub fn update_datastore(
         data.gc_schedule = update.gc_schedule;
     }
 
+    let mut scrub_schedule_changed = false;
+    if update.scrub_schedule.is_some() {
+        scrub_schedule_changed = data.scrub_schedule != update.scrub_schedule;
+        data.scrub_schedule = update.scrub_schedule;
+    }
+
     macro_rules! prune_disabled {
         ($(($param:literal, $($member:tt)+)),+) => {
             $(
@@ -359,6 +470,12 @@ pub fn update_datastore(
         ("keep-weekly", keep.keep_weekly),
         ("keep-monthly", keep.keep_monthly),
         ("keep-yearly", keep.keep_yearly),
+        ("keep-within", keep.keep_within),
+        ("keep-within-hourly", keep.keep_within_hourly),
+        ("keep-within-daily", keep.keep_within_daily),
+        ("keep-within-weekly", keep.keep_within_weekly),
+        ("keep-within-monthly", keep.keep_within_monthly),
+        ("keep-within-yearly", keep.keep_within_yearly),
         ("prune-schedule", prune_schedule)
     }
 
@@ -380,17 +497,40 @@ pub fn update_datastore(
     if update.verify_new.is_some() {
         data.verify_new = update.verify_new;
     }
+    if update.retention_lock.is_some() {
+        data.retention_lock = update.retention_lock;
+    }
+    if update.chunk_mirror.is_some() && data.chunk_mirror != update.chunk_mirror {
+        data.chunk_mirror = update.chunk_mirror;
+        setup_chunk_mirror(&data)?;
+    }
 
     if update.notify_user.is_some() {
         data.notify_user = update.notify_user;
     }
 
+    if update.notification_mode.is_some() {
+        data.notification_mode = update.notification_mode;
+    }
+
     if update.tuning.is_some() {
         data.tuning = update.tuning;
     }
 
-    if update.maintenance_mode.is_some() {
-        data.maintenance_mode = update.maintenance_mode;
+    if let Some(maintenance_mode) = update.maintenance_mode {
+        if unmounting {
+            bail!("datastore '{name}' is being unmounted");
+        }
+        let mode = MaintenanceMode::deserialize(
+            MaintenanceMode::API_SCHEMA.parse_property_string(&maintenance_mode)?,
+        )?;
+        if *mode.ty() == MaintenanceType::Unmount {
+            param_bail!(
+                "maintenance-mode",
+                "'unmount' can only be set by unmounting a removable datastore"
+            );
+        }
+        data.maintenance_mode = Some(maintenance_mode);
     }
 
     config.set_data(&name, "datastore", &data)?;
@@ -402,10 +542,25 @@ pub fn update_datastore(
     if gc_schedule_changed {
         jobstate::update_job_last_run_time("garbage_collection", &name)?;
     }
+    if scrub_schedule_changed {
+        jobstate::update_job_last_run_time("scrub", &name)?;
+    }
 
     Ok(())
 }
 
+/// checks that an append-only API token does not destroy the contents of a datastore
+fn check_destroy_data_access(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+    destroy_data: bool,
+) -> Result<(), Error> {
+    if destroy_data {
+        check_user_not_append_only(user_info, auth_id)?;
+    }
+    Ok(())
+}
+
 #[api(
     protected: true,
     input: {
@@ -446,6 +601,9 @@ pub async fn delete_datastore(
     digest: Option<String>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    check_destroy_data_access(&CachedUserInfo::new()?, &auth_id, destroy_data)?;
+
     let _lock = pbs_config::datastore::lock_config()?;
 
     let (config, expected_digest) = pbs_config::datastore::config()?;
@@ -483,7 +641,6 @@ pub async fn delete_datastore(
         }
     }
 
-    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
 
     let upid = WorkerTask::new_thread(
@@ -497,6 +654,7 @@ pub async fn delete_datastore(
             // ignore errors
             let _ = jobstate::remove_state_file("prune", &name);
             let _ = jobstate::remove_state_file("garbage_collection", &name);
+            let _ = jobstate::remove_state_file("scrub", &name);
 
             if let Err(err) =
                 proxmox_async::runtime::block_on(crate::server::notify_datastore_removed())
@@ -520,3 +678,39 @@ pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_LIST_DATASTORES)
     .post(&API_METHOD_CREATE_DATASTORE)
     .match_all("name", &ITEM_ROUTER);
+
+#[test]
+fn destroy_data_append_only_test() -> Result<(), Error> {
+    let (user_cfg, _) = pbs_config::user::test_cfg_from_str(
+        r###"
+user: admin@pbs
+
+token: admin@pbs!append
+	append-only true
+
+token: admin@pbs!regular
+
+"###,
+    )
+    .expect("test user.cfg is not parsable");
+    let acl_tree = pbs_config::acl::AclTree::from_raw(
+        r###"
+acl:1:/datastore:admin@pbs,admin@pbs!append,admin@pbs!regular:Admin
+"###,
+    )
+    .expect("test acl.cfg is not parsable");
+
+    let user_info = CachedUserInfo::test_new(user_cfg, acl_tree);
+
+    let append_auth_id: Authid = "admin@pbs!append".parse()?;
+    let regular_auth_id: Authid = "admin@pbs!regular".parse()?;
+
+    // removing only the configuration keeps the backups
+    assert!(check_destroy_data_access(&user_info, &append_auth_id, false).is_ok());
+    assert!(check_destroy_data_access(&user_info, &append_auth_id, true).is_err());
+
+    assert!(check_destroy_data_access(&user_info, &regular_auth_id, true).is_ok());
+    assert!(check_destroy_data_access(&user_info, &"admin@pbs".parse()?, true).is_ok());
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use anyhow::{bail, Error};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use proxmox_router::{Permission, Router, RpcEnvironment};
+use proxmox_schema::{api, param_bail};
+
+use pbs_api_types::{
+    GotifyConfig, GotifyConfigUpdater, NOTIFICATION_ID_SCHEMA, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY,
+    PROXMOX_CONFIG_DIGEST_SCHEMA,
+};
+
+use pbs_config::notifications;
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "List of configured gotify notification targets.",
+        type: Array,
+        items: { type: GotifyConfig },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List configured gotify notification targets.
+pub fn list_gotify_targets(
+    _param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<GotifyConfig>, Error> {
+    let (config, digest) = notifications::config()?;
+
+    let mut list: Vec<GotifyConfig> = config.convert_to_typed_array("gotify")?;
+
+    // don't return token via api
+    for item in list.iter_mut() {
+        item.token = None;
+    }
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: GotifyConfig,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Create a new gotify notification target.
+pub fn create_gotify_target(config: GotifyConfig) -> Result<(), Error> {
+    let _lock = notifications::lock_config()?;
+
+    let (mut section_config, _digest) = notifications::config()?;
+
+    if section_config.sections.get(&config.name).is_some() {
+        bail!(
+            "notification target or matcher '{}' already exists.",
+            config.name
+        );
+    }
+
+    if config.token.is_none() {
+        param_bail!("token", "gotify targets require an application token");
+    }
+
+    section_config.set_data(&config.name, "gotify", &config)?;
+
+    notifications::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Remove a gotify notification target.
+pub fn delete_gotify_target(
+    name: String,
+    digest: Option<String>,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let _lock = notifications::lock_config()?;
+
+    let (mut section_config, expected_digest) = notifications::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let _: GotifyConfig = section_config.lookup("gotify", &name)?;
+
+    super::ensure_target_unused(&section_config, &name)?;
+
+    section_config.sections.remove(&name);
+
+    notifications::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: GotifyConfig },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Read a gotify notification target.
+pub fn read_gotify_target(
+    name: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<GotifyConfig, Error> {
+    let (section_config, digest) = notifications::config()?;
+
+    let mut config: GotifyConfig = section_config.lookup("gotify", &name)?;
+
+    config.token = None;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(config)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the enable property.
+    Enable,
+    /// Delete the comment property.
+    Comment,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+            update: {
+                type: GotifyConfigUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Update a gotify notification target.
+pub fn update_gotify_target(
+    name: String,
+    update: GotifyConfigUpdater,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let _lock = notifications::lock_config()?;
+
+    let (mut section_config, expected_digest) = notifications::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut config: GotifyConfig = section_config.lookup("gotify", &name)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Enable => {
+                    config.enable = true;
+                }
+                DeletableProperty::Comment => {
+                    config.comment = None;
+                }
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            config.comment = None;
+        } else {
+            config.comment = Some(comment);
+        }
+    }
+
+    if let Some(enable) = update.enable {
+        config.enable = enable;
+    }
+    if let Some(server) = update.server {
+        config.server = server;
+    }
+    if update.token.is_some() {
+        config.token = update.token;
+    }
+
+    section_config.set_data(&name, "gotify", &config)?;
+
+    notifications::save_config(&section_config)?;
+
+    Ok(())
+}
+
+const ITEM_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_GOTIFY_TARGET)
+    .put(&API_METHOD_UPDATE_GOTIFY_TARGET)
+    .delete(&API_METHOD_DELETE_GOTIFY_TARGET);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_GOTIFY_TARGETS)
+    .post(&API_METHOD_CREATE_GOTIFY_TARGET)
+    .match_all("name", &ITEM_ROUTER);
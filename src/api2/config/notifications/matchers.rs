@@ -0,0 +1,310 @@
+use anyhow::{bail, Error};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use proxmox_router::{Permission, Router, RpcEnvironment};
+use proxmox_schema::{api, param_bail};
+use proxmox_section_config::SectionConfigData;
+
+use pbs_api_types::{
+    MatcherConfig, MatcherConfigUpdater, NOTIFICATION_ID_SCHEMA, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY,
+    PROXMOX_CONFIG_DIGEST_SCHEMA,
+};
+
+use pbs_config::notifications::{self, NOTIFICATION_TARGET_TYPES};
+
+use crate::server::notifications::check_match_field;
+
+/// Check the match rules and that all referenced targets exist.
+fn check_matcher_config(
+    section_config: &SectionConfigData,
+    config: &MatcherConfig,
+) -> Result<(), Error> {
+    for field in config.match_field.iter().flatten() {
+        if let Err(err) = check_match_field(field) {
+            param_bail!("match-field", err);
+        }
+    }
+
+    for target in config.target.iter().flatten() {
+        match section_config.sections.get(target) {
+            Some((ty, _)) if NOTIFICATION_TARGET_TYPES.contains(&ty.as_str()) => (),
+            _ => param_bail!("target", "notification target '{}' does not exist", target),
+        }
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "List of configured notification matchers.",
+        type: Array,
+        items: { type: MatcherConfig },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List configured notification matchers.
+pub fn list_matchers(
+    _param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<MatcherConfig>, Error> {
+    let (config, digest) = notifications::config()?;
+
+    let list: Vec<MatcherConfig> = config.convert_to_typed_array("matcher")?;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: MatcherConfig,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Create a new notification matcher.
+pub fn create_matcher(config: MatcherConfig) -> Result<(), Error> {
+    let _lock = notifications::lock_config()?;
+
+    let (mut section_config, _digest) = notifications::config()?;
+
+    if section_config.sections.get(&config.name).is_some() {
+        bail!(
+            "notification target or matcher '{}' already exists.",
+            config.name
+        );
+    }
+
+    check_matcher_config(&section_config, &config)?;
+
+    section_config.set_data(&config.name, "matcher", &config)?;
+
+    notifications::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Remove a notification matcher.
+pub fn delete_matcher(
+    name: String,
+    digest: Option<String>,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let _lock = notifications::lock_config()?;
+
+    let (mut section_config, expected_digest) = notifications::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let _: MatcherConfig = section_config.lookup("matcher", &name)?;
+
+    section_config.sections.remove(&name);
+
+    notifications::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: MatcherConfig },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Read a notification matcher.
+pub fn read_matcher(name: String, rpcenv: &mut dyn RpcEnvironment) -> Result<MatcherConfig, Error> {
+    let (section_config, digest) = notifications::config()?;
+
+    let config: MatcherConfig = section_config.lookup("matcher", &name)?;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(config)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the enable property.
+    Enable,
+    /// Delete the match-field property.
+    MatchField,
+    /// Delete the match-severity property.
+    MatchSeverity,
+    /// Delete the mode property.
+    Mode,
+    /// Delete the invert-match property.
+    InvertMatch,
+    /// Delete the target property.
+    Target,
+    /// Delete the comment property.
+    Comment,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+            update: {
+                type: MatcherConfigUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Update a notification matcher.
+pub fn update_matcher(
+    name: String,
+    update: MatcherConfigUpdater,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let _lock = notifications::lock_config()?;
+
+    let (mut section_config, expected_digest) = notifications::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut config: MatcherConfig = section_config.lookup("matcher", &name)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Enable => {
+                    config.enable = true;
+                }
+                DeletableProperty::MatchField => {
+                    config.match_field = None;
+                }
+                DeletableProperty::MatchSeverity => {
+                    config.match_severity = None;
+                }
+                DeletableProperty::Mode => {
+                    config.mode = None;
+                }
+                DeletableProperty::InvertMatch => {
+                    config.invert_match = None;
+                }
+                DeletableProperty::Target => {
+                    config.target = None;
+                }
+                DeletableProperty::Comment => {
+                    config.comment = None;
+                }
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            config.comment = None;
+        } else {
+            config.comment = Some(comment);
+        }
+    }
+
+    if let Some(enable) = update.enable {
+        config.enable = enable;
+    }
+    if update.match_field.is_some() {
+        config.match_field = update.match_field;
+    }
+    if update.match_severity.is_some() {
+        config.match_severity = update.match_severity;
+    }
+    if update.mode.is_some() {
+        config.mode = update.mode;
+    }
+    if update.invert_match.is_some() {
+        config.invert_match = update.invert_match;
+    }
+    if update.target.is_some() {
+        config.target = update.target;
+    }
+
+    check_matcher_config(&section_config, &config)?;
+
+    section_config.set_data(&name, "matcher", &config)?;
+
+    notifications::save_config(&section_config)?;
+
+    Ok(())
+}
+
+const ITEM_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_MATCHER)
+    .put(&API_METHOD_UPDATE_MATCHER)
+    .delete(&API_METHOD_DELETE_MATCHER);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_MATCHERS)
+    .post(&API_METHOD_CREATE_MATCHER)
+    .match_all("name", &ITEM_ROUTER);
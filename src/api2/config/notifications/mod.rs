@@ -0,0 +1,134 @@
+//! Notification targets and matchers
+
+use anyhow::{bail, Error};
+use serde_json::Value;
+
+use proxmox_router::list_subdirs_api_method;
+use proxmox_router::{Permission, Router, RpcEnvironment, SubdirMap};
+use proxmox_schema::api;
+use proxmox_section_config::SectionConfigData;
+use proxmox_sortable_macro::sortable;
+
+use pbs_api_types::{
+    GotifyConfig, NotificationTargetInfo, NotificationTargetType, SmtpConfig, WebhookConfig,
+    NOTIFICATION_ID_SCHEMA, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY,
+};
+
+pub mod gotify;
+pub mod matchers;
+pub mod smtp;
+pub mod webhook;
+
+/// Fail if a notification target with this name is referenced by a matcher.
+pub(crate) fn ensure_target_unused(config: &SectionConfigData, name: &str) -> Result<(), Error> {
+    for (matcher, (ty, data)) in config.sections.iter() {
+        if ty != "matcher" {
+            continue;
+        }
+        let used = data["target"]
+            .as_array()
+            .map(|targets| targets.iter().any(|target| target == name))
+            .unwrap_or(false);
+        if used {
+            bail!("notification target '{name}' is used by matcher '{matcher}'");
+        }
+    }
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "List of configured notification targets.",
+        type: Array,
+        items: { type: NotificationTargetInfo },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List all notification targets.
+pub fn list_targets(
+    _param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<NotificationTargetInfo>, Error> {
+    let (config, digest) = pbs_config::notifications::config()?;
+
+    let mut list = Vec::new();
+
+    for smtp in config.convert_to_typed_array::<SmtpConfig>("smtp")? {
+        list.push(NotificationTargetInfo {
+            name: smtp.name,
+            ty: NotificationTargetType::Smtp,
+            enable: smtp.enable,
+            server: smtp.server,
+            comment: smtp.comment,
+        });
+    }
+
+    for gotify in config.convert_to_typed_array::<GotifyConfig>("gotify")? {
+        list.push(NotificationTargetInfo {
+            name: gotify.name,
+            ty: NotificationTargetType::Gotify,
+            enable: gotify.enable,
+            server: gotify.server,
+            comment: gotify.comment,
+        });
+    }
+
+    for webhook in config.convert_to_typed_array::<WebhookConfig>("webhook")? {
+        list.push(NotificationTargetInfo {
+            name: webhook.name,
+            ty: NotificationTargetType::Webhook,
+            enable: webhook.enable,
+            server: webhook.url,
+            comment: webhook.comment,
+        });
+    }
+
+    list.sort();
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: NOTIFICATION_ID_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Send a test notification to a target.
+pub async fn test_target(name: String) -> Result<(), Error> {
+    crate::server::notifications::send_test_notification(&name).await
+}
+
+const TARGET_ITEM_ROUTER: Router =
+    Router::new().subdirs(&[("test", &Router::new().post(&API_METHOD_TEST_TARGET))]);
+
+const TARGETS_ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_TARGETS)
+    .match_all("name", &TARGET_ITEM_ROUTER);
+
+#[sortable]
+const SUBDIRS: SubdirMap = &sorted!([
+    ("gotify", &gotify::ROUTER),
+    ("matchers", &matchers::ROUTER),
+    ("smtp", &smtp::ROUTER),
+    ("targets", &TARGETS_ROUTER),
+    ("webhook", &webhook::ROUTER),
+]);
+
+pub const ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(SUBDIRS))
+    .subdirs(SUBDIRS);
@@ -16,6 +16,19 @@ use pbs_config::prune;
 
 use pbs_config::CachedUserInfo;
 
+use crate::backup::check_user_not_append_only;
+
+/// checks whether `auth_id` may configure or run the prune job, append-only API tokens must not
+/// since pruning removes backups
+pub fn check_prune_job_modify_access(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+    job: &PruneJobConfig,
+) -> Result<(), Error> {
+    user_info.check_privs(auth_id, &job.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+    check_user_not_append_only(user_info, auth_id)
+}
+
 #[api(
     input: {
         properties: {},
@@ -106,7 +119,7 @@ pub fn create_prune_job(
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let user_info = CachedUserInfo::new()?;
 
-    user_info.check_privs(&auth_id, &config.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+    check_prune_job_modify_access(&user_info, &auth_id, &config)?;
 
     do_create_prune_job(config, None)
 }
@@ -170,6 +183,18 @@ pub enum DeletableProperty {
     KeepMonthly,
     /// Delete number of yearly backups to keep.
     KeepYearly,
+    /// Delete the keep-within time span.
+    KeepWithin,
+    /// Delete the keep-within-hourly time span.
+    KeepWithinHourly,
+    /// Delete the keep-within-daily time span.
+    KeepWithinDaily,
+    /// Delete the keep-within-weekly time span.
+    KeepWithinWeekly,
+    /// Delete the keep-within-monthly time span.
+    KeepWithinMonthly,
+    /// Delete the keep-within-yearly time span.
+    KeepWithinYearly,
 }
 
 #[api(
@@ -226,7 +251,7 @@ pub fn update_prune_job(
 
     let mut data: PruneJobConfig = config.lookup("prune", &id)?;
 
-    user_info.check_privs(&auth_id, &data.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+    check_prune_job_modify_access(&user_info, &auth_id, &data)?;
 
     if let Some(delete) = delete {
         for delete_prop in delete {
@@ -261,6 +286,24 @@ pub fn update_prune_job(
                 DeletableProperty::KeepYearly => {
                     data.options.keep.keep_yearly = None;
                 }
+                DeletableProperty::KeepWithin => {
+                    data.options.keep.keep_within = None;
+                }
+                DeletableProperty::KeepWithinHourly => {
+                    data.options.keep.keep_within_hourly = None;
+                }
+                DeletableProperty::KeepWithinDaily => {
+                    data.options.keep.keep_within_daily = None;
+                }
+                DeletableProperty::KeepWithinWeekly => {
+                    data.options.keep.keep_within_weekly = None;
+                }
+                DeletableProperty::KeepWithinMonthly => {
+                    data.options.keep.keep_within_monthly = None;
+                }
+                DeletableProperty::KeepWithinYearly => {
+                    data.options.keep.keep_within_yearly = None;
+                }
             }
         }
     }
@@ -278,7 +321,7 @@ pub fn update_prune_job(
     }
 
     if recheck_privs {
-        user_info.check_privs(&auth_id, &data.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+        check_prune_job_modify_access(&user_info, &auth_id, &data)?;
     }
 
     let mut schedule_changed = false;
@@ -317,6 +360,24 @@ pub fn update_prune_job(
     if let Some(value) = update.options.keep.keep_yearly {
         data.options.keep.keep_yearly = Some(value);
     }
+    if let Some(value) = update.options.keep.keep_within {
+        data.options.keep.keep_within = Some(value);
+    }
+    if let Some(value) = update.options.keep.keep_within_hourly {
+        data.options.keep.keep_within_hourly = Some(value);
+    }
+    if let Some(value) = update.options.keep.keep_within_daily {
+        data.options.keep.keep_within_daily = Some(value);
+    }
+    if let Some(value) = update.options.keep.keep_within_weekly {
+        data.options.keep.keep_within_weekly = Some(value);
+    }
+    if let Some(value) = update.options.keep.keep_within_monthly {
+        data.options.keep.keep_within_monthly = Some(value);
+    }
+    if let Some(value) = update.options.keep.keep_within_yearly {
+        data.options.keep.keep_within_yearly = Some(value);
+    }
 
     config.set_data(&id, "prune", &data)?;
 
@@ -389,3 +450,54 @@ pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_LIST_PRUNE_JOBS)
     .post(&API_METHOD_CREATE_PRUNE_JOB)
     .match_all("id", &ITEM_ROUTER);
+
+#[test]
+fn prune_job_append_only_test() -> Result<(), Error> {
+    let (user_cfg, _) = pbs_config::user::test_cfg_from_str(
+        r###"
+user: write@pbs
+
+token: write@pbs!append
+	append-only true
+
+token: write@pbs!regular
+
+user: audit@pbs
+
+"###,
+    )
+    .expect("test user.cfg is not parsable");
+    let acl_tree = pbs_config::acl::AclTree::from_raw(
+        r###"
+acl:1:/datastore/store1:write@pbs,write@pbs!append,write@pbs!regular:DatastoreAdmin
+acl:1:/datastore/store1:audit@pbs:DatastoreAudit
+"###,
+    )
+    .expect("test acl.cfg is not parsable");
+
+    let user_info = CachedUserInfo::test_new(user_cfg, acl_tree);
+
+    let job = PruneJobConfig {
+        id: "prune1".to_string(),
+        store: "store1".to_string(),
+        disable: false,
+        schedule: "daily".to_string(),
+        comment: None,
+        options: Default::default(),
+    };
+
+    let check = |auth_id: &str| -> Result<(), Error> {
+        check_prune_job_modify_access(&user_info, &auth_id.parse()?, &job)
+    };
+
+    assert!(check("write@pbs").is_ok());
+    assert!(check("write@pbs!regular").is_ok());
+
+    // Datastore.Modify is not enough for append-only tokens
+    assert!(check("write@pbs!append").is_err());
+
+    // no Datastore.Modify
+    assert!(check("audit@pbs").is_err());
+
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use anyhow::Error;
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use proxmox_router::{http_bail, Permission, Router, RpcEnvironment};
+use proxmox_schema::{api, param_bail};
+
+use pbs_api_types::{
+    Authid, DatastoreQuota, DatastoreQuotaUpdater, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY,
+    PROXMOX_CONFIG_DIGEST_SCHEMA, QUOTA_ID_SCHEMA,
+};
+use pbs_config::quota;
+
+use pbs_config::CachedUserInfo;
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "List configured quotas.",
+        type: Array,
+        items: { type: DatastoreQuota },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit on the quota's datastore or namespace.",
+    },
+)]
+/// List all datastore quotas.
+pub fn list_quotas(
+    _param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<DatastoreQuota>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, digest) = quota::config()?;
+
+    let list = config.convert_to_typed_array("quota")?;
+
+    let list = list
+        .into_iter()
+        .filter(|quota: &DatastoreQuota| {
+            let privs = user_info.lookup_privs(&auth_id, &quota.acl_path());
+            privs & PRIV_DATASTORE_AUDIT != 0
+        })
+        .collect();
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: DatastoreQuota,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Modify on the quota's datastore or namespace.",
+    },
+)]
+/// Create a new datastore quota.
+pub fn create_quota(config: DatastoreQuota, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    user_info.check_privs(&auth_id, &config.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+
+    let _lock = quota::lock_config()?;
+
+    let (mut section_config, _digest) = quota::config()?;
+
+    if section_config.sections.get(&config.id).is_some() {
+        param_bail!("id", "quota '{}' already exists.", config.id);
+    }
+
+    section_config.set_data(&config.id, "quota", &config)?;
+
+    quota::save_config(&section_config)
+}
+
+#[api(
+   input: {
+        properties: {
+            id: {
+                schema: QUOTA_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: DatastoreQuota },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit on the quota's datastore or namespace.",
+    },
+)]
+/// Read a datastore quota.
+pub fn read_quota(id: String, rpcenv: &mut dyn RpcEnvironment) -> Result<DatastoreQuota, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, digest) = quota::config()?;
+
+    let quota: DatastoreQuota = config.lookup("quota", &id)?;
+
+    user_info.check_privs(&auth_id, &quota.acl_path(), PRIV_DATASTORE_AUDIT, true)?;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(quota)
+}
+
+#[api]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Reset the namespace to the root namespace.
+    Ns,
+    /// Delete the owner restriction.
+    Owner,
+    /// Delete the logical size limit.
+    LogicalLimit,
+    /// Delete the unique size limit.
+    UniqueLimit,
+    /// Delete the soft limit.
+    SoftLimit,
+    /// Delete the comment.
+    Comment,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: QUOTA_ID_SCHEMA,
+            },
+            update: {
+                type: DatastoreQuotaUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Modify on the quota's datastore or namespace.",
+    },
+)]
+/// Update a datastore quota.
+pub fn update_quota(
+    id: String,
+    update: DatastoreQuotaUpdater,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let _lock = quota::lock_config()?;
+
+    // pass/compare digest
+    let (mut config, expected_digest) = quota::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut data: DatastoreQuota = config.lookup("quota", &id)?;
+
+    user_info.check_privs(&auth_id, &data.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Ns => data.ns = None,
+                DeletableProperty::Owner => data.owner = None,
+                DeletableProperty::LogicalLimit => data.logical_limit = None,
+                DeletableProperty::UniqueLimit => data.unique_limit = None,
+                DeletableProperty::SoftLimit => data.soft_limit = None,
+                DeletableProperty::Comment => data.comment = None,
+            }
+        }
+    }
+
+    let mut recheck_privs = false;
+    if let Some(store) = update.store {
+        recheck_privs = true;
+        data.store = store;
+    }
+
+    if let Some(ns) = update.ns {
+        recheck_privs = true;
+        data.ns = if ns.is_root() { None } else { Some(ns) };
+    }
+
+    if recheck_privs {
+        user_info.check_privs(&auth_id, &data.acl_path(), PRIV_DATASTORE_MODIFY, true)?;
+    }
+
+    if update.owner.is_some() {
+        data.owner = update.owner;
+    }
+    if update.logical_limit.is_some() {
+        data.logical_limit = update.logical_limit;
+    }
+    if update.unique_limit.is_some() {
+        data.unique_limit = update.unique_limit;
+    }
+    if update.soft_limit.is_some() {
+        data.soft_limit = update.soft_limit;
+    }
+    if update.comment.is_some() {
+        data.comment = update.comment;
+    }
+
+    config.set_data(&id, "quota", &data)?;
+
+    quota::save_config(&config)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: QUOTA_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Modify on the quota's datastore or namespace.",
+    },
+)]
+/// Remove a datastore quota.
+pub fn delete_quota(
+    id: String,
+    digest: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let _lock = quota::lock_config()?;
+
+    let (mut config, expected_digest) = quota::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    match config.lookup::<DatastoreQuota>("quota", &id) {
+        Ok(quota) => {
+            user_info.check_privs(&auth_id, &quota.acl_path(), PRIV_DATASTORE_MODIFY, true)?
+        }
+        Err(_) => http_bail!(NOT_FOUND, "quota '{}' does not exist.", id),
+    }
+
+    config.sections.remove(&id);
+
+    quota::save_config(&config)
+}
+
+const ITEM_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_QUOTA)
+    .put(&API_METHOD_UPDATE_QUOTA)
+    .delete(&API_METHOD_DELETE_QUOTA);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_QUOTAS)
+    .post(&API_METHOD_CREATE_QUOTA)
+    .match_all("id", &ITEM_ROUTER);
@@ -0,0 +1,339 @@
+use ::serde::{Deserialize, Serialize};
+use anyhow::Error;
+use hex::FromHex;
+use serde_json::Value;
+
+use proxmox_router::{http_bail, Permission, Router, RpcEnvironment};
+use proxmox_schema::{api, param_bail};
+
+use pbs_api_types::{
+    DataStoreConfig, S3Client, S3ClientConfig, S3ClientConfigUpdater, S3ClientWithoutSecret,
+    PRIV_SYS_AUDIT, PRIV_SYS_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA, S3_BUCKET_NAME_SCHEMA,
+    S3_CLIENT_ID_SCHEMA, S3_SECRET_KEY_SCHEMA,
+};
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "The list of configured S3 clients (with config digest).",
+        type: Array,
+        items: { type: S3ClientWithoutSecret },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List all S3 client configurations.
+pub fn list_s3_clients(
+    _param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<S3ClientWithoutSecret>, Error> {
+    let (config, digest) = pbs_config::s3::config()?;
+
+    // Note: This removes the secret key (we do not want to return it).
+    let list: Vec<S3ClientWithoutSecret> = config.convert_to_typed_array("s3client")?;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: S3_CLIENT_ID_SCHEMA,
+            },
+            config: {
+                type: S3ClientConfig,
+                flatten: true,
+            },
+            "secret-key": {
+                // We expect the plain secret key here (not base64 encoded)
+                schema: S3_SECRET_KEY_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Create new S3 client configuration.
+pub fn create_s3_client(
+    id: String,
+    config: S3ClientConfig,
+    secret_key: String,
+) -> Result<(), Error> {
+    let _lock = pbs_config::s3::lock_config()?;
+
+    let (mut section_config, _digest) = pbs_config::s3::config()?;
+
+    if section_config.sections.get(&id).is_some() {
+        param_bail!("id", "S3 client '{}' already exists.", id);
+    }
+
+    let entry = S3Client {
+        id: id.clone(),
+        config,
+        secret_key,
+    };
+
+    section_config.set_data(&id, "s3client", &entry)?;
+
+    pbs_config::s3::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+   input: {
+        properties: {
+            id: {
+                schema: S3_CLIENT_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: S3ClientWithoutSecret },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    }
+)]
+/// Read S3 client configuration data.
+pub fn read_s3_client(
+    id: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<S3ClientWithoutSecret, Error> {
+    let (config, digest) = pbs_config::s3::config()?;
+    let data: S3ClientWithoutSecret = config.lookup("s3client", &id)?;
+    rpcenv["digest"] = hex::encode(digest).into();
+    Ok(data)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the comment property.
+    Comment,
+    /// Delete the fingerprint property.
+    Fingerprint,
+    /// Delete the port property.
+    Port,
+    /// Delete the region property.
+    Region,
+    /// Delete the path-style property.
+    PathStyle,
+    /// Delete the use-http property.
+    UseHttp,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: S3_CLIENT_ID_SCHEMA,
+            },
+            update: {
+                type: S3ClientConfigUpdater,
+                flatten: true,
+            },
+            "secret-key": {
+                // We expect the plain secret key here (not base64 encoded)
+                optional: true,
+                schema: S3_SECRET_KEY_SCHEMA,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Update S3 client configuration.
+pub fn update_s3_client(
+    id: String,
+    update: S3ClientConfigUpdater,
+    secret_key: Option<String>,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+) -> Result<(), Error> {
+    let _lock = pbs_config::s3::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::s3::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut data: S3Client = config.lookup("s3client", &id)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Comment => {
+                    data.config.comment = None;
+                }
+                DeletableProperty::Fingerprint => {
+                    data.config.fingerprint = None;
+                }
+                DeletableProperty::Port => {
+                    data.config.port = None;
+                }
+                DeletableProperty::Region => {
+                    data.config.region = None;
+                }
+                DeletableProperty::PathStyle => {
+                    data.config.path_style = None;
+                }
+                DeletableProperty::UseHttp => {
+                    data.config.use_http = None;
+                }
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            data.config.comment = None;
+        } else {
+            data.config.comment = Some(comment);
+        }
+    }
+    if let Some(endpoint) = update.endpoint {
+        data.config.endpoint = endpoint;
+    }
+    if update.port.is_some() {
+        data.config.port = update.port;
+    }
+    if update.region.is_some() {
+        data.config.region = update.region;
+    }
+    if let Some(access_key) = update.access_key {
+        data.config.access_key = access_key;
+    }
+    if let Some(secret_key) = secret_key {
+        data.secret_key = secret_key;
+    }
+    if update.fingerprint.is_some() {
+        data.config.fingerprint = update.fingerprint;
+    }
+    if update.path_style.is_some() {
+        data.config.path_style = update.path_style;
+    }
+    if update.use_http.is_some() {
+        data.config.use_http = update.use_http;
+    }
+
+    config.set_data(&id, "s3client", &data)?;
+
+    pbs_config::s3::save_config(&config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: S3_CLIENT_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Remove an S3 client from the configuration file.
+pub fn delete_s3_client(id: String, digest: Option<String>) -> Result<(), Error> {
+    let (datastores, _) = pbs_config::datastore::config()?;
+
+    let store_list: Vec<DataStoreConfig> = datastores.convert_to_typed_array("datastore")?;
+    for store in store_list {
+        let backend = store.backend_config()?;
+        if backend.client.as_deref() == Some(id.as_str()) {
+            param_bail!(
+                "id",
+                "S3 client '{}' is used by datastore '{}'",
+                id,
+                store.name
+            );
+        }
+    }
+
+    let _lock = pbs_config::s3::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::s3::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    match config.sections.get(&id) {
+        Some(_) => {
+            config.sections.remove(&id);
+        }
+        None => http_bail!(NOT_FOUND, "S3 client '{}' does not exist.", id),
+    }
+
+    pbs_config::s3::save_config(&config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: S3_CLIENT_ID_SCHEMA,
+            },
+            bucket: {
+                schema: S3_BUCKET_NAME_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Check that a bucket is accessible using the given S3 client configuration.
+pub async fn check_s3_bucket(id: String, bucket: String) -> Result<(), Error> {
+    let client = pbs_datastore::s3::S3Client::new(&id, &bucket)?;
+    client.head_bucket().await
+}
+
+const CHECK_BUCKET_ROUTER: Router = Router::new().get(&API_METHOD_CHECK_S3_BUCKET);
+
+const ITEM_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_S3_CLIENT)
+    .put(&API_METHOD_UPDATE_S3_CLIENT)
+    .delete(&API_METHOD_DELETE_S3_CLIENT)
+    .subdirs(&[("check-bucket", &CHECK_BUCKET_ROUTER)]);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_S3_CLIENTS)
+    .post(&API_METHOD_CREATE_S3_CLIENT)
+    .match_all("id", &ITEM_ROUTER);
@@ -8,13 +8,16 @@ use proxmox_schema::{api, param_bail};
 
 use pbs_api_types::{
     Authid, SyncJobConfig, SyncJobConfigUpdater, JOB_ID_SCHEMA, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_REMOTE_AUDIT,
-    PRIV_REMOTE_READ, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_READ,
+    PRIV_REMOTE_AUDIT, PRIV_REMOTE_DATASTORE_BACKUP, PRIV_REMOTE_DATASTORE_PRUNE, PRIV_REMOTE_READ,
+    PROXMOX_CONFIG_DIGEST_SCHEMA,
 };
 use pbs_config::sync;
 
 use pbs_config::CachedUserInfo;
 
+use crate::backup::check_user_not_append_only;
+
 pub fn check_sync_job_read_access(
     user_info: &CachedUserInfo,
     auth_id: &Authid,
@@ -34,7 +37,57 @@ pub fn check_sync_job_read_access(
     }
 }
 
-/// checks whether user can run the corresponding pull job
+fn check_sync_job_owner(auth_id: &Authid, job: &SyncJobConfig) -> bool {
+    match job.owner {
+        Some(ref owner) => {
+            owner == auth_id
+                || (owner.is_token() && !auth_id.is_token() && owner.user() == auth_id.user())
+        }
+        // default sync owner
+        None => auth_id == Authid::root_auth_id(),
+    }
+}
+
+/// checks whether user can run the corresponding push job
+///
+/// the local source is read with the privileges of the job owner, so reading requires
+/// Datastore.Read or Datastore.Backup (the latter limited to owned groups by the push code).
+/// the remote side checks remote datastore/namespace/group access of the remote's auth id.
+fn check_sync_job_push_access(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+    job: &SyncJobConfig,
+) -> bool {
+    let remote = match &job.remote {
+        Some(remote) => remote,
+        None => return false,
+    };
+
+    let ns_anchor_privs = user_info.lookup_privs(auth_id, &job.acl_path());
+    if ns_anchor_privs & (PRIV_DATASTORE_READ | PRIV_DATASTORE_BACKUP) == 0 {
+        return false;
+    }
+
+    // reading with the privileges of another user is only allowed for datastore admins
+    if !check_sync_job_owner(auth_id, job) && ns_anchor_privs & PRIV_DATASTORE_MODIFY == 0 {
+        return false;
+    }
+
+    let remote_privs = user_info.lookup_privs(auth_id, &["remote", remote, &job.remote_store]);
+    if remote_privs & PRIV_REMOTE_DATASTORE_BACKUP == 0 {
+        return false;
+    }
+
+    if let Some(true) = job.remove_vanished {
+        if remote_privs & PRIV_REMOTE_DATASTORE_PRUNE == 0 {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// checks whether user can run the corresponding sync job
 ///
 /// namespace creation/deletion ACL and backup group ownership checks happen in the pull code directly.
 /// remote side checks/filters remote datastore/namespace/group access.
@@ -43,6 +96,10 @@ pub fn check_sync_job_modify_access(
     auth_id: &Authid,
     job: &SyncJobConfig,
 ) -> bool {
+    if job.is_push() {
+        return check_sync_job_push_access(user_info, auth_id, job);
+    }
+
     let ns_anchor_privs = user_info.lookup_privs(auth_id, &job.acl_path());
     if ns_anchor_privs & PRIV_DATASTORE_BACKUP == 0 {
         return false;
@@ -54,17 +111,8 @@ pub fn check_sync_job_modify_access(
         }
     }
 
-    let correct_owner = match job.owner {
-        Some(ref owner) => {
-            owner == auth_id
-                || (owner.is_token() && !auth_id.is_token() && owner.user() == auth_id.user())
-        }
-        // default sync owner
-        None => auth_id == Authid::root_auth_id(),
-    };
-
     // same permission as changing ownership after syncing
-    if !correct_owner && ns_anchor_privs & PRIV_DATASTORE_MODIFY == 0 {
+    if !check_sync_job_owner(auth_id, job) && ns_anchor_privs & PRIV_DATASTORE_MODIFY == 0 {
         return false;
     }
 
@@ -75,6 +123,19 @@ pub fn check_sync_job_modify_access(
     true
 }
 
+/// checks that an append-only API token does not configure or run a sync job which removes
+/// vanished backups, since that deletes existing backups on the target
+pub fn check_sync_job_not_append_only(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+    job: &SyncJobConfig,
+) -> Result<(), Error> {
+    if let Some(true) = job.remove_vanished {
+        check_user_not_append_only(user_info, auth_id)?;
+    }
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {},
@@ -135,6 +196,8 @@ pub fn create_sync_job(
 
     let _lock = sync::lock_config()?;
 
+    check_sync_job_not_append_only(&user_info, &auth_id, &config)?;
+
     if !check_sync_job_modify_access(&user_info, &auth_id, &config) {
         bail!("permission check failed");
     }
@@ -143,6 +206,10 @@ pub fn create_sync_job(
         bail!("source and target datastore can't be the same");
     }
 
+    if config.is_push() && config.remote.is_none() {
+        param_bail!("remote", "push sync jobs require a remote");
+    }
+
     if let Some(max_depth) = config.max_depth {
         if let Some(ref ns) = config.ns {
             ns.check_max_depth(max_depth)?;
@@ -231,6 +298,26 @@ pub enum DeletableProperty {
     MaxDepth,
     /// Delete the transfer_last property,
     TransferLast,
+    /// Delete the repair_corrupt property,
+    RepairCorrupt,
+    /// Delete the parallel_groups property,
+    ParallelGroups,
+    /// Delete the chunk_concurrency property,
+    ChunkConcurrency,
+    /// Delete the newer_than property,
+    NewerThan,
+    /// Delete the older_than property,
+    OlderThan,
+    /// Delete the verified_only property,
+    VerifiedOnly,
+    /// Delete the protected_only property,
+    ProtectedOnly,
+    /// Delete the encryption_filter property,
+    EncryptionFilter,
+    /// Delete the rename_rules property,
+    RenameRules,
+    /// Delete the sync_direction property (-> meaning pull),
+    SyncDirection,
 }
 
 #[api(
@@ -331,6 +418,36 @@ pub fn update_sync_job(
                 DeletableProperty::TransferLast => {
                     data.transfer_last = None;
                 }
+                DeletableProperty::RepairCorrupt => {
+                    data.repair_corrupt = None;
+                }
+                DeletableProperty::ParallelGroups => {
+                    data.parallel_groups = None;
+                }
+                DeletableProperty::ChunkConcurrency => {
+                    data.chunk_concurrency = None;
+                }
+                DeletableProperty::NewerThan => {
+                    data.snapshot_filter.newer_than = None;
+                }
+                DeletableProperty::OlderThan => {
+                    data.snapshot_filter.older_than = None;
+                }
+                DeletableProperty::VerifiedOnly => {
+                    data.snapshot_filter.verified_only = None;
+                }
+                DeletableProperty::ProtectedOnly => {
+                    data.snapshot_filter.protected_only = None;
+                }
+                DeletableProperty::EncryptionFilter => {
+                    data.snapshot_filter.encryption_filter = None;
+                }
+                DeletableProperty::RenameRules => {
+                    data.rename_rules = None;
+                }
+                DeletableProperty::SyncDirection => {
+                    data.sync_direction = None;
+                }
             }
         }
     }
@@ -368,6 +485,21 @@ pub fn update_sync_job(
     if let Some(transfer_last) = update.transfer_last {
         data.transfer_last = Some(transfer_last);
     }
+    if let Some(repair_corrupt) = update.repair_corrupt {
+        data.repair_corrupt = Some(repair_corrupt);
+    }
+    if let Some(parallel_groups) = update.parallel_groups {
+        data.parallel_groups = Some(parallel_groups);
+    }
+    if let Some(chunk_concurrency) = update.chunk_concurrency {
+        data.chunk_concurrency = Some(chunk_concurrency);
+    }
+    if let Some(rename_rules) = update.rename_rules {
+        data.rename_rules = Some(rename_rules);
+    }
+    if let Some(sync_direction) = update.sync_direction {
+        data.sync_direction = Some(sync_direction);
+    }
 
     if update.limit.rate_in.is_some() {
         data.limit.rate_in = update.limit.rate_in;
@@ -385,6 +517,26 @@ pub fn update_sync_job(
         data.limit.burst_out = update.limit.burst_out;
     }
 
+    if update.snapshot_filter.newer_than.is_some() {
+        data.snapshot_filter.newer_than = update.snapshot_filter.newer_than;
+    }
+
+    if update.snapshot_filter.older_than.is_some() {
+        data.snapshot_filter.older_than = update.snapshot_filter.older_than;
+    }
+
+    if update.snapshot_filter.verified_only.is_some() {
+        data.snapshot_filter.verified_only = update.snapshot_filter.verified_only;
+    }
+
+    if update.snapshot_filter.protected_only.is_some() {
+        data.snapshot_filter.protected_only = update.snapshot_filter.protected_only;
+    }
+
+    if update.snapshot_filter.encryption_filter.is_some() {
+        data.snapshot_filter.encryption_filter = update.snapshot_filter.encryption_filter;
+    }
+
     let schedule_changed = data.schedule != update.schedule;
     if update.schedule.is_some() {
         data.schedule = update.schedule;
@@ -405,6 +557,12 @@ pub fn update_sync_job(
         }
     }
 
+    if data.is_push() && data.remote.is_none() {
+        param_bail!("remote", "push sync jobs require a remote");
+    }
+
+    check_sync_job_not_append_only(&user_info, &auth_id, &data)?;
+
     if !check_sync_job_modify_access(&user_info, &auth_id, &data) {
         bail!("permission check failed");
     }
@@ -506,6 +664,7 @@ acl:1:/datastore/localstore2:write@pbs:DatastorePowerUser
 acl:1:/datastore/localstore3:write@pbs:DatastoreAdmin
 acl:1:/remote/remote1:read@pbs,write@pbs:RemoteAudit
 acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
+acl:1:/remote/remote1/remotestore2:write@pbs:RemoteSyncPushOperator
 "###,
     )
     .expect("test acl.cfg is not parsable");
@@ -533,6 +692,12 @@ acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
         schedule: None,
         limit: pbs_api_types::RateLimitConfig::default(), // no limit
         transfer_last: None,
+        repair_corrupt: None,
+        parallel_groups: None,
+        chunk_concurrency: None,
+        snapshot_filter: Default::default(),
+        rename_rules: None,
+        sync_direction: None,
     };
 
     // should work without ACLs
@@ -662,5 +827,130 @@ acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
         &job
     ));
 
+    // pushing requires Remote.DatastoreBackup on the remote datastore
+    job.sync_direction = Some(pbs_api_types::SyncDirection::Push);
+    job.owner = Some(write_auth_id.clone());
+    job.remove_vanished = None;
+    job.store = "localstore1".to_string();
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+    job.remote_store = "remotestore2".to_string();
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+
+    // and a remote to push to
+    job.remote = None;
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        root_auth_id,
+        &job
+    ));
+    job.remote = Some("remote1".to_string());
+
+    // user with only audit privileges on the local datastore can't push
+    job.owner = Some(read_auth_id.clone());
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        &read_auth_id,
+        &job
+    ));
+
+    // removing vanished snapshots on the remote needs Remote.DatastorePrune as well
+    job.owner = Some(write_auth_id.clone());
+    job.remove_vanished = Some(true);
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+    job.remote_store = "remotestore1".to_string();
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn sync_job_append_only_test() -> Result<(), Error> {
+    let (user_cfg, _) = pbs_config::user::test_cfg_from_str(
+        r###"
+user: write@pbs
+
+token: write@pbs!append
+	append-only true
+
+token: write@pbs!regular
+
+"###,
+    )
+    .expect("test user.cfg is not parsable");
+    let acl_tree = pbs_config::acl::AclTree::from_raw(
+        r###"
+acl:1:/datastore/localstore2:write@pbs,write@pbs!append,write@pbs!regular:DatastorePowerUser
+acl:1:/remote/remote1/remotestore1:write@pbs,write@pbs!append,write@pbs!regular:RemoteSyncOperator
+"###,
+    )
+    .expect("test acl.cfg is not parsable");
+
+    let user_info = CachedUserInfo::test_new(user_cfg, acl_tree);
+
+    let append_auth_id: Authid = "write@pbs!append".parse()?;
+    let regular_auth_id: Authid = "write@pbs!regular".parse()?;
+
+    let mut job = SyncJobConfig {
+        id: "regular".to_string(),
+        remote: Some("remote1".to_string()),
+        remote_store: "remotestore1".to_string(),
+        remote_ns: None,
+        store: "localstore2".to_string(),
+        ns: None,
+        owner: Some(append_auth_id.clone()),
+        comment: None,
+        remove_vanished: None,
+        max_depth: None,
+        group_filter: None,
+        schedule: None,
+        limit: pbs_api_types::RateLimitConfig::default(), // no limit
+        transfer_last: None,
+        repair_corrupt: None,
+        parallel_groups: None,
+        chunk_concurrency: None,
+        snapshot_filter: Default::default(),
+        rename_rules: None,
+        sync_direction: None,
+    };
+
+    // append-only tokens can configure and run jobs which only add backups
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &append_auth_id,
+        &job
+    ));
+    assert!(check_sync_job_not_append_only(&user_info, &append_auth_id, &job).is_ok());
+
+    // Datastore.Prune would allow removing vanished backups, but the token is append-only
+    job.remove_vanished = Some(true);
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &append_auth_id,
+        &job
+    ));
+    assert!(check_sync_job_not_append_only(&user_info, &append_auth_id, &job).is_err());
+
+    // regular tokens and users are not affected
+    job.owner = Some(regular_auth_id.clone());
+    assert!(check_sync_job_not_append_only(&user_info, &regular_auth_id, &job).is_ok());
+    job.owner = Some("write@pbs".parse()?);
+    assert!(check_sync_job_not_append_only(&user_info, &"write@pbs".parse()?, &job).is_ok());
+
     Ok(())
 }
@@ -90,7 +90,7 @@ fn check_job_privs(auth_id: &Authid, user_info: &CachedUserInfo, upid: &UPID) ->
                 }
             }
         }
-        ("garbage_collection", Some(workerid)) => {
+        ("garbage_collection", Some(workerid)) | ("scrub", Some(workerid)) => {
             return user_info.check_privs(
                 auth_id,
                 &["datastore", workerid],
@@ -139,7 +139,8 @@ fn check_job_store(upid: &UPID, store: &str) -> bool {
         ("prune", Some(workerid))
         | ("prunejob", Some(workerid))
         | ("backup", Some(workerid))
-        | ("garbage_collection", Some(workerid)) => {
+        | ("garbage_collection", Some(workerid))
+        | ("scrub", Some(workerid)) => {
             return workerid == store || workerid.starts_with(&format!("{}:", store));
         }
         _ => {}
@@ -7,16 +7,20 @@ use proxmox_schema::api;
 use proxmox_sys::task_log;
 
 use pbs_api_types::{
-    Authid, BackupNamespace, GroupFilter, RateLimitConfig, SyncJobConfig, DATASTORE_SCHEMA,
-    GROUP_FILTER_LIST_SCHEMA, NS_MAX_DEPTH_REDUCED_SCHEMA, PRIV_DATASTORE_BACKUP,
-    PRIV_DATASTORE_PRUNE, PRIV_REMOTE_READ, REMOTE_ID_SCHEMA, REMOVE_VANISHED_BACKUPS_SCHEMA,
-    TRANSFER_LAST_SCHEMA,
+    Authid, BackupNamespace, GroupFilter, RateLimitConfig, SyncDirection, SyncJobConfig,
+    SyncRenameRule, SyncSnapshotFilter, DATASTORE_SCHEMA, GROUP_FILTER_LIST_SCHEMA,
+    NS_MAX_DEPTH_REDUCED_SCHEMA, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_PRUNE, PRIV_REMOTE_READ,
+    REMOTE_ID_SCHEMA, REMOVE_VANISHED_BACKUPS_SCHEMA, REPAIR_CORRUPT_SCHEMA,
+    SYNC_CHUNK_CONCURRENCY_SCHEMA, SYNC_DRY_RUN_SCHEMA, SYNC_PARALLEL_GROUPS_SCHEMA,
+    SYNC_RENAME_RULE_LIST_SCHEMA, TRANSFER_LAST_SCHEMA,
 };
 use pbs_config::CachedUserInfo;
 use proxmox_rest_server::WorkerTask;
 
+use crate::backup::check_not_append_only;
 use crate::server::jobstate::Job;
 use crate::server::pull::{pull_store, PullParameters};
+use crate::server::push::{push_store, PushParameters};
 
 pub fn check_pull_privs(
     auth_id: &Authid,
@@ -88,6 +92,40 @@ impl TryFrom<&SyncJobConfig> for PullParameters {
             sync_job.group_filter.clone(),
             sync_job.limit.clone(),
             sync_job.transfer_last,
+            sync_job.repair_corrupt,
+            sync_job.parallel_groups,
+            sync_job.chunk_concurrency,
+            sync_job.snapshot_filter.clone(),
+            sync_job.rename_rules.clone(),
+        )
+    }
+}
+
+impl TryFrom<&SyncJobConfig> for PushParameters {
+    type Error = Error;
+
+    fn try_from(sync_job: &SyncJobConfig) -> Result<Self, Self::Error> {
+        let remote = match sync_job.remote.as_deref() {
+            Some(remote) => remote,
+            None => bail!("push sync jobs require a remote"),
+        };
+
+        PushParameters::new(
+            &sync_job.store,
+            sync_job.ns.clone().unwrap_or_default(),
+            remote,
+            &sync_job.remote_store,
+            sync_job.remote_ns.clone().unwrap_or_default(),
+            sync_job
+                .owner
+                .as_ref()
+                .unwrap_or_else(|| Authid::root_auth_id())
+                .clone(),
+            sync_job.remove_vanished,
+            sync_job.max_depth,
+            sync_job.group_filter.clone(),
+            sync_job.limit.clone(),
+            sync_job.transfer_last,
         )
     }
 }
@@ -98,6 +136,7 @@ pub fn do_sync_job(
     auth_id: &Authid,
     schedule: Option<String>,
     to_stdout: bool,
+    dry_run: bool,
 ) -> Result<String, Error> {
     let job_id = format!(
         "{}:{}:{}:{}:{}",
@@ -113,7 +152,15 @@ pub fn do_sync_job(
         bail!("can't sync to same datastore");
     }
 
-    let (email, notify) = crate::server::lookup_datastore_notify_settings(&sync_job.store);
+    if sync_job.is_push() && sync_job.remote.is_none() {
+        bail!("push sync jobs require a remote");
+    }
+
+    if sync_job.is_push() && dry_run {
+        bail!("dry-run is only supported for pull sync jobs");
+    }
+
+    let (email, notify, mode) = crate::server::lookup_datastore_notify_settings(&sync_job.store);
 
     let upid_str = WorkerTask::spawn(
         &worker_type,
@@ -121,22 +168,22 @@ pub fn do_sync_job(
         auth_id.to_string(),
         to_stdout,
         move |worker| async move {
-            job.start(&worker.upid().to_string())?;
+            // a dry run neither counts as a run of the job nor sends notifications
+            if !dry_run {
+                job.start(&worker.upid().to_string())?;
+            }
 
             let worker2 = worker.clone();
             let sync_job2 = sync_job.clone();
 
             let worker_future = async move {
-                let pull_params = PullParameters::try_from(&sync_job)?;
-
                 task_log!(worker, "Starting datastore sync job '{}'", job_id);
                 if let Some(event_str) = schedule {
                     task_log!(worker, "task triggered by schedule '{}'", event_str);
                 }
-                task_log!(
-                    worker,
-                    "sync datastore '{}' from '{}{}'",
-                    sync_job.store,
+
+                let remote_store = format!(
+                    "{}{}",
                     sync_job
                         .remote
                         .as_deref()
@@ -144,7 +191,29 @@ pub fn do_sync_job(
                     sync_job.remote_store,
                 );
 
-                pull_store(&worker, pull_params).await?;
+                match sync_job.sync_direction.unwrap_or_default() {
+                    SyncDirection::Pull => {
+                        let mut pull_params = PullParameters::try_from(&sync_job)?;
+                        pull_params.set_dry_run(dry_run);
+                        task_log!(
+                            worker,
+                            "sync datastore '{}' from '{}'",
+                            sync_job.store,
+                            remote_store,
+                        );
+                        pull_store(&worker, pull_params).await?;
+                    }
+                    SyncDirection::Push => {
+                        let push_params = PushParameters::try_from(&sync_job)?;
+                        task_log!(
+                            worker,
+                            "sync datastore '{}' to '{}'",
+                            sync_job.store,
+                            remote_store,
+                        );
+                        push_store(&worker, push_params).await?;
+                    }
+                }
 
                 task_log!(worker, "sync job '{}' end", &job_id);
 
@@ -160,6 +229,10 @@ pub fn do_sync_job(
                 abort = abort_future => abort,
             };
 
+            if dry_run {
+                return result;
+            }
+
             let status = worker2.create_state(&result);
 
             match job.finish(status) {
@@ -169,12 +242,10 @@ pub fn do_sync_job(
                 }
             }
 
-            if let Some(email) = email {
-                if let Err(err) =
-                    crate::server::send_sync_status(&email, notify, &sync_job2, &result)
-                {
-                    eprintln!("send sync notification failed: {}", err);
-                }
+            if let Err(err) =
+                crate::server::send_sync_status(email.as_deref(), notify, mode, &sync_job2, &result)
+            {
+                eprintln!("send sync notification failed: {}", err);
             }
 
             result
@@ -225,6 +296,30 @@ pub fn do_sync_job(
                 schema: TRANSFER_LAST_SCHEMA,
                 optional: true,
             },
+            "repair-corrupt": {
+                schema: REPAIR_CORRUPT_SCHEMA,
+                optional: true,
+            },
+            "parallel-groups": {
+                schema: SYNC_PARALLEL_GROUPS_SCHEMA,
+                optional: true,
+            },
+            "chunk-concurrency": {
+                schema: SYNC_CHUNK_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            "snapshot-filter": {
+                type: SyncSnapshotFilter,
+                flatten: true,
+            },
+            "rename-rules": {
+                schema: SYNC_RENAME_RULE_LIST_SCHEMA,
+                optional: true,
+            },
+            "dry-run": {
+                schema: SYNC_DRY_RUN_SCHEMA,
+                optional: true,
+            },
         },
     },
     access: {
@@ -249,10 +344,17 @@ async fn pull(
     group_filter: Option<Vec<GroupFilter>>,
     limit: RateLimitConfig,
     transfer_last: Option<usize>,
+    repair_corrupt: Option<bool>,
+    parallel_groups: Option<usize>,
+    chunk_concurrency: Option<usize>,
+    snapshot_filter: SyncSnapshotFilter,
+    rename_rules: Option<Vec<SyncRenameRule>>,
+    dry_run: Option<bool>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let delete = remove_vanished.unwrap_or(false);
+    let dry_run = dry_run.unwrap_or(false);
 
     if remote.is_none() && store == remote_store {
         bail!("can't sync to same datastore");
@@ -274,7 +376,11 @@ async fn pull(
         delete,
     )?;
 
-    let pull_params = PullParameters::new(
+    if delete && !dry_run {
+        check_not_append_only(&auth_id)?;
+    }
+
+    let mut pull_params = PullParameters::new(
         &store,
         ns,
         remote.as_deref(),
@@ -286,7 +392,13 @@ async fn pull(
         group_filter,
         limit,
         transfer_last,
+        repair_corrupt,
+        parallel_groups,
+        chunk_concurrency,
+        snapshot_filter,
+        rename_rules,
     )?;
+    pull_params.set_dry_run(dry_run);
 
     // fixme: set to_stdout to false?
     // FIXME: add namespace to worker id?
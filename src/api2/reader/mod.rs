@@ -323,15 +323,13 @@ fn download_chunk(
             ));
         }
 
-        let (path, _) = env.datastore.chunk_path(&digest);
-        let path2 = path.clone();
+        env.debug(format!("download chunk {}", digest_str));
 
-        env.debug(format!("download chunk {:?}", path));
-
-        let data =
-            proxmox_async::runtime::block_in_place(|| std::fs::read(path)).map_err(move |err| {
-                http_err!(BAD_REQUEST, "reading file {:?} failed: {}", path2, err)
-            })?;
+        let data = env
+            .datastore
+            .read_raw_chunk_data(&digest)
+            .await
+            .map_err(|err| http_err!(BAD_REQUEST, "{}", err))?;
 
         let body = Body::from(data);
 
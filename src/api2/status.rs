@@ -1,15 +1,21 @@
 //! Datastote status
 
 use anyhow::Error;
+use futures::FutureExt;
+use http::request::Parts;
+use http::{header, Response, StatusCode};
+use hyper::Body;
 use serde_json::Value;
 
 use proxmox_router::list_subdirs_api_method;
-use proxmox_router::{ApiMethod, Permission, Router, RpcEnvironment, SubdirMap};
-use proxmox_schema::api;
+use proxmox_router::{
+    ApiHandler, ApiMethod, ApiResponseFuture, Permission, Router, RpcEnvironment, SubdirMap,
+};
+use proxmox_schema::{api, ObjectSchema};
 
 use pbs_api_types::{
     Authid, DataStoreStatusListItem, Operation, RRDMode, RRDTimeFrame, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_BACKUP,
+    PRIV_DATASTORE_BACKUP, PRIV_SYS_AUDIT,
 };
 
 use pbs_config::CachedUserInfo;
@@ -137,10 +143,46 @@ pub async fn datastore_status(
     Ok(list)
 }
 
-const SUBDIRS: SubdirMap = &[(
-    "datastore-usage",
-    &Router::new().get(&API_METHOD_DATASTORE_STATUS),
-)];
+pub const API_METHOD_METRICS: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&metrics),
+    &ObjectSchema::new(
+        "Host, datastore, job and task metrics in the OpenMetrics text format.",
+        &[],
+    ),
+)
+.access(
+    Some("Requires Sys.Audit on /system/status, usually granted to a dedicated API token."),
+    &Permission::Privilege(&["system", "status"], PRIV_SYS_AUDIT, false),
+);
+fn metrics(
+    _parts: Parts,
+    _req_body: Body,
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let data = crate::server::openmetrics::generate_metrics().await?;
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(
+                header::CONTENT_TYPE,
+                crate::server::openmetrics::OPENMETRICS_CONTENT_TYPE,
+            )
+            .body(Body::from(data))
+            .unwrap())
+    }
+    .boxed()
+}
+
+const SUBDIRS: SubdirMap = &[
+    (
+        "datastore-usage",
+        &Router::new().get(&API_METHOD_DATASTORE_STATUS),
+    ),
+    ("metrics", &Router::new().get(&API_METHOD_METRICS)),
+];
 
 pub const ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SUBDIRS))
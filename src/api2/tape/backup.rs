@@ -9,8 +9,8 @@ use proxmox_schema::api;
 use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
 
 use pbs_api_types::{
-    print_ns_and_snapshot, print_store_and_ns, Authid, MediaPoolConfig, Operation,
-    TapeBackupJobConfig, TapeBackupJobSetup, TapeBackupJobStatus, Userid, JOB_ID_SCHEMA,
+    print_ns_and_snapshot, print_store_and_ns, Authid, MediaPoolConfig, NotificationMode,
+    Operation, TapeBackupJobConfig, TapeBackupJobSetup, TapeBackupJobStatus, Userid, JOB_ID_SCHEMA,
     PRIV_DATASTORE_READ, PRIV_TAPE_AUDIT, PRIV_TAPE_WRITE, UPID_SCHEMA,
 };
 
@@ -166,7 +166,12 @@ pub fn do_tape_backup_job(
         .notify_user
         .as_ref()
         .unwrap_or_else(|| Userid::root_userid());
-    let email = lookup_user_email(notify_user);
+    let mode = setup.notification_mode.unwrap_or_default();
+    // in notification system mode, media change requests are routed by the matchers as well
+    let email = match mode {
+        NotificationMode::LegacySendmail => lookup_user_email(notify_user),
+        NotificationMode::NotificationSystem => None,
+    };
 
     let upid_str = WorkerTask::new_thread(
         &worker_type,
@@ -214,16 +219,15 @@ pub fn do_tape_backup_job(
 
             let status = worker.create_state(&job_result);
 
-            if let Some(email) = email {
-                if let Err(err) = crate::server::send_tape_backup_status(
-                    &email,
-                    Some(job.jobname()),
-                    &setup,
-                    &job_result,
-                    summary,
-                ) {
-                    eprintln!("send tape backup notification failed: {}", err);
-                }
+            if let Err(err) = crate::server::send_tape_backup_status(
+                email.as_deref(),
+                mode,
+                Some(job.jobname()),
+                &setup,
+                &job_result,
+                summary,
+            ) {
+                eprintln!("send tape backup notification failed: {}", err);
             }
 
             if let Err(err) = job.finish(status) {
@@ -332,7 +336,12 @@ pub fn backup(
         .notify_user
         .as_ref()
         .unwrap_or_else(|| Userid::root_userid());
-    let email = lookup_user_email(notify_user);
+    let mode = setup.notification_mode.unwrap_or_default();
+    // in notification system mode, media change requests are routed by the matchers as well
+    let email = match mode {
+        NotificationMode::LegacySendmail => lookup_user_email(notify_user),
+        NotificationMode::NotificationSystem => None,
+    };
 
     let upid_str = WorkerTask::new_thread(
         "tape-backup",
@@ -354,16 +363,15 @@ pub fn backup(
                 force_media_set,
             );
 
-            if let Some(email) = email {
-                if let Err(err) = crate::server::send_tape_backup_status(
-                    &email,
-                    None,
-                    &setup,
-                    &job_result,
-                    summary,
-                ) {
-                    eprintln!("send tape backup notification failed: {}", err);
-                }
+            if let Err(err) = crate::server::send_tape_backup_status(
+                email.as_deref(),
+                mode,
+                None,
+                &setup,
+                &job_result,
+                summary,
+            ) {
+                eprintln!("send tape backup notification failed: {}", err);
             }
 
             // ignore errors
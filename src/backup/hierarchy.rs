@@ -68,6 +68,28 @@ pub fn check_ns_privs_full(
     );
 }
 
+/// Asserts that `auth_id` is not restricted to appending new backups.
+///
+/// Append-only API tokens may create and read backups, but must not remove or alter existing
+/// ones, regardless of their privileges.
+pub fn check_not_append_only(auth_id: &Authid) -> Result<(), Error> {
+    check_user_not_append_only(&CachedUserInfo::new()?, auth_id)
+}
+
+/// Like [`check_not_append_only`], but uses the passed `user_info`.
+pub fn check_user_not_append_only(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+) -> Result<(), Error> {
+    if user_info.is_append_only(auth_id) {
+        proxmox_router::http_bail!(
+            FORBIDDEN,
+            "permission check failed - API token '{auth_id}' is append-only"
+        );
+    }
+    Ok(())
+}
+
 pub fn can_access_any_namespace(
     store: Arc<DataStore>,
     auth_id: &Authid,
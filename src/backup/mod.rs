@@ -6,5 +6,8 @@ pub const CATALOG_NAME: &str = "catalog.pcat1.didx";
 mod verify;
 pub use verify::*;
 
+mod scrub;
+pub use scrub::*;
+
 mod hierarchy;
 pub use hierarchy::*;
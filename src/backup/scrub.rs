@@ -0,0 +1,240 @@
+//! Chunk store scrubbing
+//!
+//! Unlike verification, scrubbing does not look at snapshots at all. It walks the whole chunk
+//! store and checks every chunk file on its own, which also covers chunks that are only
+//! referenced by snapshots verified a long time ago.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{bail, format_err, Error};
+use hex::FromHex;
+
+use proxmox_http::{RateLimit, RateLimiter};
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::{CryptMode, ScrubStatus};
+use pbs_datastore::{DataBlob, DataStore, DatastoreBackend};
+
+use super::verify::{rename_corrupted_chunk, repair_corrupted_chunk};
+
+/// Name of the file in the datastore base directory holding the last scrub status.
+pub const SCRUB_STATUS_FILENAME: &str = ".scrub-status";
+
+/// Load the status of the last scrub run of `datastore`.
+pub fn load_scrub_status(datastore: &DataStore) -> Result<ScrubStatus, Error> {
+    let path = datastore.base_path().join(SCRUB_STATUS_FILENAME);
+    match file_read_optional_string(path)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(ScrubStatus::default()),
+    }
+}
+
+fn save_scrub_status(datastore: &DataStore, status: &ScrubStatus) -> Result<(), Error> {
+    let path = datastore.base_path().join(SCRUB_STATUS_FILENAME);
+    let data = serde_json::to_vec(status)?;
+
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(path, &data, options, false)
+}
+
+/// Check a single chunk, returns whether the chunk is encrypted.
+///
+/// The CRC is checked for all chunks, unencrypted chunks are decoded and their digest is
+/// compared to the file name.
+fn check_chunk(blob: &DataBlob, digest: &[u8; 32]) -> Result<bool, Error> {
+    blob.verify_crc()?;
+
+    match blob.crypt_mode()? {
+        CryptMode::Encrypt => Ok(true),
+        CryptMode::None => {
+            blob.decode(None, Some(digest))?;
+            Ok(false)
+        }
+        CryptMode::SignOnly => bail!("invalid CryptMode for chunk"),
+    }
+}
+
+/// Make sure the mirror copy of a good chunk exists and is intact, returns true if it had to be
+/// written again.
+fn check_mirror_copy(
+    datastore: &DataStore,
+    chunk: &DataBlob,
+    digest: &[u8; 32],
+    worker: &dyn WorkerTaskContext,
+) -> Result<bool, Error> {
+    let digest_str = hex::encode(digest);
+    match datastore.load_mirror_chunk(digest) {
+        Ok(Some(copy)) => match check_chunk(&copy, digest) {
+            Ok(_) => return Ok(false),
+            Err(err) => task_warn!(
+                worker,
+                "mirror copy of chunk {digest_str} is corrupt - {err}"
+            ),
+        },
+        Ok(None) => task_log!(worker, "mirror copy of chunk {digest_str} is missing"),
+        Err(err) => task_warn!(worker, "{err}"),
+    }
+    datastore.restore_mirror_chunk(chunk, digest)?;
+    Ok(true)
+}
+
+/// Check all chunks of the chunk store.
+///
+/// Corrupt chunks are replaced by their mirror copy if the datastore has one, otherwise they
+/// are renamed to `<digest>.<n>.bad`. Missing or corrupt mirror copies of good chunks are
+/// written again.
+///
+/// Reading is throttled to the `scrub-rate-limit` of the datastore, if set. Fails if chunks
+/// could not be repaired, the status is saved in any case.
+pub fn scrub_chunk_store(
+    worker: Arc<dyn WorkerTaskContext>,
+    datastore: Arc<DataStore>,
+    upid: Option<String>,
+) -> Result<ScrubStatus, Error> {
+    let mut status = ScrubStatus {
+        upid,
+        ..Default::default()
+    };
+
+    let remote_backend = matches!(datastore.backend(), DatastoreBackend::S3(_));
+
+    let mut limiter = datastore
+        .scrub_rate_limit()
+        .map(|rate| RateLimiter::new(rate, rate));
+    if let Some(rate) = datastore.scrub_rate_limit() {
+        task_log!(worker, "read rate limited to {rate} bytes/s");
+    }
+
+    let start_time = Instant::now();
+    let mut last_percentage = 0;
+
+    for (entry, percentage, bad) in datastore.get_chunk_iterator()? {
+        worker.check_abort()?;
+        worker.fail_on_shutdown()?;
+
+        let entry = entry.map_err(|err| {
+            format_err!(
+                "chunk iterator on datastore '{}' failed - {err}",
+                datastore.name()
+            )
+        })?;
+
+        if last_percentage != percentage {
+            last_percentage = percentage;
+            task_log!(
+                worker,
+                "processed {percentage}% ({} chunks)",
+                status.checked_chunks
+            );
+        }
+
+        if bad {
+            status.bad_chunks += 1;
+            continue;
+        }
+
+        let name = entry.file_name().to_bytes();
+        let digest = <[u8; 32]>::from_hex(&name[..64])?;
+        let (path, digest_str) = datastore.chunk_path(&digest);
+
+        // the chunk might have been removed by GC in the meantime
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                task_warn!(worker, "unable to read chunk {digest_str} - {err}");
+                status.skipped_chunks += 1;
+                continue;
+            }
+        };
+
+        if data.is_empty() && remote_backend {
+            // marker of a chunk stored on the remote backend
+            status.skipped_chunks += 1;
+            continue;
+        }
+
+        if let Some(ref mut limiter) = limiter {
+            // the mirror copy gets read as well
+            let copies = if datastore.has_chunk_mirror() { 2 } else { 1 };
+            let delay = limiter.register_traffic(Instant::now(), copies * data.len() as u64);
+            if !delay.is_zero() {
+                std::thread::sleep(delay);
+            }
+        }
+
+        status.checked_chunks += 1;
+        status.checked_bytes += data.len() as u64;
+
+        let result = DataBlob::from_raw(data).and_then(|blob| {
+            let encrypted = check_chunk(&blob, &digest)?;
+            Ok((blob, encrypted))
+        });
+
+        match result {
+            Ok((blob, encrypted)) => {
+                if encrypted {
+                    status.encrypted_chunks += 1;
+                }
+                if datastore.has_chunk_mirror() {
+                    match check_mirror_copy(&datastore, &blob, &digest, &*worker) {
+                        Ok(true) => status.repaired_mirror_copies += 1,
+                        Ok(false) => (),
+                        Err(err) => task_warn!(worker, "{err}"),
+                    }
+                }
+            }
+            Err(err) => {
+                task_warn!(worker, "chunk {digest_str} is corrupt - {err}");
+                let check = |copy: &DataBlob| check_chunk(copy, &digest).map(|_| ());
+                if repair_corrupted_chunk(&datastore, &digest, &check, &*worker) {
+                    status.repaired_chunks += 1;
+                } else {
+                    status.corrupt_chunks += 1;
+                    rename_corrupted_chunk(datastore.clone(), &digest, &*worker);
+                }
+            }
+        }
+    }
+
+    let elapsed = start_time.elapsed().as_secs_f64();
+    let read_mib = (status.checked_bytes as f64) / (1024.0 * 1024.0);
+
+    task_log!(
+        worker,
+        "checked {} chunks ({read_mib:.2} MiB) in {elapsed:.2} seconds, speed {:.2} MiB/s",
+        status.checked_chunks,
+        read_mib / elapsed,
+    );
+    task_log!(
+        worker,
+        "{} encrypted (CRC only), {} skipped, {} already marked bad",
+        status.encrypted_chunks,
+        status.skipped_chunks,
+        status.bad_chunks,
+    );
+    if datastore.has_chunk_mirror() {
+        task_log!(
+            worker,
+            "{} chunks repaired from mirror, {} mirror copies written again",
+            status.repaired_chunks,
+            status.repaired_mirror_copies,
+        );
+    }
+
+    save_scrub_status(&datastore, &status)?;
+
+    if status.corrupt_chunks > 0 {
+        bail!("found {} corrupt chunks", status.corrupt_chunks);
+    }
+
+    Ok(status)
+}
@@ -69,7 +69,7 @@ fn verify_blob(backup_dir: &BackupDir, info: &FileInfo) -> Result<(), Error> {
     }
 }
 
-fn rename_corrupted_chunk(
+pub(crate) fn rename_corrupted_chunk(
     datastore: Arc<DataStore>,
     digest: &[u8; 32],
     worker: &dyn WorkerTaskContext,
@@ -105,6 +105,37 @@ fn rename_corrupted_chunk(
     };
 }
 
+/// Try to replace a corrupt chunk by its mirror copy, which has to pass `check`.
+///
+/// Returns true if the chunk got repaired.
+pub(crate) fn repair_corrupted_chunk(
+    datastore: &DataStore,
+    digest: &[u8; 32],
+    check: &dyn Fn(&DataBlob) -> Result<(), Error>,
+    worker: &dyn WorkerTaskContext,
+) -> bool {
+    match datastore.repair_chunk(digest, check) {
+        Ok(true) => {
+            task_log!(
+                worker,
+                "repaired chunk {} from mirror copy",
+                hex::encode(digest)
+            );
+            true
+        }
+        Ok(false) => false,
+        Err(err) => {
+            task_log!(
+                worker,
+                "could not repair chunk {} - {}",
+                hex::encode(digest),
+                err
+            );
+            false
+        }
+    }
+}
+
 fn verify_index_chunks(
     verify_worker: &VerifyWorker,
     index: Box<dyn IndexFile + Send>,
@@ -148,8 +179,13 @@ fn verify_index_chunks(
             }
 
             if let Err(err) = chunk.verify_unencrypted(size as usize, &digest) {
-                corrupt_chunks2.lock().unwrap().insert(digest);
                 task_log!(worker2, "{}", err);
+                let check = |copy: &DataBlob| copy.verify_unencrypted(size as usize, &digest);
+                if repair_corrupted_chunk(&datastore2, &digest, &check, &*worker2) {
+                    verified_chunks2.lock().unwrap().insert(digest);
+                    return Ok(());
+                }
+                corrupt_chunks2.lock().unwrap().insert(digest);
                 errors2.fetch_add(1, Ordering::SeqCst);
                 rename_corrupted_chunk(datastore2.clone(), &digest, &worker2);
             } else {
@@ -213,16 +249,31 @@ fn verify_index_chunks(
 
         match verify_worker.datastore.load_chunk(&info.digest) {
             Err(err) => {
-                verify_worker
-                    .corrupt_chunks
-                    .lock()
-                    .unwrap()
-                    .insert(info.digest);
                 task_log!(
                     verify_worker.worker,
                     "can't verify chunk, load failed - {}",
                     err
                 );
+                let size = info.size();
+                let check = |copy: &DataBlob| copy.verify_unencrypted(size as usize, &info.digest);
+                if repair_corrupted_chunk(
+                    &verify_worker.datastore,
+                    &info.digest,
+                    &check,
+                    &*verify_worker.worker,
+                ) {
+                    verify_worker
+                        .verified_chunks
+                        .lock()
+                        .unwrap()
+                        .insert(info.digest);
+                    continue;
+                }
+                verify_worker
+                    .corrupt_chunks
+                    .lock()
+                    .unwrap()
+                    .insert(info.digest);
                 errors.fetch_add(1, Ordering::SeqCst);
                 rename_corrupted_chunk(
                     verify_worker.datastore.clone(),
@@ -338,6 +389,17 @@ pub fn verify_backup_dir(
         return Ok(true);
     }
 
+    // an incomplete snapshot cannot be restored, so don't report it as verified
+    if backup_dir.is_partially_pulled() {
+        task_log!(
+            verify_worker.worker,
+            "verify {}:{} failed: snapshot is only partially synced",
+            verify_worker.datastore.name(),
+            backup_dir.dir(),
+        );
+        return Ok(false);
+    }
+
     let snap_lock = lock_dir_noblock_shared(
         &backup_dir.full_path(),
         "snapshot",
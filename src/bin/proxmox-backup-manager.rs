@@ -6,18 +6,21 @@ use anyhow::{format_err, Error};
 use serde_json::{json, Value};
 
 use proxmox_router::{cli::*, RpcEnvironment};
-use proxmox_schema::api;
+use proxmox_schema::{api, ApiType, ArraySchema, ReturnType};
 use proxmox_sys::fs::CreateOptions;
 
 use pbs_api_types::percent_encoding::percent_encode_component;
 use pbs_api_types::{
-    BackupNamespace, GroupFilter, RateLimitConfig, SyncJobConfig, DATASTORE_SCHEMA,
-    GROUP_FILTER_LIST_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA, NS_MAX_DEPTH_SCHEMA,
-    REMOTE_ID_SCHEMA, REMOVE_VANISHED_BACKUPS_SCHEMA, TRANSFER_LAST_SCHEMA, UPID_SCHEMA,
-    VERIFICATION_OUTDATED_AFTER_SCHEMA,
+    BackupNamespace, GroupFilter, NamespaceSpaceUsage, RateLimitConfig, SyncJobConfig,
+    SyncRenameRule, SyncSnapshotFilter, DATASTORE_SCHEMA, GROUP_FILTER_LIST_SCHEMA,
+    IGNORE_VERIFIED_BACKUPS_SCHEMA, NS_MAX_DEPTH_SCHEMA, REMOTE_ID_SCHEMA,
+    REMOVE_VANISHED_BACKUPS_SCHEMA, REPAIR_CORRUPT_SCHEMA, SYNC_CHUNK_CONCURRENCY_SCHEMA,
+    SYNC_DRY_RUN_SCHEMA, SYNC_PARALLEL_GROUPS_SCHEMA, SYNC_RENAME_RULE_LIST_SCHEMA,
+    TRANSFER_LAST_SCHEMA, UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
 };
 use pbs_client::{display_task_log, view_task_result};
 use pbs_config::sync;
+use pbs_tools::format::render_bytes_human_readable;
 use pbs_tools::json::required_string_param;
 
 use proxmox_rest_server::wait_for_local_worker;
@@ -111,6 +114,202 @@ fn garbage_collection_commands() -> CommandLineInterface {
     cmd_def.into()
 }
 
+#[api(
+   input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// Start checking all chunks of a specific datastore.
+async fn start_scrub(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/scrub", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+   input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// Show the result of the last chunk store scrub of a specific datastore.
+async fn scrub_status(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/scrub", store);
+
+    let mut result = client.get(&path, None).await?;
+    let mut data = result["data"].take();
+    let return_type = &api2::admin::datastore::API_METHOD_SCRUB_STATUS.returns;
+
+    let options = default_table_format_options();
+
+    format_and_print_result_full(&mut data, return_type, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+fn scrub_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert(
+            "status",
+            CliCommand::new(&API_METHOD_SCRUB_STATUS)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "start",
+            CliCommand::new(&API_METHOD_START_SCRUB)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        );
+
+    cmd_def.into()
+}
+
+#[api(
+   input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// Start space accounting for a specific datastore.
+async fn start_space_accounting(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/space-accounting", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+const NAMESPACE_SPACE_USAGE_RETURN_TYPE: ReturnType = ReturnType {
+    optional: false,
+    schema: &ArraySchema::new(
+        "Space usage per namespace.",
+        &NamespaceSpaceUsage::API_SCHEMA,
+    )
+    .schema(),
+};
+
+#[api(
+   input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// Show the result of the last space accounting run for a specific datastore.
+async fn space_accounting_status(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/space-accounting", store);
+
+    let mut result = client.get(&path, None).await?;
+    let mut data = result["data"].take();
+
+    if output_format != "text" {
+        format_and_print_result(&data, &output_format);
+        return Ok(Value::Null);
+    }
+
+    match data["time"].as_i64() {
+        Some(time) if time > 0 => {
+            println!("Last run: {}", proxmox_time::epoch_to_rfc3339(time)?)
+        }
+        _ => {
+            println!("Space accounting never ran on datastore '{store}'.");
+            return Ok(Value::Null);
+        }
+    }
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("ns"))
+        .column(ColumnConfig::new("referenced-size").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("unique-size").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("shared-size").renderer(render_bytes_human_readable));
+
+    format_and_print_result_full(
+        &mut data["namespaces"],
+        &NAMESPACE_SPACE_USAGE_RETURN_TYPE,
+        &output_format,
+        &options,
+    );
+
+    Ok(Value::Null)
+}
+
+fn space_accounting_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert(
+            "status",
+            CliCommand::new(&API_METHOD_SPACE_ACCOUNTING_STATUS)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "start",
+            CliCommand::new(&API_METHOD_START_SPACE_ACCOUNTING)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        );
+
+    cmd_def.into()
+}
+
 #[api(
     input: {
         properties: {
@@ -276,6 +475,30 @@ fn task_mgmt_cli() -> CommandLineInterface {
                 schema: TRANSFER_LAST_SCHEMA,
                 optional: true,
             },
+            "repair-corrupt": {
+                schema: REPAIR_CORRUPT_SCHEMA,
+                optional: true,
+            },
+            "parallel-groups": {
+                schema: SYNC_PARALLEL_GROUPS_SCHEMA,
+                optional: true,
+            },
+            "chunk-concurrency": {
+                schema: SYNC_CHUNK_CONCURRENCY_SCHEMA, SYNC_DRY_RUN_SCHEMA,
+                optional: true,
+            },
+            "snapshot-filter": {
+                type: SyncSnapshotFilter,
+                flatten: true,
+            },
+            "rename-rules": {
+                schema: SYNC_RENAME_RULE_LIST_SCHEMA,
+                optional: true,
+            },
+            "dry-run": {
+                schema: SYNC_DRY_RUN_SCHEMA,
+                optional: true,
+            },
         }
    }
 )]
@@ -292,6 +515,12 @@ async fn pull_datastore(
     group_filter: Option<Vec<GroupFilter>>,
     limit: RateLimitConfig,
     transfer_last: Option<usize>,
+    repair_corrupt: Option<bool>,
+    parallel_groups: Option<usize>,
+    chunk_concurrency: Option<usize>,
+    snapshot_filter: SyncSnapshotFilter,
+    rename_rules: Option<Vec<SyncRenameRule>>,
+    dry_run: Option<bool>,
     param: Value,
 ) -> Result<Value, Error> {
     let output_format = get_output_format(&param);
@@ -328,6 +557,26 @@ async fn pull_datastore(
         args["transfer-last"] = json!(transfer_last)
     }
 
+    if let Some(repair_corrupt) = repair_corrupt {
+        args["repair-corrupt"] = Value::from(repair_corrupt);
+    }
+
+    if parallel_groups.is_some() {
+        args["parallel-groups"] = json!(parallel_groups);
+    }
+
+    if chunk_concurrency.is_some() {
+        args["chunk-concurrency"] = json!(chunk_concurrency);
+    }
+
+    if rename_rules.is_some() {
+        args["rename-rules"] = json!(rename_rules);
+    }
+
+    if let Some(dry_run) = dry_run {
+        args["dry-run"] = Value::from(dry_run);
+    }
+
     let mut limit_json = json!(limit);
     let limit_map = limit_json
         .as_object_mut()
@@ -335,6 +584,13 @@ async fn pull_datastore(
 
     args.as_object_mut().unwrap().append(limit_map);
 
+    let mut filter_json = json!(snapshot_filter);
+    let filter_map = filter_json
+        .as_object_mut()
+        .ok_or_else(|| format_err!("snapshot filter is not an Object"))?;
+
+    args.as_object_mut().unwrap().append(filter_map);
+
     let result = client.post("api2/json/pull", Some(args)).await?;
 
     view_task_result(&client, result, &output_format).await?;
@@ -436,20 +692,27 @@ async fn run() -> Result<(), Error> {
         .insert("datastore", datastore_commands())
         .insert("disk", disk_commands())
         .insert("dns", dns_commands())
+        .insert("group", group_commands())
         .insert("ldap", ldap_commands())
         .insert("network", network_commands())
         .insert("node", node_commands())
+        .insert("notification", notification_commands())
         .insert("user", user_commands())
         .insert("openid", openid_commands())
         .insert("remote", remote_commands())
+        .insert("role", role_commands())
+        .insert("s3", s3_commands())
         .insert("traffic-control", traffic_control_commands())
         .insert("garbage-collection", garbage_collection_commands())
+        .insert("scrub", scrub_commands())
+        .insert("space-accounting", space_accounting_commands())
         .insert("acme", acme_mgmt_cli())
         .insert("cert", cert_mgmt_cli())
         .insert("subscription", subscription_commands())
         .insert("sync-job", sync_job_commands())
         .insert("verify-job", verify_job_commands())
         .insert("prune-job", prune_job_commands())
+        .insert("quota", quota_commands())
         .insert("task", task_mgmt_cli())
         .insert(
             "pull",
@@ -20,7 +20,7 @@ use proxmox_sys::linux::procfs::{Loadavg, ProcFsMemInfo, ProcFsNetDev, ProcFsSta
 use proxmox_sys::logrotate::LogRotate;
 use proxmox_sys::{task_log, task_warn};
 
-use pbs_datastore::DataStore;
+use pbs_datastore::{get_datastore_mount_status, DataStore};
 
 use proxmox_rest_server::{
     cleanup_old_tasks, cookie_from_header, rotate_task_log_archive, ApiConfig, Redirector,
@@ -59,6 +59,7 @@ use proxmox_backup::tools::{
 use proxmox_backup::api2::pull::do_sync_job;
 use proxmox_backup::api2::tape::backup::do_tape_backup_job;
 use proxmox_backup::server::do_prune_job;
+use proxmox_backup::server::do_scrub_job;
 use proxmox_backup::server::do_verification_job;
 
 fn main() -> Result<(), Error> {
@@ -449,6 +450,7 @@ async fn run_task_scheduler() {
 
 async fn schedule_tasks() -> Result<(), Error> {
     schedule_datastore_garbage_collection().await;
+    schedule_datastore_scrub_jobs().await;
     schedule_datastore_prune_jobs().await;
     schedule_datastore_sync_jobs().await;
     schedule_datastore_verify_jobs().await;
@@ -481,6 +483,10 @@ async fn schedule_datastore_garbage_collection() {
             None => continue,
         };
 
+        if get_datastore_mount_status(&store_config) == Some(false) {
+            continue;
+        }
+
         let event: CalendarEvent = match event_str.parse() {
             Ok(event) => event,
             Err(err) => {
@@ -556,6 +562,59 @@ async fn schedule_datastore_garbage_collection() {
     }
 }
 
+async fn schedule_datastore_scrub_jobs() {
+    let config = match pbs_config::datastore::config() {
+        Err(err) => {
+            eprintln!("unable to read datastore config - {err}");
+            return;
+        }
+        Ok((config, _digest)) => config,
+    };
+
+    for (store, (_, store_config)) in config.sections {
+        let store_config: DataStoreConfig = match serde_json::from_value(store_config) {
+            Ok(c) => c,
+            Err(err) => {
+                eprintln!("datastore config from_value failed - {err}");
+                continue;
+            }
+        };
+
+        let event_str = match store_config.scrub_schedule {
+            Some(event_str) => event_str,
+            None => continue,
+        };
+
+        if get_datastore_mount_status(&store_config) == Some(false) {
+            continue;
+        }
+
+        let worker_type = "scrub";
+        if !check_schedule(worker_type, &event_str, &store) {
+            continue;
+        }
+
+        let job = match Job::new(worker_type, &store) {
+            Ok(job) => job,
+            Err(_) => continue, // could not get lock
+        };
+
+        let datastore = match DataStore::lookup_datastore(&store, Some(Operation::Read)) {
+            Ok(datastore) => datastore,
+            Err(err) => {
+                log::warn!("skipping scheduled scrub on {store}, could not look it up - {err}");
+                continue;
+            }
+        };
+
+        let auth_id = Authid::root_auth_id();
+
+        if let Err(err) = do_scrub_job(job, datastore, auth_id, Some(event_str), false) {
+            eprintln!("unable to start scrub job on datastore {store} - {err}");
+        }
+    }
+}
+
 async fn schedule_datastore_prune_jobs() {
     let config = match pbs_config::prune::config() {
         Err(err) => {
@@ -581,6 +640,10 @@ async fn schedule_datastore_prune_jobs() {
             continue; // no 'keep' values set, keep all
         }
 
+        if datastore_is_unmounted(&job_config.store) {
+            continue;
+        }
+
         let worker_type = "prunejob";
         let auth_id = Authid::root_auth_id().clone();
         if check_schedule(worker_type, &job_config.schedule, &job_id) {
@@ -624,6 +687,10 @@ async fn schedule_datastore_sync_jobs() {
             None => continue,
         };
 
+        if datastore_is_unmounted(&job_config.store) {
+            continue;
+        }
+
         let worker_type = "syncjob";
         if check_schedule(worker_type, &event_str, &job_id) {
             let job = match Job::new(worker_type, &job_id) {
@@ -632,7 +699,8 @@ async fn schedule_datastore_sync_jobs() {
             };
 
             let auth_id = Authid::root_auth_id().clone();
-            if let Err(err) = do_sync_job(job, job_config, &auth_id, Some(event_str), false) {
+            if let Err(err) = do_sync_job(job, job_config, &auth_id, Some(event_str), false, false)
+            {
                 eprintln!("unable to start datastore sync job {job_id} - {err}");
             }
         };
@@ -660,6 +728,10 @@ async fn schedule_datastore_verify_jobs() {
             None => continue,
         };
 
+        if datastore_is_unmounted(&job_config.store) {
+            continue;
+        }
+
         let worker_type = "verificationjob";
         let auth_id = Authid::root_auth_id().clone();
         if check_schedule(worker_type, &event_str, &job_id) {
@@ -696,6 +768,10 @@ async fn schedule_tape_backup_jobs() {
             None => continue,
         };
 
+        if datastore_is_unmounted(&job_config.setup.store) {
+            continue;
+        }
+
         let worker_type = "tape-backup-job";
         let auth_id = Authid::root_auth_id().clone();
         if check_schedule(worker_type, &event_str, &job_id) {
@@ -1133,8 +1209,11 @@ fn collect_disk_stats_sync() -> (DiskStat, Vec<DiskStat>) {
                 {
                     continue;
                 }
-                let path = std::path::Path::new(&config.path);
-                datastores.push(gather_disk_stats(disk_manager.clone(), path, &config.name));
+                if get_datastore_mount_status(&config) == Some(false) {
+                    continue;
+                }
+                let path = config.absolute_path();
+                datastores.push(gather_disk_stats(disk_manager.clone(), &path, &config.name));
             }
         }
         Err(err) => {
@@ -1211,6 +1290,17 @@ fn rrd_update_disk_stat(disk: &DiskStat, rrd_prefix: &str) {
     }
 }
 
+/// Returns true for removable datastores which are currently not mounted. Scheduled jobs on such a
+/// datastore are not started, so they will run as soon as the device is available again.
+fn datastore_is_unmounted(store: &str) -> bool {
+    match pbs_config::datastore::config()
+        .and_then(|(config, _digest)| config.lookup::<DataStoreConfig>("datastore", store))
+    {
+        Ok(config) => get_datastore_mount_status(&config) == Some(false),
+        Err(_) => false,
+    }
+}
+
 fn check_schedule(worker_type: &str, event_str: &str, id: &str) -> bool {
     let event: CalendarEvent = match event_str.parse() {
         Ok(event) => event,
@@ -17,8 +17,9 @@ use proxmox_schema::api;
 use pbs_client::tools::key_source::get_encryption_key_password;
 use pbs_datastore::dynamic_index::DynamicIndexReader;
 use pbs_datastore::file_formats::{
-    COMPRESSED_BLOB_MAGIC_1_0, DYNAMIC_SIZED_CHUNK_INDEX_1_0, ENCRYPTED_BLOB_MAGIC_1_0,
-    ENCR_COMPR_BLOB_MAGIC_1_0, FIXED_SIZED_CHUNK_INDEX_1_0, UNCOMPRESSED_BLOB_MAGIC_1_0,
+    COMPRESSED_BLOB_MAGIC_1_0, COMPRESSED_BLOB_MAGIC_2_0, DYNAMIC_SIZED_CHUNK_INDEX_1_0,
+    ENCRYPTED_BLOB_MAGIC_1_0, ENCR_COMPR_BLOB_MAGIC_1_0, ENCR_COMPR_BLOB_MAGIC_2_0,
+    FIXED_SIZED_CHUNK_INDEX_1_0, UNCOMPRESSED_BLOB_MAGIC_1_0,
 };
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
@@ -260,7 +261,9 @@ fn inspect_file(
         UNCOMPRESSED_BLOB_MAGIC_1_0
         | COMPRESSED_BLOB_MAGIC_1_0
         | ENCRYPTED_BLOB_MAGIC_1_0
-        | ENCR_COMPR_BLOB_MAGIC_1_0 => {
+        | ENCR_COMPR_BLOB_MAGIC_1_0
+        | COMPRESSED_BLOB_MAGIC_2_0
+        | ENCR_COMPR_BLOB_MAGIC_2_0 => {
             let data_blob = DataBlob::load_from_reader(&mut file)?;
             let key_file_path = keyfile.as_ref().map(Path::new);
 
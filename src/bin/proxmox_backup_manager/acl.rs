@@ -61,6 +61,8 @@ pub fn acl_commands() -> CommandLineInterface {
             CliCommand::new(&api2::access::acl::API_METHOD_UPDATE_ACL)
                 .arg_param(&["path", "role"])
                 .completion_cb("auth-id", pbs_config::user::complete_authid)
+                .completion_cb("group", pbs_config::user::complete_group_id)
+                .completion_cb("role", pbs_config::role::complete_any_role_id)
                 .completion_cb("path", pbs_config::datastore::complete_acl_path),
         );
 
@@ -6,6 +6,7 @@ use proxmox_schema::api;
 
 use pbs_api_types::{DataStoreConfig, DATASTORE_SCHEMA, PROXMOX_CONFIG_DIGEST_SCHEMA};
 use pbs_client::view_task_result;
+use pbs_tools::json::required_string_param;
 
 use proxmox_backup::api2;
 use proxmox_backup::client_helpers::connect_to_localhost;
@@ -139,6 +140,96 @@ async fn delete_datastore(mut param: Value, rpcenv: &mut dyn RpcEnvironment) ->
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Refresh the local cache of a datastore from its S3 backend.
+async fn s3_refresh(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/s3-refresh", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Mount the backing device of a removable datastore.
+async fn mount_datastore(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/mount", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Unmount a removable datastore, waits for running operations to finish.
+async fn unmount_datastore(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let store = required_string_param(&param, "store")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/unmount", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
 pub fn datastore_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_DATASTORES))
@@ -164,6 +255,10 @@ pub fn datastore_commands() -> CommandLineInterface {
                 .completion_cb(
                     "prune-schedule",
                     pbs_config::datastore::complete_calendar_event,
+                )
+                .completion_cb(
+                    "scrub-schedule",
+                    pbs_config::datastore::complete_calendar_event,
                 ),
         )
         .insert(
@@ -171,6 +266,24 @@ pub fn datastore_commands() -> CommandLineInterface {
             CliCommand::new(&API_METHOD_DELETE_DATASTORE)
                 .arg_param(&["name"])
                 .completion_cb("name", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "mount",
+            CliCommand::new(&API_METHOD_MOUNT_DATASTORE)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "unmount",
+            CliCommand::new(&API_METHOD_UNMOUNT_DATASTORE)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "s3-refresh",
+            CliCommand::new(&API_METHOD_S3_REFRESH)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
         );
 
     cmd_def.into()
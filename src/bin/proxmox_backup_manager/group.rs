@@ -0,0 +1,73 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
+use proxmox_schema::api;
+
+use proxmox_backup::api2;
+
+fn render_members(value: &Value, _record: &Value) -> Result<String, Error> {
+    let members: Vec<&str> = value
+        .as_array()
+        .map(|list| list.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    Ok(members.join(", "))
+}
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// List configured groups.
+fn list_groups(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::access::group::API_METHOD_LIST_GROUPS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("groupid"))
+        .column(ColumnConfig::new("members").renderer(render_members))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+pub fn group_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_GROUPS))
+        .insert(
+            "create",
+            CliCommand::new(&api2::access::group::API_METHOD_CREATE_GROUP)
+                .arg_param(&["groupid"])
+                .completion_cb("members", pbs_config::user::complete_userid),
+        )
+        .insert(
+            "update",
+            CliCommand::new(&api2::access::group::API_METHOD_UPDATE_GROUP)
+                .arg_param(&["groupid"])
+                .completion_cb("groupid", pbs_config::user::complete_group_id)
+                .completion_cb("members", pbs_config::user::complete_userid)
+                .completion_cb("add-members", pbs_config::user::complete_userid)
+                .completion_cb("remove-members", pbs_config::user::complete_userid),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(&api2::access::group::API_METHOD_DELETE_GROUP)
+                .arg_param(&["groupid"])
+                .completion_cb("groupid", pbs_config::user::complete_group_id),
+        );
+
+    cmd_def.into()
+}
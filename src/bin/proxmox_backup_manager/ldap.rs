@@ -6,7 +6,9 @@ use serde_json::Value;
 use proxmox_router::{cli::*, ApiHandler, Permission, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::{Realm, PRIV_PERMISSIONS_MODIFY, REALM_ID_SCHEMA, REMOVE_VANISHED_SCHEMA};
+use pbs_api_types::{
+    Realm, SyncScope, PRIV_PERMISSIONS_MODIFY, REALM_ID_SCHEMA, REMOVE_VANISHED_SCHEMA,
+};
 
 use proxmox_backup::{api2, client_helpers::connect_to_localhost};
 
@@ -90,7 +92,11 @@ fn show_ldap_realm(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Valu
                 description: "Enable newly synced users immediately",
                 optional: true,
                 type: bool,
-            }
+            },
+            scope: {
+                type: SyncScope,
+                optional: true,
+            },
          },
     },
     access: {
@@ -8,14 +8,24 @@ mod datastore;
 pub use datastore::*;
 mod dns;
 pub use dns::*;
+mod group;
+pub use group::*;
 mod ldap;
 pub use ldap::*;
 mod network;
 pub use network::*;
+mod notifications;
+pub use notifications::*;
 mod prune;
 pub use prune::*;
+mod quota;
+pub use quota::*;
 mod remote;
 pub use remote::*;
+mod role;
+pub use role::*;
+mod s3;
+pub use s3::*;
 mod sync;
 pub use sync::*;
 mod verify;
@@ -0,0 +1,176 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
+use proxmox_schema::api;
+
+use pbs_config::notifications::{complete_matcher_name, complete_target_name};
+
+use proxmox_backup::api2::config::notifications as notification_api;
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// List all notification targets.
+fn list_targets(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &notification_api::API_METHOD_LIST_TARGETS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("name"))
+        .column(ColumnConfig::new("type"))
+        .column(ColumnConfig::new("enable"))
+        .column(ColumnConfig::new("server"))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// List notification matchers.
+fn list_matchers(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &notification_api::matchers::API_METHOD_LIST_MATCHERS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("name"))
+        .column(ColumnConfig::new("enable"))
+        .column(ColumnConfig::new("match-severity"))
+        .column(ColumnConfig::new("match-field"))
+        .column(ColumnConfig::new("target"))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+fn target_type_commands(
+    create: &'static proxmox_router::ApiMethod,
+    read: &'static proxmox_router::ApiMethod,
+    update: &'static proxmox_router::ApiMethod,
+    delete: &'static proxmox_router::ApiMethod,
+) -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert("create", CliCommand::new(create).arg_param(&["name"]))
+        .insert(
+            "show",
+            CliCommand::new(read)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_target_name),
+        )
+        .insert(
+            "update",
+            CliCommand::new(update)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_target_name),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(delete)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_target_name),
+        );
+
+    cmd_def.into()
+}
+
+pub fn notification_commands() -> CommandLineInterface {
+    let target_cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_TARGETS))
+        .insert(
+            "test",
+            CliCommand::new(&notification_api::API_METHOD_TEST_TARGET)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_target_name),
+        );
+
+    let matcher_cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_MATCHERS))
+        .insert(
+            "create",
+            CliCommand::new(&notification_api::matchers::API_METHOD_CREATE_MATCHER)
+                .arg_param(&["name"])
+                .completion_cb("target", complete_target_name),
+        )
+        .insert(
+            "show",
+            CliCommand::new(&notification_api::matchers::API_METHOD_READ_MATCHER)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_matcher_name),
+        )
+        .insert(
+            "update",
+            CliCommand::new(&notification_api::matchers::API_METHOD_UPDATE_MATCHER)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_matcher_name)
+                .completion_cb("target", complete_target_name),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(&notification_api::matchers::API_METHOD_DELETE_MATCHER)
+                .arg_param(&["name"])
+                .completion_cb("name", complete_matcher_name),
+        );
+
+    let cmd_def = CliCommandMap::new()
+        .insert("target", target_cmd_def)
+        .insert("matcher", matcher_cmd_def)
+        .insert(
+            "smtp",
+            target_type_commands(
+                &notification_api::smtp::API_METHOD_CREATE_SMTP_TARGET,
+                &notification_api::smtp::API_METHOD_READ_SMTP_TARGET,
+                &notification_api::smtp::API_METHOD_UPDATE_SMTP_TARGET,
+                &notification_api::smtp::API_METHOD_DELETE_SMTP_TARGET,
+            ),
+        )
+        .insert(
+            "gotify",
+            target_type_commands(
+                &notification_api::gotify::API_METHOD_CREATE_GOTIFY_TARGET,
+                &notification_api::gotify::API_METHOD_READ_GOTIFY_TARGET,
+                &notification_api::gotify::API_METHOD_UPDATE_GOTIFY_TARGET,
+                &notification_api::gotify::API_METHOD_DELETE_GOTIFY_TARGET,
+            ),
+        )
+        .insert(
+            "webhook",
+            target_type_commands(
+                &notification_api::webhook::API_METHOD_CREATE_WEBHOOK_TARGET,
+                &notification_api::webhook::API_METHOD_READ_WEBHOOK_TARGET,
+                &notification_api::webhook::API_METHOD_UPDATE_WEBHOOK_TARGET,
+                &notification_api::webhook::API_METHOD_DELETE_WEBHOOK_TARGET,
+            ),
+        );
+
+    cmd_def.into()
+}
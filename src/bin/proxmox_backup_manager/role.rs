@@ -0,0 +1,73 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
+use proxmox_schema::api;
+
+use proxmox_backup::api2;
+
+fn render_privs(value: &Value, _record: &Value) -> Result<String, Error> {
+    let privs: Vec<&str> = value
+        .as_array()
+        .map(|list| list.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    Ok(privs.join(", "))
+}
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// List built-in and custom roles.
+fn list_roles(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::access::role::API_METHOD_LIST_ROLES;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    if let Some(list) = data.as_array_mut() {
+        list.sort_by(|a, b| a["roleid"].as_str().cmp(&b["roleid"].as_str()));
+    }
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("roleid"))
+        .column(ColumnConfig::new("special"))
+        .column(ColumnConfig::new("privs").renderer(render_privs))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+pub fn role_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_ROLES))
+        .insert(
+            "create",
+            CliCommand::new(&api2::access::role::API_METHOD_CREATE_ROLE).arg_param(&["roleid"]),
+        )
+        .insert(
+            "update",
+            CliCommand::new(&api2::access::role::API_METHOD_UPDATE_ROLE)
+                .arg_param(&["roleid"])
+                .completion_cb("roleid", pbs_config::role::complete_role_id),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(&api2::access::role::API_METHOD_DELETE_ROLE)
+                .arg_param(&["roleid"])
+                .completion_cb("roleid", pbs_config::role::complete_role_id),
+        );
+
+    cmd_def.into()
+}
@@ -1,12 +1,15 @@
 use anyhow::Error;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::JOB_ID_SCHEMA;
+use pbs_api_types::{JOB_ID_SCHEMA, SYNC_DRY_RUN_SCHEMA};
+use pbs_client::view_task_result;
+use pbs_tools::json::required_string_param;
 
 use proxmox_backup::api2;
+use proxmox_backup::client_helpers::connect_to_localhost;
 
 fn render_group_filter(value: &Value, _record: &Value) -> Result<String, Error> {
     if let Some(group_filters) = value.as_array() {
@@ -42,6 +45,7 @@ fn list_sync_jobs(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value
         .column(ColumnConfig::new("store"))
         .column(ColumnConfig::new("remote"))
         .column(ColumnConfig::new("remote-store"))
+        .column(ColumnConfig::new("sync-direction"))
         .column(ColumnConfig::new("schedule"))
         .column(ColumnConfig::new("group-filter").renderer(render_group_filter))
         .column(ColumnConfig::new("rate-in"))
@@ -93,6 +97,10 @@ fn show_sync_job(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value,
             id: {
                 schema: JOB_ID_SCHEMA,
             },
+            "dry-run": {
+                schema: SYNC_DRY_RUN_SCHEMA,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -102,7 +110,20 @@ fn show_sync_job(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value,
 )]
 /// Run the specified sync job
 async fn run_sync_job(param: Value) -> Result<Value, Error> {
-    crate::run_job("sync", param).await
+    if !param["dry-run"].as_bool().unwrap_or(false) {
+        return crate::run_job("sync", param).await;
+    }
+
+    let output_format = get_output_format(&param);
+    let id = required_string_param(&param, "id")?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/sync/{id}/run");
+    let result = client.post(&path, Some(json!({ "dry-run": true }))).await?;
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
 }
 
 pub fn sync_job_commands() -> CommandLineInterface {
@@ -94,6 +94,7 @@ fn list_tokens(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, E
             ColumnConfig::new("enable").renderer(pbs_tools::format::render_bool_with_default_true),
         )
         .column(ColumnConfig::new("expire").renderer(render_expire))
+        .column(ColumnConfig::new("append-only"))
         .column(ColumnConfig::new("comment"));
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
@@ -223,6 +224,13 @@ pub fn user_commands() -> CommandLineInterface {
                 .arg_param(&["userid", "token-name"])
                 .completion_cb("userid", pbs_config::user::complete_userid),
         )
+        .insert(
+            "update-token",
+            CliCommand::new(&api2::access::user::API_METHOD_UPDATE_TOKEN)
+                .arg_param(&["userid", "token-name"])
+                .completion_cb("userid", pbs_config::user::complete_userid)
+                .completion_cb("token-name", pbs_config::user::complete_token_name),
+        )
         .insert(
             "delete-token",
             CliCommand::new(&api2::access::user::API_METHOD_DELETE_TOKEN)
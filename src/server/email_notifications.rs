@@ -11,10 +11,13 @@ use proxmox_schema::ApiType;
 use proxmox_sys::email::sendmail;
 
 use pbs_api_types::{
-    APTUpdateInfo, DataStoreConfig, DatastoreNotify, GarbageCollectionStatus, Notify,
-    SyncJobConfig, TapeBackupJobSetup, User, Userid, VerificationJobConfig,
+    APTUpdateInfo, DataStoreConfig, DatastoreNotify, DatastoreQuota, GarbageCollectionStatus,
+    NotificationMode, NotificationSeverity, Notify, StorageUsage, SyncJobConfig,
+    TapeBackupJobSetup, User, Userid, VerificationJobConfig,
 };
 
+use crate::server::notifications::{has_notification_targets, send_notification, Notification};
+
 const GC_OK_TEMPLATE: &str = r###"
 
 Datastore:            {{datastore}}
@@ -318,20 +321,56 @@ fn send_job_status_mail(email: &str, subject: &str, text: &str) -> Result<(), Er
     Ok(())
 }
 
+/// Deliver a job notification, either as mail to `email` or via the notification system.
+fn send_job_notification(
+    mode: NotificationMode,
+    email: Option<&str>,
+    notification: Notification,
+) -> Result<(), Error> {
+    match mode {
+        NotificationMode::LegacySendmail => match email {
+            Some(email) => send_job_status_mail(email, &notification.title, &notification.body),
+            None => Ok(()),
+        },
+        NotificationMode::NotificationSystem => send_notification(&notification),
+    }
+}
+
+/// Send a notification which does not belong to a job.
+///
+/// If no notification target is configured for it, a mail is sent to `email` instead.
+fn send_system_notification(email: Option<&str>, notification: Notification) -> Result<(), Error> {
+    if has_notification_targets(&notification)? {
+        send_notification(&notification)
+    } else if let Some(email) = email {
+        send_job_status_mail(email, &notification.title, &notification.body)
+    } else {
+        Ok(())
+    }
+}
+
+fn result_severity<T>(result: &Result<T, Error>) -> NotificationSeverity {
+    match result {
+        Ok(_) => NotificationSeverity::Info,
+        Err(_) => NotificationSeverity::Error,
+    }
+}
+
 pub fn send_gc_status(
-    email: &str,
+    email: Option<&str>,
     notify: DatastoreNotify,
+    mode: NotificationMode,
     datastore: &str,
     status: &GarbageCollectionStatus,
     result: &Result<(), Error>,
 ) -> Result<(), Error> {
     match notify.gc {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
+        Some(notify) if mode == NotificationMode::LegacySendmail => {
             if notify == Notify::Never || (result.is_ok() && notify == Notify::Error) {
                 return Ok(());
             }
         }
+        _ => { /* send notifications by default, matchers filter in the notification system */ }
     }
 
     let (fqdn, port) = get_server_url();
@@ -365,14 +404,16 @@ pub fn send_gc_status(
         Err(_) => format!("Garbage Collect Datastore '{datastore}' failed"),
     };
 
-    send_job_status_mail(email, &subject, &text)?;
+    let notification = Notification::new("gc", result_severity(result), subject, text)
+        .with_field("datastore", datastore);
 
-    Ok(())
+    send_job_notification(mode, email, notification)
 }
 
 pub fn send_verify_status(
-    email: &str,
+    email: Option<&str>,
     notify: DatastoreNotify,
+    mode: NotificationMode,
     job: VerificationJobConfig,
     result: &Result<Vec<String>, Error>,
 ) -> Result<(), Error> {
@@ -401,12 +442,12 @@ pub fn send_verify_status(
     };
 
     match notify.verify {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
+        Some(notify) if mode == NotificationMode::LegacySendmail => {
             if notify == Notify::Never || (result_is_ok && notify == Notify::Error) {
                 return Ok(());
             }
         }
+        _ => { /* send notifications by default, matchers filter in the notification system */ }
     }
 
     let subject = match result {
@@ -414,9 +455,16 @@ pub fn send_verify_status(
         _ => format!("Verify Datastore '{}' failed", job.store),
     };
 
-    send_job_status_mail(email, &subject, &text)?;
+    let severity = if result_is_ok {
+        NotificationSeverity::Info
+    } else {
+        NotificationSeverity::Error
+    };
+    let notification = Notification::new("verify", severity, subject, text)
+        .with_field("datastore", &job.store)
+        .with_field("job-id", &job.id);
 
-    Ok(())
+    send_job_notification(mode, email, notification)
 }
 
 pub fn send_prune_status(
@@ -424,13 +472,12 @@ pub fn send_prune_status(
     jobname: &str,
     result: &Result<(), Error>,
 ) -> Result<(), Error> {
-    let (email, notify) = match lookup_datastore_notify_settings(store) {
-        (Some(email), notify) => (email, notify),
-        (None, _) => return Ok(()),
-    };
+    let (email, notify, mode) = lookup_datastore_notify_settings(store);
 
     let notify_prune = notify.prune.unwrap_or(Notify::Error);
-    if notify_prune == Notify::Never || (result.is_ok() && notify_prune == Notify::Error) {
+    if mode == NotificationMode::LegacySendmail
+        && (notify_prune == Notify::Never || (result.is_ok() && notify_prune == Notify::Error))
+    {
         return Ok(());
     }
 
@@ -455,24 +502,27 @@ pub fn send_prune_status(
         Err(_) => format!("Pruning datastore '{store}' failed"),
     };
 
-    send_job_status_mail(&email, &subject, &text)?;
+    let notification = Notification::new("prune", result_severity(result), subject, text)
+        .with_field("datastore", store)
+        .with_field("job-id", jobname);
 
-    Ok(())
+    send_job_notification(mode, email.as_deref(), notification)
 }
 
 pub fn send_sync_status(
-    email: &str,
+    email: Option<&str>,
     notify: DatastoreNotify,
+    mode: NotificationMode,
     job: &SyncJobConfig,
     result: &Result<(), Error>,
 ) -> Result<(), Error> {
     match notify.sync {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
+        Some(notify) if mode == NotificationMode::LegacySendmail => {
             if notify == Notify::Never || (result.is_ok() && notify == Notify::Error) {
                 return Ok(());
             }
         }
+        _ => { /* send notifications by default, matchers filter in the notification system */ }
     }
 
     let (fqdn, port) = get_server_url();
@@ -503,13 +553,16 @@ pub fn send_sync_status(
         Err(_) => format!("{} datastore '{}' failed", source_str, job.remote_store,),
     };
 
-    send_job_status_mail(email, &subject, &text)?;
+    let notification = Notification::new("sync", result_severity(result), subject, text)
+        .with_field("datastore", &job.store)
+        .with_field("job-id", &job.id);
 
-    Ok(())
+    send_job_notification(mode, email, notification)
 }
 
 pub fn send_tape_backup_status(
-    email: &str,
+    email: Option<&str>,
+    mode: NotificationMode,
     id: Option<&str>,
     job: &TapeBackupJobSetup,
     result: &Result<(), Error>,
@@ -542,17 +595,24 @@ pub fn send_tape_backup_status(
         (Err(_), None) => format!("Tape Backup datastore '{}' failed", job.store,),
     };
 
-    send_job_status_mail(email, &subject, &text)?;
+    let mut notification = Notification::new("tape-backup", result_severity(result), subject, text)
+        .with_field("datastore", &job.store)
+        .with_field("media-pool", &job.pool);
+    if let Some(id) = id {
+        notification = notification.with_field("job-id", id);
+    }
 
-    Ok(())
+    send_job_notification(mode, email, notification)
 }
 
-/// Send email to a person to request a manual media change
+/// Request a manual media change
+///
+/// The request is mailed to `to` if set, and always handed to the notification system.
 pub fn send_load_media_email(
     changer: bool,
     device: &str,
     label_text: &str,
-    to: &str,
+    to: Option<&str>,
     reason: Option<String>,
 ) -> Result<(), Error> {
     use std::fmt::Write as _;
@@ -579,7 +639,59 @@ pub fn send_load_media_email(
     }
     let _ = writeln!(text, "Media: {label_text}");
 
-    send_job_status_mail(to, &subject, &text)
+    let notification = Notification::new("tape-load", NotificationSeverity::Notice, subject, text)
+        .with_field("device", device);
+
+    send_system_notification(to, notification)
+}
+
+/// Warn about a quota whose usage exceeds its soft limit.
+pub fn send_quota_warning(quota: &DatastoreQuota, usage: &StorageUsage) -> Result<(), Error> {
+    use std::fmt::Write as _;
+
+    let (email, _notify, mode) = lookup_datastore_notify_settings(&quota.store);
+
+    let subject = format!(
+        "Datastore '{}' quota '{}' almost exhausted",
+        quota.store, quota.id
+    );
+
+    let mut text = String::new();
+    let _ = writeln!(text, "Datastore: {}", quota.store);
+    let _ = writeln!(text, "Quota:     {}", quota.id);
+    if let Some(ref ns) = quota.ns {
+        let _ = writeln!(text, "Namespace: {ns}");
+    }
+    if let Some(ref owner) = quota.owner {
+        let _ = writeln!(text, "Owner:     {owner}");
+    }
+    text.push('\n');
+
+    let mut usage_line = |name: &str, used: u64, limit: Option<HumanByte>| {
+        if let Some(limit) = limit {
+            let _ = writeln!(
+                text,
+                "{name} {} of {} ({:.2}%)",
+                HumanByte::from(used),
+                limit,
+                used as f64 * 100.0 / limit.as_u64().max(1) as f64,
+            );
+        }
+    };
+    usage_line("Logical size:", usage.logical_size, quota.logical_limit);
+    usage_line("Unique size: ", usage.unique_size, quota.unique_limit);
+
+    let _ = write!(
+        text,
+        "\nThe usage exceeds the soft limit of {}%, new backups will fail once a limit is reached.\n",
+        quota.soft_limit.unwrap_or(100),
+    );
+
+    let notification = Notification::new("quota", NotificationSeverity::Warning, subject, text)
+        .with_field("datastore", &quota.store)
+        .with_field("quota-id", &quota.id);
+
+    send_job_notification(mode, email.as_deref(), notification)
 }
 
 fn get_server_url() -> (String, usize) {
@@ -601,25 +713,26 @@ fn get_server_url() -> (String, usize) {
 }
 
 pub fn send_updates_available(updates: &[&APTUpdateInfo]) -> Result<(), Error> {
+    let nodename = proxmox_sys::nodename();
+    let subject = format!("New software packages available ({nodename})");
+
+    let (fqdn, port) = get_server_url();
+
+    let text = HANDLEBARS.render(
+        "package_update_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "updates": updates,
+        }),
+    )?;
+
+    let notification =
+        Notification::new("package-updates", NotificationSeverity::Info, subject, text);
+
     // update mails always go to the root@pam configured email..
-    if let Some(email) = lookup_user_email(Userid::root_userid()) {
-        let nodename = proxmox_sys::nodename();
-        let subject = format!("New software packages available ({nodename})");
-
-        let (fqdn, port) = get_server_url();
-
-        let text = HANDLEBARS.render(
-            "package_update_template",
-            &json!({
-                "fqdn": fqdn,
-                "port": port,
-                "updates": updates,
-            }),
-        )?;
-
-        send_job_status_mail(&email, &subject, &text)?;
-    }
-    Ok(())
+    let email = lookup_user_email(Userid::root_userid());
+    send_system_notification(email.as_deref(), notification)
 }
 
 /// send email on certificate renewal failure.
@@ -629,24 +742,23 @@ pub fn send_certificate_renewal_mail(result: &Result<(), Error>) -> Result<(), E
         _ => return Ok(()),
     };
 
-    if let Some(email) = lookup_user_email(Userid::root_userid()) {
-        let (fqdn, port) = get_server_url();
+    let (fqdn, port) = get_server_url();
 
-        let text = HANDLEBARS.render(
-            "certificate_renewal_err_template",
-            &json!({
-                "fqdn": fqdn,
-                "port": port,
-                "error": error,
-            }),
-        )?;
+    let text = HANDLEBARS.render(
+        "certificate_renewal_err_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "error": error,
+        }),
+    )?;
 
-        let subject = "Could not renew certificate";
+    let subject = "Could not renew certificate";
 
-        send_job_status_mail(&email, subject, &text)?;
-    }
+    let notification = Notification::new("acme", NotificationSeverity::Error, subject, text);
 
-    Ok(())
+    let email = lookup_user_email(Userid::root_userid());
+    send_system_notification(email.as_deref(), notification)
 }
 
 /// Lookup users email address
@@ -661,7 +773,9 @@ pub fn lookup_user_email(userid: &Userid) -> Option<String> {
 }
 
 /// Lookup Datastore notify settings
-pub fn lookup_datastore_notify_settings(store: &str) -> (Option<String>, DatastoreNotify) {
+pub fn lookup_datastore_notify_settings(
+    store: &str,
+) -> (Option<String>, DatastoreNotify, NotificationMode) {
     let mut email = None;
 
     let notify = DatastoreNotify {
@@ -673,12 +787,12 @@ pub fn lookup_datastore_notify_settings(store: &str) -> (Option<String>, Datasto
 
     let (config, _digest) = match pbs_config::datastore::config() {
         Ok(result) => result,
-        Err(_) => return (email, notify),
+        Err(_) => return (email, notify, NotificationMode::default()),
     };
 
     let config: DataStoreConfig = match config.lookup("datastore", store) {
         Ok(result) => result,
-        Err(_) => return (email, notify),
+        Err(_) => return (email, notify, NotificationMode::default()),
     };
 
     email = match config.notify_user {
@@ -686,15 +800,17 @@ pub fn lookup_datastore_notify_settings(store: &str) -> (Option<String>, Datasto
         None => lookup_user_email(Userid::root_userid()),
     };
 
+    let mode = config.notification_mode.unwrap_or_default();
+
     let notify_str = config.notify.unwrap_or_default();
 
     if let Ok(value) = DatastoreNotify::API_SCHEMA.parse_property_string(&notify_str) {
         if let Ok(notify) = serde_json::from_value(value) {
-            return (email, notify);
+            return (email, notify, mode);
         }
     }
 
-    (email, notify)
+    (email, notify, mode)
 }
 
 // Handlerbar helper functions
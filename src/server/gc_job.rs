@@ -19,7 +19,7 @@ pub fn do_garbage_collection_job(
 ) -> Result<String, Error> {
     let store = datastore.name().to_string();
 
-    let (email, notify) = crate::server::lookup_datastore_notify_settings(&store);
+    let (email, notify, mode) = crate::server::lookup_datastore_notify_settings(&store);
 
     let worker_type = job.jobtype().to_string();
     let upid_str = WorkerTask::new_thread(
@@ -35,7 +35,7 @@ pub fn do_garbage_collection_job(
                 task_log!(worker, "task triggered by schedule '{event_str}'");
             }
 
-            let result = datastore.garbage_collection(&*worker, worker.upid());
+            let result = datastore.garbage_collection(&*worker, worker.upid(), schedule.is_some());
 
             let status = worker.create_state(&result);
 
@@ -43,11 +43,11 @@ pub fn do_garbage_collection_job(
                 eprintln!("could not finish job state for {}: {err}", job.jobtype());
             }
 
-            if let Some(email) = email {
-                let gc_status = datastore.last_gc_status();
-                if let Err(err) = send_gc_status(&email, notify, &store, &gc_status, &result) {
-                    eprintln!("send gc notification failed: {err}");
-                }
+            let gc_status = datastore.last_gc_status();
+            if let Err(err) =
+                send_gc_status(email.as_deref(), notify, mode, &store, &gc_status, &result)
+            {
+                eprintln!("send gc notification failed: {err}");
             }
 
             result
@@ -22,18 +22,28 @@ pub use prune_job::*;
 mod gc_job;
 pub use gc_job::*;
 
+mod scrub_job;
+pub use scrub_job::*;
+
 mod realm_sync_job;
 pub use realm_sync_job::*;
 
 mod email_notifications;
 pub use email_notifications::*;
 
+pub mod notifications;
+
+pub mod openmetrics;
+
+pub mod quota;
+
 mod report;
 pub use report::*;
 
 pub mod auth;
 
 pub(crate) mod pull;
+pub(crate) mod push;
 
 pub(crate) async fn reload_proxy_certificate() -> Result<(), Error> {
     let proxy_pid = proxmox_rest_server::read_pid(pbs_buildcfg::PROXMOX_BACKUP_PROXY_PID_FN)?;
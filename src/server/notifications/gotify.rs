@@ -0,0 +1,56 @@
+use anyhow::{bail, format_err, Error};
+use hyper::{Body, Request};
+use serde_json::json;
+
+use pbs_api_types::{GotifyConfig, NotificationSeverity};
+
+use super::Notification;
+
+fn severity_to_priority(severity: NotificationSeverity) -> u32 {
+    match severity {
+        NotificationSeverity::Info => 1,
+        NotificationSeverity::Notice => 3,
+        NotificationSeverity::Warning => 5,
+        NotificationSeverity::Error => 9,
+    }
+}
+
+/// Send a notification to a gotify server.
+pub(super) async fn send(config: &GotifyConfig, notification: &Notification) -> Result<(), Error> {
+    let uri = format!("{}/message", config.server.trim_end_matches('/'));
+    let token = config
+        .token
+        .as_deref()
+        .ok_or_else(|| format_err!("gotify target '{}' has no token", config.name))?;
+
+    let body = json!({
+        "title": notification.title,
+        "message": notification.body,
+        "priority": severity_to_priority(notification.severity),
+        "extras": {
+            "client::display": {
+                "contentType": "text/plain",
+            },
+        },
+    });
+
+    let request = Request::builder()
+        .method("POST")
+        .uri(&uri)
+        .header("Content-Type", "application/json")
+        .header("X-Gotify-Key", token)
+        .body(Body::from(body.to_string()))?;
+
+    let response = super::webhook::http_client().request(request).await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        bail!(
+            "gotify server returned {status} - {}",
+            String::from_utf8_lossy(&body)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use anyhow::{bail, format_err, Error};
+use regex::Regex;
+
+use pbs_api_types::MatcherConfig;
+
+use super::Notification;
+
+enum FieldMatcher {
+    Exact { field: String, value: String },
+    Regex { field: String, regex: Regex },
+}
+
+impl FieldMatcher {
+    fn parse(matcher: &str) -> Result<Self, Error> {
+        let (ty, rule) = matcher
+            .split_once(':')
+            .ok_or_else(|| format_err!("invalid field matcher '{matcher}'"))?;
+        let (field, value) = rule
+            .split_once('=')
+            .ok_or_else(|| format_err!("invalid field matcher '{matcher}'"))?;
+        let field = field.to_string();
+
+        Ok(match ty {
+            "exact" => FieldMatcher::Exact {
+                field,
+                value: value.to_string(),
+            },
+            "regex" => FieldMatcher::Regex {
+                field,
+                regex: Regex::new(value)
+                    .map_err(|err| format_err!("invalid regex in field matcher - {err}"))?,
+            },
+            _ => bail!("unknown field matcher type '{ty}'"),
+        })
+    }
+
+    fn matches(&self, notification: &Notification) -> bool {
+        match self {
+            FieldMatcher::Exact { field, value } => notification.fields.get(field) == Some(value),
+            FieldMatcher::Regex { field, regex } => notification
+                .fields
+                .get(field)
+                .map(|value| regex.is_match(value))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Check that a 'match-field' rule is valid.
+pub fn check_match_field(matcher: &str) -> Result<(), Error> {
+    FieldMatcher::parse(matcher).map(|_| ())
+}
+
+/// Evaluate a matcher against a notification.
+///
+/// A matcher without any rules matches every notification.
+pub(super) fn matches(matcher: &MatcherConfig, notification: &Notification) -> Result<bool, Error> {
+    let mut results = Vec::new();
+
+    if let Some(severities) = &matcher.match_severity {
+        results.push(severities.contains(&notification.severity));
+    }
+
+    for field in matcher.match_field.iter().flatten() {
+        results.push(FieldMatcher::parse(field)?.matches(notification));
+    }
+
+    let matched = if results.is_empty() {
+        true
+    } else {
+        match matcher.mode.unwrap_or_default() {
+            pbs_api_types::MatcherMode::All => results.iter().all(|r| *r),
+            pbs_api_types::MatcherMode::Any => results.iter().any(|r| *r),
+        }
+    };
+
+    Ok(matched != matcher.invert_match.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pbs_api_types::{MatcherMode, NotificationSeverity};
+
+    fn matcher(
+        match_field: &[&str],
+        match_severity: &[NotificationSeverity],
+        mode: MatcherMode,
+    ) -> MatcherConfig {
+        MatcherConfig {
+            name: "test".to_string(),
+            enable: true,
+            match_field: Some(match_field.iter().map(|s| s.to_string()).collect()),
+            match_severity: Some(match_severity.to_vec()),
+            mode: Some(mode),
+            invert_match: None,
+            target: None,
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn test_matcher() -> Result<(), Error> {
+        let notification = Notification::new("gc", NotificationSeverity::Error, "title", "body")
+            .with_field("datastore", "store1");
+
+        let m = matcher(
+            &["exact:type=gc", "regex:datastore=^store\\d$"],
+            &[NotificationSeverity::Error],
+            MatcherMode::All,
+        );
+        assert!(matches(&m, &notification)?);
+
+        let m = matcher(
+            &["exact:type=sync"],
+            &[NotificationSeverity::Error],
+            MatcherMode::All,
+        );
+        assert!(!matches(&m, &notification)?);
+
+        let m = matcher(
+            &["exact:type=sync"],
+            &[NotificationSeverity::Error],
+            MatcherMode::Any,
+        );
+        assert!(matches(&m, &notification)?);
+
+        let mut m = matcher(&["exact:job-id=nightly"], &[], MatcherMode::Any);
+        m.match_severity = None;
+        assert!(!matches(&m, &notification)?);
+        m.invert_match = Some(true);
+        assert!(matches(&m, &notification)?);
+
+        m.match_field = None;
+        m.invert_match = None;
+        assert!(matches(&m, &notification)?);
+
+        assert!(check_match_field("regex:type=(").is_err());
+        assert!(check_match_field("fuzzy:type=gc").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Notification system
+//!
+//! Notifications carry a severity and a set of metadata fields (`type`, `hostname` and, where
+//! applicable, `datastore` and `job-id`). The configured matchers decide which notification
+//! targets (direct SMTP, gotify, generic HTTP webhook) a notification is sent to.
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{bail, format_err, Error};
+use serde_json::{json, Value};
+
+use proxmox_section_config::SectionConfigData;
+
+use pbs_api_types::{GotifyConfig, MatcherConfig, NotificationSeverity, SmtpConfig, WebhookConfig};
+
+mod gotify;
+mod matcher;
+mod smtp;
+mod webhook;
+
+pub use matcher::check_match_field;
+
+/// A single notification event.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub severity: NotificationSeverity,
+    pub title: String,
+    pub body: String,
+    /// Metadata the matchers can select on.
+    pub fields: HashMap<String, String>,
+    pub timestamp: i64,
+}
+
+impl Notification {
+    /// Create a new notification for the given event type, e.g. 'gc' or 'sync'.
+    pub fn new(
+        event_type: &str,
+        severity: NotificationSeverity,
+        title: impl Into<String>,
+        body: impl Into<String>,
+    ) -> Self {
+        let mut fields = HashMap::new();
+        fields.insert("type".to_string(), event_type.to_string());
+        fields.insert("hostname".to_string(), proxmox_sys::nodename().to_string());
+
+        Self {
+            severity,
+            title: title.into(),
+            body: body.into(),
+            fields,
+            timestamp: proxmox_time::epoch_i64(),
+        }
+    }
+
+    /// Add a metadata field.
+    pub fn with_field(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    fn template_data(&self) -> Value {
+        json!({
+            "title": self.title,
+            "message": self.body,
+            "severity": self.severity,
+            "timestamp": self.timestamp,
+            "fields": self.fields,
+        })
+    }
+}
+
+enum Target {
+    Smtp(SmtpConfig),
+    Gotify(GotifyConfig),
+    Webhook(WebhookConfig),
+}
+
+impl Target {
+    fn lookup(config: &SectionConfigData, name: &str) -> Result<Self, Error> {
+        let (ty, _) = config
+            .sections
+            .get(name)
+            .ok_or_else(|| format_err!("notification target '{name}' does not exist"))?;
+
+        Ok(match ty.as_str() {
+            "smtp" => Target::Smtp(config.lookup("smtp", name)?),
+            "gotify" => Target::Gotify(config.lookup("gotify", name)?),
+            "webhook" => Target::Webhook(config.lookup("webhook", name)?),
+            _ => bail!("'{name}' is not a notification target"),
+        })
+    }
+
+    fn enabled(&self) -> bool {
+        match self {
+            Target::Smtp(config) => config.enable,
+            Target::Gotify(config) => config.enable,
+            Target::Webhook(config) => config.enable,
+        }
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        match self {
+            Target::Smtp(config) => smtp::send(config, notification).await,
+            Target::Gotify(config) => gotify::send(config, notification).await,
+            Target::Webhook(config) => webhook::send(config, notification).await,
+        }
+    }
+}
+
+/// Send a notification to all targets selected by the configured matchers.
+///
+/// Errors of single targets are logged, the notification is still sent to the other targets.
+pub fn send_notification(notification: &Notification) -> Result<(), Error> {
+    proxmox_async::runtime::block_on(send_notification_async(notification))
+}
+
+/// Returns whether the configured matchers select any enabled target for a notification.
+pub fn has_notification_targets(notification: &Notification) -> Result<bool, Error> {
+    let (config, _digest) = pbs_config::notifications::config()?;
+
+    let targets = matching_targets(&config, notification)?;
+
+    Ok(targets.iter().any(|name| {
+        Target::lookup(&config, name)
+            .map(|target| target.enabled())
+            .unwrap_or(false)
+    }))
+}
+
+fn matching_targets(
+    config: &SectionConfigData,
+    notification: &Notification,
+) -> Result<BTreeSet<String>, Error> {
+    let matchers: Vec<MatcherConfig> = config.convert_to_typed_array("matcher")?;
+
+    let mut targets = BTreeSet::new();
+    for matcher in matchers.iter().filter(|matcher| matcher.enable) {
+        match matcher::matches(matcher, notification) {
+            Ok(true) => targets.extend(matcher.target.iter().flatten().cloned()),
+            Ok(false) => (),
+            Err(err) => log::error!("notification matcher '{}' failed - {err}", matcher.name),
+        }
+    }
+
+    Ok(targets)
+}
+
+async fn send_notification_async(notification: &Notification) -> Result<(), Error> {
+    let (config, _digest) = pbs_config::notifications::config()?;
+
+    let mut failed = Vec::new();
+    for name in matching_targets(&config, notification)? {
+        let result = match Target::lookup(&config, &name) {
+            Ok(target) if !target.enabled() => continue,
+            Ok(target) => target.send(notification).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            log::error!("could not send notification to target '{name}' - {err}");
+            failed.push(name);
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!(
+            "sending notification to target(s) {} failed",
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Send a test notification to a single target, ignoring matchers and the 'enable' flag.
+pub async fn send_test_notification(name: &str) -> Result<(), Error> {
+    let (config, _digest) = pbs_config::notifications::config()?;
+    let target = Target::lookup(&config, name)?;
+
+    let notification = Notification::new(
+        "test",
+        NotificationSeverity::Info,
+        "Test notification",
+        format!("This is a test of the notification target '{name}'."),
+    );
+
+    target.send(&notification).await
+}
@@ -0,0 +1,298 @@
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use openssl::ssl::{SslConnector, SslMethod};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+
+use pbs_api_types::{SmtpConfig, SmtpMode};
+
+use super::Notification;
+
+const SMTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const SMTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Deliver a notification via SMTP.
+pub(super) async fn send(config: &SmtpConfig, notification: &Notification) -> Result<(), Error> {
+    let mut recipients = config.mailto.clone().unwrap_or_default();
+    for userid in config.mailto_user.iter().flatten() {
+        match crate::server::lookup_user_email(userid) {
+            Some(email) => recipients.push(email),
+            None => log::warn!("user '{userid}' has no email address configured"),
+        }
+    }
+    recipients.sort();
+    recipients.dedup();
+
+    if recipients.is_empty() {
+        bail!("no recipients for SMTP target '{}'", config.name);
+    }
+
+    let message = format_message(config, &recipients, notification)?;
+
+    tokio::time::timeout(SMTP_TIMEOUT, deliver(config, &recipients, &message))
+        .await
+        .map_err(|_| format_err!("SMTP delivery to '{}' timed out", config.server))?
+}
+
+async fn deliver(config: &SmtpConfig, recipients: &[String], message: &str) -> Result<(), Error> {
+    let mode = config.mode.unwrap_or_default();
+    let port = config.port.unwrap_or_else(|| mode.default_port());
+
+    let tcp = tokio::time::timeout(
+        SMTP_CONNECT_TIMEOUT,
+        TcpStream::connect((config.server.as_str(), port)),
+    )
+    .await
+    .map_err(|_| format_err!("connecting to {}:{port} timed out", config.server))?
+    .map_err(|err| format_err!("unable to connect to {}:{port} - {err}", config.server))?;
+
+    match mode {
+        SmtpMode::Insecure => {
+            let mut session = SmtpSession::new(tcp);
+            session.expect_reply(220).await?;
+            let extensions = session.ehlo().await?;
+            session
+                .transaction(config, &extensions, recipients, message)
+                .await
+        }
+        SmtpMode::Starttls => {
+            let mut session = SmtpSession::new(tcp);
+            session.expect_reply(220).await?;
+            let extensions = session.ehlo().await?;
+            if !extensions
+                .iter()
+                .any(|ext| ext.eq_ignore_ascii_case("STARTTLS"))
+            {
+                bail!("SMTP server {} does not support STARTTLS", config.server);
+            }
+            session.command("STARTTLS", 220).await?;
+
+            let tls = tls_connect(&config.server, session.into_inner()).await?;
+            let mut session = SmtpSession::new(tls);
+            let extensions = session.ehlo().await?;
+            session
+                .transaction(config, &extensions, recipients, message)
+                .await
+        }
+        SmtpMode::Tls => {
+            let tls = tls_connect(&config.server, tcp).await?;
+            let mut session = SmtpSession::new(tls);
+            session.expect_reply(220).await?;
+            let extensions = session.ehlo().await?;
+            session
+                .transaction(config, &extensions, recipients, message)
+                .await
+        }
+    }
+}
+
+async fn tls_connect(
+    server: &str,
+    tcp: TcpStream,
+) -> Result<tokio_openssl::SslStream<TcpStream>, Error> {
+    let ssl = SslConnector::builder(SslMethod::tls())?
+        .build()
+        .configure()?
+        .into_ssl(server)?;
+
+    let mut stream = tokio_openssl::SslStream::new(ssl, tcp)?;
+    Pin::new(&mut stream)
+        .connect()
+        .await
+        .map_err(|err| format_err!("TLS handshake with {server} failed - {err}"))?;
+
+    Ok(stream)
+}
+
+struct SmtpSession<S> {
+    stream: BufStream<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SmtpSession<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    /// Read a (possibly multi-line) reply, returning the code and the text of all lines.
+    async fn read_reply(&mut self) -> Result<(u16, Vec<String>), Error> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("SMTP server closed the connection");
+            }
+            let line = line.trim_end();
+            if line.len() < 3 {
+                bail!("invalid SMTP reply '{line}'");
+            }
+            let code: u16 = line[..3]
+                .parse()
+                .map_err(|_| format_err!("invalid SMTP reply '{line}'"))?;
+            lines.push(line.get(4..).unwrap_or("").to_string());
+
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, lines));
+            }
+        }
+    }
+
+    async fn expect_reply(&mut self, expected: u16) -> Result<Vec<String>, Error> {
+        let (code, lines) = self.read_reply().await?;
+        if code != expected {
+            bail!("unexpected SMTP reply {code} - {}", lines.join(" "));
+        }
+        Ok(lines)
+    }
+
+    async fn request(&mut self, command: &str) -> Result<(u16, Vec<String>), Error> {
+        self.stream.write_all(command.as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+        self.read_reply().await
+    }
+
+    async fn command(&mut self, command: &str, expected: u16) -> Result<Vec<String>, Error> {
+        let (code, lines) = self.request(command).await?;
+        if code != expected {
+            bail!("unexpected SMTP reply {code} - {}", lines.join(" "));
+        }
+        Ok(lines)
+    }
+
+    /// Greet the server and return the supported extensions.
+    async fn ehlo(&mut self) -> Result<Vec<String>, Error> {
+        let lines = self
+            .command(&format!("EHLO {}", proxmox_sys::nodename()), 250)
+            .await?;
+        // the first line is the server greeting
+        Ok(lines.into_iter().skip(1).collect())
+    }
+
+    async fn authenticate(
+        &mut self,
+        config: &SmtpConfig,
+        extensions: &[String],
+    ) -> Result<(), Error> {
+        let username = match config.username.as_deref() {
+            Some(username) => username,
+            None => return Ok(()),
+        };
+        let password = config.password.as_deref().unwrap_or("");
+
+        let mechanisms: Vec<String> = extensions
+            .iter()
+            .filter_map(|ext| {
+                let (name, args) = ext.split_once(' ')?;
+                name.eq_ignore_ascii_case("AUTH").then_some(args)
+            })
+            .flat_map(|args| args.split_whitespace())
+            .map(|mechanism| mechanism.to_ascii_uppercase())
+            .collect();
+
+        if mechanisms.iter().any(|m| m == "PLAIN") {
+            let credentials = base64::encode(format!("\0{username}\0{password}"));
+            self.command(&format!("AUTH PLAIN {credentials}"), 235)
+                .await
+                .map_err(|err| format_err!("SMTP authentication failed - {err}"))?;
+        } else if mechanisms.iter().any(|m| m == "LOGIN") {
+            self.command("AUTH LOGIN", 334).await?;
+            self.command(&base64::encode(username), 334).await?;
+            self.command(&base64::encode(password), 235)
+                .await
+                .map_err(|err| format_err!("SMTP authentication failed - {err}"))?;
+        } else {
+            bail!("SMTP server supports neither AUTH PLAIN nor AUTH LOGIN");
+        }
+
+        Ok(())
+    }
+
+    async fn transaction(
+        &mut self,
+        config: &SmtpConfig,
+        extensions: &[String],
+        recipients: &[String],
+        message: &str,
+    ) -> Result<(), Error> {
+        self.authenticate(config, extensions).await?;
+
+        self.command(&format!("MAIL FROM:<{}>", config.from_address), 250)
+            .await?;
+        for recipient in recipients {
+            let (code, lines) = self.request(&format!("RCPT TO:<{recipient}>")).await?;
+            if code != 250 && code != 251 {
+                bail!(
+                    "recipient '{recipient}' rejected - {code} {}",
+                    lines.join(" ")
+                );
+            }
+        }
+
+        self.command("DATA", 354).await?;
+        self.stream.write_all(message.as_bytes()).await?;
+        self.command(".", 250).await?;
+
+        // we do not care if the server closes the connection without a proper reply
+        let _ = self.command("QUIT", 221).await;
+
+        Ok(())
+    }
+}
+
+/// Format the mail, the body is base64 encoded so no dot-stuffing is necessary.
+fn format_message(
+    config: &SmtpConfig,
+    recipients: &[String],
+    notification: &Notification,
+) -> Result<String, Error> {
+    let author = match &config.author {
+        Some(author) => author.clone(),
+        None => format!("Proxmox Backup Server - {}", proxmox_sys::nodename()),
+    };
+
+    let date = proxmox_time::strftime_local("%a, %d %b %Y %T %z", notification.timestamp)?;
+
+    let mut message = String::new();
+    message.push_str(&format!(
+        "From: {} <{}>\r\n",
+        encode_header(&author),
+        config.from_address
+    ));
+    message.push_str(&format!("To: {}\r\n", recipients.join(", ")));
+    message.push_str(&format!(
+        "Subject: {}\r\n",
+        encode_header(&notification.title)
+    ));
+    message.push_str(&format!("Date: {date}\r\n"));
+    message.push_str("Auto-Submitted: auto-generated\r\n");
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    message.push_str("Content-Transfer-Encoding: base64\r\n");
+    message.push_str("\r\n");
+
+    let body = base64::encode(notification.body.replace('\n', "\r\n"));
+    for line in body.as_bytes().chunks(76) {
+        // base64 output is pure ASCII
+        message.push_str(std::str::from_utf8(line).unwrap());
+        message.push_str("\r\n");
+    }
+
+    Ok(message)
+}
+
+/// Encode a header value as RFC 2047 encoded word if it is not plain ASCII.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", base64::encode(value))
+    }
+}
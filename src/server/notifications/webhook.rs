@@ -0,0 +1,107 @@
+use anyhow::{bail, format_err, Error};
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError};
+use hyper::{Body, Request};
+use serde_json::{Map, Value};
+
+use proxmox_http::client::Client;
+
+use pbs_api_types::{WebhookConfig, WebhookMethod};
+
+use super::Notification;
+
+/// HTTP client for notification targets, honoring the node's HTTP proxy setting.
+pub(super) fn http_client() -> Client {
+    let proxy_config = crate::config::node::config()
+        .ok()
+        .and_then(|(node_config, _digest)| node_config.http_proxy());
+
+    crate::tools::pbs_simple_http(proxy_config)
+}
+
+fn handlebars_json_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _rc: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("json: param not found"))?;
+
+    out.write(&param.value().to_string())?;
+
+    Ok(())
+}
+
+fn template_data(config: &WebhookConfig, notification: &Notification) -> Result<Value, Error> {
+    let mut secrets = Map::new();
+    for secret in config.secret.iter().flatten() {
+        let (name, value) = secret
+            .split_once('=')
+            .ok_or_else(|| format_err!("invalid secret, expected '<name>=<value>'"))?;
+        secrets.insert(name.to_string(), value.into());
+    }
+
+    let mut data = notification.template_data();
+    data["secrets"] = Value::Object(secrets);
+
+    Ok(data)
+}
+
+/// Send a notification to a generic HTTP endpoint.
+///
+/// The url, header values and body are handlebars templates, rendered without any escaping.
+/// Use the `json` helper to embed values into a JSON body, e.g.
+/// `{"text": {{ json message }}}`. Without a body template the notification is sent as JSON
+/// object.
+pub(super) async fn send(config: &WebhookConfig, notification: &Notification) -> Result<(), Error> {
+    let mut hb = Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.register_helper("json", Box::new(handlebars_json_helper));
+
+    let data = template_data(config, notification)?;
+    let render = |template: &str| {
+        hb.render_template(template, &data)
+            .map_err(|err| format_err!("unable to render template - {err}"))
+    };
+
+    let method = match config.method.unwrap_or_default() {
+        WebhookMethod::Post => "POST",
+        WebhookMethod::Put => "PUT",
+    };
+
+    let mut builder = Request::builder().method(method).uri(render(&config.url)?);
+
+    let mut have_content_type = false;
+    for header in config.header.iter().flatten() {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| format_err!("invalid header, expected '<name>: <value>'"))?;
+        have_content_type |= name.eq_ignore_ascii_case("content-type");
+        builder = builder.header(name.trim(), render(value.trim())?);
+    }
+
+    let body = match &config.body {
+        Some(template) => render(template)?,
+        None => notification.template_data().to_string(),
+    };
+    if !have_content_type {
+        builder = builder.header("Content-Type", "application/json");
+    }
+
+    let request = builder.body(Body::from(body))?;
+
+    let response = http_client().request(request).await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        bail!(
+            "webhook returned {status} - {}",
+            String::from_utf8_lossy(&body)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,555 @@
+//! Export of host, datastore, job and task metrics in the OpenMetrics text format
+//!
+//! The metrics are collected on request, so that they can be scraped by Prometheus (or any other
+//! OpenMetrics compatible collector) via the `/status/metrics` API endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::Error;
+
+use proxmox_rest_server::{TaskListInfoIterator, TaskState};
+
+use pbs_api_types::{
+    DataStoreConfig, Operation, PruneJobConfig, RRDMode, RRDTimeFrame, SyncJobConfig,
+    TapeBackupJobConfig, VerificationJobConfig, UPID,
+};
+use pbs_datastore::{get_datastore_mount_status, DataStore};
+
+use crate::rrd_cache::extract_rrd_data;
+use crate::server::jobstate::{compute_schedule_status, JobState};
+
+/// Content type of the generated text.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Tasks started within this time span (in seconds) are included in the task counts.
+const TASK_COUNT_TIMESPAN: i64 = 24 * 3600;
+
+#[derive(Clone, Copy)]
+enum MetricType {
+    Gauge,
+    Counter,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// Helper to write metric families with their samples.
+#[derive(Default)]
+struct MetricWriter {
+    data: String,
+    family: String,
+    suffix: &'static str,
+}
+
+impl MetricWriter {
+    /// Start a new metric family, all following samples belong to it.
+    fn family(&mut self, name: &str, ty: MetricType, help: &str) {
+        self.family = name.to_string();
+        self.suffix = match ty {
+            MetricType::Gauge => "",
+            MetricType::Counter => "_total",
+        };
+        let _ = writeln!(self.data, "# TYPE {name} {}", ty.as_str());
+        let _ = writeln!(self.data, "# HELP {name} {help}");
+    }
+
+    fn sample(&mut self, labels: &[(&str, &str)], value: f64) {
+        self.data.push_str(&self.family);
+        self.data.push_str(self.suffix);
+
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+                .collect();
+            let _ = write!(self.data, "{{{}}}", labels.join(","));
+        }
+
+        if value.is_nan() {
+            self.data.push_str(" NaN\n");
+        } else {
+            let _ = writeln!(self.data, " {value}");
+        }
+    }
+
+    /// Write a family consisting of a single, unlabeled sample.
+    fn single(&mut self, name: &str, ty: MetricType, help: &str, value: f64) {
+        self.family(name, ty, help);
+        self.sample(&[], value);
+    }
+
+    fn finish(mut self) -> String {
+        self.data.push_str("# EOF\n");
+        self.data
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Generate the current metrics in the OpenMetrics text format.
+pub async fn generate_metrics() -> Result<String, Error> {
+    let mut writer = MetricWriter::default();
+
+    write_host_metrics(&mut writer).await;
+    write_datastore_metrics(&mut writer).await?;
+    write_job_metrics(&mut writer)?;
+    write_task_metrics(&mut writer)?;
+
+    Ok(writer.finish())
+}
+
+/// Returns the most recent value of an RRD host metric.
+fn last_rrd_value(name: &str) -> Option<f64> {
+    let entry = extract_rrd_data("host", name, RRDTimeFrame::Hour, RRDMode::Average).ok()??;
+    entry.data.iter().rev().find_map(|value| *value)
+}
+
+async fn write_host_metrics(writer: &mut MetricWriter) {
+    use proxmox_sys::linux::procfs::{read_loadavg, read_meminfo, read_proc_net_dev};
+
+    // cpu usage is computed relative to the last read of /proc/stat, so use the values the
+    // stat generator stored instead of interfering with it
+    if let Some(cpu) = last_rrd_value("cpu") {
+        writer.single(
+            "pbs_host_cpu_usage_ratio",
+            MetricType::Gauge,
+            "CPU usage of the host.",
+            cpu,
+        );
+    }
+    if let Some(iowait) = last_rrd_value("iowait") {
+        writer.single(
+            "pbs_host_iowait_ratio",
+            MetricType::Gauge,
+            "CPU time spent waiting for IO.",
+            iowait,
+        );
+    }
+
+    match read_meminfo() {
+        Ok(meminfo) => {
+            let stats = [
+                (
+                    "pbs_host_memory_total_bytes",
+                    "Total memory.",
+                    meminfo.memtotal,
+                ),
+                (
+                    "pbs_host_memory_used_bytes",
+                    "Used memory.",
+                    meminfo.memused,
+                ),
+                (
+                    "pbs_host_swap_total_bytes",
+                    "Total swap.",
+                    meminfo.swaptotal,
+                ),
+                ("pbs_host_swap_used_bytes", "Used swap.", meminfo.swapused),
+            ];
+            for (name, help, value) in stats {
+                writer.single(name, MetricType::Gauge, help, value as f64);
+            }
+        }
+        Err(err) => log::error!("read_meminfo failed - {err}"),
+    }
+
+    match read_loadavg() {
+        Ok(loadavg) => {
+            writer.family(
+                "pbs_host_load_average",
+                MetricType::Gauge,
+                "System load average.",
+            );
+            writer.sample(&[("interval", "1m")], loadavg.0);
+            writer.sample(&[("interval", "5m")], loadavg.1);
+            writer.sample(&[("interval", "15m")], loadavg.2);
+        }
+        Err(err) => log::error!("read_loadavg failed - {err}"),
+    }
+
+    match read_proc_net_dev() {
+        Ok(netdev) => {
+            let nics: Vec<_> = netdev
+                .iter()
+                .filter(|item| pbs_config::network::is_physical_nic(&item.device))
+                .collect();
+
+            writer.family(
+                "pbs_host_network_receive_bytes",
+                MetricType::Counter,
+                "Bytes received on physical network interfaces.",
+            );
+            for item in nics.iter() {
+                writer.sample(&[("interface", &item.device)], item.receive as f64);
+            }
+            writer.family(
+                "pbs_host_network_transmit_bytes",
+                MetricType::Counter,
+                "Bytes sent on physical network interfaces.",
+            );
+            for item in nics.iter() {
+                writer.sample(&[("interface", &item.device)], item.send as f64);
+            }
+        }
+        Err(err) => log::error!("read_proc_net_dev failed - {err}"),
+    }
+
+    match crate::tools::fs::fs_info("/".into()).await {
+        Ok(info) => {
+            writer.single(
+                "pbs_host_rootfs_size_bytes",
+                MetricType::Gauge,
+                "Size of the root filesystem.",
+                info.total as f64,
+            );
+            writer.single(
+                "pbs_host_rootfs_used_bytes",
+                MetricType::Gauge,
+                "Used space on the root filesystem.",
+                info.used as f64,
+            );
+            writer.single(
+                "pbs_host_rootfs_available_bytes",
+                MetricType::Gauge,
+                "Available space on the root filesystem.",
+                info.available as f64,
+            );
+        }
+        Err(err) => log::error!("reading root filesystem usage failed - {err}"),
+    }
+}
+
+#[derive(Default)]
+struct DatastoreMetrics {
+    available: bool,
+    usage: Option<proxmox_sys::fs::FileSystemInformation>,
+    gc_status: Option<pbs_api_types::GarbageCollectionStatus>,
+}
+
+async fn write_datastore_metrics(writer: &mut MetricWriter) -> Result<(), Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let list: Vec<DataStoreConfig> = config.convert_to_typed_array("datastore")?;
+
+    let mut stores = Vec::new();
+    for store_config in list {
+        let mut metrics = DatastoreMetrics::default();
+
+        let offline = store_config
+            .get_maintenance_mode()
+            .map_or(false, |mode| mode.check(Some(Operation::Read)).is_err());
+
+        if !offline && get_datastore_mount_status(&store_config) != Some(false) {
+            if let Ok(datastore) =
+                DataStore::lookup_datastore(&store_config.name, Some(Operation::Lookup))
+            {
+                metrics.available = true;
+                metrics.usage = crate::tools::fs::fs_info(datastore.base_path()).await.ok();
+
+                let gc_status = datastore.last_gc_status();
+                if gc_status.upid.is_some() {
+                    metrics.gc_status = Some(gc_status);
+                }
+            }
+        }
+
+        stores.push((store_config.name, metrics));
+    }
+
+    writer.family(
+        "pbs_datastore_available",
+        MetricType::Gauge,
+        "Whether the datastore is available (not unmounted or in offline maintenance).",
+    );
+    for (name, metrics) in stores.iter() {
+        let value = if metrics.available { 1.0 } else { 0.0 };
+        writer.sample(&[("datastore", name)], value);
+    }
+
+    type UsageField = fn(&proxmox_sys::fs::FileSystemInformation) -> u64;
+    let usage_metrics: [(&str, &str, UsageField); 3] = [
+        (
+            "pbs_datastore_size_bytes",
+            "Size of the datastore.",
+            |info| info.total,
+        ),
+        (
+            "pbs_datastore_used_bytes",
+            "Used space of the datastore.",
+            |info| info.used,
+        ),
+        (
+            "pbs_datastore_available_bytes",
+            "Available space of the datastore.",
+            |info| info.available,
+        ),
+    ];
+    for (family, help, field) in usage_metrics {
+        writer.family(family, MetricType::Gauge, help);
+        for (name, metrics) in stores.iter() {
+            if let Some(usage) = &metrics.usage {
+                writer.sample(&[("datastore", name)], field(usage) as f64);
+            }
+        }
+    }
+
+    type GcField = fn(&pbs_api_types::GarbageCollectionStatus) -> f64;
+    let gc_metrics: [(&str, &str, GcField); 8] = [
+        (
+            "pbs_datastore_gc_index_files",
+            "Number of index files processed by the last garbage collection.",
+            |status| status.index_file_count as f64,
+        ),
+        (
+            "pbs_datastore_gc_index_data_bytes",
+            "Bytes referenced by all index files at the last garbage collection.",
+            |status| status.index_data_bytes as f64,
+        ),
+        (
+            "pbs_datastore_gc_disk_bytes",
+            "Bytes used by chunks on disk at the last garbage collection.",
+            |status| status.disk_bytes as f64,
+        ),
+        (
+            "pbs_datastore_gc_disk_chunks",
+            "Number of chunks on disk at the last garbage collection.",
+            |status| status.disk_chunks as f64,
+        ),
+        (
+            "pbs_datastore_gc_removed_bytes",
+            "Bytes removed by the last garbage collection.",
+            |status| status.removed_bytes as f64,
+        ),
+        (
+            "pbs_datastore_gc_pending_bytes",
+            "Bytes pending removal after the last garbage collection.",
+            |status| status.pending_bytes as f64,
+        ),
+        (
+            "pbs_datastore_gc_still_bad_chunks",
+            "Number of chunks still marked as bad after the last garbage collection.",
+            |status| status.still_bad as f64,
+        ),
+        (
+            "pbs_datastore_deduplication_factor",
+            "Deduplication factor as of the last garbage collection.",
+            |status| {
+                if status.disk_bytes > 0 {
+                    status.index_data_bytes as f64 / status.disk_bytes as f64
+                } else {
+                    1.0
+                }
+            },
+        ),
+    ];
+    for (family, help, field) in gc_metrics {
+        writer.family(family, MetricType::Gauge, help);
+        for (name, metrics) in stores.iter() {
+            if let Some(status) = &metrics.gc_status {
+                writer.sample(&[("datastore", name)], field(status));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct JobMetrics {
+    jobtype: &'static str,
+    id: String,
+    running: bool,
+    last_run: Option<(i64, i64, bool)>,
+    next_run: Option<i64>,
+}
+
+fn load_job_metrics(
+    jobtype: &'static str,
+    id: String,
+    schedule: Option<&str>,
+) -> Result<JobMetrics, Error> {
+    let state = JobState::load(jobtype, &id)?;
+    let next_run = compute_schedule_status(&state, schedule)?.next_run;
+
+    let (running, last_run) = match &state {
+        JobState::Created { .. } => (false, None),
+        JobState::Started { .. } => (true, None),
+        JobState::Finished { upid, state, .. } => {
+            let upid: UPID = upid.parse()?;
+            let success = !matches!(state, TaskState::Error { .. } | TaskState::Unknown { .. });
+            (false, Some((upid.starttime, state.endtime(), success)))
+        }
+    };
+
+    Ok(JobMetrics {
+        jobtype,
+        id,
+        running,
+        last_run,
+        next_run,
+    })
+}
+
+fn write_job_metrics(writer: &mut MetricWriter) -> Result<(), Error> {
+    let mut jobs = Vec::new();
+
+    let mut push_job =
+        |jobtype: &'static str, id: String, schedule: Option<&str>| match load_job_metrics(
+            jobtype,
+            id.clone(),
+            schedule,
+        ) {
+            Ok(metrics) => jobs.push(metrics),
+            Err(err) => log::error!("could not load state of {jobtype} '{id}' - {err}"),
+        };
+
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let list: Vec<DataStoreConfig> = config.convert_to_typed_array("datastore")?;
+    for store in list {
+        push_job(
+            "garbage_collection",
+            store.name,
+            store.gc_schedule.as_deref(),
+        );
+    }
+
+    let (config, _digest) = pbs_config::prune::config()?;
+    let list: Vec<PruneJobConfig> = config.convert_to_typed_array("prune")?;
+    for job in list {
+        push_job("prunejob", job.id, Some(&job.schedule));
+    }
+
+    let (config, _digest) = pbs_config::verify::config()?;
+    let list: Vec<VerificationJobConfig> = config.convert_to_typed_array("verification")?;
+    for job in list {
+        push_job("verificationjob", job.id, job.schedule.as_deref());
+    }
+
+    let (config, _digest) = pbs_config::sync::config()?;
+    let list: Vec<SyncJobConfig> = config.convert_to_typed_array("sync")?;
+    for job in list {
+        push_job("syncjob", job.id, job.schedule.as_deref());
+    }
+
+    let (config, _digest) = pbs_config::tape_job::config()?;
+    let list: Vec<TapeBackupJobConfig> = config.convert_to_typed_array("backup")?;
+    for job in list {
+        push_job("tape-backup-job", job.id, job.schedule.as_deref());
+    }
+
+    writer.family(
+        "pbs_job_running",
+        MetricType::Gauge,
+        "Whether the job is currently running.",
+    );
+    for job in jobs.iter() {
+        let value = if job.running { 1.0 } else { 0.0 };
+        writer.sample(&[("type", job.jobtype), ("id", &job.id)], value);
+    }
+
+    writer.family(
+        "pbs_job_last_run_timestamp_seconds",
+        MetricType::Gauge,
+        "End time of the last run of the job.",
+    );
+    for job in jobs.iter() {
+        if let Some((_, endtime, _)) = job.last_run {
+            writer.sample(&[("type", job.jobtype), ("id", &job.id)], endtime as f64);
+        }
+    }
+
+    writer.family(
+        "pbs_job_last_run_duration_seconds",
+        MetricType::Gauge,
+        "Duration of the last run of the job.",
+    );
+    for job in jobs.iter() {
+        if let Some((starttime, endtime, _)) = job.last_run {
+            let duration = endtime.saturating_sub(starttime).max(0);
+            writer.sample(&[("type", job.jobtype), ("id", &job.id)], duration as f64);
+        }
+    }
+
+    writer.family(
+        "pbs_job_last_run_success",
+        MetricType::Gauge,
+        "Whether the last run of the job finished without errors.",
+    );
+    for job in jobs.iter() {
+        if let Some((_, _, success)) = job.last_run {
+            let value = if success { 1.0 } else { 0.0 };
+            writer.sample(&[("type", job.jobtype), ("id", &job.id)], value);
+        }
+    }
+
+    writer.family(
+        "pbs_job_next_run_timestamp_seconds",
+        MetricType::Gauge,
+        "Time of the next scheduled run of the job.",
+    );
+    for job in jobs.iter() {
+        if let Some(next_run) = job.next_run {
+            writer.sample(&[("type", job.jobtype), ("id", &job.id)], next_run as f64);
+        }
+    }
+
+    Ok(())
+}
+
+fn task_state_label(state: &Option<TaskState>) -> &'static str {
+    match state {
+        None => "running",
+        Some(TaskState::OK { .. }) => "ok",
+        Some(TaskState::Warning { .. }) => "warning",
+        Some(TaskState::Error { .. }) => "error",
+        Some(TaskState::Unknown { .. }) => "unknown",
+    }
+}
+
+fn write_task_metrics(writer: &mut MetricWriter) -> Result<(), Error> {
+    let since = proxmox_time::epoch_i64() - TASK_COUNT_TIMESPAN;
+
+    let mut counts: BTreeMap<(String, &'static str), u64> = BTreeMap::new();
+
+    for info in TaskListInfoIterator::new(false)? {
+        let info = match info {
+            Ok(info) => info,
+            Err(_) => break,
+        };
+
+        if let Some(state) = &info.state {
+            if state.endtime() < since {
+                // the remaining tasks ended before the time span
+                break;
+            }
+        }
+        if info.upid.starttime < since {
+            continue;
+        }
+
+        let key = (info.upid.worker_type.clone(), task_state_label(&info.state));
+        *counts.entry(key).or_default() += 1;
+    }
+
+    writer.family(
+        "pbs_tasks",
+        MetricType::Gauge,
+        "Number of tasks started within the last 24 hours, by type and state.",
+    );
+    for ((worker_type, state), count) in counts {
+        writer.sample(&[("type", &worker_type), ("state", state)], count as f64);
+    }
+
+    Ok(())
+}
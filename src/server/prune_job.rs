@@ -123,6 +123,18 @@ pub(crate) fn cli_keep_options(opts: &mut Vec<String>, options: &KeepOptions) {
             _ => {}
         };
     }
+    for (key, span) in [
+        ("within", &options.keep_within),
+        ("within-hourly", &options.keep_within_hourly),
+        ("within-daily", &options.keep_within_daily),
+        ("within-weekly", &options.keep_within_weekly),
+        ("within-monthly", &options.keep_within_monthly),
+        ("within-yearly", &options.keep_within_yearly),
+    ] {
+        if let Some(span) = span {
+            opts.push(format!("--keep-{key} {span}"));
+        }
+    }
 }
 
 pub fn do_prune_job(
@@ -1,6 +1,6 @@
 //! Sync datastore from remote server
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{Seek, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -11,12 +11,15 @@ use anyhow::{bail, format_err, Error};
 use http::StatusCode;
 use proxmox_rest_server::WorkerTask;
 use proxmox_router::HttpError;
+use proxmox_sys::fs::{file_read_optional_string, make_tmp_file, replace_file, CreateOptions};
 use proxmox_sys::{task_log, task_warn};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use pbs_api_types::{
     print_store_and_ns, Authid, BackupDir, BackupGroup, BackupNamespace, CryptMode, GroupFilter,
-    GroupListItem, Operation, RateLimitConfig, Remote, SnapshotListItem, MAX_NAMESPACE_DEPTH,
+    GroupListItem, Operation, RateLimitConfig, Remote, SnapshotListItem, SnapshotVerifyState,
+    SyncEncryptionFilter, SyncRenameRule, SyncSnapshotFilter, VerifyState, MAX_NAMESPACE_DEPTH,
     PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_READ,
 };
 use pbs_client::{BackupReader, BackupRepository, HttpClient, RemoteChunkReader};
@@ -34,7 +37,10 @@ use pbs_datastore::{
 };
 use pbs_tools::sha::sha256;
 
-use crate::backup::{check_ns_modification_privs, check_ns_privs, ListAccessibleBackupGroups};
+use crate::backup::{
+    check_ns_modification_privs, check_ns_privs, verify_backup_dir_with_lock,
+    ListAccessibleBackupGroups, VerifyWorker,
+};
 use crate::tools::parallel_handler::ParallelHandler;
 
 struct RemoteReader {
@@ -54,6 +60,7 @@ pub(crate) struct PullTarget {
 }
 
 pub(crate) struct RemoteSource {
+    remote: String,
     repo: BackupRepository,
     ns: BackupNamespace,
     client: HttpClient,
@@ -64,6 +71,15 @@ pub(crate) struct LocalSource {
     ns: BackupNamespace,
 }
 
+/// A snapshot on the source side, with the properties needed for filtering.
+struct SourceSnapshot {
+    dir: BackupDir,
+    verify_state: Option<VerifyState>,
+    protected: bool,
+    /// Whether at least one archive of the snapshot is encrypted
+    encrypted: bool,
+}
+
 #[async_trait::async_trait]
 /// `PullSource` is a trait that provides an interface for pulling data/information from a source.
 /// The trait includes methods for listing namespaces, groups, and backup directories,
@@ -89,10 +105,13 @@ trait PullSource: Send + Sync {
         namespace: &BackupNamespace,
         group: &BackupGroup,
         worker: &WorkerTask,
-    ) -> Result<Vec<BackupDir>, Error>;
+    ) -> Result<Vec<SourceSnapshot>, Error>;
     fn get_ns(&self) -> BackupNamespace;
     fn get_store(&self) -> &str;
 
+    /// Returns the name of the remote, `None` for local sources.
+    fn get_remote(&self) -> Option<&str>;
+
     /// Returns a reader for reading data from a specific backup directory.
     async fn reader(
         &self,
@@ -190,7 +209,7 @@ impl PullSource for RemoteSource {
         namespace: &BackupNamespace,
         group: &BackupGroup,
         worker: &WorkerTask,
-    ) -> Result<Vec<BackupDir>, Error> {
+    ) -> Result<Vec<SourceSnapshot>, Error> {
         let path = format!("api2/json/admin/datastore/{}/snapshots", self.repo.store());
 
         let mut args = json!({
@@ -220,9 +239,19 @@ impl PullSource for RemoteSource {
                     return None;
                 }
 
-                Some(snapshot)
+                let encrypted = item
+                    .files
+                    .iter()
+                    .any(|file| file.crypt_mode == Some(CryptMode::Encrypt));
+
+                Some(SourceSnapshot {
+                    dir: snapshot,
+                    verify_state: item.verification.map(|verification| verification.state),
+                    protected: item.protected,
+                    encrypted,
+                })
             })
-            .collect::<Vec<BackupDir>>())
+            .collect::<Vec<SourceSnapshot>>())
     }
 
     fn get_ns(&self) -> BackupNamespace {
@@ -233,6 +262,10 @@ impl PullSource for RemoteSource {
         &self.repo.store()
     }
 
+    fn get_remote(&self) -> Option<&str> {
+        Some(&self.remote)
+    }
+
     async fn reader(
         &self,
         ns: &BackupNamespace,
@@ -285,14 +318,35 @@ impl PullSource for LocalSource {
         namespace: &BackupNamespace,
         group: &BackupGroup,
         _worker: &WorkerTask,
-    ) -> Result<Vec<BackupDir>, Error> {
+    ) -> Result<Vec<SourceSnapshot>, Error> {
         Ok(self
             .store
             .backup_group(namespace.clone(), group.clone())
             .iter_snapshots()?
             .filter_map(Result::ok)
-            .map(|snapshot| snapshot.dir().to_owned())
-            .collect::<Vec<BackupDir>>())
+            .map(|snapshot| {
+                let (verify_state, encrypted) = match snapshot.load_manifest() {
+                    Ok((manifest, _)) => (
+                        serde_json::from_value::<SnapshotVerifyState>(
+                            manifest.unprotected["verify_state"].clone(),
+                        )
+                        .ok()
+                        .map(|verification| verification.state),
+                        manifest
+                            .files()
+                            .iter()
+                            .any(|file| file.crypt_mode == CryptMode::Encrypt),
+                    ),
+                    Err(_) => (None, false),
+                };
+                SourceSnapshot {
+                    dir: snapshot.dir().to_owned(),
+                    verify_state,
+                    protected: snapshot.is_protected(),
+                    encrypted,
+                }
+            })
+            .collect::<Vec<SourceSnapshot>>())
     }
 
     fn get_ns(&self) -> BackupNamespace {
@@ -303,6 +357,10 @@ impl PullSource for LocalSource {
         self.store.name()
     }
 
+    fn get_remote(&self) -> Option<&str> {
+        None
+    }
+
     async fn reader(
         &self,
         ns: &BackupNamespace,
@@ -473,6 +531,74 @@ impl PullReader for LocalReader {
     }
 }
 
+/// Snapshot filters of a pull operation, with the time limits resolved at its start.
+struct SnapshotFilter {
+    /// Only snapshots newer than this epoch
+    newer_than: Option<i64>,
+    /// Only snapshots older than this epoch
+    older_than: Option<i64>,
+    verified_only: bool,
+    protected_only: bool,
+    encryption: Option<SyncEncryptionFilter>,
+}
+
+impl SnapshotFilter {
+    fn new(filter: &SyncSnapshotFilter, now: i64) -> Result<Self, Error> {
+        let newer_than = filter
+            .newer_than
+            .as_deref()
+            .map(|value| pbs_api_types::parse_sync_time_limit(value, now))
+            .transpose()?;
+        let older_than = filter
+            .older_than
+            .as_deref()
+            .map(|value| -> Result<i64, Error> {
+                let span: proxmox_time::TimeSpan = value.parse()?;
+                Ok(now - f64::from(span) as i64)
+            })
+            .transpose()?;
+
+        Ok(Self {
+            newer_than,
+            older_than,
+            verified_only: filter.verified_only.unwrap_or(false),
+            protected_only: filter.protected_only.unwrap_or(false),
+            encryption: filter.encryption_filter,
+        })
+    }
+
+    /// Returns why `snapshot` is excluded by the filters, if it is.
+    fn skip_reason(&self, snapshot: &SourceSnapshot) -> Option<SkipReason> {
+        if self
+            .newer_than
+            .map_or(false, |time| snapshot.dir.time <= time)
+        {
+            return Some(SkipReason::NewerThan);
+        }
+        if self
+            .older_than
+            .map_or(false, |time| snapshot.dir.time >= time)
+        {
+            return Some(SkipReason::OlderThan);
+        }
+        if self.verified_only && snapshot.verify_state != Some(VerifyState::Ok) {
+            return Some(SkipReason::NotVerified);
+        }
+        if self.protected_only && !snapshot.protected {
+            return Some(SkipReason::NotProtected);
+        }
+        match self.encryption {
+            Some(SyncEncryptionFilter::Encrypted) if !snapshot.encrypted => {
+                Some(SkipReason::EncryptionFilter)
+            }
+            Some(SyncEncryptionFilter::Unencrypted) if snapshot.encrypted => {
+                Some(SkipReason::EncryptionFilter)
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Parameters for a pull operation.
 pub(crate) struct PullParameters {
     /// Where data is pulled from
@@ -489,10 +615,23 @@ pub(crate) struct PullParameters {
     group_filter: Vec<GroupFilter>,
     /// How many snapshots should be transferred at most (taking the newest N snapshots)
     transfer_last: Option<usize>,
+    /// Whether to repair local snapshots which failed verification from the source
+    repair_corrupt: bool,
+    /// How many groups to pull in parallel
+    parallel_groups: usize,
+    /// How many chunks to download in parallel per group
+    chunk_concurrency: usize,
+    /// Filters for the snapshots of each group
+    snapshot_filter: SnapshotFilter,
+    /// Only log what would be pulled or removed, without writing to the target datastore
+    dry_run: bool,
+    /// Rules for renaming namespaces and backup IDs
+    rename_rules: Vec<SyncRenameRule>,
 }
 
 impl PullParameters {
     /// Creates a new instance of `PullParameters`.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         store: &str,
         ns: BackupNamespace,
@@ -505,12 +644,21 @@ impl PullParameters {
         group_filter: Option<Vec<GroupFilter>>,
         limit: RateLimitConfig,
         transfer_last: Option<usize>,
+        repair_corrupt: Option<bool>,
+        parallel_groups: Option<usize>,
+        chunk_concurrency: Option<usize>,
+        snapshot_filter: SyncSnapshotFilter,
+        rename_rules: Option<Vec<SyncRenameRule>>,
     ) -> Result<Self, Error> {
         if let Some(max_depth) = max_depth {
             ns.check_max_depth(max_depth)?;
             remote_ns.check_max_depth(max_depth)?;
         };
         let remove_vanished = remove_vanished.unwrap_or(false);
+        let repair_corrupt = repair_corrupt.unwrap_or(false);
+        let parallel_groups = parallel_groups.unwrap_or(1);
+        let chunk_concurrency = chunk_concurrency.unwrap_or(20);
+        let snapshot_filter = SnapshotFilter::new(&snapshot_filter, proxmox_time::epoch_i64())?;
 
         let source: Arc<dyn PullSource> = if let Some(remote) = remote {
             let (remote_config, _digest) = pbs_config::remote::config()?;
@@ -524,6 +672,7 @@ impl PullParameters {
             );
             let client = crate::api2::config::remote::remote_client_config(&remote, Some(limit))?;
             Arc::new(RemoteSource {
+                remote: remote.name,
                 repo,
                 ns: remote_ns,
                 client,
@@ -540,6 +689,7 @@ impl PullParameters {
         };
 
         let group_filter = group_filter.unwrap_or_default();
+        let rename_rules = rename_rules.unwrap_or_default();
 
         Ok(Self {
             source,
@@ -549,8 +699,58 @@ impl PullParameters {
             max_depth,
             group_filter,
             transfer_last,
+            repair_corrupt,
+            parallel_groups,
+            chunk_concurrency,
+            snapshot_filter,
+            dry_run: false,
+            rename_rules,
         })
     }
+
+    /// Maps a source namespace to the target, applying the namespace rename rules.
+    fn map_namespace(&self, namespace: &BackupNamespace) -> Result<BackupNamespace, Error> {
+        let relative = namespace.map_prefix(&self.source.get_ns(), &BackupNamespace::root())?;
+        let renamed = SyncRenameRule::rename_namespace(&self.rename_rules, &relative)?;
+        renamed.map_prefix(&BackupNamespace::root(), &self.target.ns)
+    }
+
+    /// Maps a source group to the target, applying the backup ID rename rules.
+    fn map_group(&self, group: &BackupGroup) -> Result<BackupGroup, Error> {
+        let id = SyncRenameRule::rename_backup_id(&self.rename_rules, &group.id)?;
+        Ok(BackupGroup::new(group.ty, id))
+    }
+
+    /// Returns where `group` of `namespace` is pulled from, for recording it in renamed groups.
+    fn sync_source(&self, namespace: &BackupNamespace, group: &BackupGroup) -> SyncSource {
+        SyncSource {
+            remote: self.source.get_remote().map(String::from),
+            store: self.source.get_store().to_string(),
+            ns: namespace.clone(),
+            group: group.clone(),
+        }
+    }
+
+    /// Only log what would be pulled or removed, without writing to the target datastore.
+    pub(crate) fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+}
+
+/// Statistics of pulling chunks.
+#[derive(Default)]
+struct PullStats {
+    /// Number of bytes downloaded
+    bytes: usize,
+    /// Number of referenced chunks which already existed locally
+    present_chunks: usize,
+}
+
+impl std::ops::AddAssign for PullStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.bytes += rhs.bytes;
+        self.present_chunks += rhs.present_chunks;
+    }
 }
 
 async fn pull_index_chunks<I: IndexFile>(
@@ -559,7 +759,8 @@ async fn pull_index_chunks<I: IndexFile>(
     target: Arc<DataStore>,
     index: I,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
+    concurrency: usize,
+) -> Result<PullStats, Error> {
     use futures::stream::{self, StreamExt, TryStreamExt};
 
     let start_time = SystemTime::now();
@@ -594,12 +795,14 @@ async fn pull_index_chunks<I: IndexFile>(
     let verify_and_write_channel = verify_pool.channel();
 
     let bytes = Arc::new(AtomicUsize::new(0));
+    let present_chunks = Arc::new(AtomicUsize::new(0));
 
     stream
         .map(|info| {
             let target = Arc::clone(&target);
             let chunk_reader = chunk_reader.clone();
             let bytes = Arc::clone(&bytes);
+            let present_chunks = Arc::clone(&present_chunks);
             let verify_and_write_channel = verify_and_write_channel.clone();
 
             Ok::<_, Error>(async move {
@@ -608,6 +811,7 @@ async fn pull_index_chunks<I: IndexFile>(
                 })?;
                 if chunk_exists {
                     //task_log!(worker, "chunk {} exists {}", pos, hex::encode(digest));
+                    present_chunks.fetch_add(1, Ordering::SeqCst);
                     return Ok::<_, Error>(());
                 }
                 //task_log!(worker, "sync {} chunk {}", pos, hex::encode(digest));
@@ -624,7 +828,7 @@ async fn pull_index_chunks<I: IndexFile>(
                 Ok(())
             })
         })
-        .try_buffer_unordered(20)
+        .try_buffer_unordered(concurrency)
         .try_for_each(|_res| futures::future::ok(()))
         .await?;
 
@@ -643,7 +847,10 @@ async fn pull_index_chunks<I: IndexFile>(
         (bytes as f64) / (1024.0 * 1024.0 * elapsed)
     );
 
-    Ok(())
+    Ok(PullStats {
+        bytes,
+        present_chunks: present_chunks.load(Ordering::SeqCst),
+    })
 }
 
 fn verify_archive(info: &FileInfo, csum: &[u8; 32], size: u64) -> Result<(), Error> {
@@ -677,7 +884,8 @@ async fn pull_single_archive<'a>(
     snapshot: &'a pbs_datastore::BackupDir,
     archive_info: &'a FileInfo,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
+    chunk_concurrency: usize,
+) -> Result<PullStats, Error> {
     let archive_name = &archive_info.filename;
     let mut path = snapshot.full_path();
     path.push(archive_name);
@@ -693,6 +901,8 @@ async fn pull_single_archive<'a>(
 
     let mut tmpfile = std::fs::OpenOptions::new().read(true).open(&tmp_path)?;
 
+    let mut stats = PullStats::default();
+
     match archive_type(archive_name)? {
         ArchiveType::DynamicIndex => {
             let index = DynamicIndexReader::new(tmpfile).map_err(|err| {
@@ -704,12 +914,13 @@ async fn pull_single_archive<'a>(
             if reader.skip_chunk_sync(snapshot.datastore().name()) {
                 task_log!(worker, "skipping chunk sync for same datastore");
             } else {
-                pull_index_chunks(
+                stats = pull_index_chunks(
                     worker,
                     reader.chunk_reader(archive_info.crypt_mode),
                     snapshot.datastore().clone(),
                     index,
                     downloaded_chunks,
+                    chunk_concurrency,
                 )
                 .await?;
             }
@@ -724,12 +935,13 @@ async fn pull_single_archive<'a>(
             if reader.skip_chunk_sync(snapshot.datastore().name()) {
                 task_log!(worker, "skipping chunk sync for same datastore");
             } else {
-                pull_index_chunks(
+                stats = pull_index_chunks(
                     worker,
                     reader.chunk_reader(archive_info.crypt_mode),
                     snapshot.datastore().clone(),
                     index,
                     downloaded_chunks,
+                    chunk_concurrency,
                 )
                 .await?;
             }
@@ -743,7 +955,7 @@ async fn pull_single_archive<'a>(
     if let Err(err) = std::fs::rename(&tmp_path, &path) {
         bail!("Atomic rename file {:?} failed - {}", path, err);
     }
-    Ok(())
+    Ok(stats)
 }
 
 /// Actual implementation of pulling a snapshot.
@@ -755,12 +967,18 @@ async fn pull_single_archive<'a>(
 /// -- if file already exists, verify contents
 /// -- if not, pull it from the remote
 /// - Download log if not already existing
+///
+/// If `progress` is set, it is updated after each pulled archive.
 async fn pull_snapshot<'a>(
     worker: &'a WorkerTask,
     reader: Arc<dyn PullReader + 'a>,
     snapshot: &'a pbs_datastore::BackupDir,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
+    chunk_concurrency: usize,
+    mut progress: Option<PullProgress>,
+) -> Result<PullStats, Error> {
+    let mut stats = PullStats::default();
+
     let mut manifest_name = snapshot.full_path();
     manifest_name.push(MANIFEST_BLOB_NAME);
 
@@ -776,7 +994,7 @@ async fn pull_snapshot<'a>(
     {
         tmp_manifest_blob = data;
     } else {
-        return Ok(());
+        return Ok(stats);
     }
 
     if manifest_name.exists() {
@@ -800,7 +1018,7 @@ async fn pull_snapshot<'a>(
             };
             task_log!(worker, "no data changes");
             let _ = std::fs::remove_file(&tmp_manifest_name);
-            return Ok(()); // nothing changed
+            return Ok(stats); // nothing changed
         }
     }
 
@@ -845,14 +1063,29 @@ async fn pull_snapshot<'a>(
             }
         }
 
-        pull_single_archive(
+        let archive_stats = pull_single_archive(
             worker,
             reader.clone(),
             snapshot,
             item,
             downloaded_chunks.clone(),
+            chunk_concurrency,
         )
         .await?;
+
+        if let Some(progress) = progress.as_mut() {
+            progress
+                .archives
+                .insert(item.filename.clone(), archive_stats.bytes as u64);
+            progress.save(snapshot)?;
+        }
+        stats += archive_stats;
+    }
+
+    // the snapshot is complete once the manifest is in place, so the progress has to go first
+    if progress.is_some() {
+        std::fs::remove_file(snapshot.pull_progress_file())
+            .map_err(|err| format_err!("unable to remove pull progress - {err}"))?;
     }
 
     if let Err(err) = std::fs::rename(&tmp_manifest_name, &manifest_name) {
@@ -868,10 +1101,108 @@ async fn pull_snapshot<'a>(
         .cleanup_unreferenced_files(&manifest)
         .map_err(|err| format_err!("failed to cleanup unreferenced files - {err}"))?;
 
-    Ok(())
+    snapshot
+        .datastore()
+        .upload_snapshot_to_backend(snapshot)
+        .map_err(|err| format_err!("failed to upload snapshot to backend - {err}"))?;
+
+    Ok(stats)
+}
+
+/// Progress of pulling a new snapshot.
+///
+/// It is stored in the snapshot directory until the snapshot is complete, so that the next sync
+/// can resume an interrupted pull. Archives which are already pulled are skipped, and so are the
+/// chunks of the interrupted archive which already made it into the chunk store.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PullProgress {
+    /// Pulled archives with the number of bytes downloaded for them
+    archives: BTreeMap<String, u64>,
+}
+
+impl PullProgress {
+    fn load(snapshot: &pbs_datastore::BackupDir) -> Result<Self, Error> {
+        Self::load_file(&snapshot.pull_progress_file())
+    }
+
+    fn load_file(path: &Path) -> Result<Self, Error> {
+        let data = file_read_optional_string(path)?.unwrap_or_default();
+        if data.is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&data)
+            .map_err(|err| format_err!("unable to parse pull progress {path:?} - {err}"))
+    }
+
+    fn save(&self, snapshot: &pbs_datastore::BackupDir) -> Result<(), Error> {
+        self.save_file(&snapshot.pull_progress_file())
+    }
+
+    fn save_file(&self, path: &Path) -> Result<(), Error> {
+        let data = serde_json::to_vec(self)?;
+        replace_file(path, &data, CreateOptions::new(), false)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.archives.values().sum()
+    }
+}
+
+/// Source of a group which was pulled under another name because of rename rules.
+///
+/// It is stored in the local group directory, so that later syncs don't pull a different source
+/// group into it and `remove-vanished` only considers groups pulled from the same source.
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+struct SyncSource {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote: Option<String>,
+    store: String,
+    ns: BackupNamespace,
+    #[serde(flatten)]
+    group: BackupGroup,
+}
+
+impl SyncSource {
+    fn load(group: &pbs_datastore::BackupGroup) -> Result<Option<Self>, Error> {
+        let path = group.sync_source_file();
+        match file_read_optional_string(&path)? {
+            Some(data) => serde_json::from_str(&data)
+                .map(Some)
+                .map_err(|err| format_err!("unable to parse sync source {path:?} - {err}")),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, group: &pbs_datastore::BackupGroup) -> Result<(), Error> {
+        let data = serde_json::to_vec(self)?;
+        replace_file(group.sync_source_file(), &data, CreateOptions::new(), false)
+    }
+
+    /// Whether both were pulled from the same namespace of the same datastore.
+    fn same_namespace(&self, other: &Self) -> bool {
+        self.remote == other.remote && self.store == other.store && self.ns == other.ns
+    }
+}
+
+impl std::fmt::Display for SyncSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let store = match &self.remote {
+            Some(remote) => format!("{remote}/{}", self.store),
+            None => self.store.clone(),
+        };
+        write!(
+            f,
+            "{} of {}",
+            self.group,
+            print_store_and_ns(&store, &self.ns)
+        )
+    }
 }
 
-/// Pulls a `snapshot`, removing newly created ones on error, but keeping existing ones in any case.
+/// Pulls a `snapshot`, keeping newly created ones as partial snapshots on error, so that the
+/// next sync can resume them. Existing snapshots are kept in any case.
 ///
 /// The `reader` is configured to read from the source backup directory, while the
 /// `snapshot` is pointing to the local datastore and target namespace.
@@ -880,15 +1211,55 @@ async fn pull_snapshot_from<'a>(
     reader: Arc<dyn PullReader + 'a>,
     snapshot: &'a pbs_datastore::BackupDir,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    chunk_concurrency: usize,
 ) -> Result<(), Error> {
     let (_path, is_new, _snap_lock) = snapshot
         .datastore()
         .create_locked_backup_dir(snapshot.backup_ns(), snapshot.as_ref())?;
 
-    if is_new {
-        task_log!(worker, "sync snapshot {}", snapshot.dir());
+    if is_new || snapshot.is_partially_pulled() {
+        let progress = if is_new {
+            task_log!(worker, "sync snapshot {}", snapshot.dir());
+            let progress = PullProgress::default();
+            progress.save(snapshot)?;
+            progress
+        } else {
+            let progress = PullProgress::load(snapshot).unwrap_or_else(|err| {
+                task_warn!(worker, "{err}");
+                PullProgress::default()
+            });
+            task_log!(
+                worker,
+                "resume sync of snapshot {} - {} archive(s) with {} bytes pulled before",
+                snapshot.dir(),
+                progress.archives.len(),
+                progress.bytes(),
+            );
+            progress
+        };
 
-        if let Err(err) = pull_snapshot(worker, reader, snapshot, downloaded_chunks).await {
+        let pull_future = pull_snapshot(
+            worker,
+            reader,
+            snapshot,
+            downloaded_chunks,
+            chunk_concurrency,
+            Some(progress),
+        );
+        let stats = match pull_future.await {
+            Ok(stats) => stats,
+            Err(err) => {
+                task_log!(
+                    worker,
+                    "keeping partially synced snapshot {} to resume it later",
+                    snapshot.dir()
+                );
+                return Err(err);
+            }
+        };
+
+        if snapshot.is_partially_pulled() {
+            // the source snapshot vanished, so this one can't be completed anymore
             if let Err(cleanup_err) = snapshot.datastore().remove_backup_dir(
                 snapshot.backup_ns(),
                 snapshot.as_ref(),
@@ -896,21 +1267,161 @@ async fn pull_snapshot_from<'a>(
             ) {
                 task_log!(worker, "cleanup error - {}", cleanup_err);
             }
-            return Err(err);
+            return Ok(());
+        }
+
+        if !is_new {
+            task_log!(
+                worker,
+                "resumed sync of snapshot {} - downloaded {} bytes, {} chunks were already present",
+                snapshot.dir(),
+                stats.bytes,
+                stats.present_chunks,
+            );
         }
+
+        snapshot
+            .datastore()
+            .apply_default_retention_lock(snapshot)
+            .map_err(|err| format_err!("unable to apply retention lock - {err}"))?;
         task_log!(worker, "sync snapshot {} done", snapshot.dir());
     } else {
         task_log!(worker, "re-sync snapshot {}", snapshot.dir());
-        pull_snapshot(worker, reader, snapshot, downloaded_chunks).await?;
+        pull_snapshot(
+            worker,
+            reader,
+            snapshot,
+            downloaded_chunks,
+            chunk_concurrency,
+            None,
+        )
+        .await?;
     }
 
     Ok(())
 }
 
-#[derive(PartialEq, Eq)]
-enum SkipReason {
+/// Whether the last verification of the local `snapshot` failed.
+fn verification_failed(snapshot: &pbs_datastore::BackupDir) -> bool {
+    let manifest = match snapshot.load_manifest() {
+        Ok((manifest, _)) => manifest,
+        Err(_) => return false,
+    };
+    let verify_state = manifest.unprotected["verify_state"].clone();
+    matches!(
+        serde_json::from_value::<SnapshotVerifyState>(verify_state),
+        Ok(SnapshotVerifyState {
+            state: VerifyState::Failed,
+            ..
+        })
+    )
+}
+
+/// Repairs a local `snapshot` which failed verification, returns whether it verifies again.
+///
+/// Repairing a snapshot consists of the following steps:
+/// - Load the index files from the source, they need to match the local ones
+/// - Pull all referenced chunks which are missing locally, e.g. because verification renamed
+///   them to `.bad`
+/// - Verify the snapshot again
+async fn repair_snapshot(
+    worker: &Arc<WorkerTask>,
+    reader: Arc<dyn PullReader>,
+    snapshot: &pbs_datastore::BackupDir,
+    chunk_concurrency: usize,
+) -> Result<bool, Error> {
+    let snap_lock = proxmox_sys::fs::lock_dir_noblock_shared(
+        &snapshot.full_path(),
+        "snapshot",
+        "locked by another operation",
+    )?;
+
+    let (manifest, _) = snapshot.load_manifest()?;
+
+    let downloaded_chunks = Arc::new(Mutex::new(HashSet::new()));
+
+    for item in manifest.files() {
+        let file_type = archive_type(&item.filename)?;
+        if file_type == ArchiveType::Blob {
+            continue;
+        }
+
+        let mut tmp_path = snapshot.full_path();
+        tmp_path.push(&item.filename);
+        tmp_path.set_extension("tmp");
+
+        task_log!(worker, "repair archive {}", item.filename);
+
+        // the index is only needed to look up its chunks, so don't keep it around
+        let result = reader
+            .load_file_into(&item.filename, &tmp_path, worker)
+            .await
+            .and_then(|_| Ok(std::fs::File::open(&tmp_path)?));
+        let _ = std::fs::remove_file(&tmp_path);
+        let tmpfile = result?;
+
+        let chunk_reader = reader.chunk_reader(item.crypt_mode);
+        let target = snapshot.datastore().clone();
+
+        match file_type {
+            ArchiveType::DynamicIndex => {
+                let index = DynamicIndexReader::new(tmpfile)
+                    .map_err(|err| format_err!("unable to read dynamic index - {err}"))?;
+                let (csum, size) = index.compute_csum();
+                verify_archive(item, &csum, size)
+                    .map_err(|err| format_err!("source archive differs - {err}"))?;
+                pull_index_chunks(
+                    worker,
+                    chunk_reader,
+                    target,
+                    index,
+                    downloaded_chunks.clone(),
+                    chunk_concurrency,
+                )
+                .await?;
+            }
+            ArchiveType::FixedIndex => {
+                let index = FixedIndexReader::new(tmpfile)
+                    .map_err(|err| format_err!("unable to read fixed index - {err}"))?;
+                let (csum, size) = index.compute_csum();
+                verify_archive(item, &csum, size)
+                    .map_err(|err| format_err!("source archive differs - {err}"))?;
+                pull_index_chunks(
+                    worker,
+                    chunk_reader,
+                    target,
+                    index,
+                    downloaded_chunks.clone(),
+                    chunk_concurrency,
+                )
+                .await?;
+            }
+            ArchiveType::Blob => unreachable!(),
+        }
+    }
+
+    let verify_worker = VerifyWorker::new(worker.clone(), snapshot.datastore().clone());
+    proxmox_async::runtime::block_in_place(|| {
+        verify_backup_dir_with_lock(
+            &verify_worker,
+            snapshot,
+            worker.upid().clone(),
+            None,
+            snap_lock,
+        )
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SkipReason {
     AlreadySynced,
+    AlreadyPushed,
     TransferLast,
+    NewerThan,
+    OlderThan,
+    NotVerified,
+    NotProtected,
+    EncryptionFilter,
 }
 
 impl std::fmt::Display for SkipReason {
@@ -920,21 +1431,27 @@ impl std::fmt::Display for SkipReason {
             "{}",
             match self {
                 SkipReason::AlreadySynced => "older than the newest local snapshot",
+                SkipReason::AlreadyPushed => "older than the newest snapshot on the remote",
                 SkipReason::TransferLast => "due to transfer-last",
+                SkipReason::NewerThan => "due to newer-than",
+                SkipReason::OlderThan => "due to older-than",
+                SkipReason::NotVerified => "not verified successfully",
+                SkipReason::NotProtected => "not protected",
+                SkipReason::EncryptionFilter => "due to encryption-filter",
             }
         )
     }
 }
 
-struct SkipInfo {
+pub(crate) struct SkipInfo {
     oldest: i64,
     newest: i64,
-    count: u64,
+    pub(crate) count: u64,
     skip_reason: SkipReason,
 }
 
 impl SkipInfo {
-    fn new(skip_reason: SkipReason) -> Self {
+    pub(crate) fn new(skip_reason: SkipReason) -> Self {
         SkipInfo {
             oldest: i64::MAX,
             newest: i64::MIN,
@@ -943,13 +1460,13 @@ impl SkipInfo {
         }
     }
 
-    fn reset(&mut self) {
+    pub(crate) fn reset(&mut self) {
         self.count = 0;
         self.oldest = i64::MAX;
         self.newest = i64::MIN;
     }
 
-    fn update(&mut self, backup_time: i64) {
+    pub(crate) fn update(&mut self, backup_time: i64) {
         self.count += 1;
 
         if backup_time < self.oldest {
@@ -986,6 +1503,148 @@ impl std::fmt::Display for SkipInfo {
     }
 }
 
+/// Progress of pulling the groups of a namespace, some of which might be pulled in parallel.
+struct GroupsProgress {
+    total_groups: u64,
+    done_groups: u64,
+    /// Pulled snapshots of all groups
+    done_snapshots: u64,
+    /// Done and total snapshots of the groups currently being pulled
+    active: HashMap<BackupGroup, (u64, u64)>,
+}
+
+impl GroupsProgress {
+    fn new(total_groups: u64) -> Self {
+        Self {
+            total_groups,
+            done_groups: 0,
+            done_snapshots: 0,
+            active: HashMap::new(),
+        }
+    }
+
+    fn start_group(&mut self, group: &BackupGroup, group_snapshots: u64) {
+        self.active.insert(group.clone(), (0, group_snapshots));
+    }
+
+    fn update_group(&mut self, group: &BackupGroup, done_snapshots: u64) {
+        if let Some((done, _total)) = self.active.get_mut(group) {
+            self.done_snapshots += done_snapshots.saturating_sub(*done);
+            *done = done_snapshots;
+        }
+    }
+
+    fn finish_group(&mut self, group: &BackupGroup) {
+        self.active.remove(group);
+        self.done_groups += 1;
+    }
+
+    /// Progress of the namespace as a whole, as returned by [`pull_ns`].
+    fn store_progress(&self) -> StoreProgress {
+        StoreProgress {
+            done_groups: self.done_groups,
+            total_groups: self.total_groups,
+            done_snapshots: self.done_snapshots,
+            group_snapshots: 0,
+        }
+    }
+}
+
+impl std::fmt::Display for GroupsProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.active.len() <= 1 {
+            // same output as when pulling one group after the other
+            let (done_snapshots, group_snapshots) =
+                self.active.values().next().copied().unwrap_or_default();
+            let progress = StoreProgress {
+                done_groups: self.done_groups,
+                total_groups: self.total_groups,
+                done_snapshots,
+                group_snapshots,
+            };
+            return write!(f, "{progress}");
+        }
+
+        let active: f64 = self
+            .active
+            .values()
+            .filter(|(_done, total)| *total > 0)
+            .map(|(done, total)| (*done as f64) / (*total as f64))
+            .sum();
+        let percentage = (self.done_groups as f64 + active) / (self.total_groups as f64);
+
+        write!(
+            f,
+            "{:.2}% ({}/{} groups, {} in progress)",
+            percentage * 100.0,
+            self.done_groups,
+            self.total_groups,
+            self.active.len(),
+        )
+    }
+}
+
+/// Estimates how many bytes pulling a snapshot from `reader` would transfer.
+///
+/// Counts the uncompressed size of all chunks referenced by the source indexes which are neither
+/// in the target datastore nor in `known_chunks`, plus the size of the blobs. The manifest and
+/// indexes are downloaded to a temporary file outside of the target datastore.
+async fn estimate_snapshot_bytes(
+    worker: &WorkerTask,
+    reader: Arc<dyn PullReader>,
+    target: &DataStore,
+    known_chunks: &mut HashSet<[u8; 32]>,
+) -> Result<u64, Error> {
+    let (tmp_file, tmp_path) = make_tmp_file("/tmp/proxmox-backup-pull", CreateOptions::new())?;
+    drop(tmp_file);
+
+    let result: Result<u64, Error> = async {
+        let manifest = match reader
+            .load_file_into(MANIFEST_BLOB_NAME, &tmp_path, worker)
+            .await?
+        {
+            Some(blob) => BackupManifest::try_from(blob)?,
+            None => return Ok(0), // vanished since start of sync
+        };
+
+        let mut bytes = 0;
+        for item in manifest.files() {
+            let index: Box<dyn IndexFile + Send> = match archive_type(&item.filename)? {
+                ArchiveType::Blob => {
+                    bytes += item.size;
+                    continue;
+                }
+                ArchiveType::DynamicIndex => {
+                    reader
+                        .load_file_into(&item.filename, &tmp_path, worker)
+                        .await?;
+                    Box::new(DynamicIndexReader::open(&tmp_path)?)
+                }
+                ArchiveType::FixedIndex => {
+                    reader
+                        .load_file_into(&item.filename, &tmp_path, worker)
+                        .await?;
+                    Box::new(FixedIndexReader::open(&tmp_path)?)
+                }
+            };
+            for pos in 0..index.index_count() {
+                let info = match index.chunk_info(pos) {
+                    Some(info) => info,
+                    None => continue,
+                };
+                if known_chunks.insert(info.digest) && !target.chunk_path(&info.digest).0.exists() {
+                    bytes += info.size();
+                }
+            }
+        }
+        Ok(bytes)
+    }
+    .await;
+
+    let _ = std::fs::remove_file(&tmp_path);
+    result
+}
+
 /// Pulls a group according to `params`.
 ///
 /// Pulling a group consists of the following steps:
@@ -1004,20 +1663,50 @@ impl std::fmt::Display for SkipInfo {
 /// - remote snapshot access is checked by remote (twice: query and opening the backup reader)
 /// - local group owner is already checked by pull_store
 async fn pull_group(
-    worker: &WorkerTask,
+    worker: &Arc<WorkerTask>,
     params: &PullParameters,
     source_namespace: &BackupNamespace,
     group: &BackupGroup,
-    progress: &mut StoreProgress,
+    target_group: &BackupGroup,
+    progress: &Mutex<GroupsProgress>,
+    downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
 ) -> Result<(), Error> {
     let mut already_synced_skip_info = SkipInfo::new(SkipReason::AlreadySynced);
     let mut transfer_last_skip_info = SkipInfo::new(SkipReason::TransferLast);
 
-    let mut raw_list: Vec<BackupDir> = params
+    let mut raw_list: Vec<SourceSnapshot> = params
         .source
         .list_backup_dirs(source_namespace, group, worker)
         .await?;
-    raw_list.sort_unstable_by(|a, b| a.time.cmp(&b.time));
+    raw_list.sort_unstable_by(|a, b| a.dir.time.cmp(&b.dir.time));
+
+    // all source snapshots count as existing for remove-vanished, even filtered ones
+    let source_snapshots: HashSet<i64> =
+        raw_list.iter().map(|snapshot| snapshot.dir.time).collect();
+
+    let mut filter_skip_infos: Vec<SkipInfo> = Vec::new();
+    raw_list.retain(
+        |snapshot| match params.snapshot_filter.skip_reason(snapshot) {
+            Some(reason) => {
+                match filter_skip_infos
+                    .iter_mut()
+                    .find(|info| info.skip_reason == reason)
+                {
+                    Some(info) => info.update(snapshot.dir.time),
+                    None => {
+                        let mut info = SkipInfo::new(reason);
+                        info.update(snapshot.dir.time);
+                        filter_skip_infos.push(info);
+                    }
+                }
+                false
+            }
+            None => true,
+        },
+    );
+    for info in filter_skip_infos {
+        task_log!(worker, "{}", info);
+    }
 
     let total_amount = raw_list.len();
 
@@ -1026,20 +1715,19 @@ async fn pull_group(
         .map(|count| total_amount.saturating_sub(count))
         .unwrap_or_default();
 
-    let target_ns = source_namespace.map_prefix(&params.source.get_ns(), &params.target.ns)?;
+    let target_ns = params.map_namespace(source_namespace)?;
 
-    let mut source_snapshots = HashSet::new();
     let last_sync_time = params
         .target
         .store
-        .last_successful_backup(&target_ns, group)?
+        .last_successful_backup(&target_ns, target_group)?
         .unwrap_or(i64::MIN);
 
     let list: Vec<BackupDir> = raw_list
         .into_iter()
+        .map(|snapshot| snapshot.dir)
         .enumerate()
         .filter(|&(pos, ref dir)| {
-            source_snapshots.insert(dir.time);
             if last_sync_time > dir.time {
                 already_synced_skip_info.update(dir.time);
                 return false;
@@ -1061,35 +1749,110 @@ async fn pull_group(
         .map(|(_, dir)| dir)
         .collect();
 
-    // start with 65536 chunks (up to 256 GiB)
-    let downloaded_chunks = Arc::new(Mutex::new(HashSet::with_capacity(1024 * 64)));
-
-    progress.group_snapshots = list.len() as u64;
+    progress
+        .lock()
+        .unwrap()
+        .start_group(group, list.len() as u64);
+
+    if params.dry_run {
+        let mut known_chunks = HashSet::new();
+        for (pos, from_snapshot) in list.into_iter().enumerate() {
+            let to_snapshot = params.target.store.backup_dir(
+                target_ns.clone(),
+                (target_group.clone(), from_snapshot.time).into(),
+            )?;
+
+            if to_snapshot.full_path().join(MANIFEST_BLOB_NAME).exists() {
+                task_log!(worker, "would re-sync snapshot {}", from_snapshot);
+            } else {
+                let reader = params
+                    .source
+                    .reader(source_namespace, &from_snapshot)
+                    .await?;
+                let bytes = estimate_snapshot_bytes(
+                    worker,
+                    reader,
+                    &params.target.store,
+                    &mut known_chunks,
+                )
+                .await?;
+                task_log!(
+                    worker,
+                    "would sync snapshot {} (about {} bytes)",
+                    from_snapshot,
+                    bytes
+                );
+            }
+            progress.lock().unwrap().update_group(group, pos as u64 + 1);
+        }
 
-    for (pos, from_snapshot) in list.into_iter().enumerate() {
-        let to_snapshot = params
+        let local_group = params
             .target
             .store
-            .backup_dir(target_ns.clone(), from_snapshot.clone())?;
+            .backup_group(target_ns.clone(), target_group.clone());
+        if !local_group.exists() {
+            return Ok(()); // nothing to remove or repair
+        }
+    } else {
+        for (pos, from_snapshot) in list.into_iter().enumerate() {
+            let to_snapshot = params.target.store.backup_dir(
+                target_ns.clone(),
+                (target_group.clone(), from_snapshot.time).into(),
+            )?;
+
+            let reader = params
+                .source
+                .reader(source_namespace, &from_snapshot)
+                .await?;
+            let result = pull_snapshot_from(
+                worker,
+                reader,
+                &to_snapshot,
+                downloaded_chunks.clone(),
+                params.chunk_concurrency,
+            )
+            .await;
 
-        let reader = params
-            .source
-            .reader(source_namespace, &from_snapshot)
-            .await?;
-        let result =
-            pull_snapshot_from(worker, reader, &to_snapshot, downloaded_chunks.clone()).await;
+            {
+                let mut progress = progress.lock().unwrap();
+                progress.update_group(group, pos as u64 + 1);
+                task_log!(worker, "percentage done: {}", progress);
+            }
 
-        progress.done_snapshots = pos as u64 + 1;
-        task_log!(worker, "percentage done: {}", progress);
+            result?; // stop on error
+        }
+    }
 
-        result?; // stop on error
+    for info in params
+        .target
+        .store
+        .backup_group(target_ns.clone(), target_group.clone())
+        .list_backups()?
+    {
+        let snapshot = info.backup_dir;
+        if !snapshot.is_partially_pulled() || source_snapshots.contains(&snapshot.backup_time()) {
+            continue;
+        }
+        task_log!(
+            worker,
+            "{}delete partially synced snapshot {} - vanished from source",
+            if params.dry_run { "would " } else { "" },
+            snapshot.dir()
+        );
+        if params.dry_run {
+            continue;
+        }
+        params
+            .target
+            .store
+            .remove_backup_dir(&target_ns, snapshot.as_ref(), true)?;
     }
 
     if params.remove_vanished {
         let group = params
             .target
             .store
-            .backup_group(target_ns.clone(), group.clone());
+            .backup_group(target_ns.clone(), target_group.clone());
         let local_list = group.list_backups()?;
         for info in local_list {
             let snapshot = info.backup_dir;
@@ -1104,7 +1867,15 @@ async fn pull_group(
                 );
                 continue;
             }
-            task_log!(worker, "delete vanished snapshot {}", snapshot.dir());
+            task_log!(
+                worker,
+                "{}delete vanished snapshot {}",
+                if params.dry_run { "would " } else { "" },
+                snapshot.dir()
+            );
+            if params.dry_run {
+                continue;
+            }
             params
                 .target
                 .store
@@ -1112,6 +1883,54 @@ async fn pull_group(
         }
     }
 
+    if params.repair_corrupt {
+        let local_group = params
+            .target
+            .store
+            .backup_group(target_ns.clone(), target_group.clone());
+        let mut failed = 0;
+        for info in local_group.list_backups()? {
+            let snapshot = info.backup_dir;
+            if !source_snapshots.contains(&snapshot.backup_time()) {
+                continue;
+            }
+            if !verification_failed(&snapshot) {
+                continue;
+            }
+            if params.dry_run {
+                task_log!(worker, "would repair snapshot {}", snapshot.dir());
+                continue;
+            }
+            task_log!(worker, "repair snapshot {}", snapshot.dir());
+
+            let source_dir: BackupDir = (group.clone(), snapshot.backup_time()).into();
+            let result = match params.source.reader(source_namespace, &source_dir).await {
+                Ok(reader) => {
+                    repair_snapshot(worker, reader, &snapshot, params.chunk_concurrency).await
+                }
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(true) => task_log!(worker, "repair snapshot {} done", snapshot.dir()),
+                Ok(false) => {
+                    task_warn!(
+                        worker,
+                        "snapshot {} still fails verification",
+                        snapshot.dir()
+                    );
+                    failed += 1;
+                }
+                Err(err) => {
+                    task_warn!(worker, "repair snapshot {} failed - {err}", snapshot.dir());
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            bail!("unable to repair {failed} snapshot(s)");
+        }
+    }
+
     Ok(())
 }
 
@@ -1120,6 +1939,12 @@ fn check_and_create_ns(params: &PullParameters, ns: &BackupNamespace) -> Result<
     let store_ns_str = print_store_and_ns(params.target.store.name(), ns);
 
     if !ns.is_root() && !params.target.store.namespace_path(ns).exists() {
+        // renamed namespaces might need their parents to be created as well
+        let parent = ns.parent();
+        if !parent.is_root() && !params.target.store.namespace_path(&parent).exists() {
+            check_and_create_ns(params, &parent)?;
+        }
+
         check_ns_modification_privs(params.target.store.name(), ns, &params.owner)
             .map_err(|err| format_err!("Creating {ns} not allowed - {err}"))?;
 
@@ -1130,8 +1955,10 @@ fn check_and_create_ns(params: &PullParameters, ns: &BackupNamespace) -> Result<
             }
         };
 
-        if let Err(err) = params.target.store.create_namespace(&ns.parent(), name) {
-            bail!("sync into {store_ns_str} failed - namespace creation failed: {err}");
+        if !params.dry_run {
+            if let Err(err) = params.target.store.create_namespace(&ns.parent(), name) {
+                bail!("sync into {store_ns_str} failed - namespace creation failed: {err}");
+            }
         }
         created = true;
     }
@@ -1151,6 +1978,10 @@ fn check_and_remove_ns(params: &PullParameters, local_ns: &BackupNamespace) -> R
     check_ns_modification_privs(params.target.store.name(), local_ns, &params.owner)
         .map_err(|err| format_err!("Removing {local_ns} not allowed - {err}"))?;
 
+    if params.dry_run {
+        return Ok(true);
+    }
+
     params
         .target
         .store
@@ -1165,15 +1996,22 @@ fn check_and_remove_vanished_ns(
     let mut errors = false;
     let user_info = CachedUserInfo::new()?;
 
+    // with rename rules, only namespaces below the renamed anchor belong to this sync
+    let anchor = params.map_namespace(&params.source.get_ns())?;
+    if !params.target.store.namespace_exists(&anchor) {
+        return Ok(errors); // e.g. not created by a dry run
+    }
+
     // clamp like remote does so that we don't list more than we can ever have synced.
     let max_depth = params
         .max_depth
-        .unwrap_or_else(|| MAX_NAMESPACE_DEPTH - params.source.get_ns().depth());
+        .unwrap_or_else(|| MAX_NAMESPACE_DEPTH - params.source.get_ns().depth())
+        .min(MAX_NAMESPACE_DEPTH - anchor.depth());
 
     let mut local_ns_list: Vec<BackupNamespace> = params
         .target
         .store
-        .recursive_iter_backup_ns_ok(params.target.ns.clone(), Some(max_depth))?
+        .recursive_iter_backup_ns_ok(anchor.clone(), Some(max_depth))?
         .filter(|ns| {
             let user_privs =
                 user_info.lookup_privs(&params.owner, &ns.acl_path(params.target.store.name()));
@@ -1185,7 +2023,7 @@ fn check_and_remove_vanished_ns(
     local_ns_list.sort_unstable_by_key(|b| std::cmp::Reverse(b.name_len()));
 
     for local_ns in local_ns_list {
-        if local_ns == params.target.ns {
+        if local_ns == anchor {
             continue;
         }
 
@@ -1197,6 +2035,7 @@ fn check_and_remove_vanished_ns(
             continue;
         }
         match check_and_remove_ns(params, &local_ns) {
+            Ok(true) if params.dry_run => task_log!(worker, "Would remove namespace {}", local_ns),
             Ok(true) => task_log!(worker, "Removed namespace {}", local_ns),
             Ok(false) => task_log!(
                 worker,
@@ -1231,13 +2070,20 @@ fn check_and_remove_vanished_ns(
 /// - creation and removal of sub-NS checked here
 /// - access to sub-NS checked here
 pub(crate) async fn pull_store(
-    worker: &WorkerTask,
+    worker: &Arc<WorkerTask>,
     mut params: PullParameters,
 ) -> Result<(), Error> {
     // explicit create shared lock to prevent GC on newly created chunks
     let _shared_store_lock = params.target.store.try_shared_chunk_store_lock()?;
     let mut errors = false;
 
+    if params.dry_run {
+        task_log!(
+            worker,
+            "(dry run - nothing is written to the target datastore)"
+        );
+    }
+
     let old_max_depth = params.max_depth;
     let mut namespaces = if params.source.get_ns().is_root() && old_max_depth == Some(0) {
         vec![params.source.get_ns()] // backwards compat - don't query remote namespaces!
@@ -1267,13 +2113,23 @@ pub(crate) async fn pull_store(
     errors |= old_max_depth != params.max_depth; // fail job if we switched to backwards-compat mode
     namespaces.sort_unstable_by_key(|a| a.name_len());
 
+    // shared with the tasks pulling the groups of a namespace
+    let params = Arc::new(params);
+
     let (mut groups, mut snapshots) = (0, 0);
     let mut synced_ns = HashSet::with_capacity(namespaces.len());
 
     for namespace in namespaces {
         let source_store_ns_str = print_store_and_ns(params.source.get_store(), &namespace);
 
-        let target_ns = namespace.map_prefix(&params.source.get_ns(), &params.target.ns)?;
+        let target_ns = match params.map_namespace(&namespace) {
+            Ok(target_ns) => target_ns,
+            Err(err) => {
+                task_log!(worker, "Cannot sync {} - {}", source_store_ns_str, err);
+                errors = true;
+                continue;
+            }
+        };
         let target_store_ns_str = print_store_and_ns(params.target.store.name(), &target_ns);
 
         task_log!(worker, "----");
@@ -1285,8 +2141,15 @@ pub(crate) async fn pull_store(
         );
 
         synced_ns.insert(target_ns.clone());
+        // renamed namespaces might be nested below ones which are not synced themselves
+        let mut parent = target_ns.parent();
+        while parent.depth() > params.target.ns.depth() {
+            synced_ns.insert(parent.clone());
+            parent = parent.parent();
+        }
 
         match check_and_create_ns(&params, &target_ns) {
+            Ok(true) if params.dry_run => task_log!(worker, "Would create namespace {}", target_ns),
             Ok(true) => task_log!(worker, "Created namespace {}", target_ns),
             Ok(false) => {}
             Err(err) => {
@@ -1302,7 +2165,7 @@ pub(crate) async fn pull_store(
             }
         }
 
-        match pull_ns(worker, &namespace, &mut params).await {
+        match pull_ns(worker, &namespace, &params).await {
             Ok((ns_progress, ns_errors)) => {
                 errors |= ns_errors;
 
@@ -1346,18 +2209,20 @@ pub(crate) async fn pull_store(
 /// Pulling a namespace consists of the following steps:
 /// - Query list of groups on the remote (in `source_ns`)
 /// - Filter list according to configured group filters
-/// - Iterate list and attempt to pull each group in turn
-/// - (remove_vanished) remove groups with matching owner and matching the configured group filters which are
+/// - Iterate list and attempt to pull each group, up to `parallel_groups` at a time
+/// - (remove_vanished) once all groups are done, remove groups with matching owner and matching the configured group filters which are
 ///   not or no longer available on the remote
 ///
 /// Permission checks:
 /// - remote namespaces are filtered by remote
 /// - owner check for vanished groups done here
 pub(crate) async fn pull_ns(
-    worker: &WorkerTask,
+    worker: &Arc<WorkerTask>,
     namespace: &BackupNamespace,
-    params: &mut PullParameters,
+    params: &Arc<PullParameters>,
 ) -> Result<(StoreProgress, bool), Error> {
+    use futures::stream::{self, StreamExt};
+
     let mut list: Vec<BackupGroup> = params.source.list_groups(namespace, &params.owner).await?;
 
     list.sort_unstable_by(|a, b| {
@@ -1383,72 +2248,119 @@ pub(crate) async fn pull_ns(
 
     let mut errors = false;
 
-    let mut new_groups = HashSet::new();
-    for group in list.iter() {
-        new_groups.insert(group.clone());
+    // renamed groups must not clash, the map points from the target to the source group
+    let mut new_groups = HashMap::new();
+    let mut mapped_list = Vec::with_capacity(list.len());
+    for group in list {
+        let target_group = match params.map_group(&group) {
+            Ok(target_group) => target_group,
+            Err(err) => {
+                task_log!(worker, "sync group {} failed - {}", group, err);
+                errors = true;
+                continue;
+            }
+        };
+        if let Some(other) = new_groups.get(&target_group) {
+            task_log!(
+                worker,
+                "sync group {} failed - renamed to {} like group {}",
+                group,
+                target_group,
+                other
+            );
+            errors = true;
+            continue;
+        }
+        new_groups.insert(target_group.clone(), group.clone());
+        mapped_list.push((group, target_group));
     }
 
-    let mut progress = StoreProgress::new(list.len() as u64);
-
-    let target_ns = namespace.map_prefix(&params.source.get_ns(), &params.target.ns)?;
+    let progress = Arc::new(Mutex::new(GroupsProgress::new(mapped_list.len() as u64)));
 
-    for (done, group) in list.into_iter().enumerate() {
-        progress.done_groups = done as u64;
-        progress.done_snapshots = 0;
-        progress.group_snapshots = 0;
+    // shared by all groups, so chunks referenced by several groups are only downloaded once,
+    // start with 65536 chunks (up to 256 GiB)
+    let downloaded_chunks = Arc::new(Mutex::new(HashSet::with_capacity(1024 * 64)));
 
-        let (owner, _lock_guard) =
-            match params
-                .target
-                .store
-                .create_locked_backup_group(&target_ns, &group, &params.owner)
-            {
-                Ok(result) => result,
-                Err(err) => {
-                    task_log!(
-                        worker,
-                        "sync group {} failed - group lock failed: {}",
-                        &group,
-                        err
-                    );
-                    errors = true;
-                    // do not stop here, instead continue
-                    task_log!(worker, "create_locked_backup_group failed");
-                    continue;
-                }
-            };
+    let target_ns = params.map_namespace(namespace)?;
 
-        // permission check
-        if params.owner != owner {
-            // only the owner is allowed to create additional snapshots
-            task_log!(
-                worker,
-                "sync group {} failed - owner check failed ({} != {})",
-                &group,
-                params.owner,
-                owner
-            );
-            errors = true; // do not stop here, instead continue
-        } else if let Err(err) = pull_group(worker, params, namespace, &group, &mut progress).await
-        {
-            task_log!(worker, "sync group {} failed - {}", &group, err,);
-            errors = true; // do not stop here, instead continue
-        }
+    if params.parallel_groups > 1 {
+        task_log!(
+            worker,
+            "pulling up to {} groups in parallel",
+            params.parallel_groups
+        );
     }
 
-    if params.remove_vanished {
+    // each group is pulled in its own task, so that writing the chunks of one group does not
+    // block the others. Errors of a group are logged and don't stop the other groups
+    let group_errors: Vec<bool> = stream::iter(mapped_list)
+        .map(|(group, target_group)| {
+            let worker = Arc::clone(worker);
+            let params = Arc::clone(params);
+            let namespace = namespace.clone();
+            let target_ns = target_ns.clone();
+            let progress = Arc::clone(&progress);
+            let downloaded_chunks = Arc::clone(&downloaded_chunks);
+            tokio::spawn(async move {
+                let errors = pull_ns_group(
+                    &worker,
+                    &params,
+                    &namespace,
+                    &target_ns,
+                    &group,
+                    &target_group,
+                    &progress,
+                    downloaded_chunks,
+                )
+                .await;
+                progress.lock().unwrap().finish_group(&group);
+                errors
+            })
+        })
+        .buffer_unordered(params.parallel_groups)
+        .map(|result| match result {
+            Ok(errors) => errors,
+            Err(err) => {
+                task_log!(worker, "pulling group failed - {}", err);
+                true
+            }
+        })
+        .collect()
+        .await;
+    errors |= group_errors.into_iter().any(|errors| errors);
+
+    // a dry run does not create the target namespace
+    if params.remove_vanished && params.target.store.namespace_exists(&target_ns) {
         let result: Result<(), Error> = proxmox_lang::try_block!({
             for local_group in params.target.store.iter_backup_groups(target_ns.clone())? {
                 let local_group = local_group?;
+                let recorded_source = SyncSource::load(&local_group)?;
                 let local_group = local_group.group();
-                if new_groups.contains(local_group) {
+                if new_groups.contains_key(local_group) {
                     continue;
                 }
                 let owner = params.target.store.get_owner(&target_ns, local_group)?;
                 if check_backup_owner(&owner, &params.owner).is_err() {
                     continue;
                 }
-                if !local_group.apply_filters(&params.group_filter) {
+                // renamed groups are filtered by their source group, and only removed if they
+                // were pulled from the namespace being synced
+                let source_group = match &recorded_source {
+                    Some(source) => {
+                        if !source.same_namespace(&params.sync_source(namespace, local_group)) {
+                            continue;
+                        }
+                        &source.group
+                    }
+                    // with rename rules, only groups with a recorded source were pulled by us
+                    None if !params.rename_rules.is_empty() => continue,
+                    None => local_group,
+                };
+                if !source_group.apply_filters(&params.group_filter) {
+                    continue;
+                }
+                if params.dry_run {
+                    task_log!(worker, "would delete vanished group '{local_group}'");
                     continue;
                 }
                 task_log!(worker, "delete vanished group '{local_group}'",);
@@ -1479,5 +2391,311 @@ pub(crate) async fn pull_ns(
         };
     }
 
+    let progress = progress.lock().unwrap().store_progress();
     Ok((progress, errors))
 }
+
+/// Returns whether a local group exists and contains any snapshots.
+fn has_snapshots(group: &pbs_datastore::BackupGroup) -> Result<bool, Error> {
+    if !group.exists() {
+        return Ok(false);
+    }
+    Ok(group.iter_snapshots()?.next().is_some())
+}
+
+/// Pulls a single group of a namespace after locking it and checking its owner, returns whether
+/// there were errors.
+#[allow(clippy::too_many_arguments)]
+async fn pull_ns_group(
+    worker: &Arc<WorkerTask>,
+    params: &PullParameters,
+    namespace: &BackupNamespace,
+    target_ns: &BackupNamespace,
+    group: &BackupGroup,
+    target_group: &BackupGroup,
+    progress: &Mutex<GroupsProgress>,
+    downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+) -> bool {
+    if group != target_group {
+        task_log!(worker, "sync group {} as {}", group, target_group);
+    }
+
+    let mut _lock_guard = None;
+    let owner = if params.dry_run {
+        // a dry run must not create the group, only check the owner of an existing one
+        if params
+            .target
+            .store
+            .backup_group(target_ns.clone(), target_group.clone())
+            .exists()
+        {
+            match params.target.store.get_owner(target_ns, target_group) {
+                Ok(owner) => owner,
+                Err(err) => {
+                    task_log!(worker, "sync group {} failed - {}", group, err);
+                    return true;
+                }
+            }
+        } else {
+            task_log!(worker, "would create group {}", target_group);
+            params.owner.clone()
+        }
+    } else {
+        match params
+            .target
+            .store
+            .create_locked_backup_group(target_ns, target_group, &params.owner)
+        {
+            Ok((owner, lock_guard)) => {
+                _lock_guard = Some(lock_guard);
+                owner
+            }
+            Err(err) => {
+                task_log!(
+                    worker,
+                    "sync group {} failed - group lock failed: {}",
+                    group,
+                    err
+                );
+                task_log!(worker, "create_locked_backup_group failed");
+                return true;
+            }
+        }
+    };
+
+    // permission check
+    if params.owner != owner {
+        // only the owner is allowed to create additional snapshots
+        task_log!(
+            worker,
+            "sync group {} failed - owner check failed ({} != {})",
+            group,
+            params.owner,
+            owner
+        );
+        return true;
+    }
+
+    if !params.rename_rules.is_empty() {
+        // record the source, so that no other group gets pulled into the renamed one later
+        let local_group = params
+            .target
+            .store
+            .backup_group(target_ns.clone(), target_group.clone());
+        let source = params.sync_source(namespace, group);
+        let result = match SyncSource::load(&local_group) {
+            Ok(Some(recorded)) if recorded != source => Err(format_err!(
+                "{} was pulled from {} before",
+                target_group,
+                recorded
+            )),
+            Ok(Some(_)) => Ok(()),
+            // don't claim a group with snapshots from elsewhere, remove-vanished could delete them
+            Ok(None) => match has_snapshots(&local_group) {
+                Ok(true) => Err(format_err!(
+                    "{} already contains snapshots which were not pulled from {}",
+                    target_group,
+                    source
+                )),
+                Ok(false) if params.dry_run => Ok(()),
+                Ok(false) => source.save(&local_group),
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            task_log!(worker, "sync group {} failed - {}", group, err);
+            return true;
+        }
+    }
+
+    if let Err(err) = pull_group(
+        worker,
+        params,
+        namespace,
+        group,
+        target_group,
+        progress,
+        downloaded_chunks,
+    )
+    .await
+    {
+        task_log!(worker, "sync group {} failed - {}", group, err);
+        return true;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::BackupType;
+
+    #[test]
+    fn test_pull_progress() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("pull-progress-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // a missing or empty file means nothing was pulled yet
+        let progress = PullProgress::load_file(&path)?;
+        assert!(progress.archives.is_empty());
+        std::fs::write(&path, b"")?;
+        assert!(PullProgress::load_file(&path)?.archives.is_empty());
+
+        let mut progress = PullProgress::default();
+        progress
+            .archives
+            .insert("drive-scsi0.img.fidx".to_string(), 4096);
+        progress
+            .archives
+            .insert("qemu-server.conf.blob".to_string(), 512);
+        progress.save_file(&path)?;
+
+        let loaded = PullProgress::load_file(&path)?;
+        assert_eq!(loaded.archives, progress.archives);
+        assert_eq!(loaded.bytes(), 4608);
+
+        // a corrupt file is an error, not an empty progress
+        std::fs::write(&path, b"{ garbage")?;
+        assert!(PullProgress::load_file(&path).is_err());
+
+        let _ = std::fs::remove_file(&path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_groups_progress() {
+        let first = BackupGroup::new(BackupType::Vm, "100");
+        let second = BackupGroup::new(BackupType::Vm, "101");
+        let third = BackupGroup::new(BackupType::Ct, "200");
+
+        let mut progress = GroupsProgress::new(4);
+        progress.finish_group(&third);
+
+        // a single active group is reported like a sequential pull
+        progress.start_group(&first, 2);
+        progress.update_group(&first, 1);
+        assert_eq!(
+            progress.to_string(),
+            "37.50% (1/4 groups, 1/2 snapshots in group #2)"
+        );
+
+        // parallel groups are interpolated by their own progress
+        progress.start_group(&second, 4);
+        assert_eq!(progress.to_string(), "37.50% (1/4 groups, 2 in progress)");
+        progress.update_group(&second, 2);
+        // updates are absolute, so repeating them does not count twice
+        progress.update_group(&first, 1);
+        assert_eq!(progress.to_string(), "50.00% (1/4 groups, 2 in progress)");
+        assert_eq!(progress.store_progress().done_snapshots, 3);
+
+        progress.update_group(&first, 2);
+        progress.finish_group(&first);
+        assert_eq!(
+            progress.to_string(),
+            "62.50% (2/4 groups, 2/4 snapshots in group #3)"
+        );
+
+        progress.update_group(&second, 4);
+        progress.finish_group(&second);
+        assert_eq!(progress.to_string(), "75.00% (3/4 groups)");
+
+        let store_progress = progress.store_progress();
+        assert_eq!(store_progress.done_groups, 3);
+        assert_eq!(store_progress.done_snapshots, 6);
+    }
+
+    fn source_snapshot(
+        time: i64,
+        verify_state: Option<VerifyState>,
+        protected: bool,
+        encrypted: bool,
+    ) -> SourceSnapshot {
+        SourceSnapshot {
+            dir: (BackupType::Vm, "100".to_string(), time).into(),
+            verify_state,
+            protected,
+            encrypted,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_filter() -> Result<(), Error> {
+        let now = 1_700_000_000;
+        let day = 86400;
+
+        let skip_reason = |filter: &SyncSnapshotFilter, snapshot: &SourceSnapshot| {
+            SnapshotFilter::new(filter, now).map(|filter| filter.skip_reason(snapshot))
+        };
+
+        // without filters, nothing is skipped
+        let filter = SyncSnapshotFilter::default();
+        assert!(skip_reason(&filter, &source_snapshot(0, None, false, true))?.is_none());
+
+        // newer-than and older-than are exclusive bounds relative to the start of the sync
+        let filter = SyncSnapshotFilter {
+            newer_than: Some("7d".to_string()),
+            older_than: Some("1d".to_string()),
+            ..Default::default()
+        };
+        let check = |time| skip_reason(&filter, &source_snapshot(time, None, false, false));
+        assert!(check(now - 7 * day)? == Some(SkipReason::NewerThan));
+        assert!(check(now - 7 * day + 1)?.is_none());
+        assert!(check(now - day - 1)?.is_none());
+        assert!(check(now - day)? == Some(SkipReason::OlderThan));
+        assert!(check(now)? == Some(SkipReason::OlderThan));
+
+        // newer-than also accepts absolute times
+        let filter = SyncSnapshotFilter {
+            newer_than: Some("2023-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        let check = |time| skip_reason(&filter, &source_snapshot(time, None, false, false));
+        assert!(check(1_672_531_200)? == Some(SkipReason::NewerThan));
+        assert!(check(1_672_531_201)?.is_none());
+
+        let filter = SyncSnapshotFilter {
+            verified_only: Some(true),
+            ..Default::default()
+        };
+        let check = |state| skip_reason(&filter, &source_snapshot(now, state, false, false));
+        assert!(check(None)? == Some(SkipReason::NotVerified));
+        assert!(check(Some(VerifyState::Failed))? == Some(SkipReason::NotVerified));
+        assert!(check(Some(VerifyState::Ok))?.is_none());
+
+        let filter = SyncSnapshotFilter {
+            protected_only: Some(true),
+            ..Default::default()
+        };
+        let check = |protected| skip_reason(&filter, &source_snapshot(now, None, protected, false));
+        assert!(check(false)? == Some(SkipReason::NotProtected));
+        assert!(check(true)?.is_none());
+
+        let encrypted_only = SyncSnapshotFilter {
+            encryption_filter: Some(SyncEncryptionFilter::Encrypted),
+            ..Default::default()
+        };
+        let unencrypted_only = SyncSnapshotFilter {
+            encryption_filter: Some(SyncEncryptionFilter::Unencrypted),
+            ..Default::default()
+        };
+        let encrypted = source_snapshot(now, None, false, true);
+        let unencrypted = source_snapshot(now, None, false, false);
+        assert!(skip_reason(&encrypted_only, &encrypted)?.is_none());
+        assert!(skip_reason(&encrypted_only, &unencrypted)? == Some(SkipReason::EncryptionFilter));
+        assert!(skip_reason(&unencrypted_only, &unencrypted)?.is_none());
+        assert!(skip_reason(&unencrypted_only, &encrypted)? == Some(SkipReason::EncryptionFilter));
+
+        // invalid time limits are rejected when setting up the filter
+        let filter = SyncSnapshotFilter {
+            older_than: Some("2023-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert!(SnapshotFilter::new(&filter, now).is_err());
+
+        Ok(())
+    }
+}
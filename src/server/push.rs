@@ -0,0 +1,800 @@
+//! Sync datastore by pushing contents to remote server
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Error};
+use serde_json::json;
+
+use proxmox_rest_server::WorkerTask;
+use proxmox_sys::task_log;
+
+use pbs_api_types::{
+    print_store_and_ns, Authid, BackupDir, BackupGroup, BackupNamespace, GroupFilter,
+    GroupListItem, NamespaceListItem, Operation, RateLimitConfig, Remote, SnapshotListItem,
+    MAX_NAMESPACE_DEPTH, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_READ,
+};
+use pbs_client::{BackupRepository, BackupWriter, HttpClient};
+use pbs_datastore::dynamic_index::DynamicIndexReader;
+use pbs_datastore::fixed_index::FixedIndexReader;
+use pbs_datastore::index::IndexFile;
+use pbs_datastore::manifest::{
+    archive_type, ArchiveType, BackupManifest, FileInfo, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME,
+};
+use pbs_datastore::read_chunk::AsyncReadChunk;
+use pbs_datastore::{DataStore, ListNamespacesRecursive, LocalChunkReader, StoreProgress};
+
+use super::pull::{SkipInfo, SkipReason};
+use crate::backup::{check_ns_privs_full, ListAccessibleBackupGroups};
+
+/// Remote datastore snapshots are pushed into
+pub(crate) struct PushTarget {
+    repo: BackupRepository,
+    ns: BackupNamespace,
+    client: HttpClient,
+}
+
+/// Parameters for a push operation
+pub(crate) struct PushParameters {
+    /// Local datastore where data is pushed from
+    source: Arc<DataStore>,
+    /// Local namespace anchor where data is pushed from
+    source_ns: BackupNamespace,
+    /// Where data should be pushed into
+    target: PushTarget,
+    /// Local user whose privileges are used for reading the source (groups on the remote are
+    /// owned by the auth id configured for the remote)
+    local_user: Authid,
+    /// Whether to remove groups which exist on the remote, but not locally
+    remove_vanished: bool,
+    /// How many levels of sub-namespaces to push (0 == no recursion, None == maximum recursion)
+    max_depth: Option<usize>,
+    /// Filters for reducing the push scope
+    group_filter: Vec<GroupFilter>,
+    /// How many snapshots should be transferred at most (taking the newest N snapshots)
+    transfer_last: Option<usize>,
+}
+
+impl PushParameters {
+    /// Creates a new instance of `PushParameters`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        store: &str,
+        ns: BackupNamespace,
+        remote: &str,
+        remote_store: &str,
+        remote_ns: BackupNamespace,
+        local_user: Authid,
+        remove_vanished: Option<bool>,
+        max_depth: Option<usize>,
+        group_filter: Option<Vec<GroupFilter>>,
+        limit: RateLimitConfig,
+        transfer_last: Option<usize>,
+    ) -> Result<Self, Error> {
+        if let Some(max_depth) = max_depth {
+            ns.check_max_depth(max_depth)?;
+            remote_ns.check_max_depth(max_depth)?;
+        };
+        let remove_vanished = remove_vanished.unwrap_or(false);
+
+        let (remote_config, _digest) = pbs_config::remote::config()?;
+        let remote: Remote = remote_config.lookup("remote", remote)?;
+
+        let repo = BackupRepository::new(
+            Some(remote.config.auth_id.clone()),
+            Some(remote.config.host.clone()),
+            remote.config.port,
+            remote_store.to_string(),
+        );
+        let client = crate::api2::config::remote::remote_client_config(&remote, Some(limit))?;
+
+        let target = PushTarget {
+            repo,
+            ns: remote_ns,
+            client,
+        };
+
+        let group_filter = group_filter.unwrap_or_default();
+
+        Ok(Self {
+            source: DataStore::lookup_datastore(store, Some(Operation::Read))?,
+            source_ns: ns,
+            target,
+            local_user,
+            remove_vanished,
+            max_depth,
+            group_filter,
+            transfer_last,
+        })
+    }
+
+    fn api_path(&self, endpoint: &str) -> String {
+        format!(
+            "api2/json/admin/datastore/{}/{endpoint}",
+            self.target.repo.store()
+        )
+    }
+}
+
+fn ns_args(ns: &BackupNamespace) -> serde_json::Value {
+    if ns.is_root() {
+        json!({})
+    } else {
+        json!({ "ns": ns })
+    }
+}
+
+async fn list_target_namespaces(params: &PushParameters) -> Result<Vec<BackupNamespace>, Error> {
+    let mut args = json!({});
+    if let Some(max_depth) = params.max_depth {
+        args["max-depth"] = json!(max_depth);
+    }
+    if !params.target.ns.is_root() {
+        args["parent"] = json!(params.target.ns);
+    }
+
+    let mut result = params
+        .target
+        .client
+        .get(&params.api_path("namespace"), Some(args))
+        .await
+        .map_err(|err| format_err!("Failed to retrieve namespaces from remote - {err}"))?;
+
+    let list: Vec<NamespaceListItem> = serde_json::from_value(result["data"].take())?;
+    Ok(list.into_iter().map(|item| item.ns).collect())
+}
+
+async fn list_target_groups(
+    params: &PushParameters,
+    ns: &BackupNamespace,
+) -> Result<Vec<GroupListItem>, Error> {
+    let mut result = params
+        .target
+        .client
+        .get(&params.api_path("groups"), Some(ns_args(ns)))
+        .await
+        .map_err(|err| format_err!("Failed to retrieve backup groups from remote - {err}"))?;
+
+    Ok(serde_json::from_value(result["data"].take())?)
+}
+
+async fn list_target_snapshots(
+    params: &PushParameters,
+    ns: &BackupNamespace,
+    group: &BackupGroup,
+) -> Result<Vec<SnapshotListItem>, Error> {
+    let mut args = ns_args(ns);
+    args["backup-type"] = json!(group.ty);
+    args["backup-id"] = json!(group.id);
+
+    let mut result = params
+        .target
+        .client
+        .get(&params.api_path("snapshots"), Some(args))
+        .await
+        .map_err(|err| format_err!("Failed to retrieve snapshots from remote - {err}"))?;
+
+    Ok(serde_json::from_value(result["data"].take())?)
+}
+
+async fn check_and_create_target_ns(
+    params: &PushParameters,
+    existing: &HashSet<BackupNamespace>,
+    ns: &BackupNamespace,
+) -> Result<bool, Error> {
+    if ns.is_root() || existing.contains(ns) {
+        return Ok(false);
+    }
+
+    let name = match ns.components().last() {
+        Some(name) => name.to_owned(),
+        None => bail!("Failed to determine last component of namespace."),
+    };
+
+    let mut args = json!({ "name": name });
+    let parent = ns.parent();
+    if !parent.is_root() {
+        args["parent"] = json!(parent);
+    }
+
+    params
+        .target
+        .client
+        .post(&params.api_path("namespace"), Some(args))
+        .await
+        .map_err(|err| format_err!("namespace creation failed - {err}"))?;
+
+    Ok(true)
+}
+
+async fn remove_target_ns(params: &PushParameters, ns: &BackupNamespace) -> Result<(), Error> {
+    let mut args = ns_args(ns);
+    args["delete-groups"] = json!(true);
+
+    params
+        .target
+        .client
+        .delete(&params.api_path("namespace"), Some(args))
+        .await?;
+
+    Ok(())
+}
+
+async fn remove_target_group(
+    params: &PushParameters,
+    ns: &BackupNamespace,
+    group: &BackupGroup,
+) -> Result<(), Error> {
+    let mut args = ns_args(ns);
+    args["backup-type"] = json!(group.ty);
+    args["backup-id"] = json!(group.id);
+
+    params
+        .target
+        .client
+        .delete(&params.api_path("groups"), Some(args))
+        .await?;
+
+    Ok(())
+}
+
+async fn remove_target_snapshot(
+    params: &PushParameters,
+    ns: &BackupNamespace,
+    snapshot: &BackupDir,
+) -> Result<(), Error> {
+    let mut args = ns_args(ns);
+    args["backup-type"] = json!(snapshot.group.ty);
+    args["backup-id"] = json!(snapshot.group.id);
+    args["backup-time"] = json!(snapshot.time);
+
+    params
+        .target
+        .client
+        .delete(&params.api_path("snapshots"), Some(args))
+        .await?;
+
+    Ok(())
+}
+
+async fn push_index<I: IndexFile>(
+    writer: &BackupWriter,
+    previous_manifest: Option<&BackupManifest>,
+    chunk_reader: Arc<dyn AsyncReadChunk>,
+    known_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    file: &FileInfo,
+    index: I,
+) -> Result<(), Error> {
+    if let Some(previous_manifest) = previous_manifest {
+        // known chunks of the previous snapshot don't need to be uploaded again, but failing to
+        // fetch them is no reason to fail the whole snapshot
+        if previous_manifest.lookup_file_info(&file.filename).is_ok() {
+            let _ = match archive_type(&file.filename)? {
+                ArchiveType::FixedIndex => writer
+                    .download_previous_fixed_index(
+                        &file.filename,
+                        previous_manifest,
+                        known_chunks.clone(),
+                    )
+                    .await
+                    .map(drop),
+                ArchiveType::DynamicIndex => writer
+                    .download_previous_dynamic_index(
+                        &file.filename,
+                        previous_manifest,
+                        known_chunks.clone(),
+                    )
+                    .await
+                    .map(drop),
+                ArchiveType::Blob => Ok(()),
+            };
+        }
+    }
+
+    let stats = writer
+        .upload_index_chunk_info(&file.filename, &index, chunk_reader, known_chunks)
+        .await?;
+
+    if stats.size != file.size || stats.csum != file.csum {
+        bail!("index {} changed while pushing", file.filename);
+    }
+
+    Ok(())
+}
+
+/// Pushes a single snapshot.
+///
+/// All index files are uploaded together with the chunks not already present in the previous
+/// snapshot on the remote, followed by the blobs, the client log (if any) and finally the
+/// manifest. Chunks and blobs are transferred as stored, so encrypted snapshots are pushed without
+/// requiring access to the encryption key.
+async fn push_snapshot(
+    params: &PushParameters,
+    source_ns: &BackupNamespace,
+    target_ns: &BackupNamespace,
+    snapshot: &BackupDir,
+) -> Result<(), Error> {
+    let backup_dir = params
+        .source
+        .backup_dir(source_ns.clone(), snapshot.clone())?;
+    let _dir_lock = proxmox_sys::fs::lock_dir_noblock_shared(
+        &backup_dir.full_path(),
+        "snapshot",
+        "locked by another operation",
+    )?;
+
+    let (manifest, _) = backup_dir.load_manifest()?;
+
+    let writer = BackupWriter::start_shared(
+        &params.target.client,
+        None,
+        params.target.repo.store(),
+        target_ns,
+        snapshot,
+        false,
+        false,
+    )
+    .await?;
+
+    let previous_manifest = writer.download_previous_manifest().await.ok();
+    let known_chunks = Arc::new(Mutex::new(HashSet::new()));
+
+    for file in manifest.files() {
+        let path = backup_dir.full_path().join(&file.filename);
+        let chunk_reader: Arc<dyn AsyncReadChunk> = Arc::new(LocalChunkReader::new(
+            params.source.clone(),
+            None,
+            file.chunk_crypt_mode(),
+        ));
+
+        match archive_type(&file.filename)? {
+            ArchiveType::FixedIndex => {
+                let index = FixedIndexReader::open(&path)?;
+                push_index(
+                    &writer,
+                    previous_manifest.as_ref(),
+                    chunk_reader,
+                    known_chunks.clone(),
+                    file,
+                    index,
+                )
+                .await?;
+            }
+            ArchiveType::DynamicIndex => {
+                let index = DynamicIndexReader::open(&path)?;
+                push_index(
+                    &writer,
+                    previous_manifest.as_ref(),
+                    chunk_reader,
+                    known_chunks.clone(),
+                    file,
+                    index,
+                )
+                .await?;
+            }
+            ArchiveType::Blob => {
+                let stats = writer
+                    .upload_blob(std::fs::File::open(&path)?, &file.filename)
+                    .await?;
+                if stats.size != file.size || stats.csum != file.csum {
+                    bail!("blob {} changed while pushing", file.filename);
+                }
+            }
+        }
+    }
+
+    let client_log = backup_dir.full_path().join(CLIENT_LOG_BLOB_NAME);
+    if client_log.exists() {
+        writer
+            .upload_blob(std::fs::File::open(&client_log)?, CLIENT_LOG_BLOB_NAME)
+            .await?;
+    }
+
+    let manifest_path = backup_dir.full_path().join(MANIFEST_BLOB_NAME);
+    writer
+        .upload_blob(std::fs::File::open(&manifest_path)?, MANIFEST_BLOB_NAME)
+        .await?;
+
+    writer.finish().await
+}
+
+/// Pushes a group according to `params`.
+///
+/// Pushing a group consists of the following steps:
+/// - List local snapshots of the group, ignoring unfinished ones
+/// - Query the list of snapshots of this group in the target namespace on the remote
+/// - Iterate over local snapshots
+/// -- push snapshot, unless it's older than or as old as the last remote snapshot
+/// - (remove_vanished) remove snapshots on the remote which no longer exist locally
+///
+/// Permission checks:
+/// - local group access and remote group ownership are already checked by push_namespace
+/// - remote snapshot access is checked by remote
+async fn push_group(
+    worker: &WorkerTask,
+    params: &PushParameters,
+    source_ns: &BackupNamespace,
+    group: &BackupGroup,
+    target_group_exists: bool,
+    progress: &mut StoreProgress,
+) -> Result<(), Error> {
+    let mut already_synced_skip_info = SkipInfo::new(SkipReason::AlreadyPushed);
+    let mut transfer_last_skip_info = SkipInfo::new(SkipReason::TransferLast);
+
+    let target_ns = source_ns.map_prefix(&params.source_ns, &params.target.ns)?;
+
+    let mut raw_list: Vec<BackupDir> = params
+        .source
+        .backup_group(source_ns.clone(), group.clone())
+        .list_backups()?
+        .into_iter()
+        .filter(|info| info.is_finished())
+        .map(|info| info.backup_dir.dir().clone())
+        .collect();
+    raw_list.sort_unstable_by(|a, b| a.time.cmp(&b.time));
+
+    let total_amount = raw_list.len();
+
+    let cutoff = params
+        .transfer_last
+        .map(|count| total_amount.saturating_sub(count))
+        .unwrap_or_default();
+
+    let target_snapshots = if target_group_exists {
+        list_target_snapshots(params, &target_ns, group).await?
+    } else {
+        Vec::new()
+    };
+
+    // in-progress snapshots on the remote have no size yet
+    let last_sync_time = target_snapshots
+        .iter()
+        .filter(|item| item.size.is_some())
+        .map(|item| item.backup.time)
+        .max()
+        .unwrap_or(i64::MIN);
+
+    let mut source_snapshots = HashSet::new();
+    let list: Vec<BackupDir> = raw_list
+        .into_iter()
+        .enumerate()
+        .filter(|&(pos, ref dir)| {
+            source_snapshots.insert(dir.time);
+            if last_sync_time >= dir.time {
+                already_synced_skip_info.update(dir.time);
+                return false;
+            } else if already_synced_skip_info.count > 0 {
+                task_log!(worker, "{}", already_synced_skip_info);
+                already_synced_skip_info.reset();
+            }
+
+            if pos < cutoff {
+                transfer_last_skip_info.update(dir.time);
+                return false;
+            } else if transfer_last_skip_info.count > 0 {
+                task_log!(worker, "{}", transfer_last_skip_info);
+                transfer_last_skip_info.reset();
+            }
+            true
+        })
+        .map(|(_, dir)| dir)
+        .collect();
+
+    if already_synced_skip_info.count > 0 {
+        task_log!(worker, "{}", already_synced_skip_info);
+    }
+
+    progress.group_snapshots = list.len() as u64;
+
+    for (pos, snapshot) in list.into_iter().enumerate() {
+        task_log!(worker, "sync snapshot {}", snapshot);
+        let result = push_snapshot(params, source_ns, &target_ns, &snapshot).await;
+
+        progress.done_snapshots = pos as u64 + 1;
+        task_log!(worker, "percentage done: {}", progress);
+
+        result?; // stop on error
+    }
+
+    if params.remove_vanished {
+        for item in target_snapshots {
+            let snapshot = item.backup;
+            if source_snapshots.contains(&snapshot.time) {
+                continue;
+            }
+            if item.protected {
+                task_log!(
+                    worker,
+                    "don't delete vanished snapshot {} (protected)",
+                    snapshot
+                );
+                continue;
+            }
+            task_log!(worker, "delete vanished snapshot {}", snapshot);
+            remove_target_snapshot(params, &target_ns, &snapshot).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Pushes a namespace according to `params`.
+///
+/// Pushing a namespace consists of the following steps:
+/// - List local groups in `source_ns` accessible by the local user
+/// - Filter list according to configured group filters
+/// - Iterate list and attempt to push each group in turn
+/// - (remove_vanished) remove groups on the remote owned by the remote's auth id and matching the
+///   configured group filters, which are not or no longer available locally
+///
+/// Permission checks:
+/// - local group access is checked when listing groups
+/// - remote owner check for pushed and vanished groups done here
+pub(crate) async fn push_namespace(
+    worker: &WorkerTask,
+    source_ns: &BackupNamespace,
+    params: &PushParameters,
+) -> Result<(StoreProgress, bool), Error> {
+    let mut list: Vec<BackupGroup> = ListAccessibleBackupGroups::new_with_privs(
+        &params.source,
+        source_ns.clone(),
+        0,
+        Some(PRIV_DATASTORE_READ),
+        Some(PRIV_DATASTORE_BACKUP),
+        Some(&params.local_user),
+    )?
+    .filter_map(Result::ok)
+    .map(|backup_group| backup_group.group().clone())
+    .collect();
+
+    list.sort_unstable_by(|a, b| {
+        let type_order = a.ty.cmp(&b.ty);
+        if type_order == std::cmp::Ordering::Equal {
+            a.id.cmp(&b.id)
+        } else {
+            type_order
+        }
+    });
+
+    let unfiltered_count = list.len();
+    let list: Vec<BackupGroup> = list
+        .into_iter()
+        .filter(|group| group.apply_filters(&params.group_filter))
+        .collect();
+    task_log!(
+        worker,
+        "found {} groups to sync (out of {} total)",
+        list.len(),
+        unfiltered_count
+    );
+
+    let target_ns = source_ns.map_prefix(&params.source_ns, &params.target.ns)?;
+    let remote_auth_id = params.target.repo.auth_id();
+
+    let target_groups: HashMap<BackupGroup, Option<Authid>> =
+        list_target_groups(params, &target_ns)
+            .await?
+            .into_iter()
+            .map(|item| (item.backup, item.owner))
+            .collect();
+
+    let mut errors = false;
+    let mut progress = StoreProgress::new(list.len() as u64);
+
+    for (done, group) in list.iter().enumerate() {
+        progress.done_groups = done as u64;
+        progress.done_snapshots = 0;
+        progress.group_snapshots = 0;
+
+        let target_group_exists = match target_groups.get(group) {
+            Some(Some(owner)) if owner != remote_auth_id => {
+                // only the owner is allowed to create additional snapshots
+                task_log!(
+                    worker,
+                    "sync group {} failed - owner check failed ({} != {})",
+                    group,
+                    remote_auth_id,
+                    owner
+                );
+                errors = true; // do not stop here, instead continue
+                continue;
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if let Err(err) = push_group(
+            worker,
+            params,
+            source_ns,
+            group,
+            target_group_exists,
+            &mut progress,
+        )
+        .await
+        {
+            task_log!(worker, "sync group {} failed - {}", group, err);
+            errors = true; // do not stop here, instead continue
+        }
+    }
+
+    if params.remove_vanished {
+        let local_groups: HashSet<&BackupGroup> = list.iter().collect();
+        for (target_group, owner) in target_groups.iter() {
+            if local_groups.contains(target_group) {
+                continue;
+            }
+            if owner.as_ref() != Some(remote_auth_id) {
+                continue;
+            }
+            if !target_group.apply_filters(&params.group_filter) {
+                continue;
+            }
+            task_log!(worker, "delete vanished group '{target_group}'");
+            if let Err(err) = remove_target_group(params, &target_ns, target_group).await {
+                task_log!(
+                    worker,
+                    "delete vanished group '{target_group}' failed - {err}"
+                );
+                errors = true;
+            }
+        }
+    }
+
+    Ok((progress, errors))
+}
+
+/// Pushes a store according to `params`.
+///
+/// Pushing a store consists of the following steps:
+/// - List local namespaces accessible by the local user
+/// - Query list of namespaces on the remote
+/// - Iterate local list
+/// -- create sub-NS on the remote if needed
+/// -- attempt to push each NS in turn
+/// - (remove_vanished && max_depth > 0) remove sub-NS on the remote which are not or no longer
+///   available locally
+///
+/// Permission checks:
+/// - access to local datastore, namespace anchor and remote entry need to be checked at call site
+/// - access to local sub-NS checked here
+/// - creation and removal of remote sub-NS and remote group/snapshot access checked by remote
+pub(crate) async fn push_store(worker: &WorkerTask, params: PushParameters) -> Result<(), Error> {
+    let mut errors = false;
+
+    params.target.client.login().await?;
+
+    let mut namespaces: Vec<BackupNamespace> = ListNamespacesRecursive::new_max_depth(
+        params.source.clone(),
+        params.source_ns.clone(),
+        params.max_depth.unwrap_or(MAX_NAMESPACE_DEPTH),
+    )?
+    .collect::<Result<_, Error>>()?;
+
+    let ns_layers_to_be_pushed = namespaces
+        .iter()
+        .map(BackupNamespace::depth)
+        .max()
+        .map_or(0, |v| v - params.source_ns.depth());
+    let target_depth = params.target.ns.depth();
+
+    if ns_layers_to_be_pushed + target_depth > MAX_NAMESPACE_DEPTH {
+        bail!(
+            "Syncing would exceed max allowed namespace depth. ({}+{} > {})",
+            ns_layers_to_be_pushed,
+            target_depth,
+            MAX_NAMESPACE_DEPTH
+        );
+    }
+
+    namespaces.sort_unstable_by_key(|a| a.name_len());
+
+    let target_namespaces: HashSet<BackupNamespace> =
+        if params.target.ns.is_root() && params.max_depth == Some(0) {
+            HashSet::new() // root always exists, no need to query the remote
+        } else {
+            list_target_namespaces(&params).await?.into_iter().collect()
+        };
+
+    let (mut groups, mut snapshots) = (0, 0);
+    // all namespaces present on the source, including those the local user cannot read, which
+    // must not be treated as vanished
+    let mut listed_ns = HashSet::with_capacity(namespaces.len());
+
+    for namespace in namespaces {
+        let target_ns = namespace.map_prefix(&params.source_ns, &params.target.ns)?;
+        listed_ns.insert(target_ns.clone());
+
+        if check_ns_privs_full(
+            params.source.name(),
+            &namespace,
+            &params.local_user,
+            PRIV_DATASTORE_READ,
+            PRIV_DATASTORE_BACKUP,
+        )
+        .is_err()
+        {
+            continue;
+        }
+
+        let source_store_ns_str = print_store_and_ns(params.source.name(), &namespace);
+        let target_store_ns_str = print_store_and_ns(params.target.repo.store(), &target_ns);
+
+        task_log!(worker, "----");
+        task_log!(
+            worker,
+            "Syncing {} into {}",
+            source_store_ns_str,
+            target_store_ns_str
+        );
+
+        match check_and_create_target_ns(&params, &target_namespaces, &target_ns).await {
+            Ok(true) => task_log!(worker, "Created namespace {}", target_ns),
+            Ok(false) => {}
+            Err(err) => {
+                task_log!(
+                    worker,
+                    "Cannot sync {} into {} - {}",
+                    source_store_ns_str,
+                    target_store_ns_str,
+                    err,
+                );
+                errors = true;
+                continue;
+            }
+        }
+
+        match push_namespace(worker, &namespace, &params).await {
+            Ok((ns_progress, ns_errors)) => {
+                errors |= ns_errors;
+
+                if params.max_depth != Some(0) {
+                    groups += ns_progress.done_groups;
+                    snapshots += ns_progress.done_snapshots;
+                    task_log!(
+                        worker,
+                        "Finished syncing namespace {}, current progress: {} groups, {} snapshots",
+                        namespace,
+                        groups,
+                        snapshots,
+                    );
+                }
+            }
+            Err(err) => {
+                errors = true;
+                task_log!(
+                    worker,
+                    "Encountered errors while syncing namespace {} - {}",
+                    &namespace,
+                    err,
+                );
+            }
+        };
+    }
+
+    if params.remove_vanished {
+        let mut vanished_ns: Vec<&BackupNamespace> = target_namespaces
+            .iter()
+            .filter(|ns| !ns.is_root() && **ns != params.target.ns && !listed_ns.contains(*ns))
+            .collect();
+
+        // children first!
+        vanished_ns.sort_unstable_by_key(|b| std::cmp::Reverse(b.name_len()));
+
+        for ns in vanished_ns {
+            match remove_target_ns(&params, ns).await {
+                Ok(()) => task_log!(worker, "Removed namespace {}", ns),
+                Err(err) => {
+                    task_log!(worker, "Failed to remove namespace {} - {}", ns, err);
+                    errors = true;
+                }
+            }
+        }
+    }
+
+    if errors {
+        bail!("sync failed with some errors.");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,429 @@
+//! Datastore storage quotas
+//!
+//! Quotas limit the logical size and/or the estimated unique chunk size of the snapshots in a
+//! namespace (including sub-namespaces) and/or of a backup owner. The usage is computed from
+//! the snapshots on disk and cached for a few minutes. Running backup sessions account what they
+//! upload in the shared cache, so the limits hold across concurrent sessions, and fail once a
+//! limit is exceeded.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Error};
+use lazy_static::lazy_static;
+
+use proxmox_human_byte::HumanByte;
+
+use pbs_api_types::{Authid, BackupNamespace, DatastoreQuota, StorageUsage};
+use pbs_datastore::usage::UsageCollector;
+use pbs_datastore::DataStore;
+
+/// Maximum age of cached quota usage, in seconds.
+const QUOTA_USAGE_CACHE_MAX_AGE: i64 = 300;
+
+struct CachedUsage {
+    config: DatastoreQuota,
+    timestamp: i64,
+    /// Usage of the finished snapshots
+    usage: StorageUsage,
+    /// Data uploaded by running backup sessions
+    active: StorageUsage,
+}
+
+impl CachedUsage {
+    fn total(&self) -> StorageUsage {
+        StorageUsage {
+            snapshots: self.usage.snapshots,
+            logical_size: self.usage.logical_size + self.active.logical_size,
+            unique_size: self.usage.unique_size + self.active.unique_size,
+        }
+    }
+}
+
+lazy_static! {
+    static ref QUOTA_USAGE_CACHE: Mutex<HashMap<String, CachedUsage>> = Mutex::new(HashMap::new());
+}
+
+fn account_snapshots(
+    datastore: &Arc<DataStore>,
+    ns: BackupNamespace,
+    owner_filter: Option<&DatastoreQuota>,
+    collector: &mut UsageCollector,
+) -> Result<(), Error> {
+    for group in datastore.iter_backup_groups_ok(ns.clone())? {
+        if let Some(quota) = owner_filter {
+            match group.get_owner() {
+                Ok(owner) if quota.covers(datastore.name(), &ns, &owner) => (),
+                Ok(_) => continue,
+                Err(err) => {
+                    log::warn!("quota: skipping group {group:?} without owner - {err}");
+                    continue;
+                }
+            }
+        }
+
+        for info in group.list_backups()? {
+            if !info.is_finished() {
+                continue;
+            }
+            if let Err(err) = collector.add_snapshot(&info.backup_dir) {
+                log::warn!("quota: unable to account {:?} - {err}", info.backup_dir);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute the current usage covered by `quota`.
+pub fn compute_quota_usage(
+    datastore: &Arc<DataStore>,
+    quota: &DatastoreQuota,
+) -> Result<StorageUsage, Error> {
+    let mut collector = UsageCollector::new(Arc::clone(datastore));
+
+    let ns = quota.ns.clone().unwrap_or_default();
+    for ns in datastore.recursive_iter_backup_ns_ok(ns, None)? {
+        account_snapshots(datastore, ns, Some(quota), &mut collector)?;
+    }
+
+    Ok(collector.usage())
+}
+
+/// Compute the usage of the snapshots directly in `ns`, ignoring sub-namespaces.
+pub fn compute_namespace_usage(
+    datastore: &Arc<DataStore>,
+    ns: BackupNamespace,
+) -> Result<StorageUsage, Error> {
+    let mut collector = UsageCollector::new(Arc::clone(datastore));
+    account_snapshots(datastore, ns, None, &mut collector)?;
+    Ok(collector.usage())
+}
+
+/// Returns the (possibly cached) usage covered by `quota`, including the data uploaded by
+/// running backup sessions.
+pub fn quota_usage(
+    datastore: &Arc<DataStore>,
+    quota: &DatastoreQuota,
+) -> Result<StorageUsage, Error> {
+    let now = proxmox_time::epoch_i64();
+
+    if let Some(cached) = QUOTA_USAGE_CACHE.lock().unwrap().get(&quota.id) {
+        if cached.config == *quota && now - cached.timestamp < QUOTA_USAGE_CACHE_MAX_AGE {
+            return Ok(cached.total());
+        }
+    }
+
+    let usage = compute_quota_usage(datastore, quota)?;
+
+    // keep the data of running sessions, it is not on disk as part of a finished snapshot yet
+    let mut cache = QUOTA_USAGE_CACHE.lock().unwrap();
+    let cached = cache
+        .entry(quota.id.clone())
+        .or_insert_with(|| CachedUsage {
+            config: quota.clone(),
+            timestamp: now,
+            usage,
+            active: StorageUsage::default(),
+        });
+    cached.config = quota.clone();
+    cached.timestamp = now;
+    cached.usage = usage;
+
+    Ok(cached.total())
+}
+
+/// Adds data uploaded by a running session to the usage of `quota`, returns the new total.
+fn account_active_usage(
+    quota: &DatastoreQuota,
+    logical_size: u64,
+    unique_size: u64,
+) -> StorageUsage {
+    let mut cache = QUOTA_USAGE_CACHE.lock().unwrap();
+    let cached = cache
+        .entry(quota.id.clone())
+        .or_insert_with(|| CachedUsage {
+            config: quota.clone(),
+            timestamp: 0, // force recomputation on the next lookup
+            usage: StorageUsage::default(),
+            active: StorageUsage::default(),
+        });
+    cached.active.logical_size += logical_size;
+    cached.active.unique_size += unique_size;
+    cached.total()
+}
+
+/// Removes the data of a session from the running sessions' usage of `quota`, and adds it to
+/// the finished snapshots if the session was successful.
+fn release_active_usage(
+    quota: &DatastoreQuota,
+    logical_size: u64,
+    unique_size: u64,
+    finished: bool,
+) {
+    if let Some(cached) = QUOTA_USAGE_CACHE.lock().unwrap().get_mut(&quota.id) {
+        cached.active.logical_size = cached.active.logical_size.saturating_sub(logical_size);
+        cached.active.unique_size = cached.active.unique_size.saturating_sub(unique_size);
+        if finished {
+            cached.usage.snapshots += 1;
+            cached.usage.logical_size += logical_size;
+            cached.usage.unique_size += unique_size;
+        }
+    }
+}
+
+struct TrackedQuota {
+    config: DatastoreQuota,
+    usage: StorageUsage,
+    warned: bool,
+}
+
+impl TrackedQuota {
+    fn exceeded(&self) -> Option<String> {
+        let check = |name: &str, used: u64, limit: Option<HumanByte>| match limit {
+            Some(limit) if used > limit.as_u64() => Some(format!(
+                "{name} {} exceeds limit of {limit}",
+                HumanByte::from(used)
+            )),
+            _ => None,
+        };
+
+        check(
+            "logical size",
+            self.usage.logical_size,
+            self.config.logical_limit,
+        )
+        .or_else(|| {
+            check(
+                "unique size",
+                self.usage.unique_size,
+                self.config.unique_limit,
+            )
+        })
+    }
+
+    fn over_soft_limit(&self) -> bool {
+        let percentage = match self.config.soft_limit {
+            Some(percentage) => percentage as u64,
+            None => return false,
+        };
+
+        let over = |used: u64, limit: Option<HumanByte>| match limit {
+            Some(limit) => used as u128 * 100 > limit.as_u64() as u128 * percentage as u128,
+            None => false,
+        };
+
+        over(self.usage.logical_size, self.config.logical_limit)
+            || over(self.usage.unique_size, self.config.unique_limit)
+    }
+}
+
+/// Tracks the quotas covering a single backup session.
+///
+/// The uploaded data is given back to the quotas when the tracker is dropped without the
+/// session being finished.
+#[derive(Default)]
+pub struct QuotaTracker {
+    quotas: Vec<TrackedQuota>,
+    logical_size: u64,
+    unique_size: u64,
+    finished: bool,
+}
+
+impl QuotaTracker {
+    /// Load all quotas covering a backup group in `ns` owned by `owner`.
+    pub fn new(
+        datastore: &Arc<DataStore>,
+        ns: &BackupNamespace,
+        owner: &Authid,
+    ) -> Result<Self, Error> {
+        let (config, _digest) = pbs_config::quota::config()?;
+        let list: Vec<DatastoreQuota> = config.convert_to_typed_array("quota")?;
+
+        let mut quotas = Vec::new();
+        for config in list {
+            if !config.covers(datastore.name(), ns, owner) {
+                continue;
+            }
+            let usage = quota_usage(datastore, &config)?;
+            quotas.push(TrackedQuota {
+                config,
+                usage,
+                warned: false,
+            });
+        }
+
+        Ok(Self {
+            quotas,
+            logical_size: 0,
+            unique_size: 0,
+            finished: false,
+        })
+    }
+
+    /// Fails if any of the tracked quotas is exceeded.
+    pub fn check(&self) -> Result<(), Error> {
+        for quota in self.quotas.iter() {
+            if let Some(reason) = quota.exceeded() {
+                bail!("quota '{}' exceeded - {reason}", quota.config.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Account logical (uncompressed) data added to the snapshot.
+    pub fn add_logical(&mut self, bytes: u64) -> Result<(), Error> {
+        self.logical_size += bytes;
+        for quota in self.quotas.iter_mut() {
+            quota.usage = account_active_usage(&quota.config, bytes, 0);
+        }
+        self.update()
+    }
+
+    /// Account newly stored chunk data.
+    pub fn add_unique(&mut self, bytes: u64) -> Result<(), Error> {
+        self.unique_size += bytes;
+        for quota in self.quotas.iter_mut() {
+            quota.usage = account_active_usage(&quota.config, 0, bytes);
+        }
+        self.update()
+    }
+
+    fn update(&mut self) -> Result<(), Error> {
+        self.warn_soft_limits();
+        self.check()
+    }
+
+    /// Send a warning notification for every quota above its soft limit, once per session.
+    pub fn warn_soft_limits(&mut self) {
+        for quota in self.quotas.iter_mut() {
+            if !quota.warned && quota.over_soft_limit() {
+                quota.warned = true;
+                let config = quota.config.clone();
+                let usage = quota.usage;
+                // sending blocks, so do not stall the backup session
+                std::thread::spawn(move || {
+                    if let Err(err) = crate::server::send_quota_warning(&config, &usage) {
+                        log::error!("sending quota warning for '{}' failed - {err}", config.id);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Move the data of the finished snapshot to the cached usage of finished snapshots.
+    pub fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        for quota in self.quotas.iter() {
+            release_active_usage(&quota.config, self.logical_size, self.unique_size, true);
+        }
+    }
+}
+
+impl Drop for QuotaTracker {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        for quota in self.quotas.iter() {
+            release_active_usage(&quota.config, self.logical_size, self.unique_size, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_quota(
+        id: &str,
+        logical_limit: Option<u64>,
+        unique_limit: Option<u64>,
+    ) -> DatastoreQuota {
+        DatastoreQuota {
+            id: id.to_string(),
+            store: "store1".to_string(),
+            ns: None,
+            owner: None,
+            logical_limit: logical_limit.map(HumanByte::from),
+            unique_limit: unique_limit.map(HumanByte::from),
+            soft_limit: None,
+            comment: None,
+        }
+    }
+
+    fn tracked(config: DatastoreQuota, logical_size: u64, unique_size: u64) -> TrackedQuota {
+        TrackedQuota {
+            config,
+            usage: StorageUsage {
+                snapshots: 0,
+                logical_size,
+                unique_size,
+            },
+            warned: false,
+        }
+    }
+
+    #[test]
+    fn test_quota_exceeded() {
+        let config = test_quota("limits", Some(1000), Some(100));
+
+        assert!(tracked(config.clone(), 1000, 100).exceeded().is_none());
+        assert!(tracked(config.clone(), 1001, 0).exceeded().is_some());
+        assert!(tracked(config.clone(), 0, 101).exceeded().is_some());
+
+        // without limits nothing is exceeded
+        let config = test_quota("no-limits", None, None);
+        assert!(tracked(config, u64::MAX, u64::MAX).exceeded().is_none());
+    }
+
+    #[test]
+    fn test_quota_soft_limit() {
+        let mut config = test_quota("soft", Some(1000), Some(100));
+
+        // no soft limit configured
+        assert!(!tracked(config.clone(), 1000, 100).over_soft_limit());
+
+        config.soft_limit = Some(90);
+        assert!(!tracked(config.clone(), 900, 90).over_soft_limit());
+        assert!(tracked(config.clone(), 901, 0).over_soft_limit());
+        assert!(tracked(config.clone(), 0, 91).over_soft_limit());
+
+        // only configured limits count
+        config.unique_limit = None;
+        assert!(!tracked(config.clone(), 0, u64::MAX).over_soft_limit());
+
+        // no overflow with huge limits
+        config.logical_limit = Some(HumanByte::from(u64::MAX));
+        assert!(!tracked(config, u64::MAX / 2, 0).over_soft_limit());
+    }
+
+    #[test]
+    fn test_quota_concurrent_sessions() {
+        let config = test_quota("concurrent", None, Some(100));
+
+        let tracker = || QuotaTracker {
+            quotas: vec![tracked(config.clone(), 0, 0)],
+            ..Default::default()
+        };
+
+        // the uploads of all running sessions count towards the limit
+        let mut first = tracker();
+        let mut second = tracker();
+        first.add_unique(60).unwrap();
+        assert!(second.add_unique(60).is_err());
+
+        // an aborted session gives its data back
+        drop(second);
+        first.add_unique(40).unwrap();
+
+        // a finished one keeps it
+        first.finish();
+        drop(first);
+        let mut third = tracker();
+        assert!(third.add_unique(1).is_err());
+    }
+}
@@ -7,12 +7,16 @@ use proxmox_schema::{ApiType, Schema};
 use proxmox_section_config::SectionConfigData;
 use proxmox_sys::{task_log, task_warn};
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use pbs_api_types::{
-    ApiToken, Authid, LdapRealmConfig, Realm, RemoveVanished, SyncAttributes as LdapSyncAttributes,
-    SyncDefaultsOptions, User, Userid, EMAIL_SCHEMA, FIRST_NAME_SCHEMA, LAST_NAME_SCHEMA,
-    REMOVE_VANISHED_ARRAY, USER_CLASSES_ARRAY,
+    ApiToken, Authid, Group, LdapRealmConfig, Realm, RemoveVanished,
+    SyncAttributes as LdapSyncAttributes, SyncDefaultsOptions, SyncScope, User, Userid,
+    EMAIL_SCHEMA, FIRST_NAME_SCHEMA, GROUP_CLASSES_ARRAY, LAST_NAME_SCHEMA,
+    PROXMOX_GROUP_ID_SCHEMA, REMOVE_VANISHED_ARRAY, USER_CLASSES_ARRAY,
 };
 
 use crate::{auth, server::jobstate::Job};
@@ -28,6 +32,7 @@ pub fn do_realm_sync_job(
     dry_run: bool,
     remove_vanished: Option<String>,
     enable_new: Option<bool>,
+    scope: Option<SyncScope>,
 ) -> Result<String, Error> {
     let worker_type = job.jobtype().to_string();
     let upid_str = WorkerTask::spawn(
@@ -43,6 +48,7 @@ pub fn do_realm_sync_job(
             let override_settings = GeneralSyncSettingsOverride {
                 remove_vanished,
                 enable_new,
+                scope,
             };
 
             async move {
@@ -115,31 +121,79 @@ impl LdapRealmSyncJob {
         };
 
         let users = ldap.search_entities(&parameters).await?;
-        self.update_user_config(&users)?;
+
+        let groups = if self.general_sync_settings.scope.groups() {
+            let mut config = self.ldap_config.clone();
+            if let Some(group_dn) = &self.ldap_sync_settings.group_dn {
+                config.base_dn = group_dn.clone();
+            }
+
+            let parameters = SearchParameters {
+                attributes: vec![
+                    self.ldap_sync_settings.group_name_attr.clone(),
+                    "member".into(),
+                    "uniqueMember".into(),
+                    "memberUid".into(),
+                ],
+                user_classes: self.ldap_sync_settings.group_classes.clone(),
+                user_filter: self.ldap_sync_settings.group_filter.clone(),
+            };
+
+            Some(Connection::new(config).search_entities(&parameters).await?)
+        } else {
+            None
+        };
+
+        self.update_user_config(&users, groups.as_deref())?;
 
         Ok(())
     }
 
-    fn update_user_config(&self, users: &[SearchResult]) -> Result<(), Error> {
+    fn update_user_config(
+        &self,
+        users: &[SearchResult],
+        groups: Option<&[SearchResult]>,
+    ) -> Result<(), Error> {
         let user_lock = pbs_config::user::lock_config()?;
         let acl_lock = pbs_config::acl::lock_config()?;
 
         let (mut user_config, _digest) = pbs_config::user::config()?;
         let (mut tree, _) = pbs_config::acl::config()?;
 
-        let retrieved_users = self.create_or_update_users(&mut user_config, &user_lock, users)?;
+        if self.general_sync_settings.scope.users() {
+            let retrieved_users =
+                self.create_or_update_users(&mut user_config, &user_lock, users)?;
+
+            if self.general_sync_settings.should_remove_entries() {
+                let vanished_users =
+                    self.compute_vanished_users(&user_config, &user_lock, &retrieved_users)?;
+
+                self.delete_users(
+                    &mut user_config,
+                    &user_lock,
+                    &mut tree,
+                    &acl_lock,
+                    &vanished_users,
+                )?;
+            }
+        }
+
+        if let Some(groups) = groups {
+            let retrieved_groups = self.create_or_update_groups(&mut user_config, users, groups)?;
 
-        if self.general_sync_settings.should_remove_entries() {
-            let vanished_users =
-                self.compute_vanished_users(&user_config, &user_lock, &retrieved_users)?;
+            if self.general_sync_settings.should_remove_entries() {
+                let vanished_groups =
+                    self.compute_vanished_groups(&user_config, &retrieved_groups)?;
 
-            self.delete_users(
-                &mut user_config,
-                &user_lock,
-                &mut tree,
-                &acl_lock,
-                &vanished_users,
-            )?;
+                for groupid in vanished_groups {
+                    task_log!(self.worker, "deleting group {groupid}");
+                    user_config.sections.remove(&groupid);
+
+                    if self.general_sync_settings.should_remove_acls() {
+                        tree.delete_group(&groupid);
+                    }
+                }
+            }
         }
 
         if !self.dry_run {
@@ -294,6 +348,108 @@ impl LdapRealmSyncJob {
         }
     }
 
+    fn create_or_update_groups(
+        &self,
+        user_config: &mut SectionConfigData,
+        users: &[SearchResult],
+        groups: &[SearchResult],
+    ) -> Result<HashSet<String>, Error> {
+        // map the DNs and names of the LDAP users to the user IDs of existing users
+        let mut dn_map = HashMap::new();
+        let mut name_map = HashMap::new();
+        for result in users {
+            let username = match attribute_values(result, &self.ldap_sync_settings.user_attr).next()
+            {
+                Some(username) => username,
+                None => continue,
+            };
+            let userid: Userid = match format!("{username}@{}", self.realm.as_str()).parse() {
+                Ok(userid) => userid,
+                Err(_) => continue,
+            };
+            if user_config.lookup::<User>("user", userid.as_str()).is_err() {
+                continue;
+            }
+            dn_map.insert(result.dn.to_lowercase(), userid.clone());
+            name_map.insert(username.clone(), userid);
+        }
+
+        let group_name_attr = &self.ldap_sync_settings.group_name_attr;
+        let mut retrieved_groups = HashSet::new();
+
+        for result in groups {
+            let result = try_block!({
+                let name = attribute_values(result, group_name_attr)
+                    .next()
+                    .ok_or_else(|| {
+                        format_err!(
+                            "group name attribute `{group_name_attr}` not in LDAP search result"
+                        )
+                    })?;
+
+                let groupid = format!("{name}-{realm}", realm = self.realm.as_str());
+                PROXMOX_GROUP_ID_SCHEMA
+                    .unwrap_string_schema()
+                    .check_constraints(&groupid)
+                    .map_err(|err| format_err!("invalid group name `{groupid}` - {err}"))?;
+                retrieved_groups.insert(groupid.clone());
+
+                let mut members: Vec<Userid> = attribute_values(result, "member")
+                    .chain(attribute_values(result, "uniqueMember"))
+                    .filter_map(|dn| dn_map.get(&dn.to_lowercase()))
+                    .chain(
+                        attribute_values(result, "memberUid").filter_map(|name| name_map.get(name)),
+                    )
+                    .cloned()
+                    .collect();
+                members.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                members.dedup();
+
+                let existing_group = user_config.lookup::<Group>("group", &groupid).ok();
+                let group = Group {
+                    groupid: groupid.clone(),
+                    comment: existing_group.as_ref().and_then(|g| g.comment.clone()),
+                    members: if members.is_empty() {
+                        None
+                    } else {
+                        Some(members)
+                    },
+                };
+
+                match existing_group {
+                    Some(existing_group) if existing_group == group => (),
+                    Some(_) => task_log!(self.worker, "updating group {groupid}"),
+                    None => task_log!(self.worker, "creating group {groupid}"),
+                }
+
+                user_config.set_data(&groupid, "group", &group)?;
+                anyhow::Ok(())
+            });
+            if let Err(e) = result {
+                task_log!(self.worker, "could not create/update group: {e}");
+            }
+        }
+
+        Ok(retrieved_groups)
+    }
+
+    fn compute_vanished_groups(
+        &self,
+        user_config: &SectionConfigData,
+        synced_groups: &HashSet<String>,
+    ) -> Result<Vec<String>, Error> {
+        let suffix = format!("-{}", self.realm.as_str());
+
+        Ok(user_config
+            .convert_to_typed_array::<Group>("group")?
+            .into_iter()
+            .filter(|group| {
+                group.groupid.ends_with(&suffix) && !synced_groups.contains(&group.groupid)
+            })
+            .map(|group| group.groupid)
+            .collect())
+    }
+
     fn compute_vanished_users(
         &self,
         user_config: &SectionConfigData,
@@ -323,6 +479,7 @@ impl LdapRealmSyncJob {
 
             // Delete the user
             user_config.sections.remove(userid.as_str());
+            pbs_config::user::remove_user_from_groups(user_config, userid)?;
 
             if self.general_sync_settings.should_remove_acls() {
                 let auth_id = userid.clone().into();
@@ -361,16 +518,31 @@ impl LdapRealmSyncJob {
     }
 }
 
+/// Returns the values of an attribute of a search result, the attribute name is matched
+/// case-insensitively.
+fn attribute_values<'a>(
+    result: &'a SearchResult,
+    name: &'a str,
+) -> impl Iterator<Item = &'a String> + 'a {
+    result
+        .attributes
+        .iter()
+        .filter(move |(attribute, _)| attribute.eq_ignore_ascii_case(name))
+        .flat_map(|(_, values)| values.iter())
+}
+
 /// General realm sync settings - Override for manual invocation
 struct GeneralSyncSettingsOverride {
     remove_vanished: Option<String>,
     enable_new: Option<bool>,
+    scope: Option<SyncScope>,
 }
 
 /// General realm sync settings from the realm configuration
 struct GeneralSyncSettings {
     remove_vanished: Vec<RemoveVanished>,
     enable_new: bool,
+    scope: SyncScope,
 }
 
 /// LDAP-specific realm sync settings from the realm configuration
@@ -382,6 +554,10 @@ struct LdapSyncSettings {
     attributes: Vec<String>,
     user_classes: Vec<String>,
     user_filter: Option<String>,
+    group_dn: Option<String>,
+    group_name_attr: String,
+    group_classes: Vec<String>,
+    group_filter: Option<String>,
 }
 
 impl LdapSyncSettings {
@@ -425,6 +601,20 @@ impl LdapSyncSettings {
             ]
         };
 
+        let group_classes = if let Some(group_classes) = &config.group_classes {
+            let a = GROUP_CLASSES_ARRAY.parse_property_string(group_classes)?;
+            serde_json::from_value(a)?
+        } else {
+            vec![
+                "groupofnames".into(),
+                "groupofuniquenames".into(),
+                "group".into(),
+                "posixgroup".into(),
+                "univentiongroup".into(),
+                "ipausergroup".into(),
+            ]
+        };
+
         Ok(Self {
             user_attr: config.user_attr.clone(),
             firstname_attr: firstname,
@@ -433,6 +623,13 @@ impl LdapSyncSettings {
             attributes,
             user_classes,
             user_filter: config.filter.clone(),
+            group_dn: config.group_dn.clone(),
+            group_name_attr: config
+                .group_name_attr
+                .clone()
+                .unwrap_or_else(|| "cn".into()),
+            group_classes,
+            group_filter: config.group_filter.clone(),
         })
     }
 }
@@ -442,6 +639,7 @@ impl Default for GeneralSyncSettings {
         Self {
             remove_vanished: Default::default(),
             enable_new: true,
+            scope: Default::default(),
         }
     }
 }
@@ -450,11 +648,13 @@ impl GeneralSyncSettings {
     fn apply_config(self, config: &LdapRealmConfig) -> Result<Self, Error> {
         let mut enable_new = None;
         let mut remove_vanished = None;
+        let mut scope = None;
 
         if let Some(sync_defaults_options) = &config.sync_defaults_options {
             let sync_defaults_options = Self::parse_sync_defaults_options(sync_defaults_options)?;
 
             enable_new = sync_defaults_options.enable_new;
+            scope = sync_defaults_options.scope;
 
             if let Some(vanished) = sync_defaults_options.remove_vanished.as_deref() {
                 remove_vanished = Some(Self::parse_remove_vanished(vanished)?);
@@ -464,6 +664,7 @@ impl GeneralSyncSettings {
         Ok(Self {
             enable_new: enable_new.unwrap_or(self.enable_new),
             remove_vanished: remove_vanished.unwrap_or(self.remove_vanished),
+            scope: scope.unwrap_or(self.scope),
         })
     }
 
@@ -478,6 +679,7 @@ impl GeneralSyncSettings {
         Ok(Self {
             enable_new: enable_new.unwrap_or(self.enable_new),
             remove_vanished: remove_vanished.unwrap_or(self.remove_vanished),
+            scope: override_config.scope.unwrap_or(self.scope),
         })
     }
 
@@ -0,0 +1,51 @@
+use anyhow::Error;
+use std::sync::Arc;
+
+use proxmox_sys::task_log;
+
+use pbs_api_types::Authid;
+use pbs_datastore::DataStore;
+use proxmox_rest_server::WorkerTask;
+
+use crate::backup::scrub_chunk_store;
+use crate::server::jobstate::Job;
+
+/// Runs a chunk store scrub job.
+pub fn do_scrub_job(
+    mut job: Job,
+    datastore: Arc<DataStore>,
+    auth_id: &Authid,
+    schedule: Option<String>,
+    to_stdout: bool,
+) -> Result<String, Error> {
+    let store = datastore.name().to_string();
+
+    let worker_type = job.jobtype().to_string();
+    let upid_str = WorkerTask::new_thread(
+        &worker_type,
+        Some(store.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            job.start(&worker.upid().to_string())?;
+
+            task_log!(worker, "starting chunk store scrub on store {store}");
+            if let Some(event_str) = schedule {
+                task_log!(worker, "task triggered by schedule '{event_str}'");
+            }
+
+            let upid = Some(worker.upid().to_string());
+            let result = scrub_chunk_store(worker.clone(), datastore, upid).map(|_| ());
+
+            let status = worker.create_state(&result);
+
+            if let Err(err) = job.finish(status) {
+                eprintln!("could not finish job state for {}: {err}", job.jobtype());
+            }
+
+            result
+        },
+    )?;
+
+    Ok(upid_str)
+}
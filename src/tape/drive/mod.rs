@@ -399,15 +399,13 @@ pub fn request_and_load_media(
                                     device_type,
                                     device
                                 );
-                                if let Some(to) = notify_email {
-                                    send_load_media_email(
-                                        changer.is_some(),
-                                        device,
-                                        &label_text,
-                                        to,
-                                        Some(new.to_string()),
-                                    )?;
-                                }
+                                send_load_media_email(
+                                    changer.is_some(),
+                                    device,
+                                    &label_text,
+                                    notify_email.as_deref(),
+                                    Some(new.to_string()),
+                                )?;
                                 *old = new;
                             }
                             Ok(())
@@ -1300,6 +1300,27 @@ pub fn complete_partition_name(_arg: &str, _param: &HashMap<String, String>) ->
         .collect()
 }
 
+/// Mount the filesystem with the given UUID on `target`.
+pub fn mount_by_uuid(uuid: &str, target: &Path) -> Result<(), Error> {
+    let mut command = std::process::Command::new("mount");
+    command.arg(format!("UUID={uuid}"));
+    command.arg(target);
+
+    proxmox_sys::command::run_command(command, None)?;
+
+    Ok(())
+}
+
+/// Unmount the filesystem mounted on `target`.
+pub fn unmount_by_mountpoint(target: &Path) -> Result<(), Error> {
+    let mut command = std::process::Command::new("umount");
+    command.arg(target);
+
+    proxmox_sys::command::run_command(command, None)?;
+
+    Ok(())
+}
+
 /// Read the FS UUID (parse blkid output)
 ///
 /// Note: Calling blkid is more reliable than using the udev ID_FS_UUID property.
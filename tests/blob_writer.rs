@@ -5,6 +5,7 @@ use std::sync::Arc;
 use anyhow::{bail, Error};
 use lazy_static::lazy_static;
 
+use pbs_api_types::{ChunkCompression, ChunkCompressionAlgorithm};
 use pbs_datastore::{DataBlob, DataBlobReader, DataBlobWriter};
 use pbs_tools::crypt_config::CryptConfig;
 
@@ -103,3 +104,58 @@ fn test_encrypted_compressed_blob_writer() -> Result<(), Error> {
 
     verify_test_blob(blob_writer.finish()?, &TEST_DIGEST_ENC)
 }
+
+#[test]
+fn test_lz4_compressed_blob() -> Result<(), Error> {
+    let compression = ChunkCompression::new(ChunkCompressionAlgorithm::Lz4, None);
+    let blob = DataBlob::encode_with_compression(&TEST_DATA, None, compression)?;
+    assert!(blob.is_compressed());
+
+    verify_test_blob(Cursor::new(blob.into_inner()), &TEST_DIGEST_PLAIN)
+}
+
+#[test]
+fn test_lz4_encrypted_compressed_blob() -> Result<(), Error> {
+    let compression = ChunkCompression::new(ChunkCompressionAlgorithm::Lz4, None);
+    let blob = DataBlob::encode_with_compression(&TEST_DATA, Some(&CRYPT_CONFIG), compression)?;
+    assert!(blob.is_compressed() && blob.is_encrypted());
+
+    verify_test_blob(Cursor::new(blob.into_inner()), &TEST_DIGEST_ENC)
+}
+
+#[test]
+fn test_zstd_long_compressed_blob() -> Result<(), Error> {
+    let compression = ChunkCompression::new(ChunkCompressionAlgorithm::ZstdLong, Some(19));
+    let blob = DataBlob::encode_with_compression(&TEST_DATA, None, compression)?;
+    assert!(blob.is_compressed());
+
+    verify_test_blob(Cursor::new(blob.into_inner()), &TEST_DIGEST_PLAIN)
+}
+
+#[test]
+fn test_recompress_blob() -> Result<(), Error> {
+    let blob = DataBlob::encode(&TEST_DATA, None, true)?;
+    let compression = ChunkCompression::new(ChunkCompressionAlgorithm::Lz4, None);
+    let blob = blob.recompress(compression, &TEST_DIGEST_PLAIN)?;
+
+    verify_test_blob(Cursor::new(blob.into_inner()), &TEST_DIGEST_PLAIN)
+}
+
+#[test]
+fn test_blob_uses_compression() -> Result<(), Error> {
+    let blob = DataBlob::encode(&TEST_DATA, None, true)?;
+    assert!(blob.uses_compression(ChunkCompressionAlgorithm::Zstd));
+    assert!(blob.uses_compression(ChunkCompressionAlgorithm::ZstdLong));
+    assert!(!blob.uses_compression(ChunkCompressionAlgorithm::Lz4));
+
+    let compression = ChunkCompression::new(ChunkCompressionAlgorithm::Lz4, None);
+    let blob = blob.recompress(compression, &TEST_DIGEST_PLAIN)?;
+    assert!(blob.uses_compression(ChunkCompressionAlgorithm::Lz4));
+    assert!(!blob.uses_compression(ChunkCompressionAlgorithm::Zstd));
+
+    let blob = DataBlob::encode(&TEST_DATA, None, false)?;
+    assert!(blob.uses_compression(ChunkCompressionAlgorithm::None));
+    assert!(!blob.uses_compression(ChunkCompressionAlgorithm::Zstd));
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use anyhow::Error;
 
 use pbs_api_types::PruneJobOptions;
 use pbs_datastore::manifest::MANIFEST_BLOB_NAME;
-use pbs_datastore::prune::compute_prune_info;
+use pbs_datastore::prune::{compute_prune_info, compute_prune_info_at};
 use pbs_datastore::{BackupDir, BackupInfo};
 
 fn get_prune_list(
@@ -335,3 +335,59 @@ fn test_prune_simple() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_prune_keep_within() -> Result<(), Error> {
+    let orig_list = vec![
+        create_info("host/elsa/2019-12-01T11:59:15Z", false),
+        create_info("host/elsa/2019-12-02T11:59:15Z", false),
+        create_info("host/elsa/2019-12-03T11:59:15Z", false),
+        create_info("host/elsa/2019-12-04T11:59:15Z", false),
+        create_info("host/elsa/2019-12-05T10:59:15Z", false),
+        create_info("host/elsa/2019-12-05T11:59:15Z", false),
+    ];
+
+    let now = 1575590400; // 2019-12-06T00:00:00Z
+
+    let get_remove_list = |options: &PruneJobOptions| -> Vec<PathBuf> {
+        let mut prune_info = compute_prune_info_at(orig_list.clone(), &options.keep, now).unwrap();
+        prune_info.reverse();
+        prune_info
+            .iter()
+            .filter_map(|(info, mark)| (!mark.keep()).then(|| info.backup_dir.relative_path()))
+            .collect()
+    };
+
+    // keep-within
+    let mut options = PruneJobOptions::default();
+    options.keep.keep_within = Some("2d".to_string());
+    let expect: Vec<PathBuf> = vec![
+        PathBuf::from("host/elsa/2019-12-01T11:59:15Z"),
+        PathBuf::from("host/elsa/2019-12-02T11:59:15Z"),
+        PathBuf::from("host/elsa/2019-12-03T11:59:15Z"),
+    ];
+    assert_eq!(get_remove_list(&options), expect);
+
+    // keep-within-daily
+    let mut options = PruneJobOptions::default();
+    options.keep.keep_within_daily = Some("3d".to_string());
+    let expect: Vec<PathBuf> = vec![
+        PathBuf::from("host/elsa/2019-12-01T11:59:15Z"),
+        PathBuf::from("host/elsa/2019-12-02T11:59:15Z"),
+        PathBuf::from("host/elsa/2019-12-05T10:59:15Z"),
+    ];
+    assert_eq!(get_remove_list(&options), expect);
+
+    // keep-last + keep-within-daily
+    let mut options = PruneJobOptions::default();
+    options.keep.keep_last = Some(2);
+    options.keep.keep_within_daily = Some("2d".to_string());
+    let expect: Vec<PathBuf> = vec![
+        PathBuf::from("host/elsa/2019-12-01T11:59:15Z"),
+        PathBuf::from("host/elsa/2019-12-02T11:59:15Z"),
+        PathBuf::from("host/elsa/2019-12-03T11:59:15Z"),
+    ];
+    assert_eq!(get_remove_list(&options), expect);
+
+    Ok(())
+}
@@ -411,16 +411,20 @@ Ext.define('PBS.Utils', {
 	    'label-media': [gettext('Drive'), gettext('Label Media')],
 	    'load-media': (type, id) => PBS.Utils.render_drive_load_media_id(id, gettext('Load Media')),
 	    logrotate: [null, gettext('Log Rotation')],
+	    'mount-device': [gettext('Datastore'), gettext('Mount Device')],
 	    prune: (type, id) => PBS.Utils.render_datastore_worker_id(id, gettext('Prune')),
 	    prunejob: (type, id) => PBS.Utils.render_prune_job_worker_id(id, gettext('Prune Job')),
 	    reader: (type, id) => PBS.Utils.render_datastore_worker_id(id, gettext('Read Objects')),
 	    'rewind-media': [gettext('Drive'), gettext('Rewind Media')],
+	    scrub: ['Datastore', gettext('Chunk Store Scrub')],
+	    'space-accounting': ['Datastore', gettext('Space Accounting')],
 	    sync: ['Datastore', gettext('Remote Sync')],
 	    syncjob: [gettext('Sync Job'), gettext('Remote Sync')],
 	    'tape-backup': (type, id) => PBS.Utils.render_tape_backup_id(id, gettext('Tape Backup')),
 	    'tape-backup-job': (type, id) => PBS.Utils.render_tape_backup_id(id, gettext('Tape Backup Job')),
 	    'tape-restore': ['Datastore', gettext('Tape Restore')],
 	    'unload-media': [gettext('Drive'), gettext('Unload Media')],
+	    'unmount-device': [gettext('Datastore'), gettext('Unmount Device')],
 	    verificationjob: [gettext('Verify Job'), gettext('Scheduled Verification')],
 	    verify: ['Datastore', gettext('Verification')],
 	    verify_group: ['Group', gettext('Verification')],
@@ -683,7 +687,8 @@ Ext.define('PBS.Utils', {
 	let extra = '';
 
 	if (activeTasks !== undefined) {
-	    const conflictingTasks = activeTasks.write + (type === 'offline' ? activeTasks.read : 0);
+	    const blocksReads = type === 'offline' || type === 'unmount';
+	    const conflictingTasks = activeTasks.write + (blocksReads ? activeTasks.read : 0);
 
 	    if (conflictingTasks > 0) {
 		extra += '| <i class="fa fa-spinner fa-pulse fa-fw"></i> ';
@@ -703,6 +708,8 @@ Ext.define('PBS.Utils', {
 		break;
 	    case 'offline': modeText = gettext("Offline");
 		break;
+	    case 'unmount': modeText = gettext("Unmounting");
+		break;
 	}
 	return `${modeText} ${extra}`;
     },
@@ -731,6 +738,11 @@ Ext.define('PBS.Utils', {
 	    file: gettext('File'),
 	    filesystem: gettext('Filesystem'),
 	},
+	'gc-mode': {
+	    '__default__': Proxmox.Utils.defaultText + ` (${gettext('Access Time')})`,
+	    atime: gettext('Access Time'),
+	    'digest-list': gettext('Digest List'),
+	},
     },
 
     render_tuning_options: function(tuning) {
@@ -745,6 +757,11 @@ Ext.define('PBS.Utils', {
 	sync = PBS.Utils.tuningOptions['sync-level'][sync ?? '__default__'];
 	options.push(`${gettext('Sync Level')}: ${sync}`);
 
+	let gcMode = tuning['gc-mode'];
+	delete tuning['gc-mode'];
+	gcMode = PBS.Utils.tuningOptions['gc-mode'][gcMode ?? '__default__'];
+	options.push(`${gettext('GC Mode')}: ${gcMode}`);
+
 	for (const [k, v] of Object.entries(tuning)) {
 	    options.push(`${k}: ${v}`);
 	}
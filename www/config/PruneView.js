@@ -3,6 +3,8 @@ Ext.define('pbs-prune-jobs-status', {
     fields: [
 	'id', 'disable', 'store', 'ns', 'max-depth', 'schedule',
 	'keep-last', 'keep-hourly', 'keep-daily', 'keep-weekly', 'keep-monthly', 'keep-yearly',
+	'keep-within', 'keep-within-hourly', 'keep-within-daily', 'keep-within-weekly',
+	'keep-within-monthly', 'keep-within-yearly',
 	'next-run', 'last-run-upid', 'last-run-state', 'last-run-endtime',
 	{
 	    name: 'duration',
@@ -221,6 +223,7 @@ Ext.define('PBS.config.PruneJobView', {
 		['weekly', gettext('Weekly')],
 		['monthly', gettext('Monthly')],
 		['yearly', gettext('Yearly')],
+		['within', gettext('Within')],
 	    ].map(([data, header]) => ({
 		header: header,
 		dataIndex: `keep-${data}`,
@@ -203,6 +203,42 @@ Ext.define('PBS.Datastore.Options', {
 		},
 	    },
 	},
+	"retention-lock": {
+	    required: true,
+	    header: gettext('Retention Lock'),
+	    defaultValue: '',
+	    renderer: v => v || Proxmox.Utils.NoneText,
+	    editor: {
+		xtype: 'proxmoxWindowEdit',
+		title: gettext('Retention Lock'),
+		width: 350,
+		items: {
+		    xtype: 'proxmoxtextfield',
+		    name: 'retention-lock',
+		    fieldLabel: gettext('Lock new snapshots for'),
+		    emptyText: Proxmox.Utils.NoneText,
+		    deleteEmpty: true,
+		},
+	    },
+	},
+	"chunk-mirror": {
+	    required: true,
+	    header: gettext('Chunk Mirror'),
+	    defaultValue: '',
+	    renderer: v => v || Proxmox.Utils.NoneText,
+	    editor: {
+		xtype: 'proxmoxWindowEdit',
+		title: gettext('Chunk Mirror'),
+		width: 450,
+		items: {
+		    xtype: 'proxmoxtextfield',
+		    name: 'chunk-mirror',
+		    fieldLabel: gettext('Mirror Path'),
+		    emptyText: Proxmox.Utils.NoneText,
+		    deleteEmpty: true,
+		},
+	    },
+	},
 	"maintenance-mode": {
 	    required: true,
 	    header: gettext('Maintenance mode'),
@@ -256,6 +292,30 @@ Ext.define('PBS.Datastore.Options', {
 			    deleteEmpty: true,
 			    value: '__default__',
 			},
+			{
+			    xtype: 'proxmoxKVComboBox',
+			    name: 'gc-mode',
+			    fieldLabel: gettext('GC Mode'),
+			    comboItems: Object.entries(PBS.Utils.tuningOptions['gc-mode']),
+			    deleteEmpty: true,
+			    value: '__default__',
+			},
+			{
+			    xtype: 'proxmoxintegerfield',
+			    name: 'gc-sweep-parts',
+			    fieldLabel: gettext('GC Sweep Parts'),
+			    emptyText: '1',
+			    minValue: 1,
+			    maxValue: 4096,
+			    deleteEmpty: true,
+			},
+			{
+			    xtype: 'proxmoxtextfield',
+			    name: 'scrub-rate-limit',
+			    fieldLabel: gettext('Scrub Rate Limit'),
+			    emptyText: gettext('Unlimited'),
+			    deleteEmpty: true,
+			},
 		    ],
 		},
 	    },
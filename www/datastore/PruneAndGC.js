@@ -37,6 +37,23 @@ Ext.define('PBS.Datastore.GCOptions', {
 		},
 	    });
 	},
+
+	scrub: function() {
+	    let me = this;
+	    let view = me.getView();
+	    Proxmox.Utils.API2Request({
+		url: `/admin/datastore/${view.datastore}/scrub`,
+		method: 'POST',
+		failure: function(response) {
+		    Ext.Msg.alert(gettext('Error'), response.htmlStatus);
+		},
+		success: function(response, options) {
+		    Ext.create('Proxmox.window.TaskViewer', {
+			upid: response.result.data,
+		    }).show();
+		},
+	    });
+	},
     },
 
     tbar: [
@@ -53,6 +70,12 @@ Ext.define('PBS.Datastore.GCOptions', {
 	    selModel: null,
 	    handler: 'garbageCollect',
 	},
+	{
+	    xtype: 'proxmoxButton',
+	    text: gettext('Start Scrub'),
+	    selModel: null,
+	    handler: 'scrub',
+	},
     ],
 
     listeners: {
@@ -80,6 +103,23 @@ Ext.define('PBS.Datastore.GCOptions', {
 		},
 	    },
 	},
+	"scrub-schedule": {
+	    required: true,
+	    defaultValue: Proxmox.Utils.NoneText,
+	    header: gettext('Scrub Schedule'),
+	    editor: {
+		xtype: 'proxmoxWindowEdit',
+		title: gettext('Scrub Schedule'),
+		onlineHelp: 'maintenance_scrub',
+		items: {
+		    xtype: 'pbsCalendarEvent',
+		    name: 'scrub-schedule',
+		    fieldLabel: gettext("Scrub Schedule"),
+		    emptyText: Proxmox.Utils.noneText,
+		    deleteEmpty: true,
+		},
+	    },
+	},
     },
 });
 
@@ -59,6 +59,33 @@ Ext.define('PBS.panel.PruneInputPanel', {
 		deleteEmpty: '{!isCreate}',
 	    },
 	},
+	{
+	    xtype: 'proxmoxtextfield',
+	    name: 'keep-within',
+	    fieldLabel: gettext('Keep Within'),
+	    emptyText: gettext('none'),
+	    cbind: {
+		deleteEmpty: '{!isCreate}',
+	    },
+	},
+	{
+	    xtype: 'proxmoxtextfield',
+	    name: 'keep-within-daily',
+	    fieldLabel: gettext('Keep Daily Within'),
+	    emptyText: gettext('none'),
+	    cbind: {
+		deleteEmpty: '{!isCreate}',
+	    },
+	},
+	{
+	    xtype: 'proxmoxtextfield',
+	    name: 'keep-within-monthly',
+	    fieldLabel: gettext('Keep Monthly Within'),
+	    emptyText: gettext('none'),
+	    cbind: {
+		deleteEmpty: '{!isCreate}',
+	    },
+	},
 	{
 	    xtype: 'proxmoxcheckbox',
 	    name: 'dry-run',
@@ -94,6 +121,33 @@ Ext.define('PBS.panel.PruneInputPanel', {
 		deleteEmpty: '{!isCreate}',
 	    },
 	},
+	{
+	    xtype: 'proxmoxtextfield',
+	    name: 'keep-within-hourly',
+	    fieldLabel: gettext('Keep Hourly Within'),
+	    emptyText: gettext('none'),
+	    cbind: {
+		deleteEmpty: '{!isCreate}',
+	    },
+	},
+	{
+	    xtype: 'proxmoxtextfield',
+	    name: 'keep-within-weekly',
+	    fieldLabel: gettext('Keep Weekly Within'),
+	    emptyText: gettext('none'),
+	    cbind: {
+		deleteEmpty: '{!isCreate}',
+	    },
+	},
+	{
+	    xtype: 'proxmoxtextfield',
+	    name: 'keep-within-yearly',
+	    fieldLabel: gettext('Keep Yearly Within'),
+	    emptyText: gettext('none'),
+	    cbind: {
+		deleteEmpty: '{!isCreate}',
+	    },
+	},
 	{
 	    xtype: 'fieldcontainer',
 	    layout: 'hbox',
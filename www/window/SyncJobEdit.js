@@ -54,6 +54,9 @@ Ext.define('PBS.window.SyncJobEdit', {
 	} else {
 	    values.location = 'remote';
 	}
+	if (Ext.isArray(values['rename-rules'])) {
+	    values['rename-rules'] = values['rename-rules'].join('\n');
+	}
 	me.callParent([values]);
     },
 
@@ -316,6 +319,113 @@ Ext.define('PBS.window.SyncJobEdit', {
 			    deleteEmpty: '{!isCreate}',
 			},
 		    },
+		    {
+			fieldLabel: gettext('Repair corrupt'),
+			xtype: 'proxmoxcheckbox',
+			name: 'repair-corrupt',
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('Fetch chunks of snapshots which failed verification from the source again'),
+			},
+			uncheckedValue: false,
+			value: false,
+		    },
+		],
+		advancedColumn2: [
+		    {
+			xtype: 'proxmoxintegerfield',
+			name: 'parallel-groups',
+			fieldLabel: gettext('Parallel Groups'),
+			minValue: 1,
+			maxValue: 32,
+			emptyText: '1',
+			submitEmpty: false,
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxintegerfield',
+			name: 'chunk-concurrency',
+			fieldLabel: gettext('Chunk Concurrency'),
+			minValue: 1,
+			maxValue: 256,
+			emptyText: '20',
+			submitEmpty: false,
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('Number of chunks downloaded in parallel for each group'),
+			},
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		],
+	    },
+	    {
+		xtype: 'inputpanel',
+		title: gettext('Snapshot Filter'),
+		column1: [
+		    {
+			xtype: 'proxmoxtextfield',
+			name: 'newer-than',
+			fieldLabel: gettext('Newer than'),
+			emptyText: gettext('any'),
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('Time span (e.g. 7d) or date (RFC 3339), only newer snapshots are synced'),
+			},
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxtextfield',
+			name: 'older-than',
+			fieldLabel: gettext('Older than'),
+			emptyText: gettext('any'),
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('Time span (e.g. 1d), only older snapshots are synced'),
+			},
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxKVComboBox',
+			name: 'encryption-filter',
+			fieldLabel: gettext('Encryption'),
+			value: '__default__',
+			comboItems: [
+			    ['__default__', gettext('Any')],
+			    ['encrypted', gettext('Encrypted only')],
+			    ['unencrypted', gettext('Unencrypted only')],
+			],
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		],
+		column2: [
+		    {
+			fieldLabel: gettext('Verified only'),
+			xtype: 'proxmoxcheckbox',
+			name: 'verified-only',
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('Only sync snapshots whose last verification was successful'),
+			},
+			uncheckedValue: false,
+			value: false,
+		    },
+		    {
+			fieldLabel: gettext('Protected only'),
+			xtype: 'proxmoxcheckbox',
+			name: 'protected-only',
+			uncheckedValue: false,
+			value: false,
+		    },
 		],
 	    },
 	    {
@@ -341,6 +451,39 @@ Ext.define('PBS.window.SyncJobEdit', {
 		    },
 		],
 	    },
+	    {
+		xtype: 'inputpanel',
+		onGetValues: function(values) {
+		    let rules = (values['rename-rules'] ?? '')
+			.split('\n')
+			.map(rule => rule.trim())
+			.filter(rule => rule !== '');
+		    if (rules.length === 0) {
+			delete values['rename-rules'];
+			if (!this.up('pbsSyncJobEdit').isCreate) {
+			    values.delete = 'rename-rules';
+			}
+		    } else {
+			values['rename-rules'] = rules;
+		    }
+		    return values;
+		},
+		title: gettext('Rename Rules'),
+		items: [
+		    {
+			xtype: 'textarea',
+			name: 'rename-rules',
+			fieldLabel: gettext('Rules'),
+			emptyText: 'id-prefix:cluster1-',
+			height: 150,
+			anchor: '100%',
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('One rule per line, applied in order to namespaces (ns-) or backup IDs (id-)'),
+			},
+		    },
+		],
+	    },
 	],
     },
 });